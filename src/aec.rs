@@ -0,0 +1,1090 @@
+use anyhow::Result;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::config::AecConfig;
+use crate::processor::{AudioProcessor, Parameter};
+use crate::resampler::{Resampler, ResamplerQuality};
+use crate::ring_buffer::{self, Consumer, Producer};
+
+/// 参考信号缓冲区容量（采样数，单声道），48 kHz 时约 2 秒
+const REFERENCE_CAPACITY: usize = 96000;
+
+/// 参考信号预积累量的上限 (秒)。预积累从两个块开始，每次欠载增加一块；
+/// 积累越多参考信号越晚，超过设备缓冲造成的回声延迟后就无法消除
+const REFERENCE_PREFILL_MAX_SECONDS: f32 = 0.03;
+
+/// 参考信号积压超过预积累量加上该时长 (秒) 时丢弃多余部分，时钟漂移时重新对齐
+const REFERENCE_BACKLOG_SECONDS: f32 = 0.1;
+
+/// 写入参考信号时每次混合的帧数
+const REFERENCE_CHUNK: usize = 256;
+
+/// 处理块时长 (秒)，块长取不小于该时长的 2 的幂个采样
+const BLOCK_SECONDS: f32 = 0.005;
+
+/// 参考信号块均方值高于该值时视为对方在说话（约 -70 dBFS）
+const FAR_END_POWER: f32 = 1e-7;
+
+/// 频域归一化的正则项，避免参考信号很弱时步长过大
+const REGULARIZATION: f32 = 1e-6;
+
+/// 误差能量的平滑系数（按块）
+const ENERGY_SMOOTHING: f32 = 0.9;
+
+/// 后台滤波器误差比前台小这么多倍时复制到前台（约 1.5 dB）
+const COPY_RATIO: f32 = 0.7;
+
+/// 后台滤波器误差比前台大这么多倍时视为发散，从前台恢复（约 6 dB）
+const DIVERGENCE_RATIO: f32 = 4.0;
+
+/// 回声抑制量超过该值 (dB) 后才启用双讲检测
+const CONVERGED_ERLE_DB: f32 = 6.0;
+
+/// 误差与回声估计之比超过平时的该倍数时判定为双讲
+const DOUBLE_TALK_FACTOR: f32 = 4.0;
+
+/// 双讲期间后台滤波器步长的缩小倍数。不完全停止自适应，回声路径变化被误判为双讲时仍能重新收敛
+const DOUBLE_TALK_STEP: f32 = 0.1;
+
+/// 双讲判定的保持时间 (秒)
+const DOUBLE_TALK_HANGOVER_SECONDS: f32 = 0.1;
+
+/// 残余回声抑制的过减因子
+const OVER_SUBTRACTION: f32 = 2.0;
+
+/// 残余回声抑制增益回升的平滑系数（下降不平滑，先压住回声）
+const SUPPRESSION_RELEASE: f32 = 0.7;
+
+/// 延迟估计的相关窗口 (秒)
+const DELAY_WINDOW_SECONDS: f32 = 2.0;
+
+/// 延迟估计的间隔 (秒)
+const DELAY_INTERVAL_SECONDS: f32 = 0.5;
+
+/// 窗口内对方说话的块数占比低于该值时不估计延迟
+const DELAY_MIN_ACTIVITY: f32 = 0.25;
+
+/// 包络相关系数低于该值时不采用估计结果
+const DELAY_MIN_CORRELATION: f32 = 0.3;
+
+/// 各延迟的相关系数在相邻两次估计之间的平滑系数
+const DELAY_SCORE_SMOOTHING: f32 = 0.7;
+
+/// 当前延迟处的相关系数与最大值相差不到该值时保持当前延迟，避免来回切换重置滤波器
+const DELAY_HYSTERESIS: f32 = 0.1;
+
+/// 回声抑制量超过该值 (dB) 时保持当前延迟，不因新的估计重置滤波器
+const DELAY_KEEP_ERLE_DB: f32 = 12.0;
+
+/// 滤波器在估计延迟之前多覆盖的部分占滤波器长度的比例，容纳估计误差和混响使估计偏晚的部分
+const DELAY_MARGIN_FRACTION: f32 = 0.25;
+
+/// 参考信号的写入端与读取端共享的格式
+struct ReferenceFormat {
+    /// 参考信号的采样率，输出流还没有启动时为 0
+    sample_rate: AtomicU32,
+}
+
+/// 回声参考信号的写入端：放在输出流的输出回调中，记录扬声器实际播放的声音
+///
+/// 多声道混合为单声道后写入无锁环形缓冲区，回调中不分配内存、不阻塞；
+/// 放不下时丢弃整块并计数。
+pub struct EchoReference {
+    producer: Producer,
+    format: Arc<ReferenceFormat>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// 回声参考信号的读取端，交给 [`EchoCanceller`]
+pub struct ReferenceSource {
+    consumer: Consumer,
+    format: Arc<ReferenceFormat>,
+}
+
+impl EchoReference {
+    pub fn channel() -> (Self, ReferenceSource) {
+        let (producer, consumer) = ring_buffer::ring_buffer(REFERENCE_CAPACITY);
+        let format = Arc::new(ReferenceFormat {
+            sample_rate: AtomicU32::new(0),
+        });
+        let reference = Self {
+            producer,
+            format: format.clone(),
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (reference, ReferenceSource { consumer, format })
+    }
+
+    /// 设置之后写入数据的采样率，输出流启动时调用
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.format.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// 写入一块交错排列的扬声器数据
+    pub fn push(&mut self, data: &[f32], channels: u16) {
+        let channels = channels.max(1) as usize;
+        if self.producer.free() < data.len() / channels {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut mono = [0.0f32; REFERENCE_CHUNK];
+        for chunk in data.chunks(REFERENCE_CHUNK * channels) {
+            let frames = chunk.len() / channels;
+            for (out, frame) in mono.iter_mut().zip(chunk.chunks_exact(channels)) {
+                *out = frame.iter().sum::<f32>() / channels as f32;
+            }
+            self.producer.push_slice(&mono[..frames]);
+        }
+    }
+
+    /// 因缓冲区已满而被丢弃的数据块数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// 回声消除的运行状态，可在其它线程读取
+#[derive(Clone)]
+pub struct EchoMonitor {
+    /// 回声抑制量 ERLE (dB)，对方说话时更新
+    pub erle_db: Parameter,
+    /// 估计的参考信号到麦克风的延迟 (毫秒)，还没有估计出时为 0
+    pub delay_ms: Parameter,
+    /// 检测到双讲（双方同时说话）时为 1
+    pub double_talk: Parameter,
+    reference_rate_changed: Arc<AtomicBool>,
+}
+
+impl EchoMonitor {
+    fn new() -> Self {
+        Self {
+            erle_db: Parameter::new(0.0),
+            delay_ms: Parameter::new(0.0),
+            double_talk: Parameter::new(0.0),
+            reference_rate_changed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// 扬声器采样率与回声消除准备时不同（回声消除已暂停），返回后清除标记
+    ///
+    /// 重新启动输入流（重新调用 `prepare`）后恢复。
+    pub fn take_reference_rate_changed(&self) -> bool {
+        self.reference_rate_changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// 实数信号的 2N 点 FFT，频谱只保存 0..=N 共 N + 1 个频点
+struct RealFft {
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl RealFft {
+    fn new(size: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(size);
+        let inverse = planner.plan_fft_inverse(size);
+        let scratch_len = forward.get_inplace_scratch_len().max(inverse.get_inplace_scratch_len());
+        Self {
+            forward,
+            inverse,
+            buffer: vec![Complex::default(); size],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+
+    /// 对前后两半拼成的一帧（可选加窗）做正变换
+    fn forward(&mut self, first: &[f32], second: &[f32], window: Option<&[f32]>, spectrum: &mut [Complex<f32>]) {
+        let samples = first.iter().chain(second);
+        match window {
+            Some(window) => {
+                for ((out, &sample), &w) in self.buffer.iter_mut().zip(samples).zip(window) {
+                    *out = Complex::new(sample * w, 0.0);
+                }
+            }
+            None => {
+                for (out, &sample) in self.buffer.iter_mut().zip(samples) {
+                    *out = Complex::new(sample, 0.0);
+                }
+            }
+        }
+        self.forward.process_with_scratch(&mut self.buffer, &mut self.scratch);
+        spectrum.copy_from_slice(&self.buffer[..spectrum.len()]);
+    }
+
+    /// 逆变换（已归一化），按共轭对称补全负频率
+    fn inverse(&mut self, spectrum: &[Complex<f32>], output: &mut [f32]) {
+        let size = self.buffer.len();
+        self.buffer[..spectrum.len()].copy_from_slice(spectrum);
+        for (bin, value) in spectrum.iter().enumerate().take(size - spectrum.len() + 1).skip(1) {
+            self.buffer[size - bin] = value.conj();
+        }
+        self.inverse.process_with_scratch(&mut self.buffer, &mut self.scratch);
+        let scale = 1.0 / size as f32;
+        for (out, bin) in output.iter_mut().zip(&self.buffer) {
+            *out = bin.re * scale;
+        }
+    }
+}
+
+/// 按参考信号与麦克风信号的包络互相关估计回声延迟（以块为单位）
+///
+/// 相关的是包络的上升沿（每块均方根比上一块增加的部分）：混响拖长的是声音的衰减，
+/// 起音时刻不受影响，用整个包络相关时峰值会偏向混响的重心，估计值偏晚。
+/// 各延迟的相关系数在多次估计之间平滑，双讲时偶然出现的峰值不会立即改变估计。
+struct DelayEstimator {
+    /// 参考信号包络，长度为窗口加最大延迟
+    reference: Vec<f32>,
+    /// 麦克风信号包络，长度为窗口
+    microphone: Vec<f32>,
+    /// 已写入的块数
+    blocks: usize,
+    max_lag: usize,
+    interval: usize,
+    /// 平滑后各延迟的相关系数，第一次估计时直接采用
+    scores: Vec<f32>,
+    scored: bool,
+    /// 上一次估计的结果，连续两次一致才采用
+    candidate: Option<usize>,
+    current: Option<usize>,
+}
+
+impl DelayEstimator {
+    fn new(window: usize, max_lag: usize, interval: usize) -> Self {
+        Self {
+            reference: vec![0.0; window + max_lag],
+            microphone: vec![0.0; window],
+            blocks: 0,
+            max_lag,
+            interval: interval.max(1),
+            scores: vec![0.0; max_lag + 1],
+            scored: false,
+            candidate: None,
+            current: None,
+        }
+    }
+
+    /// 写入一块的包络（均方根），估计出与当前不同的延迟时返回新的延迟块数
+    fn update(&mut self, reference: f32, microphone: f32) -> Option<usize> {
+        let reference_len = self.reference.len();
+        let window = self.microphone.len();
+        self.reference[self.blocks % reference_len] = reference;
+        self.microphone[self.blocks % window] = microphone;
+        self.blocks += 1;
+        if self.blocks < reference_len || !self.blocks.is_multiple_of(self.interval) {
+            return None;
+        }
+
+        let active = (self.blocks - window..self.blocks)
+            .filter(|&block| self.reference[block % reference_len] > FAR_END_POWER.sqrt())
+            .count();
+        if (active as f32) < window as f32 * DELAY_MIN_ACTIVITY {
+            return None;
+        }
+
+        let onset = |envelope: &[f32], block: usize| {
+            let len = envelope.len();
+            (envelope[block % len] - envelope[(block + len - 1) % len]).max(0.0)
+        };
+        let mic = |block: usize| onset(&self.microphone, block);
+        let mic_mean = (self.blocks - window..self.blocks).map(mic).sum::<f32>() / window as f32;
+        let mic_variance = (self.blocks - window..self.blocks)
+            .map(|block| (mic(block) - mic_mean).powi(2))
+            .sum::<f32>();
+        for lag in 0..=self.max_lag {
+            let reference = |block: usize| onset(&self.reference, block - lag);
+            let mean = (self.blocks - window..self.blocks).map(reference).sum::<f32>() / window as f32;
+            let mut covariance = 0.0;
+            let mut variance = 0.0;
+            for block in self.blocks - window..self.blocks {
+                let r = reference(block) - mean;
+                covariance += r * (mic(block) - mic_mean);
+                variance += r * r;
+            }
+            let correlation = covariance / (variance * mic_variance).sqrt().max(1e-20);
+            self.scores[lag] = if self.scored {
+                DELAY_SCORE_SMOOTHING * self.scores[lag] + (1.0 - DELAY_SCORE_SMOOTHING) * correlation
+            } else {
+                correlation
+            };
+        }
+        self.scored = true;
+        let mut best = (0, f32::MIN);
+        for (lag, &score) in self.scores.iter().enumerate() {
+            if score > best.1 {
+                best = (lag, score);
+            }
+        }
+        let current = self.current.map_or(f32::MIN, |lag| self.scores[lag]);
+        if best.1 < DELAY_MIN_CORRELATION {
+            return None;
+        }
+        if current > best.1 - DELAY_HYSTERESIS {
+            self.candidate = None;
+            return None;
+        }
+
+        let stable = self.candidate.is_some_and(|candidate| candidate.abs_diff(best.0) <= 1);
+        self.candidate = Some(best.0);
+        let changed = self.current.is_none_or(|current| current.abs_diff(best.0) > 1);
+        (stable && changed).then_some(best.0)
+    }
+
+    /// 采用估计的延迟；没有采用时之后的估计仍会报告这个变化
+    fn accept(&mut self, lag: usize) {
+        self.current = Some(lag);
+    }
+}
+
+/// 每块各声道共用的参考信号数据
+struct BlockContext<'a> {
+    /// 最近 P 块参考信号的频谱，第 p 段对应延迟 p 块
+    spectra: &'a [Complex<f32>],
+    /// 各频点参考信号在所有分段上的功率和，用于归一化步长
+    power: &'a [f32],
+    far_end: bool,
+    step_size: f32,
+    floor: f32,
+    window: &'a [f32],
+    hangover: usize,
+}
+
+/// 一个麦克风声道的自适应滤波器和残余回声抑制
+struct ChannelCanceller {
+    /// 当前块的麦克风输入
+    input: Vec<f32>,
+    /// 已处理、等待输出的一块
+    ready: Vec<f32>,
+    /// 前台滤波器（用于输出），P 段 × (N + 1) 个频点
+    foreground: Vec<Complex<f32>>,
+    /// 后台滤波器（持续自适应），比前台好时复制到前台
+    background: Vec<Complex<f32>>,
+    /// 平滑后的麦克风、前台误差、后台误差能量
+    microphone_energy: f32,
+    foreground_energy: f32,
+    background_energy: f32,
+    /// 平滑后的回声估计能量
+    estimate_energy: f32,
+    /// 只有回声时前台误差与回声估计的能量比，双讲检测的基准
+    residual_ratio: f32,
+    erle_db: f32,
+    double_talk: usize,
+    /// 残余回声占回声估计的比例
+    leak: f32,
+    gains: Vec<f32>,
+    /// 上一块的误差和回声估计，残余回声抑制按 50% 重叠分帧
+    previous_error: Vec<f32>,
+    previous_echo: Vec<f32>,
+    /// 重叠相加累加器
+    output: Vec<f32>,
+}
+
+/// 处理一块时各声道共用的工作缓冲区
+struct Workspace {
+    fft: RealFft,
+    spectrum: Vec<Complex<f32>>,
+    gradient: Vec<Complex<f32>>,
+    time: Vec<f32>,
+    zeros: Vec<f32>,
+    echo: Vec<f32>,
+    error: Vec<f32>,
+    background_error: Vec<f32>,
+    error_spectrum: Vec<Complex<f32>>,
+    echo_spectrum: Vec<Complex<f32>>,
+}
+
+impl Workspace {
+    fn new(block: usize) -> Self {
+        let bins = block + 1;
+        Self {
+            fft: RealFft::new(block * 2),
+            spectrum: vec![Complex::default(); bins],
+            gradient: vec![Complex::default(); bins],
+            time: vec![0.0; block * 2],
+            zeros: vec![0.0; block],
+            echo: vec![0.0; block],
+            error: vec![0.0; block],
+            background_error: vec![0.0; block],
+            error_spectrum: vec![Complex::default(); bins],
+            echo_spectrum: vec![Complex::default(); bins],
+        }
+    }
+
+    /// 滤波器对参考信号的输出（时域，一块）
+    fn filter(&mut self, filter: &[Complex<f32>], spectra: &[Complex<f32>], output: &mut [f32]) {
+        let bins = self.spectrum.len();
+        self.spectrum.fill(Complex::default());
+        for (weights, reference) in filter.chunks_exact(bins).zip(spectra.chunks_exact(bins)) {
+            for ((out, w), x) in self.spectrum.iter_mut().zip(weights).zip(reference) {
+                *out += w * x;
+            }
+        }
+        self.fft.inverse(&self.spectrum, &mut self.time);
+        output.copy_from_slice(&self.time[bins - 1..]);
+    }
+}
+
+impl ChannelCanceller {
+    fn new(block: usize, partitions: usize) -> Self {
+        let bins = block + 1;
+        Self {
+            input: vec![0.0; block],
+            ready: vec![0.0; block],
+            foreground: vec![Complex::default(); partitions * bins],
+            background: vec![Complex::default(); partitions * bins],
+            microphone_energy: 0.0,
+            foreground_energy: 0.0,
+            background_energy: 0.0,
+            estimate_energy: 0.0,
+            residual_ratio: 1.0,
+            erle_db: 0.0,
+            double_talk: 0,
+            leak: 0.0,
+            gains: vec![1.0; bins],
+            previous_error: vec![0.0; block],
+            previous_echo: vec![0.0; block],
+            output: vec![0.0; block * 2],
+        }
+    }
+
+    fn process(&mut self, context: &BlockContext, work: &mut Workspace) {
+        let block = self.input.len();
+
+        // 前台、后台滤波器的回声估计和误差
+        let mut echo = std::mem::take(&mut work.echo);
+        work.filter(&self.foreground, context.spectra, &mut echo);
+        let mut background_error = std::mem::take(&mut work.background_error);
+        work.filter(&self.background, context.spectra, &mut background_error);
+        let mut microphone = 0.0;
+        let mut foreground = 0.0;
+        let mut background = 0.0;
+        let mut estimate = 0.0;
+        for (((&d, &y), e), eb) in self
+            .input
+            .iter()
+            .zip(echo.iter())
+            .zip(work.error.iter_mut())
+            .zip(background_error.iter_mut())
+        {
+            *e = d - y;
+            *eb = d - *eb;
+            microphone += d * d;
+            foreground += *e * *e;
+            background += *eb * *eb;
+            estimate += y * y;
+        }
+
+        if context.far_end {
+            let smooth = |value: &mut f32, new: f32| *value = ENERGY_SMOOTHING * *value + (1.0 - ENERGY_SMOOTHING) * new;
+            smooth(&mut self.microphone_energy, microphone);
+            smooth(&mut self.foreground_energy, foreground);
+            smooth(&mut self.background_energy, background);
+            smooth(&mut self.estimate_energy, estimate);
+            self.erle_db = 10.0 * (self.microphone_energy / self.foreground_energy.max(1e-20)).max(1e-10).log10();
+
+            // 双讲检测：收敛后误差突然远大于平时的残余回声比例，说明麦克风里有我方语音。
+            // 我方语音本身会拉低 ERLE，已在双讲中时不再要求 ERLE 高于收敛门限
+            let ratio = self.foreground_energy / self.estimate_energy.max(1e-20);
+            let converged = self.erle_db > CONVERGED_ERLE_DB || self.double_talk > 0;
+            if converged && ratio > DOUBLE_TALK_FACTOR * self.residual_ratio {
+                self.double_talk = context.hangover;
+            } else if self.double_talk > 0 {
+                self.double_talk -= 1;
+            } else {
+                self.residual_ratio = ENERGY_SMOOTHING * self.residual_ratio + (1.0 - ENERGY_SMOOTHING) * ratio.min(1.0);
+            }
+
+            let step_size = if self.double_talk > 0 {
+                context.step_size * DOUBLE_TALK_STEP
+            } else {
+                context.step_size
+            };
+            self.adapt(&background_error, step_size, context, work);
+
+            // 两路滤波器：后台明显更好时复制到前台，发散时从前台恢复
+            if self.background_energy < COPY_RATIO * self.foreground_energy {
+                self.foreground.copy_from_slice(&self.background);
+                self.foreground_energy = self.background_energy;
+            } else if self.background_energy > DIVERGENCE_RATIO * self.foreground_energy {
+                self.background.copy_from_slice(&self.foreground);
+                self.background_energy = self.foreground_energy;
+            }
+        }
+        // 对方停顿时无法判断双讲，保持原来的状态：停顿后我方仍在说话时不会误当作已收敛的残余回声
+        work.background_error = background_error;
+
+        // 残余回声抑制：按回声估计的频谱压低误差中残留的回声
+        work.fft
+            .forward(&self.previous_error, &work.error, Some(context.window), &mut work.error_spectrum);
+        work.fft
+            .forward(&self.previous_echo, &echo, Some(context.window), &mut work.echo_spectrum);
+        let error_power: f32 = work.error_spectrum.iter().map(|bin| bin.norm_sqr()).sum();
+        let echo_power: f32 = work.echo_spectrum.iter().map(|bin| bin.norm_sqr()).sum();
+        if context.far_end && self.double_talk == 0 && echo_power > 0.0 {
+            let leak = (error_power / echo_power).min(1.0);
+            self.leak = ENERGY_SMOOTHING * self.leak + (1.0 - ENERGY_SMOOTHING) * leak;
+        }
+        for ((gain, e), y) in self.gains.iter_mut().zip(work.error_spectrum.iter_mut()).zip(&work.echo_spectrum) {
+            let residual = OVER_SUBTRACTION * self.leak * y.norm_sqr();
+            let target = (1.0 - residual / e.norm_sqr().max(1e-20)).clamp(context.floor, 1.0);
+            *gain = if target < *gain {
+                target
+            } else {
+                SUPPRESSION_RELEASE * *gain + (1.0 - SUPPRESSION_RELEASE) * target
+            };
+            *e *= *gain;
+        }
+        work.fft.inverse(&work.error_spectrum, &mut work.time);
+        for ((out, &sample), &w) in self.output.iter_mut().zip(&work.time).zip(context.window) {
+            *out += sample * w;
+        }
+        self.ready.copy_from_slice(&self.output[..block]);
+        self.output.copy_within(block.., 0);
+        self.output[block..].fill(0.0);
+        self.previous_error.copy_from_slice(&work.error);
+        self.previous_echo.copy_from_slice(&echo);
+        work.echo = echo;
+    }
+
+    /// 后台滤波器按归一化频域 LMS 更新一步（带梯度约束）
+    fn adapt(&mut self, error: &[f32], step_size: f32, context: &BlockContext, work: &mut Workspace) {
+        let bins = error.len() + 1;
+        work.fft.forward(&work.zeros, error, None, &mut work.error_spectrum);
+        for (weights, reference) in self
+            .background
+            .chunks_exact_mut(bins)
+            .zip(context.spectra.chunks_exact(bins))
+        {
+            for (((gradient, x), e), &power) in work
+                .gradient
+                .iter_mut()
+                .zip(reference)
+                .zip(&work.error_spectrum)
+                .zip(context.power)
+            {
+                *gradient = x.conj() * e * (step_size / (power + REGULARIZATION));
+            }
+            // 梯度约束：去掉循环卷积部分，保证每段滤波器只有 N 个抽头
+            work.fft.inverse(&work.gradient, &mut work.time);
+            let (head, tail) = work.time.split_at_mut(bins - 1);
+            tail.fill(0.0);
+            work.fft.forward(head, tail, None, &mut work.gradient);
+            for (w, g) in weights.iter_mut().zip(&work.gradient) {
+                *w += g;
+            }
+        }
+    }
+}
+
+/// 回声消除：用扬声器播放的声音作为参考，消除麦克风拾取的回声
+///
+/// 自适应滤波器为分段块频域自适应滤波器（PBFDAF，重叠保留法，带梯度约束的归一化 LMS），
+/// 块长约 5 ms，滤波器长度 `filter_ms`。两路结构：后台滤波器持续自适应，误差明显更小时
+/// 复制到前台滤波器，前台滤波器的输出用于消除回声；收敛后误差突然增大判定为双讲，
+/// 双讲期间后台滤波器步长缩小到十分之一，避免把我方语音当作回声，前台滤波器也不会被误差更大的结果覆盖。
+///
+/// 扬声器到麦克风的延迟（设备缓冲、声音传播）按参考信号与麦克风信号的包络互相关估计，
+/// 参考信号先延迟估计值再送入滤波器，滤波器只需覆盖房间混响；估计值变化时滤波器随之平移。
+/// 最后按回声估计的频谱抑制残余回声，最大衰减 `suppression_db`。
+///
+/// 应放在输入处理器链的最前面（非线性处理之前）。算法延迟为两个块（48 kHz 时约 10.7 ms）。
+pub struct EchoCanceller {
+    config: AecConfig,
+    source: ReferenceSource,
+    monitor: EchoMonitor,
+    sample_rate: u32,
+    block: usize,
+    partitions: usize,
+    /// 当前块内已写入的帧数
+    position: usize,
+    channels: Vec<ChannelCanceller>,
+    work: Workspace,
+    window: Vec<f32>,
+    /// 参考信号的历史（麦克风采样率），用于按估计的延迟取出参考块
+    history: Vec<f32>,
+    history_position: usize,
+    /// 参考信号延迟（帧数）
+    delay: usize,
+    /// 上一个延迟后的参考块
+    previous_reference: Vec<f32>,
+    reference_block: Vec<f32>,
+    delayed_reference: Vec<f32>,
+    spectra: Vec<Complex<f32>>,
+    power: Vec<f32>,
+    estimator: DelayEstimator,
+    /// 准备时参考信号的采样率
+    reference_rate: u32,
+    resampler: Option<Resampler>,
+    /// 从环形缓冲区取出的原始参考信号
+    chunk: Vec<f32>,
+    /// 已转换到麦克风采样率、尚未使用的参考信号
+    pending: Vec<f32>,
+    primed: bool,
+    /// 开始使用参考信号前需要积累的采样数，欠载时增加
+    prefill: usize,
+    prefill_limit: usize,
+    backlog: usize,
+    hangover: usize,
+    /// 滤波器在估计延迟之前多覆盖的块数
+    margin: usize,
+}
+
+impl EchoCanceller {
+    pub fn new(config: AecConfig, source: ReferenceSource) -> Self {
+        let mut canceller = Self {
+            config,
+            source,
+            monitor: EchoMonitor::new(),
+            sample_rate: 48000,
+            block: 1,
+            partitions: 1,
+            position: 0,
+            channels: Vec::new(),
+            work: Workspace::new(1),
+            window: Vec::new(),
+            history: Vec::new(),
+            history_position: 0,
+            delay: 0,
+            previous_reference: Vec::new(),
+            reference_block: Vec::new(),
+            delayed_reference: Vec::new(),
+            spectra: Vec::new(),
+            power: Vec::new(),
+            estimator: DelayEstimator::new(1, 0, 1),
+            reference_rate: 0,
+            resampler: None,
+            chunk: Vec::new(),
+            pending: Vec::new(),
+            primed: false,
+            prefill: 0,
+            prefill_limit: 0,
+            backlog: 0,
+            hangover: 0,
+            margin: 0,
+        };
+        canceller.prepare(48000, 1);
+        canceller
+    }
+
+    /// 运行状态（回声抑制量、延迟、双讲）
+    pub fn monitor(&self) -> EchoMonitor {
+        self.monitor.clone()
+    }
+
+    /// 一个处理块对应的参考信号采样数（参考信号采样率）
+    fn reference_block_frames(&self) -> usize {
+        (self.block as u64 * self.reference_rate as u64).div_ceil(self.sample_rate as u64) as usize
+    }
+
+    /// 取出与当前麦克风块对应的一块参考信号（麦克风采样率）
+    fn fetch_reference(&mut self) {
+        let block = self.block;
+        let rate = self.source.format.sample_rate.load(Ordering::Relaxed);
+        if rate != self.reference_rate {
+            // 输出流还没有启动，或采样率变化后需要重新准备
+            if rate != 0 {
+                self.monitor.reference_rate_changed.store(true, Ordering::Relaxed);
+            }
+            let available = self.source.consumer.len();
+            self.source.consumer.skip(available);
+            self.reference_block.fill(0.0);
+            return;
+        }
+
+        let available = self.source.consumer.len();
+        if !self.primed {
+            if available < self.prefill {
+                self.reference_block.fill(0.0);
+                return;
+            }
+            self.primed = true;
+        }
+        if available > self.prefill + self.backlog {
+            self.source.consumer.skip(available - self.prefill);
+        }
+        while self.pending.len() < block {
+            let missing = block - self.pending.len();
+            let wanted = match &self.resampler {
+                Some(_) => (missing as u64 * rate as u64).div_ceil(self.sample_rate as u64) as usize + 1,
+                None => missing,
+            }
+            .min(self.chunk.capacity());
+            self.chunk.resize(wanted, 0.0);
+            let read = self.source.consumer.pop_slice(&mut self.chunk);
+            if read == 0 {
+                // 欠载：不足的部分按静音处理，多积累一块后重新开始
+                self.primed = false;
+                self.prefill = (self.prefill + self.reference_block_frames()).min(self.prefill_limit);
+                break;
+            }
+            match &mut self.resampler {
+                Some(resampler) => resampler.process(&self.chunk[..read], &mut self.pending),
+                None => self.pending.extend_from_slice(&self.chunk[..read]),
+            }
+        }
+        let used = self.pending.len().min(block);
+        self.reference_block[..used].copy_from_slice(&self.pending[..used]);
+        self.reference_block[used..].fill(0.0);
+        self.pending.copy_within(used.., 0);
+        self.pending.truncate(self.pending.len() - used);
+    }
+
+    /// 修改参考信号延迟。延迟总是整数个块，滤波器和参考频谱按块平移，已收敛的部分不丢失
+    fn set_delay(&mut self, delay: usize) {
+        let bins = self.block + 1;
+        let partitions = self.partitions;
+        let shift = |data: &mut [Complex<f32>]| {
+            if delay >= self.delay {
+                // 延迟增加 k 块：原来第 p + k 段对应新的第 p 段
+                let k = ((delay - self.delay) / self.block).min(partitions);
+                data.copy_within(k * bins.., 0);
+                data[(partitions - k) * bins..].fill(Complex::default());
+            } else {
+                let k = ((self.delay - delay) / self.block).min(partitions);
+                data.copy_within(..(partitions - k) * bins, k * bins);
+                data[..k * bins].fill(Complex::default());
+            }
+        };
+        shift(&mut self.spectra);
+        for channel in &mut self.channels {
+            shift(&mut channel.foreground);
+            shift(&mut channel.background);
+        }
+        self.delay = delay;
+
+        // 下一块重叠保留用的前一块参考信号
+        let len = self.history.len();
+        let start = (self.history_position + len - self.block - delay) % len;
+        for (i, out) in self.previous_reference.iter_mut().enumerate() {
+            *out = self.history[(start + i) % len];
+        }
+        self.monitor
+            .delay_ms
+            .set(delay as f32 * 1000.0 / self.sample_rate as f32);
+    }
+
+    fn process_block(&mut self) {
+        let block = self.block;
+        let bins = block + 1;
+        self.fetch_reference();
+
+        // 延迟估计
+        let reference_rms = (self.reference_block.iter().map(|x| x * x).sum::<f32>() / block as f32).sqrt();
+        let channels = self.channels.len() as f32;
+        let microphone_rms = ((0..block)
+            .map(|i| self.channels.iter().map(|channel| channel.input[i]).sum::<f32>() / channels)
+            .map(|x| x * x)
+            .sum::<f32>()
+            / block as f32)
+            .sqrt();
+        // 滤波器已经收敛说明当前延迟可用，不为新的估计重置（双讲时估计可能出错）
+        if let Some(lag) = self.estimator.update(reference_rms, microphone_rms) {
+            if self.channels.iter().all(|channel| channel.erle_db < DELAY_KEEP_ERLE_DB) {
+                self.estimator.accept(lag);
+                self.set_delay(lag.saturating_sub(self.margin) * block);
+            }
+        }
+
+        // 写入参考信号历史，取出延迟后的一块
+        let len = self.history.len();
+        for (i, &sample) in self.reference_block.iter().enumerate() {
+            self.history[(self.history_position + i) % len] = sample;
+        }
+        self.history_position = (self.history_position + block) % len;
+        let start = (self.history_position + len - block - self.delay) % len;
+        for (i, out) in self.delayed_reference.iter_mut().enumerate() {
+            *out = self.history[(start + i) % len];
+        }
+
+        // 参考信号频谱：最新的一段放在最前面
+        let partitions = self.partitions;
+        self.spectra.copy_within(..(partitions - 1) * bins, bins);
+        self.work.fft.forward(
+            &self.previous_reference,
+            &self.delayed_reference,
+            None,
+            &mut self.spectra[..bins],
+        );
+        self.previous_reference.copy_from_slice(&self.delayed_reference);
+        self.power.fill(0.0);
+        for spectrum in self.spectra.chunks_exact(bins) {
+            for (power, bin) in self.power.iter_mut().zip(spectrum) {
+                *power += bin.norm_sqr();
+            }
+        }
+        let far_end = self.delayed_reference.iter().map(|x| x * x).sum::<f32>() / block as f32 > FAR_END_POWER;
+
+        let context = BlockContext {
+            spectra: &self.spectra,
+            power: &self.power,
+            far_end,
+            step_size: self.config.step_size.clamp(0.01, 1.0),
+            floor: 10f32.powf(-self.config.suppression_db.clamp(0.0, 60.0) / 20.0),
+            window: &self.window,
+            hangover: self.hangover,
+        };
+        for channel in &mut self.channels {
+            channel.process(&context, &mut self.work);
+        }
+
+        if let Some(first) = self.channels.first() {
+            if far_end {
+                self.monitor.erle_db.set(first.erle_db);
+            }
+            self.monitor
+                .double_talk
+                .set(if far_end && first.double_talk > 0 { 1.0 } else { 0.0 });
+        }
+    }
+}
+
+impl AudioProcessor for EchoCanceller {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        let channels = self.channels.len();
+        for frame in buffer.chunks_exact_mut(channels) {
+            for (channel, sample) in self.channels.iter_mut().zip(frame.iter_mut()) {
+                channel.input[self.position] = *sample;
+                *sample = channel.ready[self.position];
+            }
+            self.position += 1;
+            if self.position == self.block {
+                self.position = 0;
+                self.process_block();
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "回声消除"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        let sample_rate = sample_rate.max(1);
+        let block = ((sample_rate as f32 * BLOCK_SECONDS).round() as usize).next_power_of_two();
+        let blocks_per_second = sample_rate as f32 / block as f32;
+        let filter_frames = self.config.filter_ms.clamp(10, 500) as usize * sample_rate as usize / 1000;
+        let partitions = filter_frames.div_ceil(block).max(1);
+        let max_delay = self.config.max_delay_ms.min(1000) as usize * sample_rate as usize / 1000;
+
+        self.sample_rate = sample_rate;
+        self.block = block;
+        self.partitions = partitions;
+        self.position = 0;
+        self.channels = (0..channels.max(1))
+            .map(|_| ChannelCanceller::new(block, partitions))
+            .collect();
+        self.work = Workspace::new(block);
+        // 平方根汉宁窗，50% 重叠时分析、合成两次加窗之和恒为 1
+        self.window = (0..block * 2)
+            .map(|n| (0.5 - 0.5 * (PI * n as f32 / block as f32).cos()).sqrt())
+            .collect();
+        self.history = vec![0.0; max_delay + block * 2];
+        self.history_position = 0;
+        self.previous_reference = vec![0.0; block];
+        self.reference_block = vec![0.0; block];
+        self.delayed_reference = vec![0.0; block];
+        self.spectra = vec![Complex::default(); partitions * (block + 1)];
+        self.power = vec![0.0; block + 1];
+        self.estimator = DelayEstimator::new(
+            (DELAY_WINDOW_SECONDS * blocks_per_second) as usize,
+            max_delay / block,
+            (DELAY_INTERVAL_SECONDS * blocks_per_second) as usize,
+        );
+        self.delay = 0;
+        self.set_delay(0);
+        self.hangover = (DOUBLE_TALK_HANGOVER_SECONDS * blocks_per_second).ceil() as usize;
+        self.margin = ((partitions as f32 * DELAY_MARGIN_FRACTION) as usize).max(2);
+
+        // 输出流还没有启动时假定扬声器与麦克风采样率相同
+        let reference_rate = match self.source.format.sample_rate.load(Ordering::Relaxed) {
+            0 => sample_rate,
+            rate => rate,
+        };
+        self.reference_rate = reference_rate;
+        let chunk = block * 4;
+        self.resampler = (reference_rate != sample_rate).then(|| {
+            let mut resampler = Resampler::new(reference_rate, sample_rate, 1, ResamplerQuality::default());
+            resampler.reserve(chunk);
+            resampler
+        });
+        self.chunk = Vec::with_capacity(chunk);
+        let pending = block
+            + self
+                .resampler
+                .as_ref()
+                .map_or(chunk, |resampler| resampler.max_output_frames(chunk));
+        self.pending = Vec::with_capacity(pending);
+        self.primed = false;
+        self.prefill = self.reference_block_frames() * 2;
+        self.prefill_limit = ((reference_rate as f32 * REFERENCE_PREFILL_MAX_SECONDS) as usize).max(self.prefill);
+        self.backlog = (reference_rate as f32 * REFERENCE_BACKLOG_SECONDS) as usize;
+        self.monitor.reference_rate_changed.store(false, Ordering::Relaxed);
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.block * 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loudness::amplitude_to_db;
+
+    const RATE: u32 = 16_000;
+    /// 每次回调的帧数（10 ms）
+    const PERIOD: usize = 160;
+
+    /// 确定性的线性同余随机数，均匀分布在 [-1, 1]
+    fn random(state: &mut u32) -> f32 {
+        *state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        *state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    /// 类似语音的对方信号：白噪声按随机长度的音节开关，音节间有停顿
+    fn far_end(samples: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        let mut envelope = Vec::with_capacity(samples);
+        while envelope.len() < samples {
+            let on = (0.12 + 0.1 * random(&mut state).abs()) * RATE as f32;
+            let off = (0.03 + 0.12 * random(&mut state).abs()) * RATE as f32;
+            envelope.extend((0..on as usize).map(|n| (PI * n as f32 / on).sin()));
+            envelope.extend(std::iter::repeat_n(0.0, off as usize));
+        }
+        envelope.truncate(samples);
+        envelope.iter().map(|&gain| 0.3 * gain * random(&mut state)).collect()
+    }
+
+    /// 房间冲激响应：`bulk_ms` 的纯延迟后接指数衰减的随机反射（30 ms 衰减 60 dB）
+    fn room_response(bulk_ms: usize) -> Vec<f32> {
+        let bulk = bulk_ms * RATE as usize / 1000;
+        let tail = RATE as usize * 30 / 1000;
+        let mut state = 7;
+        let mut response = vec![0.0; bulk];
+        response.push(0.5);
+        response.extend((1..tail).map(|n| 0.3 * random(&mut state) * 10f32.powf(-3.0 * n as f32 / tail as f32)));
+        response
+    }
+
+    fn convolve(signal: &[f32], response: &[f32]) -> Vec<f32> {
+        let taps: Vec<(usize, f32)> = response.iter().copied().enumerate().filter(|&(_, h)| h != 0.0).collect();
+        (0..signal.len())
+            .map(|n| taps.iter().filter(|&&(k, _)| k <= n).map(|&(k, h)| h * signal[n - k]).sum())
+            .collect()
+    }
+
+    fn power_db(samples: &[f32]) -> f64 {
+        let power = samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64;
+        amplitude_to_db(power.sqrt())
+    }
+
+    struct Run {
+        echo: Vec<f32>,
+        output: Vec<f32>,
+        /// 每次回调后的双讲标记
+        double_talk: Vec<bool>,
+        monitor: EchoMonitor,
+        latency: usize,
+    }
+
+    /// 模拟双工流：每次回调先把扬声器信号写入参考，再处理同一时刻的麦克风信号（回声加我方语音）
+    fn run(bulk_ms: usize, far: &[f32], near: &[f32]) -> Run {
+        let echo = convolve(far, &room_response(bulk_ms));
+        let (mut reference, source) = EchoReference::channel();
+        reference.set_sample_rate(RATE);
+        let mut canceller = EchoCanceller::new(
+            AecConfig {
+                enabled: true,
+                ..AecConfig::default()
+            },
+            source,
+        );
+        canceller.prepare(RATE, 1);
+        let monitor = canceller.monitor();
+        let mut output: Vec<f32> = echo.iter().zip(near).map(|(&e, &s)| e + s).collect();
+        let mut double_talk = Vec::new();
+        for (speaker, microphone) in far.chunks(PERIOD).zip(output.chunks_mut(PERIOD)) {
+            reference.push(speaker, 1);
+            canceller.process(microphone).unwrap();
+            double_talk.push(monitor.double_talk.get() > 0.5);
+        }
+        Run {
+            echo,
+            output,
+            double_talk,
+            monitor,
+            latency: canceller.latency_frames(),
+        }
+    }
+
+    #[test]
+    fn delay_is_tracked_and_echo_is_cancelled() {
+        let seconds = 10;
+        let far = far_end(RATE as usize * seconds, 1);
+        let silence = vec![0.0; far.len()];
+        let mut delays = Vec::new();
+        for bulk_ms in [150, 300] {
+            let run = run(bulk_ms, &far, &silence);
+            // 收敛后最后 2 秒的回声抑制量：线性滤波器的估计和包括残余回声抑制的实际值
+            let settled = RATE as usize * (seconds - 2)..;
+            let erle = power_db(&run.echo[settled.clone()]) - power_db(&run.output[settled]);
+            let estimated = run.monitor.erle_db.get();
+            assert!(erle > 30.0, "{} ms 延迟时回声只减小了 {:.1} dB", bulk_ms, erle);
+            assert!(estimated > 20.0, "{} ms 延迟时估计的 ERLE 只有 {:.1} dB", bulk_ms, estimated);
+            // 纯延迟超出滤波器长度，必须先估计出延迟；估计的延迟加上滤波器长度应覆盖整个回声路径
+            let delay = run.monitor.delay_ms.get() as usize;
+            assert!(delay < bulk_ms && bulk_ms + 30 <= delay + 100, "{} ms 延迟时估计为 {} ms", bulk_ms, delay);
+            delays.push(delay);
+        }
+        // 两次估计之差与纯延迟之差相差不超过一块（8 ms）
+        assert!((delays[1] - delays[0]).abs_diff(150) <= 8, "估计的延迟 {:?} ms", delays);
+    }
+
+    #[test]
+    fn near_end_speech_survives_double_talk() {
+        let seconds = 12;
+        let far = far_end(RATE as usize * seconds, 1);
+        // 收敛后的第 6 到 9 秒我方说话：140 Hz 基频及其谐波，按音节开关，电平与回声相当
+        let talk = RATE as usize * 6..RATE as usize * 9;
+        let syllables = far_end(talk.len(), 2);
+        let mut near = vec![0.0; far.len()];
+        for (n, (out, &syllable)) in near[talk.clone()].iter_mut().zip(&syllables).enumerate() {
+            let t = n as f32 / RATE as f32;
+            let voiced: f32 = (1..=8).map(|k| (2.0 * PI * 140.0 * k as f32 * t).sin() / k as f32).sum();
+            *out = syllable.abs() * voiced;
+        }
+        let run = run(100, &far, &near);
+
+        // 双讲期间检测到我方语音，收敛后只有回声时没有误报
+        let detected = |range: std::ops::Range<usize>| {
+            run.double_talk[range.start / PERIOD..range.end / PERIOD].iter().filter(|&&flag| flag).count()
+        };
+        let periods = talk.len() / PERIOD;
+        assert!(detected(talk.clone()) > periods / 2, "双讲只检测到 {}/{} 次", detected(talk.clone()), periods);
+        assert_eq!(detected(RATE as usize * 2..talk.start), 0);
+
+        // 输出去掉处理延迟后与我方语音对齐，分解为我方语音分量和其余部分（残余回声、失真）
+        let speech = &near[talk.clone()];
+        let output = &run.output[talk.start + run.latency..talk.end + run.latency];
+        let gain = output.iter().zip(speech).map(|(&out, &s)| out as f64 * s as f64).sum::<f64>()
+            / speech.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+        let rest: Vec<f32> = output.iter().zip(speech).map(|(&out, &s)| out - gain as f32 * s).collect();
+        let speech_db = power_db(speech);
+        assert!(amplitude_to_db(gain) > -3.0, "我方语音衰减了 {:.1} dB", -amplitude_to_db(gain));
+        let rest_db = power_db(&rest);
+        assert!(rest_db < speech_db - 8.0, "其余部分 {:.1} dB，我方语音 {:.1} dB", rest_db, speech_db);
+        assert!(rest_db < power_db(&run.echo[talk]) - 10.0);
+
+        // 双讲结束后滤波器没有发散，回声仍被消除
+        let after = RATE as usize * 10..;
+        let erle = power_db(&run.echo[after.clone()]) - power_db(&run.output[after]);
+        assert!(erle > 30.0, "双讲结束后回声只减小了 {:.1} dB", erle);
+    }
+}
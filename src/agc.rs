@@ -0,0 +1,310 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::loudness::{db_to_amplitude, loudness_from_power, KWeighting};
+use crate::processor::{AudioProcessor, Parameter};
+
+/// 响度测量的子块时长 (毫秒)
+const SUB_BLOCK_MS: u32 = 100;
+
+/// 判断是否有声的瞬时响度窗口（400 ms，与 BS.1770 的测量块一致）
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+
+/// 自动增益参数，对应配置中 `type = "agc"` 的字段
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgcSettings {
+    /// 目标响度 (LUFS)
+    pub target_lufs: f32,
+    /// 最大提升 (dB)
+    pub max_boost_db: f32,
+    /// 最大衰减 (dB)
+    pub max_cut_db: f32,
+    /// 短期响度的测量窗口 (毫秒)
+    pub window_ms: u32,
+    /// 瞬时响度低于该值视为静音，停止调整增益 (LUFS)
+    pub gate_lufs: f32,
+    /// 增益每秒最多调整的量 (dB)
+    pub speed_db_per_s: f32,
+}
+
+impl Default for AgcSettings {
+    fn default() -> Self {
+        Self {
+            target_lufs: -20.0,
+            max_boost_db: 12.0,
+            max_cut_db: 12.0,
+            window_ms: 3000,
+            gate_lufs: -50.0,
+            speed_db_per_s: 6.0,
+        }
+    }
+}
+
+/// 自动增益控制：把响度调整到目标 LUFS
+///
+/// 按 ITU-R BS.1770 K 加权测量输入的响度（前馈，不受自身增益影响）：每 100 ms 计算一次
+/// 最近 400 ms 的瞬时响度，高于门限的块计入短期响度窗口，所需增益为目标响度与短期响度之差，
+/// 限制在最大提升/衰减之内；实际增益按 `speed_db_per_s` 逐采样逼近，避免音量突变。
+/// 瞬时响度低于门限（静音、停顿）时增益保持不变，停顿中的底噪不会被放大。
+///
+/// 提升后的峰值可能超过 0 dBFS，应在后面接限幅器。
+pub struct AgcProcessor {
+    settings: AgcSettings,
+    channels: usize,
+    filters: Vec<KWeighting>,
+    sub_block_frames: usize,
+    sub_block_position: usize,
+    sub_block_energy: f64,
+    /// 最近几个子块的均方值，用于瞬时响度
+    recent: [f64; MOMENTARY_SUB_BLOCKS],
+    recent_count: usize,
+    /// 高于门限的瞬时响度块（均方值），环形缓冲区在 `prepare` 中分配
+    active: Vec<f64>,
+    active_position: usize,
+    active_count: usize,
+    /// 目标增益 (dB)
+    target_gain_db: f32,
+    /// 当前增益 (dB)
+    gain_db: f32,
+    /// 每帧最多调整的增益 (dB)
+    step_db: f32,
+    gain_meter: Parameter,
+    loudness_meter: Parameter,
+}
+
+impl AgcProcessor {
+    pub fn new(settings: AgcSettings) -> Self {
+        let mut agc = Self {
+            settings,
+            channels: 1,
+            filters: Vec::new(),
+            sub_block_frames: 1,
+            sub_block_position: 0,
+            sub_block_energy: 0.0,
+            recent: [0.0; MOMENTARY_SUB_BLOCKS],
+            recent_count: 0,
+            active: Vec::new(),
+            active_position: 0,
+            active_count: 0,
+            target_gain_db: 0.0,
+            gain_db: 0.0,
+            step_db: 0.0,
+            gain_meter: Parameter::new(0.0),
+            loudness_meter: Parameter::new(f32::NEG_INFINITY),
+        };
+        agc.prepare(48000, 1);
+        agc
+    }
+
+    /// 当前施加的增益 (dB)，可在其它线程读取
+    pub fn gain_db(&self) -> Parameter {
+        self.gain_meter.clone()
+    }
+
+    /// 门限以上的短期响度 (LUFS)，还没有测到有声音频时为负无穷
+    pub fn loudness(&self) -> Parameter {
+        self.loudness_meter.clone()
+    }
+
+    /// 一个子块结束：更新瞬时响度，有声时更新短期响度和目标增益
+    fn finish_sub_block(&mut self) {
+        let power = self.sub_block_energy / self.sub_block_frames as f64;
+        self.sub_block_energy = 0.0;
+        self.recent.rotate_left(1);
+        self.recent[MOMENTARY_SUB_BLOCKS - 1] = power;
+        self.recent_count = (self.recent_count + 1).min(MOMENTARY_SUB_BLOCKS);
+        if self.recent_count < MOMENTARY_SUB_BLOCKS {
+            return;
+        }
+
+        let momentary = self.recent.iter().sum::<f64>() / MOMENTARY_SUB_BLOCKS as f64;
+        if loudness_from_power(momentary) < self.settings.gate_lufs as f64 {
+            // 静音时保持当前目标，已在调整中的增益继续走完
+            return;
+        }
+        self.active[self.active_position] = momentary;
+        self.active_position = (self.active_position + 1) % self.active.len();
+        self.active_count = (self.active_count + 1).min(self.active.len());
+
+        let mean = self.active[..self.active_count].iter().sum::<f64>() / self.active_count as f64;
+        let loudness = loudness_from_power(mean) as f32;
+        self.target_gain_db =
+            (self.settings.target_lufs - loudness).clamp(-self.settings.max_cut_db, self.settings.max_boost_db);
+        self.loudness_meter.set(loudness);
+    }
+}
+
+impl AudioProcessor for AgcProcessor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        let mut gain = db_to_amplitude(self.gain_db as f64) as f32;
+        for frame in buffer.chunks_exact_mut(self.channels) {
+            for (filter, &sample) in self.filters.iter_mut().zip(frame.iter()) {
+                let weighted = filter.process(sample) as f64;
+                self.sub_block_energy += weighted * weighted;
+            }
+            self.sub_block_position += 1;
+            if self.sub_block_position == self.sub_block_frames {
+                self.sub_block_position = 0;
+                self.finish_sub_block();
+            }
+
+            if self.gain_db != self.target_gain_db {
+                let change = (self.target_gain_db - self.gain_db).clamp(-self.step_db, self.step_db);
+                self.gain_db += change;
+                gain = db_to_amplitude(self.gain_db as f64) as f32;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+        self.gain_meter.set(self.gain_db);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "自动增益"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.filters = vec![KWeighting::new(sample_rate); self.channels];
+        self.sub_block_frames = (sample_rate * SUB_BLOCK_MS / 1000).max(1) as usize;
+        self.sub_block_position = 0;
+        self.sub_block_energy = 0.0;
+        self.recent_count = 0;
+        let window = (self.settings.window_ms / SUB_BLOCK_MS).max(1) as usize;
+        self.active = vec![0.0; window];
+        self.active_position = 0;
+        self.active_count = 0;
+        self.step_db = self.settings.speed_db_per_s / sample_rate.max(1) as f32;
+        // 增益在重新启动音频流后沿用，避免设备切换时音量跳变
+        self.target_gain_db = self.gain_db;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loudness::{amplitude_to_db, LoudnessMeter};
+    use std::f64::consts::PI;
+
+    fn sine(frequency: f64, level_db: f64, seconds: f64) -> Vec<f32> {
+        let amplitude = db_to_amplitude(level_db);
+        (0..(seconds * 48_000.0) as usize)
+            .map(|n| (amplitude * (2.0 * PI * frequency * n as f64 / 48_000.0).sin()) as f32)
+            .collect()
+    }
+
+    /// BS.1770 给出的 48 kHz K 加权滤波器系数在 `frequency` 处的增益 (dB)
+    fn reference_k_weighting_db(frequency: f64) -> f64 {
+        let response = |b: [f64; 3], a: [f64; 2]| {
+            let w = 2.0 * PI * frequency / 48_000.0;
+            let evaluate = |c: [f64; 3]| {
+                let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+                let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+                (re * re + im * im).sqrt()
+            };
+            evaluate(b) / evaluate([1.0, a[0], a[1]])
+        };
+        let shelf = response(
+            [1.53512485958697, -2.69169618940638, 1.19839281085285],
+            [-1.69065929318241, 0.73248077421585],
+        );
+        let highpass = response([1.0, -2.0, 1.0], [-1.99004745483398, 0.99007225036621]);
+        amplitude_to_db(shelf * highpass)
+    }
+
+    #[test]
+    fn k_weighting_matches_bs1770_coefficients() {
+        for frequency in [20.0, 50.0, 100.0, 500.0, 1000.0, 2000.0, 5000.0, 10_000.0, 20_000.0] {
+            let input = sine(frequency, 0.0, 1.0);
+            let mut filter = KWeighting::new(48_000);
+            let output: Vec<f32> = input.iter().map(|&sample| filter.process(sample)).collect();
+            // 跳过前 0.5 秒的瞬态，按有效值之比计算稳态增益
+            let rms = |samples: &[f32]| (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt();
+            let gain = amplitude_to_db(rms(&output[24_000..]) / rms(&input[24_000..]));
+            let expected = reference_k_weighting_db(frequency);
+            assert!((gain - expected).abs() < 0.01, "{} Hz 增益 {:.3} dB，应为 {:.3} dB", frequency, gain, expected);
+        }
+        // 1 kHz 的 K 加权增益使满幅正弦的响度为 -3.01 LUFS
+        assert!((reference_k_weighting_db(1000.0) - 0.691).abs() < 0.01);
+    }
+
+    fn settings() -> AgcSettings {
+        AgcSettings {
+            target_lufs: -20.0,
+            max_boost_db: 20.0,
+            max_cut_db: 20.0,
+            ..AgcSettings::default()
+        }
+    }
+
+    /// 按 10 ms 一块处理，返回输出和每块结束时的增益 (dB)
+    fn run(agc: &mut AgcProcessor, input: &[f32]) -> (Vec<f32>, Vec<f32>) {
+        let mut output = input.to_vec();
+        let gains = output
+            .chunks_mut(480)
+            .map(|block| {
+                agc.process(block).unwrap();
+                agc.gain_db().get()
+            })
+            .collect();
+        (output, gains)
+    }
+
+    fn integrated_lufs(samples: &[f32]) -> f64 {
+        let mut meter = LoudnessMeter::new(48_000, 1);
+        meter.process(samples);
+        meter.report().integrated_lufs.unwrap()
+    }
+
+    #[test]
+    fn quiet_speech_is_raised_to_target() {
+        // -30 dBFS 的 1 kHz 单声道正弦响度为 -33.01 LUFS
+        let input = sine(1000.0, -30.0, 8.0);
+        assert!((integrated_lufs(&input) + 33.01).abs() < 0.05);
+        let mut agc = AgcProcessor::new(settings());
+        assert_eq!(agc.loudness().get(), f32::NEG_INFINITY);
+        let (output, gains) = run(&mut agc, &input);
+
+        assert!((agc.loudness().get() + 33.01).abs() < 0.05, "测得响度 {} LUFS", agc.loudness().get());
+        assert!((gains.last().unwrap() - 13.01).abs() < 0.05, "增益 {} dB", gains.last().unwrap());
+        let settled = integrated_lufs(&output[4 * 48_000..]);
+        assert!((settled + 20.0).abs() < 0.1, "输出响度 {:.2} LUFS", settled);
+    }
+
+    #[test]
+    fn gain_changes_at_limited_speed_within_limits() {
+        let mut agc = AgcProcessor::new(AgcSettings {
+            max_cut_db: 6.0,
+            ..settings()
+        });
+        // -6 dBFS 的正弦响度为 -9.01 LUFS，需要衰减 11 dB，限制在 6 dB
+        let (_, gains) = run(&mut agc, &sine(1000.0, -6.0, 4.0));
+        assert_eq!(*gains.last().unwrap(), -6.0);
+        let mut previous = 0.0;
+        for (block, &gain) in gains.iter().enumerate() {
+            // 每 10 ms 最多调整 0.06 dB
+            assert!((gain - previous).abs() <= 0.0601, "第 {} 块增益从 {} 跳到 {} dB", block, previous, gain);
+            previous = gain;
+        }
+        // 从 0 dB 到 -6 dB 至少需要 1 秒
+        let reached = gains.iter().position(|&gain| gain == -6.0).unwrap();
+        assert!(reached >= 100, "{} 块后到达", reached);
+    }
+
+    #[test]
+    fn gain_is_held_during_silence() {
+        let mut agc = AgcProcessor::new(settings());
+        let (_, gains) = run(&mut agc, &sine(1000.0, -30.0, 8.0));
+        let converged = *gains.last().unwrap();
+        assert!((converged - 13.01).abs() < 0.05);
+        // 停顿中只有 -70 dBFS 的底噪，低于 -50 LUFS 的门限，底噪不会被继续放大；
+        // 停顿开始的 400 ms 内瞬时响度窗口仍包含语音，短期响度略有下降
+        let (_, gains) = run(&mut agc, &sine(200.0, -70.0, 5.0));
+        assert!(gains.iter().all(|&gain| (gain - converged).abs() < 0.5), "静音中增益变化过大");
+        assert!(gains[50..].iter().all(|&gain| gain == gains[50]), "静音中增益没有保持");
+        assert!((agc.loudness().get() + 33.01).abs() < 0.5);
+    }
+}
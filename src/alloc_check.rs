@@ -0,0 +1,86 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// 当前线程是否处于实时回调中
+    static REALTIME: Cell<bool> = const { Cell::new(false) };
+    /// 当前线程在实时回调中发生的分配/释放次数
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn record() {
+    let in_realtime = REALTIME.try_with(Cell::get).unwrap_or(false);
+    if in_realtime {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+/// 统计实时回调中内存分配的全局分配器（调试用）
+///
+/// 启用 `alloc-check` 特性编译时注册为全局分配器，
+/// 配合 [`RealtimeScope`] 检查音频回调是否分配或释放了内存。
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// 标记一段实时代码，离开作用域时如果期间分配或释放过内存则 panic
+///
+/// 只有启用 `alloc-check` 特性时才检查，否则为空操作。
+pub struct RealtimeScope {
+    #[cfg(feature = "alloc-check")]
+    name: &'static str,
+    #[cfg(feature = "alloc-check")]
+    start: u64,
+}
+
+impl RealtimeScope {
+    #[cfg(feature = "alloc-check")]
+    pub fn enter(name: &'static str) -> Self {
+        REALTIME.with(|realtime| realtime.set(true));
+        Self {
+            name,
+            start: allocations(),
+        }
+    }
+
+    #[cfg(not(feature = "alloc-check"))]
+    pub fn enter(_name: &'static str) -> Self {
+        Self {}
+    }
+}
+
+#[cfg(feature = "alloc-check")]
+impl Drop for RealtimeScope {
+    fn drop(&mut self) {
+        REALTIME.with(|realtime| realtime.set(false));
+        let count = allocations() - self.start;
+        if count > 0 && !std::thread::panicking() {
+            panic!("{}中发生了 {} 次内存分配/释放", self.name, count);
+        }
+    }
+}
+
+/// 当前线程在实时回调中累计的分配/释放次数
+pub fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
@@ -0,0 +1,634 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, Sender};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::audio_io::{Direction, TapReceiver};
+use crate::config::{AsrBackend, AsrConfig, AudioConfig};
+use crate::resampler::{Resampler, ResamplerQuality};
+use crate::vad::{VadEvent, VadEventKind};
+
+/// 识别结果类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptKind {
+    /// 中间结果，后续可能被修正
+    Partial,
+    /// 语句结束后的最终结果
+    Final,
+}
+
+/// 一段识别文本，时间戳相对于该方向音频流的起点
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptSegment {
+    pub kind: TranscriptKind,
+    pub text: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// 带方向信息的识别事件
+#[derive(Debug, Clone)]
+pub struct TranscriptEvent {
+    pub direction: Direction,
+    pub segment: TranscriptSegment,
+}
+
+/// 语音识别接口
+///
+/// 识别器在后台线程中运行，输入为单声道 f32 音频，
+/// 每次送入数据后返回期间产生的中间/最终结果。
+pub trait SpeechRecognizer: Send {
+    /// 送入单声道音频
+    fn feed(&mut self, samples: &[f32], sample_rate: u32) -> Result<Vec<TranscriptSegment>>;
+
+    /// 结束当前语句，输出剩余音频的最终结果
+    fn finish(&mut self) -> Result<Vec<TranscriptSegment>>;
+
+    /// 获取识别器名称
+    fn name(&self) -> &str;
+}
+
+/// 累积单条语句的音频，并根据已送入的采样数计算时间戳
+struct UtteranceBuffer {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    /// 当前语句的起点
+    offset: Duration,
+}
+
+impl UtteranceBuffer {
+    fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            sample_rate: 16000,
+            offset: Duration::ZERO,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate != self.sample_rate && !self.samples.is_empty() {
+            // 采样率变化时先把已累积部分计入起点，避免时间戳错位
+            self.offset = self.end();
+            self.samples.clear();
+        }
+        self.sample_rate = sample_rate;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn duration(&self) -> Duration {
+        samples_to_duration(self.samples.len(), self.sample_rate)
+    }
+
+    fn start(&self) -> Duration {
+        self.offset
+    }
+
+    fn end(&self) -> Duration {
+        self.offset + self.duration()
+    }
+
+    fn take(&mut self) -> Vec<f32> {
+        self.offset = self.end();
+        std::mem::take(&mut self.samples)
+    }
+}
+
+fn samples_to_duration(samples: usize, sample_rate: u32) -> Duration {
+    Duration::from_secs_f64(samples as f64 / sample_rate.max(1) as f64)
+}
+
+fn duration_to_samples(duration: Duration, sample_rate: u32) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64).round() as usize
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// 模拟识别器：按固定时长切分语句，依次输出脚本中的文本
+///
+/// 输出完全由送入的采样数决定，适合测试下游的翻译、字幕等环节。
+pub struct MockRecognizer {
+    script: Vec<String>,
+    next_line: usize,
+    segment: Duration,
+    partial_sent: bool,
+    buffer: UtteranceBuffer,
+}
+
+impl MockRecognizer {
+    pub fn new(script: Vec<String>, segment: Duration) -> Self {
+        Self {
+            script,
+            next_line: 0,
+            segment,
+            partial_sent: false,
+            buffer: UtteranceBuffer::new(),
+        }
+    }
+
+    fn peek_line(&self) -> &str {
+        if self.script.is_empty() {
+            return "";
+        }
+        &self.script[self.next_line % self.script.len()]
+    }
+
+    fn finalize(&mut self) -> TranscriptSegment {
+        let text = self.peek_line().to_string();
+        self.next_line += 1;
+        self.partial_sent = false;
+        let start = self.buffer.start();
+        self.buffer.take();
+        TranscriptSegment {
+            kind: TranscriptKind::Final,
+            text,
+            start,
+            end: self.buffer.start(),
+        }
+    }
+}
+
+impl SpeechRecognizer for MockRecognizer {
+    fn feed(&mut self, mut samples: &[f32], sample_rate: u32) -> Result<Vec<TranscriptSegment>> {
+        self.buffer.set_sample_rate(sample_rate);
+        let segment_len = duration_to_samples(self.segment, sample_rate).max(1);
+        let mut results = Vec::new();
+
+        // 在语句边界处切分，保证时间戳精确到采样
+        while !samples.is_empty() {
+            let room = segment_len - self.buffer.len();
+            let (head, tail) = samples.split_at(room.min(samples.len()));
+            self.buffer.samples.extend_from_slice(head);
+            samples = tail;
+
+            if !self.partial_sent && self.buffer.len() * 2 >= segment_len {
+                let words: Vec<&str> = self.peek_line().split_whitespace().collect();
+                let text = words[..words.len().div_ceil(2)].join(" ");
+                results.push(TranscriptSegment {
+                    kind: TranscriptKind::Partial,
+                    text,
+                    start: self.buffer.start(),
+                    end: self.buffer.end(),
+                });
+                self.partial_sent = true;
+            }
+
+            if self.buffer.len() >= segment_len {
+                results.push(self.finalize());
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn finish(&mut self) -> Result<Vec<TranscriptSegment>> {
+        if self.buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![self.finalize()])
+    }
+
+    fn name(&self) -> &str {
+        "模拟识别器"
+    }
+}
+
+const MULTIPART_BOUNDARY: &str = "----trans-audio-boundary-7d3a9c";
+
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+/// OpenAI 兼容接口的识别器，可对接本机的 whisper 服务
+///
+/// 音频按语句累积，语句达到最长时长或调用 `finish` 时
+/// 编码为 WAV 并上传到 `{endpoint}/v1/audio/transcriptions`。
+pub struct OpenAiRecognizer {
+    agent: ureq::Agent,
+    url: String,
+    model: String,
+    language: Option<String>,
+    api_key: Option<String>,
+    segment: Duration,
+    partial_interval: Option<Duration>,
+    silence_rms: f32,
+    last_partial: Duration,
+    buffer: UtteranceBuffer,
+}
+
+impl OpenAiRecognizer {
+    pub fn new(config: &AsrConfig, language: Option<String>) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build();
+        let partial_interval = if config.partial_interval_ms > 0 {
+            Some(Duration::from_millis(config.partial_interval_ms as u64))
+        } else {
+            None
+        };
+
+        Self {
+            agent,
+            url: format!("{}/v1/audio/transcriptions", config.endpoint.trim_end_matches('/')),
+            model: config.model.clone(),
+            language,
+            api_key: config.api_key.clone(),
+            segment: Duration::from_millis(config.segment_ms as u64),
+            partial_interval,
+            silence_rms: config.silence_rms,
+            last_partial: Duration::ZERO,
+            buffer: UtteranceBuffer::new(),
+        }
+    }
+
+    /// 上传一段音频并返回识别文本；静音片段直接返回 None
+    fn transcribe(&self, samples: &[f32], sample_rate: u32) -> Result<Option<String>> {
+        if rms(samples) < self.silence_rms {
+            return Ok(None);
+        }
+
+        let wav = encode_wav(samples, sample_rate)?;
+        let mut fields = vec![("model", self.model.as_str()), ("response_format", "json")];
+        if let Some(language) = &self.language {
+            fields.push(("language", language.as_str()));
+        }
+        let body = multipart_body(MULTIPART_BOUNDARY, &fields, "audio.wav", &wav);
+
+        let mut request = self.agent
+            .post(&self.url)
+            .set("Content-Type", &format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY));
+        if let Some(key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+
+        let response: TranscriptionResponse = request
+            .send_bytes(&body)
+            .with_context(|| format!("请求识别服务失败: {}", self.url))?
+            .into_json()
+            .context("解析识别结果失败")?;
+
+        let text = response.text.trim().to_string();
+        Ok(if text.is_empty() { None } else { Some(text) })
+    }
+
+    fn finalize(&mut self) -> Result<Option<TranscriptSegment>> {
+        let start = self.buffer.start();
+        let sample_rate = self.buffer.sample_rate;
+        let samples = self.buffer.take();
+        self.last_partial = Duration::ZERO;
+
+        Ok(self.transcribe(&samples, sample_rate)?.map(|text| TranscriptSegment {
+            kind: TranscriptKind::Final,
+            text,
+            start,
+            end: self.buffer.start(),
+        }))
+    }
+}
+
+impl SpeechRecognizer for OpenAiRecognizer {
+    fn feed(&mut self, mut samples: &[f32], sample_rate: u32) -> Result<Vec<TranscriptSegment>> {
+        self.buffer.set_sample_rate(sample_rate);
+        let segment_len = duration_to_samples(self.segment, sample_rate).max(1);
+        let mut results = Vec::new();
+
+        while !samples.is_empty() {
+            let room = segment_len - self.buffer.len();
+            let (head, tail) = samples.split_at(room.min(samples.len()));
+            self.buffer.samples.extend_from_slice(head);
+            samples = tail;
+
+            if self.buffer.len() >= segment_len {
+                results.extend(self.finalize()?);
+                continue;
+            }
+
+            if let Some(interval) = self.partial_interval {
+                if self.buffer.duration() >= self.last_partial + interval {
+                    self.last_partial = self.buffer.duration();
+                    if let Some(text) = self.transcribe(&self.buffer.samples, sample_rate)? {
+                        results.push(TranscriptSegment {
+                            kind: TranscriptKind::Partial,
+                            text,
+                            start: self.buffer.start(),
+                            end: self.buffer.end(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn finish(&mut self) -> Result<Vec<TranscriptSegment>> {
+        if self.buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self.finalize()?.into_iter().collect())
+    }
+
+    fn name(&self) -> &str {
+        "OpenAI 兼容识别器"
+    }
+}
+
+/// 将单声道音频编码为 16 位 PCM WAV
+fn encode_wav(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut cursor = Cursor::new(Vec::new());
+    {
+        let mut writer = hound::WavWriter::new(&mut cursor, spec).context("创建 WAV 编码器失败")?;
+        for &sample in samples {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize().context("写入 WAV 数据失败")?;
+    }
+    Ok(cursor.into_inner())
+}
+
+fn multipart_body(boundary: &str, fields: &[(&str, &str)], file_name: &str, file: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(file.len() + 512);
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: audio/wav\r\n\r\n",
+            boundary, file_name
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+/// 把送入的音频重采样到固定采样率后再交给内部识别器
+///
+/// 设备采样率变化时自动重建重采样器；语句结束时不冲刷滤波器，
+/// 仅有几十个采样的延迟，不影响时间戳。
+pub struct ResamplingRecognizer {
+    inner: Box<dyn SpeechRecognizer>,
+    sample_rate: u32,
+    quality: ResamplerQuality,
+    resampler: Option<Resampler>,
+    buffer: Vec<f32>,
+}
+
+impl ResamplingRecognizer {
+    pub fn new(inner: Box<dyn SpeechRecognizer>, sample_rate: u32, quality: ResamplerQuality) -> Self {
+        Self {
+            inner,
+            sample_rate,
+            quality,
+            resampler: None,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl SpeechRecognizer for ResamplingRecognizer {
+    fn feed(&mut self, samples: &[f32], sample_rate: u32) -> Result<Vec<TranscriptSegment>> {
+        if sample_rate == self.sample_rate {
+            return self.inner.feed(samples, sample_rate);
+        }
+        let resampler = match &mut self.resampler {
+            Some(resampler) if resampler.input_rate() == sample_rate => resampler,
+            resampler => resampler.insert(Resampler::new(sample_rate, self.sample_rate, 1, self.quality)),
+        };
+        self.buffer.clear();
+        resampler.process(samples, &mut self.buffer);
+        self.inner.feed(&self.buffer, self.sample_rate)
+    }
+
+    fn finish(&mut self) -> Result<Vec<TranscriptSegment>> {
+        self.inner.finish()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+/// 根据配置创建指定方向的识别器
+pub fn build_recognizer(config: &AudioConfig, direction: Direction) -> Box<dyn SpeechRecognizer> {
+    let recognizer = build_backend(config, direction);
+    if config.asr.sample_rate == 0 {
+        return recognizer;
+    }
+    Box::new(ResamplingRecognizer::new(recognizer, config.asr.sample_rate, config.resample_quality))
+}
+
+fn build_backend(config: &AudioConfig, direction: Direction) -> Box<dyn SpeechRecognizer> {
+    let language = match direction {
+        Direction::Input => config.local_language.clone(),
+        Direction::Output => config.remote_language.clone(),
+    };
+
+    match config.asr.backend {
+        AsrBackend::Mock => Box::new(MockRecognizer::new(
+            vec!["你好".to_string(), "这是 一段 模拟 识别 结果".to_string()],
+            Duration::from_millis(config.asr.segment_ms as u64),
+        )),
+        AsrBackend::OpenAi => Box::new(OpenAiRecognizer::new(&config.asr, language)),
+    }
+}
+
+/// 启动识别线程：从旁路接收音频，识别结果发送到 `events`
+///
+/// 提供 `boundaries` 时，送入的音频越过 VAD 判定的语音结束位置后立即结束当前语句。
+/// 旁路通道关闭（音频流停止）后输出最后一条语句并退出。
+pub fn spawn_worker(
+    direction: Direction,
+    mut recognizer: Box<dyn SpeechRecognizer>,
+    frames: TapReceiver,
+    boundaries: Option<Receiver<VadEvent>>,
+    events: Sender<TranscriptEvent>,
+) -> Result<JoinHandle<()>> {
+    let handle = thread::Builder::new()
+        .name(format!("asr-{}", direction.label()))
+        .spawn(move || {
+            info!("{}方向语音识别已启动: {}", direction.label(), recognizer.name());
+            let send = |segments: Vec<TranscriptSegment>| {
+                for segment in segments {
+                    let _ = events.send(TranscriptEvent { direction, segment });
+                }
+            };
+
+            let mut position = 0u64;
+            let mut pending_ends = VecDeque::new();
+            for frame in frames {
+                let mono = frame.to_mono();
+                position += mono.len() as u64;
+                match recognizer.feed(&mono, frame.sample_rate) {
+                    Ok(segments) => send(segments),
+                    Err(e) => warn!("{}方向语音识别失败: {:#}", direction.label(), e),
+                }
+
+                if let Some(boundaries) = &boundaries {
+                    pending_ends.extend(
+                        boundaries
+                            .try_iter()
+                            .filter(|event| event.kind == VadEventKind::SpeechEnd)
+                            .map(|event| event.sample),
+                    );
+                }
+                let mut reached_end = false;
+                while pending_ends.front().is_some_and(|&end| end <= position) {
+                    pending_ends.pop_front();
+                    reached_end = true;
+                }
+                if reached_end {
+                    match recognizer.finish() {
+                        Ok(segments) => send(segments),
+                        Err(e) => warn!("{}方向语音识别失败: {:#}", direction.label(), e),
+                    }
+                }
+            }
+
+            match recognizer.finish() {
+                Ok(segments) => send(segments),
+                Err(e) => warn!("{}方向语音识别失败: {:#}", direction.label(), e),
+            }
+        })
+        .context("启动语音识别线程失败")?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_io::AudioTap;
+
+    fn script() -> Vec<String> {
+        vec!["早上 好".to_string(), "开始 开会".to_string()]
+    }
+
+    fn secs(seconds: f64) -> Duration {
+        Duration::from_secs_f64(seconds)
+    }
+
+    /// 把音频写入旁路后关闭，收集识别线程输出的全部事件
+    fn run_worker(
+        recognizer: Box<dyn SpeechRecognizer>,
+        samples: &[f32],
+        sample_rate: u32,
+        channels: u16,
+        boundaries: Option<Receiver<VadEvent>>,
+    ) -> Vec<TranscriptSegment> {
+        let (mut tap, frames) = AudioTap::bounded(samples.len());
+        tap.set_format(sample_rate, channels);
+        tap.push(samples);
+        drop(tap);
+
+        let (events, receiver) = crossbeam_channel::unbounded();
+        let worker = spawn_worker(Direction::Input, recognizer, frames, boundaries, events).unwrap();
+        worker.join().unwrap();
+        receiver
+            .try_iter()
+            .inspect(|event| assert_eq!(event.direction, Direction::Input))
+            .map(|event| event.segment)
+            .collect()
+    }
+
+    #[test]
+    fn mock_recognizer_splits_fixed_segments() {
+        let mut recognizer = MockRecognizer::new(script(), secs(1.0));
+        let segments = recognizer.feed(&vec![0.1; 16000 * 5 / 2], 16000).unwrap();
+        let finals: Vec<_> = segments.iter().filter(|s| s.kind == TranscriptKind::Final).collect();
+        assert_eq!(finals.len(), 2);
+        assert_eq!(finals[0].text, "早上 好");
+        assert_eq!((finals[0].start, finals[0].end), (secs(0.0), secs(1.0)));
+        assert_eq!(finals[1].text, "开始 开会");
+        assert_eq!((finals[1].start, finals[1].end), (secs(1.0), secs(2.0)));
+
+        // 中间结果在语句过半时输出，只含前一半的词
+        assert_eq!(segments[0].kind, TranscriptKind::Partial);
+        assert_eq!(segments[0].text, "早上");
+        assert_eq!(segments[0].start, secs(0.0));
+
+        let rest = recognizer.finish().unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].text, "早上 好");
+        assert_eq!((rest[0].start, rest[0].end), (secs(2.0), secs(2.5)));
+        assert!(recognizer.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn worker_downmixes_and_flushes_on_close() {
+        // 立体声 1.5 秒：一条完整语句，剩余半秒在旁路关闭时输出
+        let recognizer = Box::new(MockRecognizer::new(script(), secs(1.0)));
+        let segments = run_worker(recognizer, &vec![0.1; 48000 * 3], 48000, 2, None);
+        let finals: Vec<_> = segments.iter().filter(|s| s.kind == TranscriptKind::Final).collect();
+        assert_eq!(finals.len(), 2);
+        assert_eq!((finals[0].start, finals[0].end), (secs(0.0), secs(1.0)));
+        assert_eq!((finals[1].start, finals[1].end), (secs(1.0), secs(1.5)));
+    }
+
+    #[test]
+    fn worker_finishes_utterance_at_vad_boundary() {
+        let (sender, boundaries) = crossbeam_channel::unbounded();
+        sender
+            .send(VadEvent {
+                kind: VadEventKind::SpeechEnd,
+                sample: 4000,
+                sample_rate: 16000,
+            })
+            .unwrap();
+        // 语句时长足够长，只有 VAD 边界会结束语句
+        let recognizer = Box::new(MockRecognizer::new(script(), secs(10.0)));
+        let segments = run_worker(recognizer, &vec![0.1; 16000], 16000, 1, Some(boundaries));
+        let finals: Vec<_> = segments.iter().filter(|s| s.kind == TranscriptKind::Final).collect();
+        // 越过边界的那一块送入后结束第一条语句，剩余音频在旁路关闭时输出
+        assert_eq!(finals.len(), 2);
+        assert_eq!(finals[0].text, "早上 好");
+        assert!(finals[0].end >= secs(0.25) && finals[0].end < secs(1.0));
+        assert_eq!((finals[1].start, finals[1].end), (finals[0].end, secs(1.0)));
+    }
+
+    #[test]
+    fn resampling_recognizer_keeps_timestamps() {
+        let inner = Box::new(MockRecognizer::new(script(), secs(1.0)));
+        let mut recognizer = ResamplingRecognizer::new(inner, 16000, ResamplerQuality::Fast);
+        let segments = recognizer.feed(&vec![0.1; 48000 * 2], 48000).unwrap();
+        let finals: Vec<_> = segments.iter().filter(|s| s.kind == TranscriptKind::Final).collect();
+        assert_eq!(finals.len(), 1);
+        assert_eq!((finals[0].start, finals[0].end), (secs(0.0), secs(1.0)));
+    }
+
+    #[test]
+    fn multipart_body_contains_fields_and_file() {
+        let body = multipart_body("b", &[("model", "whisper-1")], "audio.wav", b"RIFF");
+        let text = String::from_utf8(body).unwrap();
+        assert!(text.starts_with("--b\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n"));
+        assert!(text.contains("filename=\"audio.wav\"\r\nContent-Type: audio/wav\r\n\r\nRIFF\r\n--b--\r\n"));
+    }
+}
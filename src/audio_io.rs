@@ -1,207 +1,654 @@
-use anyhow::{Context, Result};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Host, StreamConfig as CpalConfig, SupportedStreamConfig};
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait};
 use crossbeam_channel::{Receiver, Sender};
 use log::{error, info, warn};
-use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
+use crate::aec::EchoReference;
+use crate::alloc_check::RealtimeScope;
+use crate::backend::{ActiveStream, AudioBackend, AudioDevice, DeviceFormat, FormatRequest};
+use crate::channels::{AudioBlock, ChannelMixer};
+use crate::config::JitterConfig;
+use crate::jitter_buffer::{self, JitterMonitor, JitterStats};
 use crate::processor::ProcessorChain;
+use crate::resampler::{Resampler, ResamplerQuality};
+use crate::ring_buffer::{self, Consumer, Producer};
+use crate::sample_format::CALLBACK_FRAMES;
+use crate::tts::SpeechInjector;
 
+/// 旁路接收端每次最多取出的采样数
+const TAP_READ_SAMPLES: usize = 8192;
+
+/// 旁路接收端没有数据时的等待间隔
+const TAP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 停止音频流后等待回调交还处理器链的最长时间
+const PARTS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 回调中最多暂存的处理错误数，取出之前再产生的错误只计数
+const CALLBACK_ERROR_CAPACITY: usize = 16;
+
+/// 音频流方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// 输入流：物理麦克风 → CABLE-A（我方说话）
+    Input,
+    /// 输出流：CABLE Output → 物理扬声器（对方说话）
+    Output,
+}
+
+impl Direction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Direction::Input => "输入",
+            Direction::Output => "输出",
+        }
+    }
+
+    /// 该方向对应的说话方："local"（我方）或 "remote"（对方）
+    pub fn speaker(&self) -> &'static str {
+        match self {
+            Direction::Input => "local",
+            Direction::Output => "remote",
+        }
+    }
+}
+
+/// 从音频回调中旁路出来的一帧数据（交错排列）
+#[derive(Debug, Clone)]
+pub struct TapFrame {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl TapFrame {
+    /// 将交错的多声道数据混合为单声道
+    pub fn to_mono(&self) -> Vec<f32> {
+        let channels = self.channels.max(1) as usize;
+        if channels == 1 {
+            return self.samples.clone();
+        }
+        self.samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect()
+    }
+}
+
+/// 音频旁路：把处理后的音频非阻塞地复制给后台线程（如语音识别）
+///
+/// 数据写入预先分配的无锁环形缓冲区，回调中不分配内存；
+/// 缓冲区放不下整块数据时直接丢弃该块并计数，绝不阻塞音频回调。
+pub struct AudioTap {
+    producer: Producer,
+    format: Arc<TapFormat>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// 旁路数据的格式，由音频流启动时写入
+struct TapFormat {
+    sample_rate: AtomicU32,
+    channels: AtomicU16,
+}
+
+impl AudioTap {
+    /// 创建最多缓存 `capacity` 个采样的旁路
+    pub fn bounded(capacity: usize) -> (Self, TapReceiver) {
+        let (producer, consumer) = ring_buffer::ring_buffer(capacity);
+        let format = Arc::new(TapFormat {
+            sample_rate: AtomicU32::new(48000),
+            channels: AtomicU16::new(1),
+        });
+        let dropped = Arc::new(AtomicU64::new(0));
+        let tap = Self {
+            producer,
+            format: format.clone(),
+            dropped: dropped.clone(),
+        };
+        let receiver = TapReceiver {
+            consumer,
+            format,
+            buffer: Vec::new(),
+        };
+        (tap, receiver)
+    }
+
+    /// 设置之后写入数据的采样率和声道数
+    pub fn set_format(&self, sample_rate: u32, channels: u16) {
+        self.format.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.format.channels.store(channels.max(1), Ordering::Relaxed);
+    }
+
+    /// 写入一块交错排列的数据，放不下时整块丢弃
+    pub fn push(&mut self, samples: &[f32]) {
+        if self.producer.free() < samples.len() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.producer.push_slice(samples);
+    }
+
+    /// 因缓冲区已满而被丢弃的数据块数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// 旁路的接收端，在后台线程中按块取出数据
+///
+/// 迭代时没有数据则短暂休眠等待；旁路被丢弃（音频流停止）且数据取完后结束。
+pub struct TapReceiver {
+    consumer: Consumer,
+    format: Arc<TapFormat>,
+    buffer: Vec<f32>,
+}
+
+impl Iterator for TapReceiver {
+    type Item = TapFrame;
+
+    fn next(&mut self) -> Option<TapFrame> {
+        loop {
+            let channels = self.format.channels.load(Ordering::Relaxed) as usize;
+            // 先判断是否已被丢弃，再读取长度，避免漏掉最后写入的数据
+            let abandoned = self.consumer.is_abandoned();
+            let available = self.consumer.len() / channels * channels;
+            if available > 0 {
+                self.buffer.resize(available.min(TAP_READ_SAMPLES / channels * channels), 0.0);
+                let read = self.consumer.pop_slice(&mut self.buffer);
+                return Some(TapFrame {
+                    samples: self.buffer[..read].to_vec(),
+                    sample_rate: self.format.sample_rate.load(Ordering::Relaxed),
+                    channels: channels as u16,
+                });
+            }
+            if abandoned {
+                return None;
+            }
+            thread::sleep(TAP_POLL_INTERVAL);
+        }
+    }
+}
+
+/// 回调中产生的处理错误
+///
+/// 错误转交到预先分配的有界队列，由监控线程取出记录，回调中不格式化、不写日志；
+/// 队列已满时丢弃错误并计数。
+#[derive(Clone)]
+struct CallbackErrors {
+    sender: Sender<anyhow::Error>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl CallbackErrors {
+    fn report(&self, error: anyhow::Error) {
+        if self.sender.try_send(error).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 音频流参数
+#[derive(Debug, Clone, Copy)]
 pub struct StreamConfig {
     pub sample_rate: u32,
     pub buffer_size: u32,
+    /// 固定输入设备的声道数，None 表示自动选择
+    pub input_channels: Option<u16>,
+    /// 固定输出设备的声道数，None 表示优先与输入设备一致
+    pub output_channels: Option<u16>,
+    /// 输入输出采样率不一致时的重采样质量
+    pub resample_quality: ResamplerQuality,
+    pub jitter: JitterConfig,
+}
+
+/// 接入音频流的附加功能
+#[derive(Default)]
+pub struct StreamHooks {
+    /// 处理后音频的旁路（如语音识别）
+    pub tap: Option<AudioTap>,
+    /// 向输出端注入合成语音
+    pub injector: Option<SpeechInjector>,
+    /// 把输出端最终播放的声音交给回声消除作为参考信号
+    pub echo_reference: Option<EchoReference>,
+}
+
+/// 音频流使用的处理器链和旁路，停止音频流后可以交给新的音频流继续使用
+pub struct StreamParts {
+    pub processor: ProcessorChain,
+    pub hooks: StreamHooks,
 }
 
+/// 输入回调独占的数据
+struct InputParts {
+    processor: ProcessorChain,
+    tap: Option<AudioTap>,
+}
+
+/// 输出回调独占的数据
+struct OutputParts {
+    injector: Option<SpeechInjector>,
+    echo_reference: Option<EchoReference>,
+}
+
+/// 回调独占的数据，回调被释放（流停止或创建失败）时交还给创建方
+struct ReturnOnDrop<T> {
+    value: Option<T>,
+    back: Sender<T>,
+}
+
+impl<T> ReturnOnDrop<T> {
+    fn new(value: T, back: Sender<T>) -> Self {
+        Self { value: Some(value), back }
+    }
+
+    fn get(&mut self) -> &mut T {
+        self.value.as_mut().expect("回调数据已交还")
+    }
+}
+
+impl<T> Drop for ReturnOnDrop<T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            let _ = self.back.try_send(value);
+        }
+    }
+}
+
+/// 持有输入输出流，drop 时流会停止
 pub struct AudioStream {
-    input_device: Device,
-    output_device: Device,
-    sample_rate: u32,
-    buffer_size: u32,
-    input_stream: cpal::Stream,
-    output_stream: cpal::Stream,
+    input_name: String,
+    output_name: String,
+    input_format: DeviceFormat,
+    output_format: DeviceFormat,
+    input_stream: Box<dyn ActiveStream>,
+    output_stream: Box<dyn ActiveStream>,
+    jitter: JitterMonitor,
+    input_parts: Receiver<InputParts>,
+    output_parts: Receiver<OutputParts>,
+    errors: Receiver<String>,
+    callback_errors: Receiver<anyhow::Error>,
+    dropped_callback_errors: Arc<AtomicU64>,
 }
 
 impl AudioStream {
+    /// 输入与输出回调之间抖动缓冲区的统计
+    pub fn jitter_stats(&self) -> JitterStats {
+        self.jitter.stats()
+    }
+
+    /// 输入设备名称
+    pub fn input_name(&self) -> &str {
+        &self.input_name
+    }
+
+    /// 输出设备名称
+    pub fn output_name(&self) -> &str {
+        &self.output_name
+    }
+
+    /// 输入设备已没有更多数据（如输入文件已读完）
+    pub fn is_finished(&self) -> bool {
+        self.input_stream.is_finished()
+    }
+
+    /// 取出音频流运行中报告的错误（如设备被拔出），多个错误时返回第一个
+    ///
+    /// 出错后设备通常已停止回调，需要重新创建音频流。
+    pub fn take_error(&self) -> Option<String> {
+        let error = self.errors.try_recv().ok();
+        self.errors.try_iter().for_each(drop);
+        error
+    }
+
+    /// 取出回调中产生的处理错误（如处理器执行失败、合成语音闪避失败），以及因队列已满未能保存的错误数
+    ///
+    /// 这些错误不会停止音频流，由调用方定期取出记录。
+    pub fn take_callback_errors(&self) -> (Vec<anyhow::Error>, u64) {
+        let errors = self.callback_errors.try_iter().collect();
+        (errors, self.dropped_callback_errors.swap(0, Ordering::Relaxed))
+    }
+
+    /// 输入设备协商后的格式
+    pub fn input_format(&self) -> DeviceFormat {
+        self.input_format
+    }
+
+    /// 输出设备协商后的格式
+    pub fn output_format(&self) -> DeviceFormat {
+        self.output_format
+    }
+
     pub fn create_duplex_stream(
+        backend: &dyn AudioBackend,
         input_name: &str,
         output_name: &str,
-        sample_rate: u32,
-        buffer_size: u32,
-        processor: Arc<Mutex<ProcessorChain>>,
+        stream_config: StreamConfig,
+        processor: ProcessorChain,
         is_input_direction: bool,
+        hooks: StreamHooks,
     ) -> Result<Self> {
-        let host = cpal::default_host();
-        let input_device = find_device_by_name(&host, input_name, true)?;
-        let output_device = find_device_by_name(&host, output_name, false)?;
-
-        // 构建输入流配置
-        let input_config = {
-            let configs: Vec<_> = input_device.supported_input_configs()
-                .context("获取输入设备支持配置失败")?
-                .collect();
-            if configs.is_empty() {
-                return Err(anyhow::anyhow!("输入设备没有支持的配置"));
-            }
-            let config = configs.iter()
-                .find(|c| c.max_sample_rate().0 >= sample_rate && c.min_sample_rate().0 <= sample_rate)
-                .unwrap_or(&configs[0]);
-            let rate = if config.max_sample_rate().0 >= sample_rate && config.min_sample_rate().0 <= sample_rate {
-                sample_rate
-            } else {
-                config.max_sample_rate().0
-            };
-            config.with_sample_rate(cpal::SampleRate(rate)).into()
-        };
+        let parts = StreamParts { processor, hooks };
+        Self::start(backend, input_name, output_name, stream_config, parts, is_input_direction).map_err(|(e, _)| e)
+    }
 
-        // 构建输出流配置
-        let output_config = {
-            let configs: Vec<_> = output_device.supported_output_configs()
-                .context("获取输出设备支持配置失败")?
-                .collect();
-            if configs.is_empty() {
-                return Err(anyhow::anyhow!("输出设备没有支持的配置"));
+    /// 创建并启动音频流，失败时交还处理器链和旁路
+    pub fn start(
+        backend: &dyn AudioBackend,
+        input_name: &str,
+        output_name: &str,
+        stream_config: StreamConfig,
+        parts: StreamParts,
+        is_input_direction: bool,
+    ) -> std::result::Result<Self, (anyhow::Error, Box<StreamParts>)> {
+        let (input_sender, input_parts) = crossbeam_channel::bounded(1);
+        let (output_sender, output_parts) = crossbeam_channel::bounded(1);
+        let StreamParts { processor, hooks } = parts;
+        let StreamHooks { tap, injector, echo_reference } = hooks;
+        let input = ReturnOnDrop::new(InputParts { processor, tap }, input_sender);
+        let output = ReturnOnDrop::new(OutputParts { injector, echo_reference }, output_sender);
+
+        let devices = Self::open(backend, input_name, output_name, &stream_config);
+        let result = devices.and_then(|(input_device, output_device, input_format, output_format)| {
+            Self::build(
+                input_device.as_ref(),
+                output_device.as_ref(),
+                input_format,
+                output_format,
+                &stream_config,
+                input,
+                output,
+                is_input_direction,
+            )
+        });
+        match result {
+            Ok(running) => Ok(Self {
+                input_name: running.input_name,
+                output_name: running.output_name,
+                input_format: running.input_format,
+                output_format: running.output_format,
+                input_stream: running.input_stream,
+                output_stream: running.output_stream,
+                jitter: running.jitter,
+                input_parts,
+                output_parts,
+                errors: running.errors,
+                callback_errors: running.callback_errors,
+                dropped_callback_errors: running.dropped_callback_errors,
+            }),
+            Err(e) => {
+                let parts = collect_parts(&input_parts, &output_parts).unwrap_or_else(|lost| {
+                    error!("{}，使用空的处理器链", lost);
+                    StreamParts {
+                        processor: ProcessorChain::new(),
+                        hooks: StreamHooks::default(),
+                    }
+                });
+                Err((e, Box::new(parts)))
             }
-            let config = configs.iter()
-                .find(|c| c.max_sample_rate().0 >= sample_rate && c.min_sample_rate().0 <= sample_rate)
-                .unwrap_or(&configs[0]);
-            let rate = if config.max_sample_rate().0 >= sample_rate && config.min_sample_rate().0 <= sample_rate {
-                sample_rate
-            } else {
-                config.max_sample_rate().0
-            };
-            config.with_sample_rate(cpal::SampleRate(rate)).into()
+        }
+    }
+
+    /// 停止音频流，交还处理器链和旁路
+    pub fn shutdown(self) -> Result<StreamParts> {
+        let Self { input_stream, output_stream, input_parts, output_parts, .. } = self;
+        drop(input_stream);
+        drop(output_stream);
+        collect_parts(&input_parts, &output_parts)
+    }
+
+    /// 检查设备能否打开并协商格式，不创建音频流
+    pub fn probe(backend: &dyn AudioBackend, input_name: &str, output_name: &str, stream_config: &StreamConfig) -> Result<()> {
+        Self::open(backend, input_name, output_name, stream_config).map(|_| ())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn open(
+        backend: &dyn AudioBackend,
+        input_name: &str,
+        output_name: &str,
+        stream_config: &StreamConfig,
+    ) -> Result<(Box<dyn AudioDevice>, Box<dyn AudioDevice>, DeviceFormat, DeviceFormat)> {
+        let input_device = backend.open_input(input_name)?;
+        let output_device = backend.open_output(output_name)?;
+
+        let sample_rate = stream_config.sample_rate;
+        let input_format = input_device.negotiate(&FormatRequest {
+            sample_rate,
+            channels: stream_config.input_channels,
+            prefer_channels: None,
+        })?;
+        // 输出优先使用与输入相同的采样率和声道数，减少重采样和混合
+        let output_format = output_device.negotiate(&FormatRequest {
+            sample_rate: input_format.sample_rate,
+            channels: stream_config.output_channels,
+            prefer_channels: Some(input_format.channels),
+        })?;
+        if input_format.sample_rate != sample_rate {
+            warn!("输入设备不支持 {} Hz，使用 {} Hz", sample_rate, input_format.sample_rate);
+        }
+        Ok((input_device, output_device, input_format, output_format))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build(
+        input_device: &dyn AudioDevice,
+        output_device: &dyn AudioDevice,
+        input_format: DeviceFormat,
+        output_format: DeviceFormat,
+        stream_config: &StreamConfig,
+        mut input: ReturnOnDrop<InputParts>,
+        mut output: ReturnOnDrop<OutputParts>,
+        is_input_direction: bool,
+    ) -> Result<RunningStreams> {
+        let input_rate = input_format.sample_rate;
+        let output_rate = output_format.sample_rate;
+        // 采样率不一致时在输入回调中重采样到输出设备的采样率
+        let mut resampler = (input_rate != output_rate).then(|| {
+            let mut resampler =
+                Resampler::new(input_rate, output_rate, input_format.channels, stream_config.resample_quality);
+            resampler.reserve(CALLBACK_FRAMES);
+            resampler
+        });
+        let mixer = ChannelMixer::new(input_format.channels, output_format.channels);
+        let passthrough = mixer.is_passthrough();
+        let parts = input.get();
+        parts.processor.prepare(input_rate, input_format.channels);
+        let latency_frames = parts.processor.latency_frames();
+        if let Some(tap) = &parts.tap {
+            tap.set_format(input_rate, input_format.channels);
+        }
+        let OutputParts { injector, echo_reference } = output.get();
+        if let Some(injector) = injector {
+            injector.prepare(output_rate, output_format.channels);
+        }
+        if let Some(reference) = echo_reference {
+            reference.set_sample_rate(output_rate);
+        }
+
+        // 错误回调只记录错误，由调用方决定是否重建音频流
+        let input_name = input_device.name();
+        let output_name = output_device.name();
+        let (error_sender, errors) = crossbeam_channel::unbounded();
+        let input_errors = error_sender.clone();
+        let input_label = input_name.clone();
+        let output_errors = error_sender;
+        let output_label = output_name.clone();
+        let (callback_error_sender, callback_errors) = crossbeam_channel::bounded(CALLBACK_ERROR_CAPACITY);
+        let dropped_callback_errors = Arc::new(AtomicU64::new(0));
+        let input_callback_errors = CallbackErrors {
+            sender: callback_error_sender,
+            dropped: dropped_callback_errors.clone(),
         };
+        let output_callback_errors = input_callback_errors.clone();
 
-        // 用于在输入和输出之间传递数据的通道
-        let (data_sender, data_receiver) = crossbeam_channel::bounded::<Vec<f32>>(1024);
+        // 输入和输出之间的抖动缓冲区，数据已是输出采样率、输入声道数
+        let (mut jitter_writer, mut jitter_reader, jitter) =
+            jitter_buffer::channel(output_rate, input_format.channels, &stream_config.jitter);
 
-        // 创建输入流
-        let processor_clone = processor.clone();
+        // 创建输入流：处理器链归输入回调独占，参数通过原子变量调整，不需要加锁
+        let mut resampled: Vec<f32> = Vec::with_capacity(
+            resampler
+                .as_ref()
+                .map_or(0, |resampler| resampler.max_output_frames(CALLBACK_FRAMES))
+                * input_format.channels as usize,
+        );
+        let input_channels = input_format.channels.max(1) as usize;
         let input_stream = input_device.build_input_stream(
-            &input_config,
-            move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                let mut buffer = data.to_vec();
-                
-                if let Ok(mut proc) = processor_clone.lock() {
-                    if let Err(e) = proc.process(&mut buffer) {
-                        error!("处理音频数据失败: {}", e);
-                    }
+            &input_format,
+            Box::new(move |buffer: &mut [f32]| {
+                let _realtime = RealtimeScope::enter("输入回调");
+                let InputParts { processor, tap } = input.get();
+                if let Err(e) = processor.process(buffer) {
+                    input_callback_errors.report(e);
                 }
 
-                if let Err(e) = data_sender.send(buffer) {
-                    error!("发送音频数据失败: {}", e);
+                if let Some(tap) = tap {
+                    tap.push(buffer);
                 }
-            },
-            move |err| {
+
+                match &mut resampler {
+                    // 按预分配的帧数分段重采样，超大的缓冲区也不会让输出缓冲区扩容
+                    Some(resampler) => {
+                        for chunk in buffer.chunks(CALLBACK_FRAMES * input_channels) {
+                            resampled.clear();
+                            resampler.process(chunk, &mut resampled);
+                            jitter_writer.write(&resampled);
+                        }
+                    }
+                    None => jitter_writer.write(buffer),
+                }
+            }),
+            Box::new(move |err| {
                 error!("输入流错误: {}", err);
-            },
-            None,
+                let _ = input_errors.send(format!("输入设备 {} 出错: {}", input_label, err));
+            }),
         )?;
 
         // 创建输出流
+        let output_channels = output_format.channels;
+        let mut block = AudioBlock::new(
+            Vec::with_capacity(CALLBACK_FRAMES * input_format.channels as usize),
+            input_format.channels,
+        );
         let output_stream = output_device.build_output_stream(
-            &output_config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                if let Ok(buffer) = data_receiver.try_recv() {
-                    let copy_len = buffer.len().min(data.len());
-                    data[..copy_len].copy_from_slice(&buffer[..copy_len]);
+            &output_format,
+            Box::new(move |data: &mut [f32]| {
+                let _realtime = RealtimeScope::enter("输出回调");
+                if passthrough {
+                    jitter_reader.read(data);
+                } else {
+                    // 输入输出声道数不同时按帧读取后混合，保持播放速度不变；
+                    // 宿主给出的缓冲区超过预分配的帧数时分段处理，不重新分配
+                    let channels = output_channels.max(1) as usize;
+                    for chunk in data.chunks_mut(CALLBACK_FRAMES * channels) {
+                        let frames = chunk.len() / channels;
+                        block.samples.resize(frames * block.channels as usize, 0.0);
+                        jitter_reader.read(&mut block.samples);
+                        mixer.process_into(&block.samples, chunk);
+                    }
+                }
+
+                let OutputParts { injector, echo_reference } = output.get();
+                if let Some(injector) = injector {
+                    if let Err(e) = injector.mix_into(data, output_channels) {
+                        output_callback_errors.report(e);
+                    }
+                }
+                // 参考信号取混入合成语音之后的最终输出，与扬声器播放的内容一致
+                if let Some(reference) = echo_reference {
+                    reference.push(data, output_channels);
                 }
-            },
-            move |err| {
+            }),
+            Box::new(move |err| {
                 error!("输出流错误: {}", err);
-            },
-            None,
+                let _ = output_errors.send(format!("输出设备 {} 出错: {}", output_label, err));
+            }),
         )?;
 
         input_stream.play()?;
         output_stream.play()?;
-        
+
         let direction = if is_input_direction { "输入" } else { "输出" };
         info!("{}流已启动: {} -> {} (处理)", direction, input_name, output_name);
+        info!(
+            "  格式: {:?} {} Hz {} 声道 → {:?} {} Hz {} 声道",
+            input_format.sample_format,
+            input_rate,
+            input_format.channels,
+            output_format.sample_format,
+            output_rate,
+            output_format.channels
+        );
+        if input_rate != output_rate {
+            info!("  重采样: {} Hz → {} Hz ({:?})", input_rate, output_rate, stream_config.resample_quality);
+        }
+        if latency_frames > 0 {
+            info!("  处理器算法延迟: {:.1} ms", latency_frames as f64 * 1000.0 / input_rate as f64);
+        }
 
-        Ok(Self {
-            input_device,
-            output_device,
-            sample_rate,
-            buffer_size,
+        Ok(RunningStreams {
+            input_name,
+            output_name,
+            input_format,
+            output_format,
             input_stream,
             output_stream,
+            jitter,
+            errors,
+            callback_errors,
+            dropped_callback_errors,
         })
     }
-
-    
-
-    fn get_supported_config(&self, device: &Device, input: bool) -> Result<SupportedStreamConfig> {
-        let configs: Vec<_> = if input {
-            device.supported_input_configs()
-                .context("获取输入设备支持配置失败")?
-                .collect()
-        } else {
-            device.supported_output_configs()
-                .context("获取输出设备支持配置失败")?
-                .collect()
-        };
-
-        if configs.is_empty() {
-            return Err(anyhow::anyhow!("设备没有支持的音频配置"));
-        }
-
-        // 查找支持指定采样率的配置
-        let config = configs.iter()
-            .find(|config| config.max_sample_rate().0 >= self.sample_rate && config.min_sample_rate().0 <= self.sample_rate)
-            .unwrap_or(&configs[0]);
-
-        // 如果配置不支持目标采样率，使用配置的采样率
-        let target_rate = if config.max_sample_rate().0 >= self.sample_rate && config.min_sample_rate().0 <= self.sample_rate {
-            self.sample_rate
-        } else {
-            config.max_sample_rate().0
-        };
-
-        Ok(config.with_sample_rate(cpal::SampleRate(target_rate)))
-    }
 }
 
-fn find_device_by_name(host: &Host, name: &str, input: bool) -> Result<Device> {
-    let devices = if input {
-        host.input_devices()?.collect::<Vec<_>>()
-    } else {
-        host.output_devices()?.collect::<Vec<_>>()
-    };
-
-    for device in devices {
-        if let Ok(device_name) = device.name() {
-            if device_name.contains(name) {
-                return Ok(device);
-            }
-        }
-    }
-
-    // 列出可用设备帮助调试
-    warn!("未找到包含 '{}' 的设备，可用设备列表:", name);
-    for device in if input { host.input_devices()? } else { host.output_devices()? } {
-        if let Ok(name) = device.name() {
-            warn!("  - {}", name);
-        }
-    }
+/// 已启动的输入输出流
+struct RunningStreams {
+    input_name: String,
+    output_name: String,
+    input_format: DeviceFormat,
+    output_format: DeviceFormat,
+    input_stream: Box<dyn ActiveStream>,
+    output_stream: Box<dyn ActiveStream>,
+    jitter: JitterMonitor,
+    errors: Receiver<String>,
+    callback_errors: Receiver<anyhow::Error>,
+    dropped_callback_errors: Arc<AtomicU64>,
+}
 
-    Err(anyhow::anyhow!("未找到音频设备: {}", name))
+/// 取回回调交还的处理器链和旁路
+fn collect_parts(input: &Receiver<InputParts>, output: &Receiver<OutputParts>) -> Result<StreamParts> {
+    let InputParts { processor, tap } = input
+        .recv_timeout(PARTS_TIMEOUT)
+        .map_err(|_| anyhow!("输入回调未交还处理器链"))?;
+    let OutputParts { injector, echo_reference } = output
+        .recv_timeout(PARTS_TIMEOUT)
+        .map_err(|_| anyhow!("输出回调未交还语音注入器和回声参考"))?;
+    Ok(StreamParts {
+        processor,
+        hooks: StreamHooks { tap, injector, echo_reference },
+    })
 }
 
 pub fn list_devices() {
     let host = cpal::default_host();
 
     info!("可用输入设备:");
-    for device in host.input_devices().unwrap() {
+    for device in host.input_devices().into_iter().flatten() {
         if let Ok(name) = device.name() {
             info!("  - {}", name);
         }
     }
 
     info!("可用输出设备:");
-    for device in host.output_devices().unwrap() {
+    for device in host.output_devices().into_iter().flatten() {
         if let Ok(name) = device.name() {
             info!("  - {}", name);
         }
     }
-}
\ No newline at end of file
+}
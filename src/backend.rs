@@ -0,0 +1,143 @@
+use anyhow::Result;
+use cpal::SampleFormat;
+
+/// 音频回调：输入流收到的数据 / 输出流待填写的数据（交错排列的 f32）
+///
+/// 输出流调用前缓冲区已清零。
+pub type DataCallback = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+
+/// 流运行期间的错误回调（如设备被拔出）
+pub type ErrorCallback = Box<dyn FnMut(String) + Send + 'static>;
+
+/// 打开设备时期望的格式
+#[derive(Debug, Clone, Copy)]
+pub struct FormatRequest {
+    pub sample_rate: u32,
+    /// 固定声道数，设备不支持时报错
+    pub channels: Option<u16>,
+    /// 优先选择的声道数，设备不支持时自动选择
+    pub prefer_channels: Option<u16>,
+}
+
+/// 设备协商得到的格式，回调中的数据统一为交错排列的 f32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 设备原生采样格式，由后端负责与 f32 之间的转换
+    pub sample_format: SampleFormat,
+}
+
+/// 音频后端：按名称查找并打开输入/输出设备
+///
+/// 实际声卡由 `CpalBackend` 提供，`VirtualBackend` 在内存中模拟设备，
+/// `FileBackend` 读写 WAV 文件，可以在没有声卡的环境中运行完整的双工处理流程。
+pub trait AudioBackend {
+    /// 获取后端名称
+    fn name(&self) -> &str;
+
+    /// 打开名称包含 `name` 的输入设备
+    fn open_input(&self, name: &str) -> Result<Box<dyn AudioDevice>>;
+
+    /// 打开名称包含 `name` 的输出设备
+    fn open_output(&self, name: &str) -> Result<Box<dyn AudioDevice>>;
+
+    /// 系统默认输入设备的名称，没有默认设备概念的后端返回 None
+    fn default_input(&self) -> Option<String> {
+        None
+    }
+
+    /// 系统默认输出设备的名称，没有默认设备概念的后端返回 None
+    fn default_output(&self) -> Option<String> {
+        None
+    }
+}
+
+/// 已打开的音频设备
+pub trait AudioDevice {
+    /// 设备完整名称
+    fn name(&self) -> String;
+
+    /// 按请求选择设备支持的格式
+    fn negotiate(&self, request: &FormatRequest) -> Result<DeviceFormat>;
+
+    /// 创建输入流，调用 `play` 后开始回调
+    fn build_input_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>>;
+
+    /// 创建输出流，调用 `play` 后开始回调
+    fn build_output_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>>;
+}
+
+/// 运行中的音频流，drop 时停止
+pub trait ActiveStream {
+    fn play(&self) -> Result<()>;
+
+    /// 输入流是否已没有更多数据（如输入文件已读完），实时设备永远返回 false
+    fn is_finished(&self) -> bool {
+        false
+    }
+}
+
+/// 按设备名称前缀把设备分派给不同后端，没有匹配的前缀时使用默认后端
+///
+/// 例如 `"file:meeting.wav"` 去掉前缀后交给文件后端打开 `meeting.wav`。
+pub struct RoutedBackend {
+    default: Box<dyn AudioBackend>,
+    routes: Vec<(String, Box<dyn AudioBackend>)>,
+}
+
+impl RoutedBackend {
+    pub fn new(default: Box<dyn AudioBackend>) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// 名称以 `prefix` 开头的设备交给 `backend`
+    pub fn with_route(mut self, prefix: &str, backend: Box<dyn AudioBackend>) -> Self {
+        self.routes.push((prefix.to_string(), backend));
+        self
+    }
+
+    fn route<'a>(&'a self, name: &'a str) -> (&'a dyn AudioBackend, &'a str) {
+        self.routes
+            .iter()
+            .find_map(|(prefix, backend)| name.strip_prefix(prefix.as_str()).map(|rest| (backend.as_ref(), rest)))
+            .unwrap_or((self.default.as_ref(), name))
+    }
+}
+
+impl AudioBackend for RoutedBackend {
+    fn name(&self) -> &str {
+        self.default.name()
+    }
+
+    fn open_input(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        let (backend, name) = self.route(name);
+        backend.open_input(name)
+    }
+
+    fn open_output(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        let (backend, name) = self.route(name);
+        backend.open_output(name)
+    }
+
+    fn default_input(&self) -> Option<String> {
+        self.default.default_input()
+    }
+
+    fn default_output(&self) -> Option<String> {
+        self.default.default_output()
+    }
+}
@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// 双二阶滤波器类型（RBJ Audio EQ Cookbook）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    /// 低频搁架，低于转折频率的部分增减 gain_db
+    LowShelf,
+    /// 高频搁架，高于转折频率的部分增减 gain_db
+    HighShelf,
+    /// 峰值（钟形），中心频率处增减 gain_db
+    Peaking,
+    /// 低通
+    LowPass,
+    /// 高通
+    HighPass,
+    /// 陷波，滤除中心频率
+    Notch,
+    /// 带通，中心频率处增益为 0 dB
+    BandPass,
+}
+
+impl FilterKind {
+    /// 该类型是否使用 gain_db
+    pub fn uses_gain(&self) -> bool {
+        matches!(self, FilterKind::LowShelf | FilterKind::HighShelf | FilterKind::Peaking)
+    }
+}
+
+/// 归一化（a0 = 1）的双二阶滤波器系数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coefficients {
+    pub b: [f64; 3],
+    pub a: [f64; 2],
+}
+
+impl Coefficients {
+    /// 直通
+    pub const IDENTITY: Self = Self {
+        b: [1.0, 0.0, 0.0],
+        a: [0.0, 0.0],
+    };
+
+    /// 按类型、频率 (Hz)、Q 和增益 (dB) 计算系数，频率限制在奈奎斯特频率以内
+    pub fn design(kind: FilterKind, frequency: f64, q: f64, gain_db: f64, sample_rate: u32) -> Self {
+        let rate = sample_rate.max(1) as f64;
+        let frequency = frequency.clamp(1.0, rate * 0.499);
+        let w0 = 2.0 * PI * frequency / rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        let a = 10f64.powf(gain_db / 40.0);
+
+        let (b, a) = match kind {
+            FilterKind::LowPass => (
+                [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            FilterKind::HighPass => (
+                [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            FilterKind::BandPass => ([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha]),
+            FilterKind::Notch => ([1.0, -2.0 * cos, 1.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha]),
+            FilterKind::Peaking => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            FilterKind::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) - (a - 1.0) * cos + k),
+                        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                        a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    ],
+                    [
+                        (a + 1.0) + (a - 1.0) * cos + k,
+                        -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                        (a + 1.0) + (a - 1.0) * cos - k,
+                    ],
+                )
+            }
+            FilterKind::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) + (a - 1.0) * cos + k),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    ],
+                    [
+                        (a + 1.0) - (a - 1.0) * cos + k,
+                        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                        (a + 1.0) - (a - 1.0) * cos - k,
+                    ],
+                )
+            }
+        };
+        Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+
+    /// 指定频率 (Hz) 处的幅度响应 (dB)
+    pub fn magnitude_db(&self, frequency: f64, sample_rate: u32) -> f64 {
+        let w = 2.0 * PI * frequency / sample_rate.max(1) as f64;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+        // H(e^jw) = (b0 + b1 e^-jw + b2 e^-2jw) / (1 + a1 e^-jw + a2 e^-2jw)
+        let numerator = (
+            self.b[0] + self.b[1] * cos1 + self.b[2] * cos2,
+            -(self.b[1] * sin1 + self.b[2] * sin2),
+        );
+        let denominator = (1.0 + self.a[0] * cos1 + self.a[1] * cos2, -(self.a[0] * sin1 + self.a[1] * sin2));
+        let power = (numerator.0 * numerator.0 + numerator.1 * numerator.1)
+            / (denominator.0 * denominator.0 + denominator.1 * denominator.1).max(1e-300);
+        10.0 * power.max(1e-30).log10()
+    }
+}
+
+/// 转置直接 II 型双二阶滤波器（f64 状态，单声道）
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    coefficients: Coefficients,
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub fn new(coefficients: Coefficients) -> Self {
+        Self { coefficients, z: [0.0; 2] }
+    }
+
+    /// 更换系数，保留滤波器状态（参数缓慢变化时不会产生爆音）
+    pub fn set_coefficients(&mut self, coefficients: Coefficients) {
+        self.coefficients = coefficients;
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let Coefficients { b, a } = self.coefficients;
+        let y = b[0] * x + self.z[0];
+        self.z[0] = b[1] * x - a[0] * y + self.z[1];
+        self.z[1] = b[2] * x - a[1] * y;
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.z = [0.0; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RBJ Audio EQ Cookbook 的模拟原型（s 按转折频率归一化）经双线性变换后在 `frequency` 处的增益 (dB)
+    ///
+    /// 双线性变换在转折频率处预畸变，数字频率 ω 对应的模拟频率为 tan(ω/2) / tan(ω0/2)。
+    fn analytic_db(kind: FilterKind, f0: f64, q: f64, gain_db: f64, frequency: f64) -> f64 {
+        let rate = 48_000.0;
+        let w = (PI * frequency / rate).tan() / (PI * f0 / rate).tan();
+        let a = 10f64.powf(gain_db / 40.0);
+        // s = jw 时二次多项式 c2·s² + c1·s + c0 的模
+        let quadratic = |c2: f64, c1: f64, c0: f64| (c0 - c2 * w * w).hypot(c1 * w);
+        let magnitude = match kind {
+            FilterKind::LowPass => 1.0 / quadratic(1.0, 1.0 / q, 1.0),
+            FilterKind::HighPass => w * w / quadratic(1.0, 1.0 / q, 1.0),
+            FilterKind::Peaking => quadratic(1.0, a / q, 1.0) / quadratic(1.0, 1.0 / (a * q), 1.0),
+            FilterKind::LowShelf => a * quadratic(1.0, a.sqrt() / q, a) / quadratic(a, a.sqrt() / q, 1.0),
+            FilterKind::HighShelf => a * quadratic(a, a.sqrt() / q, 1.0) / quadratic(1.0, a.sqrt() / q, a),
+            FilterKind::BandPass => w / q / quadratic(1.0, 1.0 / q, 1.0),
+            FilterKind::Notch => quadratic(1.0, 0.0, 1.0) / quadratic(1.0, 1.0 / q, 1.0),
+        };
+        20.0 * magnitude.log10()
+    }
+
+    /// 正弦通过滤波器、瞬态衰减后的稳态增益 (dB)，按 Hann 窗下该频率分量的幅度计算
+    fn measured_db(coefficients: Coefficients, frequency: f64) -> f64 {
+        let mut filter = Biquad::new(coefficients);
+        let output: Vec<f64> = (0..48_000)
+            .map(|n| filter.process((2.0 * PI * frequency * n as f64 / 48_000.0).sin()))
+            .skip(24_000)
+            .collect();
+        let len = output.len() as f64;
+        let (mut re, mut im, mut weight) = (0.0, 0.0, 0.0);
+        for (n, &sample) in output.iter().enumerate() {
+            let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / len).cos();
+            let angle = 2.0 * PI * frequency * (n + 24_000) as f64 / 48_000.0;
+            re += window * sample * angle.cos();
+            im += window * sample * angle.sin();
+            weight += window;
+        }
+        20.0 * (2.0 * re.hypot(im) / weight).log10()
+    }
+
+    #[test]
+    fn measured_response_matches_analytic_response() {
+        let filters = [
+            (FilterKind::Peaking, 1000.0, 1.4, 6.0),
+            (FilterKind::Peaking, 3000.0, 4.0, -9.0),
+            (FilterKind::LowShelf, 200.0, 0.707, 5.0),
+            (FilterKind::LowShelf, 500.0, 1.0, -8.0),
+            (FilterKind::HighShelf, 6000.0, 0.707, 4.0),
+            (FilterKind::HighShelf, 2000.0, 0.5, -6.0),
+            (FilterKind::HighPass, 80.0, 0.707, 0.0),
+            (FilterKind::LowPass, 4000.0, 0.707, 0.0),
+            (FilterKind::LowPass, 1000.0, 2.0, 0.0),
+        ];
+        for (kind, f0, q, gain_db) in filters {
+            let coefficients = Coefficients::design(kind, f0, q, gain_db, 48_000);
+            for frequency in [50.0, 120.0, 440.0, 1000.0, 2500.0, 6000.0, 12_000.0, 20_000.0] {
+                let expected = analytic_db(kind, f0, q, gain_db, frequency);
+                assert!(
+                    (coefficients.magnitude_db(frequency, 48_000) - expected).abs() < 1e-6,
+                    "{:?} {} Hz: 系数的响应与模拟原型不符",
+                    kind,
+                    frequency
+                );
+                // 阻带很深时测量受数值精度限制，只比较 -60 dB 以上
+                if expected > -60.0 {
+                    let measured = measured_db(coefficients, frequency);
+                    assert!(
+                        (measured - expected).abs() < 0.01,
+                        "{:?}({} Hz, Q {}, {} dB) 在 {} Hz: 实测 {:.3} dB，理论 {:.3} dB",
+                        kind,
+                        f0,
+                        q,
+                        gain_db,
+                        frequency,
+                        measured,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn characteristic_points() {
+        let at = |kind, gain_db, frequency| {
+            Coefficients::design(kind, 1000.0, std::f64::consts::FRAC_1_SQRT_2, gain_db, 48_000).magnitude_db(frequency, 48_000)
+        };
+        // 峰值滤波器中心处为 gain_db，搁架滤波器转折频率处为 gain_db 的一半
+        assert!((at(FilterKind::Peaking, 6.0, 1000.0) - 6.0).abs() < 1e-9);
+        assert!((at(FilterKind::LowShelf, 6.0, 1000.0) - 3.0).abs() < 1e-9);
+        assert!((at(FilterKind::HighShelf, -6.0, 1000.0) + 3.0).abs() < 1e-9);
+        assert!((at(FilterKind::LowShelf, 6.0, 20.0) - 6.0).abs() < 0.01);
+        assert!((at(FilterKind::HighShelf, 6.0, 20_000.0) - 6.0).abs() < 0.01);
+        // Q = 0.707 的高通、低通在转折频率处为 -3 dB
+        assert!((at(FilterKind::HighPass, 0.0, 1000.0) + 3.01).abs() < 0.01);
+        assert!((at(FilterKind::LowPass, 0.0, 1000.0) + 3.01).abs() < 0.01);
+        assert!(at(FilterKind::Notch, 0.0, 1000.0) < -100.0);
+        assert!(at(FilterKind::BandPass, 0.0, 1000.0).abs() < 1e-9);
+        assert!(!FilterKind::HighPass.uses_gain() && FilterKind::Peaking.uses_gain());
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::Message;
+
+use crate::asr::{TranscriptEvent, TranscriptKind};
+use crate::audio_io::Direction;
+use crate::config::CaptionServerConfig;
+use crate::translate::TranslatedSegment;
+
+/// 字幕叠加页面，OBS 浏览器源可直接使用
+const OVERLAY_HTML: &str = include_str!("../assets/caption_overlay.html");
+
+/// 每个客户端最多积压的消息数，超过后丢弃新消息
+const CLIENT_QUEUE: usize = 256;
+
+/// 请求头最大长度，超过后按已收到的部分判断
+const MAX_REQUEST_HEAD: usize = 8192;
+
+/// 等待完整请求头的最长时间
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 请求头尚未收完时再次窥视的间隔
+const PEEK_INTERVAL: Duration = Duration::from_millis(5);
+
+/// 字幕事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionKind {
+    Partial,
+    Final,
+    Translated,
+}
+
+/// 推送给浏览器的字幕事件（JSON）
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptionEvent {
+    #[serde(rename = "type")]
+    pub kind: CaptionKind,
+    pub direction: Direction,
+    /// "local"（我方）或 "remote"（对方）
+    pub speaker: &'static str,
+    pub text: String,
+    /// 译文对应的原文
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_language: Option<String>,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl From<&TranscriptEvent> for CaptionEvent {
+    fn from(event: &TranscriptEvent) -> Self {
+        Self {
+            kind: match event.segment.kind {
+                TranscriptKind::Partial => CaptionKind::Partial,
+                TranscriptKind::Final => CaptionKind::Final,
+            },
+            direction: event.direction,
+            speaker: event.direction.speaker(),
+            text: event.segment.text.clone(),
+            source_text: None,
+            source_language: None,
+            target_language: None,
+            start_ms: event.segment.start.as_millis() as u64,
+            end_ms: event.segment.end.as_millis() as u64,
+        }
+    }
+}
+
+impl From<&TranslatedSegment> for CaptionEvent {
+    fn from(segment: &TranslatedSegment) -> Self {
+        Self {
+            kind: CaptionKind::Translated,
+            direction: segment.direction,
+            speaker: segment.direction.speaker(),
+            text: segment.text.clone(),
+            source_text: Some(segment.source_text.clone()),
+            source_language: Some(segment.languages.source.clone()),
+            target_language: Some(segment.languages.target.clone()),
+            start_ms: segment.start.as_millis() as u64,
+            end_ms: segment.end.as_millis() as u64,
+        }
+    }
+}
+
+/// 内嵌的字幕服务器：`/` 返回叠加页面，`/ws` 为 WebSocket 推送字幕事件
+///
+/// 每个 WebSocket 客户端在独立线程中发送，慢客户端只会丢失自己的消息。
+#[derive(Clone)]
+pub struct CaptionServer {
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+    local_addr: SocketAddr,
+}
+
+impl CaptionServer {
+    /// 绑定地址并在后台线程中接受连接，端口为 0 时由系统分配
+    pub fn start(config: &CaptionServerConfig) -> Result<Self> {
+        let listener = TcpListener::bind((config.bind.as_str(), config.port))
+            .with_context(|| format!("字幕服务器绑定失败: {}:{}", config.bind, config.port))?;
+        let local_addr = listener.local_addr()?;
+        let clients: Arc<Mutex<Vec<Sender<String>>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let accept_clients = clients.clone();
+        thread::Builder::new()
+            .name("caption-server".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let clients = accept_clients.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = handle_connection(stream, clients) {
+                                    debug!("字幕服务器连接结束: {:#}", e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("字幕服务器接受连接失败: {}", e),
+                    }
+                }
+            })
+            .context("启动字幕服务器线程失败")?;
+
+        info!("字幕服务器已启动: http://{}/ （WebSocket: ws://{}/ws）", local_addr, local_addr);
+        Ok(Self { clients, local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// 当前连接的 WebSocket 客户端数
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().map(|clients| clients.len()).unwrap_or(0)
+    }
+
+    /// 向所有客户端广播字幕事件
+    pub fn broadcast(&self, event: &CaptionEvent) {
+        let json = match serde_json::to_string(event) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("序列化字幕事件失败: {}", e);
+                return;
+            }
+        };
+
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|client| match client.try_send(json.clone()) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, clients: Arc<Mutex<Vec<Sender<String>>>>) -> Result<()> {
+    // 先窥视请求头，判断是 WebSocket 升级还是普通 HTTP 请求
+    stream.set_read_timeout(Some(REQUEST_HEAD_TIMEOUT))?;
+    let request = peek_request_head(&stream)?.to_lowercase();
+
+    if request.contains("upgrade: websocket") {
+        stream.set_read_timeout(None)?;
+        let (sender, receiver) = crossbeam_channel::bounded(CLIENT_QUEUE);
+        let websocket = tungstenite::accept(stream).map_err(|e| anyhow::anyhow!("WebSocket 握手失败: {}", e))?;
+        clients.lock().map_err(|_| anyhow::anyhow!("客户端列表已损坏"))?.push(sender);
+        serve_websocket(websocket, receiver)
+    } else {
+        serve_http(stream, &request)
+    }
+}
+
+/// 窥视完整的请求头（数据留在套接字中交给握手或 HTTP 处理）
+///
+/// 请求头可能分多个 TCP 分段到达，一直等到空行出现、达到长度上限或超时。
+fn peek_request_head(stream: &TcpStream) -> Result<String> {
+    let mut head = vec![0u8; MAX_REQUEST_HEAD];
+    let deadline = Instant::now() + REQUEST_HEAD_TIMEOUT;
+    loop {
+        let len = stream.peek(&mut head)?;
+        if len == 0 {
+            anyhow::bail!("客户端在发送请求头前关闭了连接");
+        }
+        if len == head.len() || head[..len].windows(4).any(|window| window == b"\r\n\r\n") {
+            return Ok(String::from_utf8_lossy(&head[..len]).into_owned());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("等待请求头超时");
+        }
+        thread::sleep(PEEK_INTERVAL);
+    }
+}
+
+fn serve_websocket(mut websocket: tungstenite::WebSocket<TcpStream>, events: Receiver<String>) -> Result<()> {
+    for json in events {
+        websocket.send(Message::text(json))?;
+    }
+    Ok(())
+}
+
+fn serve_http(mut stream: TcpStream, request: &str) -> Result<()> {
+    // 读完请求头，避免客户端收到连接重置
+    let mut buffer = [0u8; 2048];
+    let mut received = 0;
+    while received < request.len() {
+        let n = stream.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        received += n;
+    }
+
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = if path == "/" || path.starts_with("/?") || path == "/index.html" {
+        ("200 OK", "text/html; charset=utf-8", OVERLAY_HTML)
+    } else {
+        ("404 Not Found", "text/plain; charset=utf-8", "not found")
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
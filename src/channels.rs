@@ -0,0 +1,230 @@
+use anyhow::{bail, Result};
+
+/// 交错排列的音频数据及其声道数
+#[derive(Debug, Clone, Default)]
+pub struct AudioBlock {
+    pub samples: Vec<f32>,
+    pub channels: u16,
+}
+
+impl AudioBlock {
+    pub fn new(samples: Vec<f32>, channels: u16) -> Self {
+        Self { samples, channels }
+    }
+
+    /// 帧数（每声道的采样数）
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+}
+
+/// 声道混合矩阵：输出声道 j = Σ matrix[j][i] × 输入声道 i
+///
+/// 默认矩阵：
+/// - 声道数相同：直通
+/// - 单声道 → 多声道：复制到每个声道
+/// - 多声道 → 单声道：取平均
+/// - 5.1（FL FR FC LFE SL SR）→ 立体声：按 ITU-R BS.775 降混，中置和环绕 −3 dB 混入两侧，丢弃 LFE，
+///   再按每行系数之和归一化避免削波
+/// - 其它降混：输入声道 i 平均混入输出声道 i % M
+/// - 其它升混：前 N 个声道直通，多出的声道静音
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMixer {
+    inputs: usize,
+    outputs: usize,
+    /// 按行存储，outputs × inputs
+    matrix: Vec<f32>,
+}
+
+impl ChannelMixer {
+    pub fn new(inputs: u16, outputs: u16) -> Self {
+        let inputs = inputs.max(1) as usize;
+        let outputs = outputs.max(1) as usize;
+        let mut matrix = vec![0.0; inputs * outputs];
+
+        if inputs == 1 {
+            matrix.fill(1.0);
+        } else if outputs == 1 {
+            matrix.fill(1.0 / inputs as f32);
+        } else if inputs == 6 && outputs == 2 {
+            let side = std::f32::consts::FRAC_1_SQRT_2;
+            let norm = 1.0 / (1.0 + 2.0 * side);
+            matrix.copy_from_slice(&[
+                norm, 0.0, side * norm, 0.0, side * norm, 0.0, //
+                0.0, norm, side * norm, 0.0, 0.0, side * norm,
+            ]);
+        } else if outputs < inputs {
+            for out in 0..outputs {
+                let sources: Vec<usize> = (0..inputs).filter(|i| i % outputs == out).collect();
+                for &i in &sources {
+                    matrix[out * inputs + i] = 1.0 / sources.len() as f32;
+                }
+            }
+        } else {
+            for ch in 0..inputs {
+                matrix[ch * inputs + ch] = 1.0;
+            }
+        }
+
+        Self {
+            inputs,
+            outputs,
+            matrix,
+        }
+    }
+
+    /// 使用自定义矩阵（按行存储，outputs × inputs）
+    pub fn from_matrix(inputs: u16, outputs: u16, matrix: Vec<f32>) -> Result<Self> {
+        let (inputs, outputs) = (inputs as usize, outputs as usize);
+        if inputs == 0 || outputs == 0 {
+            bail!("声道数必须大于 0");
+        }
+        if matrix.len() != inputs * outputs {
+            bail!(
+                "混合矩阵大小应为 {}×{}={}，实际为 {}",
+                outputs,
+                inputs,
+                inputs * outputs,
+                matrix.len()
+            );
+        }
+        Ok(Self {
+            inputs,
+            outputs,
+            matrix,
+        })
+    }
+
+    pub fn inputs(&self) -> u16 {
+        self.inputs as u16
+    }
+
+    pub fn outputs(&self) -> u16 {
+        self.outputs as u16
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.inputs == self.outputs
+            && (0..self.outputs).all(|out| {
+                (0..self.inputs).all(|i| self.matrix[out * self.inputs + i] == if i == out { 1.0 } else { 0.0 })
+            })
+    }
+
+    /// 按帧混合：`output` 被清空后写入 `input` 帧数 × 输出声道数个采样
+    ///
+    /// `output` 的容量在调用之间复用，只在变大时重新分配。
+    pub fn process(&self, input: &[f32], output: &mut Vec<f32>) {
+        output.clear();
+        for frame in input.chunks_exact(self.inputs) {
+            for row in self.matrix.chunks_exact(self.inputs) {
+                output.push(row.iter().zip(frame).map(|(gain, sample)| gain * sample).sum());
+            }
+        }
+    }
+
+    /// 混合到已分配好的缓冲区，帧数取两者较小值，返回写入的帧数
+    pub fn process_into(&self, input: &[f32], output: &mut [f32]) -> usize {
+        let mut frames = 0;
+        for (frame, out) in input.chunks_exact(self.inputs).zip(output.chunks_exact_mut(self.outputs)) {
+            for (sample, row) in out.iter_mut().zip(self.matrix.chunks_exact(self.inputs)) {
+                *sample = row.iter().zip(frame).map(|(gain, s)| gain * s).sum();
+            }
+            frames += 1;
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mix(mixer: &ChannelMixer, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::new();
+        mixer.process(input, &mut output);
+        output
+    }
+
+    #[test]
+    fn default_matrices() {
+        let side = std::f32::consts::FRAC_1_SQRT_2;
+        let norm = 1.0 / (1.0 + 2.0 * side);
+        let cases: [(u16, u16, Vec<f32>); 6] = [
+            (1, 2, vec![1.0, 1.0]),
+            (2, 1, vec![0.5, 0.5]),
+            (2, 2, vec![1.0, 0.0, 0.0, 1.0]),
+            (
+                6,
+                2,
+                vec![
+                    norm, 0.0, side * norm, 0.0, side * norm, 0.0, //
+                    0.0, norm, side * norm, 0.0, 0.0, side * norm,
+                ],
+            ),
+            // 其它降混：输入声道 i 平均混入输出声道 i % 2
+            (3, 2, vec![0.5, 0.0, 0.5, 0.0, 1.0, 0.0]),
+            // 升混：前 N 个声道直通，多出的声道静音
+            (2, 4, vec![1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]),
+        ];
+        for (inputs, outputs, matrix) in cases {
+            assert_eq!(
+                ChannelMixer::new(inputs, outputs),
+                ChannelMixer::from_matrix(inputs, outputs, matrix).unwrap(),
+                "{} → {} 声道",
+                inputs,
+                outputs
+            );
+        }
+    }
+
+    #[test]
+    fn mixes_frames() {
+        // 单声道复制到每个声道
+        assert_eq!(mix(&ChannelMixer::new(1, 3), &[0.5, -0.25]), [0.5, 0.5, 0.5, -0.25, -0.25, -0.25]);
+        // 立体声取平均
+        assert_eq!(mix(&ChannelMixer::new(2, 1), &[0.5, 0.25, -1.0, 1.0]), [0.375, 0.0]);
+        // 只有中置声道时两侧相同，只有 LFE 时输出静音
+        let surround = ChannelMixer::new(6, 2);
+        let center = mix(&surround, &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(center[0], center[1]);
+        assert!((center[0] - 0.2929).abs() < 1e-4);
+        assert_eq!(mix(&surround, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]), [0.0, 0.0]);
+        // 所有声道满幅同相时不削波
+        let full = mix(&surround, &[1.0; 6]);
+        assert!(full.iter().all(|&sample| (sample - 1.0).abs() < 1e-6));
+        // 升混多出的声道静音
+        assert_eq!(mix(&ChannelMixer::new(2, 4), &[0.5, -0.5]), [0.5, -0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn from_matrix_checks_size() {
+        assert!(ChannelMixer::from_matrix(2, 2, vec![1.0; 3]).is_err());
+        assert!(ChannelMixer::from_matrix(2, 3, vec![1.0; 5]).is_err());
+        assert!(ChannelMixer::from_matrix(0, 2, Vec::new()).is_err());
+        let mixer = ChannelMixer::from_matrix(2, 3, vec![1.0; 6]).unwrap();
+        assert_eq!((mixer.inputs(), mixer.outputs()), (2, 3));
+    }
+
+    #[test]
+    fn passthrough_detection() {
+        assert!(ChannelMixer::new(1, 1).is_passthrough());
+        assert!(ChannelMixer::new(2, 2).is_passthrough());
+        assert!(!ChannelMixer::new(1, 2).is_passthrough());
+        assert!(!ChannelMixer::new(2, 1).is_passthrough());
+        // 声道数相同但交换左右不是直通
+        assert!(!ChannelMixer::from_matrix(2, 2, vec![0.0, 1.0, 1.0, 0.0]).unwrap().is_passthrough());
+    }
+
+    #[test]
+    fn process_into_stops_at_partial_frame() {
+        let mixer = ChannelMixer::new(2, 1);
+        // 输入末尾不完整的一帧不处理，输出中多余的部分不改动
+        let mut output = [9.0; 4];
+        assert_eq!(mixer.process_into(&[0.5, 0.5, 1.0, 0.0, 0.25], &mut output), 2);
+        assert_eq!(output, [0.5, 0.5, 9.0, 9.0]);
+        // 输出放不下时按输出的帧数处理
+        let mut output = [0.0; 3];
+        assert_eq!(ChannelMixer::new(1, 2).process_into(&[0.1, 0.2, 0.3], &mut output), 1);
+        assert_eq!(output, [0.1, 0.1, 0.0]);
+    }
+}
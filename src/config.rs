@@ -1,8 +1,14 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+use crate::resampler::ResamplerQuality;
+
+/// 配置文件路径（当前目录）
+pub const CONFIG_PATH: &str = "config.toml";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioConfig {
     pub input_device_name: String,
@@ -11,6 +17,504 @@ pub struct AudioConfig {
     pub output_device_name: String,
     pub sample_rate: u32,
     pub buffer_size: u32,
+    /// 输入输出设备采样率不一致时的重采样质量
+    #[serde(default)]
+    pub resample_quality: ResamplerQuality,
+    #[serde(default)]
+    pub channels: ChannelConfig,
+    /// 输入流（麦克风）处理器链，按顺序执行
+    #[serde(default)]
+    pub input_chain: Vec<ProcessorConfig>,
+    /// 输出流（对方声音）处理器链，按顺序执行
+    #[serde(default)]
+    pub output_chain: Vec<ProcessorConfig>,
+    #[serde(default)]
+    pub jitter: JitterConfig,
+    #[serde(default)]
+    pub file_devices: FileDeviceConfig,
+    #[serde(default)]
+    pub recovery: RecoveryConfig,
+    #[serde(default)]
+    pub aec: AecConfig,
+    /// 我方语言（如 "zh"），不填则由识别引擎自动检测
+    #[serde(default)]
+    pub local_language: Option<String>,
+    /// 对方（会议）语言（如 "en"），不填则由识别引擎自动检测
+    #[serde(default)]
+    pub remote_language: Option<String>,
+    #[serde(default)]
+    pub vad: VadConfig,
+    #[serde(default)]
+    pub asr: AsrConfig,
+    #[serde(default)]
+    pub translation: TranslationConfig,
+    #[serde(default)]
+    pub tts: TtsConfig,
+    #[serde(default)]
+    pub subtitles: SubtitleConfig,
+    #[serde(default)]
+    pub caption_server: CaptionServerConfig,
+}
+
+/// 各设备固定使用的声道数，不设置则自动选择（输出设备优先与输入设备一致）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    pub input_device: Option<u16>,
+    pub vbcable_input: Option<u16>,
+    pub vbcable_output: Option<u16>,
+    pub output_device: Option<u16>,
+}
+
+/// 处理器链中的一项：`type` 为处理器类型，其余字段为该处理器的参数
+///
+/// 参数在创建处理器时由 `ProcessorRegistry` 校验，例如 `{ type = "gain", gain_db = 6.0 }`。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessorConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub params: toml::Table,
+}
+
+impl ProcessorConfig {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            params: toml::Table::new(),
+        }
+    }
+
+    /// 设置参数
+    pub fn with(mut self, key: &str, value: impl Into<toml::Value>) -> Self {
+        self.params.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// 语音活动检测配置，启用后语音识别按检测到的语句边界切分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VadConfig {
+    pub enabled: bool,
+    /// 分析帧长 (毫秒)
+    pub frame_ms: u32,
+    /// 最低能量门限 (dBFS)
+    pub threshold_db: f32,
+    /// 门限相对于噪声底的余量 (dB)
+    pub noise_margin_db: f32,
+    /// 过零率上限（每采样过零次数），高于该值的帧需要更高能量才算有声
+    pub zcr_max: f32,
+    /// 频谱平坦度上限，不设置则不计算频谱特征
+    pub flatness_max: Option<f32>,
+    /// 连续有声多久判定为语音开始 (毫秒)
+    pub min_speech_ms: u32,
+    /// 连续无声多久判定为语音结束 (毫秒)
+    pub hangover_ms: u32,
+    /// 语音开始位置向前预留的时长，避免切掉字头 (毫秒)
+    pub pre_roll_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            frame_ms: 20,
+            threshold_db: -50.0,
+            noise_margin_db: 10.0,
+            zcr_max: 0.35,
+            flatness_max: None,
+            min_speech_ms: 60,
+            hangover_ms: 400,
+            pre_roll_ms: 200,
+        }
+    }
+}
+
+/// 语音识别旁路接入哪个方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AsrTap {
+    /// 识别我方（麦克风）声音
+    Input,
+    /// 识别对方（会议软件输出）声音
+    Output,
+    /// 两个方向都识别
+    Both,
+}
+
+/// 语音识别后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AsrBackend {
+    /// 固定脚本的模拟识别器，用于调试
+    Mock,
+    /// OpenAI 兼容的 /v1/audio/transcriptions 接口（如本地 whisper 服务）
+    OpenAi,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AsrConfig {
+    pub enabled: bool,
+    pub backend: AsrBackend,
+    pub tap: AsrTap,
+    /// 服务地址，不含 /v1/audio/transcriptions
+    pub endpoint: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// 单条语句的最长时长 (毫秒)，超过后强制输出最终结果
+    pub segment_ms: u32,
+    /// 中间结果的刷新间隔 (毫秒)，0 表示不输出中间结果
+    pub partial_interval_ms: u32,
+    /// 低于该 RMS 的片段视为静音，不发送给识别服务
+    pub silence_rms: f32,
+    pub timeout_ms: u64,
+    /// 送入识别器前统一重采样到该采样率 (Hz)，0 表示使用设备采样率
+    pub sample_rate: u32,
+}
+
+impl Default for AsrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: AsrBackend::OpenAi,
+            tap: AsrTap::Output,
+            endpoint: "http://127.0.0.1:8080".to_string(),
+            model: "whisper-1".to_string(),
+            api_key: None,
+            segment_ms: 5000,
+            partial_interval_ms: 0,
+            silence_rms: 0.005,
+            timeout_ms: 30000,
+            sample_rate: 16000,
+        }
+    }
+}
+
+/// 翻译后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslationBackend {
+    /// 词典/回显翻译，用于离线调试
+    Dictionary,
+    /// 本地部署的 LibreTranslate 服务
+    LibreTranslate,
+    /// OpenAI 兼容的 /v1/chat/completions 接口
+    OpenAi,
+}
+
+/// 翻译配置，语言对由 `local_language` / `remote_language` 决定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranslationConfig {
+    pub enabled: bool,
+    pub backend: TranslationBackend,
+    /// 翻译对方的话：remote_language → local_language
+    pub remote_to_local: bool,
+    /// 翻译我方的话：local_language → remote_language
+    pub local_to_remote: bool,
+    pub endpoint: String,
+    /// 对话接口使用的模型名
+    pub model: String,
+    pub api_key: Option<String>,
+    pub timeout_ms: u64,
+    /// 收集同批语句的等待时间 (毫秒)
+    pub batch_window_ms: u64,
+    pub max_batch: usize,
+    /// 词典后端使用的词表
+    pub dictionary: HashMap<String, String>,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: TranslationBackend::LibreTranslate,
+            remote_to_local: true,
+            local_to_remote: true,
+            endpoint: "http://127.0.0.1:5000".to_string(),
+            model: "qwen2.5:7b".to_string(),
+            api_key: None,
+            timeout_ms: 10000,
+            batch_window_ms: 200,
+            max_batch: 8,
+            dictionary: HashMap::new(),
+        }
+    }
+}
+
+/// 语音合成后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtsBackend {
+    /// 正弦提示音，用于调试
+    Beep,
+    /// 调用外部合成程序（piper、espeak-ng 等），从标准输出读取 WAV
+    Command,
+}
+
+/// 合成语音与原声的组合方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtsMode {
+    /// 与原声混合
+    Mix,
+    /// 替换原声，对方只听到合成语音
+    Replace,
+}
+
+/// 语音合成配置：把我方语音的译文合成为对方语言注入到 CABLE-A，
+/// 或把对方语音的译文合成为我方语言在扬声器播放
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TtsConfig {
+    pub enabled: bool,
+    /// 合成我方语音的译文，注入到 CABLE-A
+    pub local_to_remote: bool,
+    /// 合成对方语音的译文，在扬声器播放
+    pub remote_to_local: bool,
+    pub backend: TtsBackend,
+    pub mode: TtsMode,
+    /// 合成程序
+    pub program: String,
+    /// 合成程序参数，`{text}` 为文本、`{lang}` 为译文语言；不含 `{text}` 时文本通过标准输入传递
+    pub args: Vec<String>,
+    /// 混合模式下关闭闪避时原声的固定增益
+    pub voice_gain: f32,
+    pub speech_gain: f32,
+    /// 语句之间的停顿 (毫秒)
+    pub gap_ms: u64,
+    /// 最多排队的语句数，超出后丢弃新语句
+    pub max_pending: usize,
+    /// 混合模式下播放合成语音时压低原声
+    pub ducking: DuckingConfig,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            local_to_remote: true,
+            remote_to_local: false,
+            backend: TtsBackend::Command,
+            mode: TtsMode::Mix,
+            program: "espeak-ng".to_string(),
+            args: vec![
+                "--stdout".to_string(),
+                "-v".to_string(),
+                "{lang}".to_string(),
+                "{text}".to_string(),
+            ],
+            voice_gain: 0.3,
+            speech_gain: 1.0,
+            gap_ms: 200,
+            max_pending: 8,
+            ducking: DuckingConfig::default(),
+        }
+    }
+}
+
+/// 闪避：合成语音（旁链）响起时压低原声，停下后恢复，原声始终能听到
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuckingConfig {
+    /// 关闭时按 `voice_gain` 固定衰减原声
+    pub enabled: bool,
+    /// 播放合成语音时原声的衰减量 (dB, 0 ~ 60)
+    pub depth_db: f32,
+    /// 合成语音高于该电平 (dBFS) 时开始闪避
+    pub threshold_db: f32,
+    /// 压低原声的时间 (毫秒)
+    pub attack_ms: f32,
+    /// 合成语音停下后保持闪避的时间 (毫秒)，词与词之间的停顿不会让原声忽大忽小
+    pub hold_ms: f32,
+    /// 恢复原声的时间 (毫秒)
+    pub release_ms: f32,
+}
+
+impl Default for DuckingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_db: 12.0,
+            threshold_db: -50.0,
+            attack_ms: 20.0,
+            hold_ms: 150.0,
+            release_ms: 400.0,
+        }
+    }
+}
+
+/// 字幕文件格式设置，输出目录通过 `trans run --subtitles <dir>` 指定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubtitleConfig {
+    /// 每行最多字符数
+    pub line_width: usize,
+    /// 双语字幕：原文和译文各占一行
+    pub bilingual: bool,
+}
+
+impl Default for SubtitleConfig {
+    fn default() -> Self {
+        Self {
+            line_width: 42,
+            bilingual: false,
+        }
+    }
+}
+
+/// 字幕服务器配置：为浏览器叠加层 / OBS 浏览器源推送实时字幕
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptionServerConfig {
+    pub enabled: bool,
+    /// 监听地址，默认只允许本机访问
+    pub bind: String,
+    pub port: u16,
+}
+
+impl Default for CaptionServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1".to_string(),
+            port: 8765,
+        }
+    }
+}
+
+/// 抖动缓冲区欠载时的补偿方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Concealment {
+    /// 直接填充静音
+    Silence,
+    /// 从最后一帧平滑衰减到静音，避免爆音
+    Fade,
+}
+
+/// 输入回调与输出回调之间的抖动缓冲区配置
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JitterConfig {
+    /// 目标缓冲延迟 (毫秒)
+    pub target_ms: u32,
+    /// 缓冲超过该延迟时丢弃最旧的数据 (毫秒)
+    pub max_ms: u32,
+    pub concealment: Concealment,
+    /// 通过微量重采样补偿两个设备的时钟漂移
+    pub drift_correction: bool,
+}
+
+impl Default for JitterConfig {
+    fn default() -> Self {
+        Self {
+            target_ms: 40,
+            max_ms: 200,
+            concealment: Concealment::Fade,
+            drift_correction: true,
+        }
+    }
+}
+
+/// 输出 WAV 文件的采样格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WavFormat {
+    /// 16 位整数 PCM
+    Pcm16,
+    /// 24 位整数 PCM
+    Pcm24,
+    /// 32 位浮点
+    Float32,
+}
+
+/// 文件设备配置：设备名称以 `file:` 开头时读写 WAV 文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileDeviceConfig {
+    /// 按实时速度读写文件；关闭后尽快处理，此时所有设备都应为文件设备
+    pub realtime: bool,
+    pub output_format: WavFormat,
+    /// 输入文件读完后继续运行的时长，让缓冲中的尾部数据写出 (毫秒)
+    pub tail_ms: u32,
+}
+
+impl Default for FileDeviceConfig {
+    fn default() -> Self {
+        Self {
+            realtime: true,
+            output_format: WavFormat::Pcm16,
+            tail_ms: 500,
+        }
+    }
+}
+
+/// 设备断开或音频流出错时的自动恢复配置
+///
+/// 麦克风（输入流）和扬声器（输出流）依次尝试首选设备、备用设备和系统默认设备，
+/// 虚拟声卡只重试原设备。使用备用设备期间定期检查首选设备，恢复后自动切回。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecoveryConfig {
+    pub enabled: bool,
+    /// 所有设备都无法打开时第一次重试的等待时间，之后每次加倍 (毫秒)
+    pub retry_initial_ms: u32,
+    /// 重试等待时间上限 (毫秒)
+    pub retry_max_ms: u32,
+    /// 使用备用设备时检查首选设备是否恢复的间隔 (毫秒)
+    pub restore_check_ms: u32,
+    /// 麦克风不可用时按顺序尝试的备用设备
+    pub input_fallbacks: Vec<String>,
+    /// 扬声器不可用时按顺序尝试的备用设备
+    pub output_fallbacks: Vec<String>,
+    /// 备用设备都不可用时使用系统默认设备
+    pub system_default: bool,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retry_initial_ms: 500,
+            retry_max_ms: 10000,
+            restore_check_ms: 2000,
+            input_fallbacks: Vec::new(),
+            output_fallbacks: Vec::new(),
+            system_default: true,
+        }
+    }
+}
+
+/// 回声消除：用扬声器播放的声音（输出流）作为参考，消除麦克风（输入流）拾取的回声
+///
+/// 使用笔记本扬声器或外放时开启，避免对方从会议中听到自己的声音；戴耳机时不需要。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AecConfig {
+    pub enabled: bool,
+    /// 自适应滤波器长度，应覆盖房间混响的主要部分 (毫秒, 10 ~ 500)
+    pub filter_ms: u32,
+    /// 扬声器到麦克风延迟的最大搜索范围 (毫秒, 0 ~ 1000)
+    pub max_delay_ms: u32,
+    /// 自适应步长 (0.01 ~ 1)，越大收敛越快，稳态残余越多
+    pub step_size: f32,
+    /// 残余回声抑制的最大衰减 (dB, 0 ~ 60)，0 为不抑制
+    pub suppression_db: f32,
+}
+
+impl Default for AecConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            filter_ms: 100,
+            max_delay_ms: 400,
+            step_size: 0.5,
+            suppression_db: 30.0,
+        }
+    }
 }
 
 impl Default for AudioConfig {
@@ -22,22 +526,32 @@ impl Default for AudioConfig {
             output_device_name: "扬声器".to_string(),
             sample_rate: 48000,
             buffer_size: 512,
+            resample_quality: ResamplerQuality::default(),
+            channels: ChannelConfig::default(),
+            input_chain: Vec::new(),
+            output_chain: Vec::new(),
+            jitter: JitterConfig::default(),
+            file_devices: FileDeviceConfig::default(),
+            recovery: RecoveryConfig::default(),
+            aec: AecConfig::default(),
+            local_language: None,
+            remote_language: None,
+            vad: VadConfig::default(),
+            asr: AsrConfig::default(),
+            translation: TranslationConfig::default(),
+            tts: TtsConfig::default(),
+            subtitles: SubtitleConfig::default(),
+            caption_server: CaptionServerConfig::default(),
         }
     }
 }
 
 impl AudioConfig {
     pub fn load_or_default() -> Result<Self> {
-        let config_path = Path::new("config.toml");
+        let config_path = Path::new(CONFIG_PATH);
         
         if config_path.exists() {
-            let content = fs::read_to_string(config_path)
-                .context("读取配置文件失败")?;
-            
-            let config: AudioConfig = toml::from_str(&content)
-                .context("解析配置文件失败")?;
-            
-            Ok(config)
+            Self::load(config_path)
         } else {
             let config = Self::default();
             config.save()?;
@@ -45,11 +559,20 @@ impl AudioConfig {
         }
     }
 
+    /// 读取并解析配置文件
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .context("读取配置文件失败")?;
+        
+        toml::from_str(&content)
+            .context("解析配置文件失败")
+    }
+
     pub fn save(&self) -> Result<()> {
         let content = toml::to_string_pretty(self)
             .context("序列化配置失败")?;
         
-        fs::write("config.toml", content)
+        fs::write(CONFIG_PATH, content)
             .context("写入配置文件失败")?;
         
         Ok(())
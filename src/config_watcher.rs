@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::Receiver;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::audio_io::Direction;
+use crate::config::AudioConfig;
+
+/// 检查配置文件是否修改的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 配置文件的修改标记：修改时间和大小
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// 监视配置文件，修改后重新读取并发送解析结果
+///
+/// 按修改时间轮询；检测到修改后等文件稳定一个轮询周期再读取，避免读到编辑器写了一半的文件。
+/// 文件暂时不存在（如编辑器先删除再写入）时忽略。
+pub fn spawn(path: PathBuf) -> Result<Receiver<Result<AudioConfig>>> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut last = stamp(&path);
+    thread::Builder::new()
+        .name("config-watcher".to_string())
+        .spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = stamp(&path);
+            if current.is_none() || current == last {
+                continue;
+            }
+            thread::sleep(POLL_INTERVAL);
+            if stamp(&path) != current {
+                continue;
+            }
+            last = current;
+            if sender.send(AudioConfig::load(&path)).is_err() {
+                return;
+            }
+        })
+        .context("启动配置监视线程失败")?;
+    Ok(receiver)
+}
+
+/// 处理器链的变化
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChainChange {
+    #[default]
+    None,
+    /// 处理器的类型、顺序和总线不变，只有参数变化，可以尝试通过参数句柄直接调整
+    Parameters,
+    /// 增删、替换或调整了处理器的顺序，需要重建处理器链
+    Structure,
+}
+
+/// 两份配置之间的差异
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConfigChanges {
+    /// 输入流处理器链变化
+    pub input_chain: ChainChange,
+    /// 输出流处理器链变化
+    pub output_chain: ChainChange,
+    /// 输入流的设备或格式变化，需要重启输入流
+    pub restart_input: bool,
+    /// 输出流的设备或格式变化，需要重启输出流
+    pub restart_output: bool,
+    /// 设备恢复策略变化，下一次检查设备时生效
+    pub recovery: bool,
+    /// 变化了但需要重启程序才能生效的配置项
+    pub requires_restart: Vec<String>,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 指定方向的处理器链的变化
+    pub fn chain(&self, direction: Direction) -> ChainChange {
+        match direction {
+            Direction::Input => self.input_chain,
+            Direction::Output => self.output_chain,
+        }
+    }
+
+    /// 指定方向的音频流是否需要重启
+    pub fn restart(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Input => self.restart_input,
+            Direction::Output => self.restart_output,
+        }
+    }
+}
+
+/// 比较两份配置，按配置项归类为可热更新和需要重启程序的变化
+pub fn diff(old: &AudioConfig, new: &AudioConfig) -> Result<ConfigChanges> {
+    let old = toml::Table::try_from(old).context("序列化配置失败")?;
+    let new = toml::Table::try_from(new).context("序列化配置失败")?;
+    let mut changes = ConfigChanges::default();
+
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let (before, after) = (old.get(key), new.get(key));
+        if before == after {
+            continue;
+        }
+        match key.as_str() {
+            "input_chain" => changes.input_chain = chain_change(before, after),
+            "recovery" => changes.recovery = true,
+            "output_chain" => changes.output_chain = chain_change(before, after),
+            "input_device_name" | "vbcable_input_name" => changes.restart_input = true,
+            "vbcable_output_name" | "output_device_name" => changes.restart_output = true,
+            "channels" => {
+                let field = |table: Option<&toml::Value>, name: &str| table.and_then(|table| table.get(name)).cloned();
+                let changed = |name: &str| field(before, name) != field(after, name);
+                changes.restart_input |= changed("input_device") || changed("vbcable_input");
+                changes.restart_output |= changed("vbcable_output") || changed("output_device");
+            }
+            "sample_rate" | "buffer_size" | "resample_quality" | "jitter" => {
+                changes.restart_input = true;
+                changes.restart_output = true;
+            }
+            other => changes.requires_restart.push(other.to_string()),
+        }
+    }
+    Ok(changes)
+}
+
+/// 区分处理器链只改了参数还是改了结构；`send` 的总线名称属于结构
+fn chain_change(before: Option<&toml::Value>, after: Option<&toml::Value>) -> ChainChange {
+    let entries = |value: Option<&toml::Value>| value.and_then(toml::Value::as_array).cloned().unwrap_or_default();
+    let (before, after) = (entries(before), entries(after));
+    let same_structure = before.len() == after.len()
+        && before.iter().zip(&after).all(|(before, after)| {
+            let kind = |entry: &toml::Value| entry.get("type").cloned();
+            kind(before) == kind(after) && (kind(before) != Some("send".into()) || before == after)
+        });
+    if same_structure {
+        ChainChange::Parameters
+    } else {
+        ChainChange::Structure
+    }
+}
+
+/// 把指定方向音频流使用的配置项恢复为旧值（切换设备失败时使用）
+pub fn restore_stream_settings(config: &mut AudioConfig, old: &AudioConfig, direction: Direction) {
+    match direction {
+        Direction::Input => {
+            config.input_device_name = old.input_device_name.clone();
+            config.vbcable_input_name = old.vbcable_input_name.clone();
+            config.channels.input_device = old.channels.input_device;
+            config.channels.vbcable_input = old.channels.vbcable_input;
+        }
+        Direction::Output => {
+            config.vbcable_output_name = old.vbcable_output_name.clone();
+            config.output_device_name = old.output_device_name.clone();
+            config.channels.vbcable_output = old.channels.vbcable_output;
+            config.channels.output_device = old.channels.output_device;
+        }
+    }
+    config.sample_rate = old.sample_rate;
+    config.buffer_size = old.buffer_size;
+    config.resample_quality = old.resample_quality;
+    config.jitter = old.jitter;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry;
+
+    fn with_chain(spec: &str) -> AudioConfig {
+        AudioConfig {
+            input_chain: registry::preset(spec).unwrap(),
+            ..AudioConfig::default()
+        }
+    }
+
+    fn input_change(before: &str, after: &str) -> ChainChange {
+        let changes = diff(&with_chain(before), &with_chain(after)).unwrap();
+        assert_eq!(changes.output_chain, ChainChange::None);
+        changes.chain(Direction::Input)
+    }
+
+    #[test]
+    fn chain_changes_are_classified() {
+        assert_eq!(input_change("gain:gain_db=3", "gain:gain_db=3"), ChainChange::None);
+        assert_eq!(input_change("gain:gain_db=3,noise_gate", "gain:gain_db=6,noise_gate:threshold_db=-30"), ChainChange::Parameters);
+        assert_eq!(input_change("gain:gain_db=3", "gain:gain_db=3,limiter"), ChainChange::Structure);
+        assert_eq!(input_change("gain:gain_db=3,limiter", "limiter,gain:gain_db=3"), ChainChange::Structure);
+        assert_eq!(input_change("gain:gain_db=3", "agc"), ChainChange::Structure);
+        // 总线名称属于结构
+        assert_eq!(input_change("send:bus=a,compressor:sidechain=a", "send:bus=b,compressor:sidechain=b"), ChainChange::Structure);
+    }
+
+    #[test]
+    fn stream_settings_require_restart() {
+        let old = AudioConfig::default();
+        let mut new = old.clone();
+        new.output_device_name = "耳机".to_string();
+        new.input_chain = registry::preset("gain:gain_db=3").unwrap();
+        let changes = diff(&old, &new).unwrap();
+        assert!(changes.restart(Direction::Output));
+        assert!(!changes.restart(Direction::Input));
+        assert_eq!(changes.input_chain, ChainChange::Structure);
+        assert!(changes.requires_restart.is_empty());
+    }
+}
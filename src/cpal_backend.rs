@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, Host, SupportedBufferSize, SupportedStreamConfig};
+use log::warn;
+
+use crate::backend::{ActiveStream, AudioBackend, AudioDevice, DataCallback, DeviceFormat, ErrorCallback, FormatRequest};
+use crate::sample_format;
+
+/// 基于 cpal 的系统音频后端
+pub struct CpalBackend {
+    host: Host,
+}
+
+impl CpalBackend {
+    /// 使用系统默认音频主机
+    pub fn new() -> Self {
+        Self {
+            host: cpal::default_host(),
+        }
+    }
+}
+
+impl Default for CpalBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn name(&self) -> &str {
+        "cpal"
+    }
+
+    fn open_input(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        let device = find_device_by_name(&self.host, name, true)?;
+        Ok(Box::new(CpalDevice { device, input: true }))
+    }
+
+    fn open_output(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        let device = find_device_by_name(&self.host, name, false)?;
+        Ok(Box::new(CpalDevice { device, input: false }))
+    }
+
+    fn default_input(&self) -> Option<String> {
+        self.host.default_input_device().and_then(|device| device.name().ok())
+    }
+
+    fn default_output(&self) -> Option<String> {
+        self.host.default_output_device().and_then(|device| device.name().ok())
+    }
+}
+
+struct CpalDevice {
+    device: Device,
+    input: bool,
+}
+
+impl CpalDevice {
+    fn supported(format: &DeviceFormat) -> SupportedStreamConfig {
+        SupportedStreamConfig::new(
+            format.channels,
+            cpal::SampleRate(format.sample_rate),
+            SupportedBufferSize::Unknown,
+            format.sample_format,
+        )
+    }
+}
+
+impl AudioDevice for CpalDevice {
+    fn name(&self) -> String {
+        self.device.name().unwrap_or_default()
+    }
+
+    fn negotiate(&self, request: &FormatRequest) -> Result<DeviceFormat> {
+        let config = get_supported_config(
+            &self.device,
+            self.input,
+            request.sample_rate,
+            request.channels,
+            request.prefer_channels,
+        )?;
+        Ok(DeviceFormat {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+            sample_format: config.sample_format(),
+        })
+    }
+
+    fn build_input_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        mut on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>> {
+        let stream = sample_format::build_input_stream(&self.device, &Self::supported(format), on_data, move |err| {
+            on_error(err.to_string())
+        })?;
+        Ok(Box::new(CpalStream(stream)))
+    }
+
+    fn build_output_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        mut on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>> {
+        let stream = sample_format::build_output_stream(&self.device, &Self::supported(format), on_data, move |err| {
+            on_error(err.to_string())
+        })?;
+        Ok(Box::new(CpalStream(stream)))
+    }
+}
+
+struct CpalStream(cpal::Stream);
+
+impl ActiveStream for CpalStream {
+    fn play(&self) -> Result<()> {
+        self.0.play()?;
+        Ok(())
+    }
+}
+
+/// 选择设备配置：`channels` 固定声道数，`prefer_channels` 仅作为优先条件
+fn get_supported_config(
+    device: &Device,
+    input: bool,
+    sample_rate: u32,
+    channels: Option<u16>,
+    prefer_channels: Option<u16>,
+) -> Result<SupportedStreamConfig> {
+    let configs: Vec<_> = if input {
+        device.supported_input_configs()
+            .context("获取输入设备支持配置失败")?
+            .collect()
+    } else {
+        device.supported_output_configs()
+            .context("获取输出设备支持配置失败")?
+            .collect()
+    };
+
+    // 只保留能转换为内部 f32 表示的格式，并按格式优先级排序（F32 优先）
+    let mut configs: Vec<_> = configs
+        .into_iter()
+        .filter(|config| sample_format::format_rank(config.sample_format()).is_some())
+        .collect();
+    configs.sort_by_key(|config| sample_format::format_rank(config.sample_format()));
+
+    if configs.is_empty() {
+        return Err(anyhow::anyhow!("设备没有支持的音频配置"));
+    }
+
+    if let Some(channels) = channels {
+        let available: Vec<u16> = configs.iter().map(|config| config.channels()).collect();
+        configs.retain(|config| config.channels() == channels);
+        if configs.is_empty() {
+            return Err(anyhow::anyhow!(
+                "设备不支持 {} 声道，可用声道数: {:?}",
+                channels,
+                available
+            ));
+        }
+    } else if let Some(preferred) = prefer_channels {
+        // 稳定排序，格式优先级不变
+        configs.sort_by_key(|config| config.channels() != preferred);
+    }
+
+    // 查找支持指定采样率的配置
+    let config = configs.iter()
+        .find(|config| config.max_sample_rate().0 >= sample_rate && config.min_sample_rate().0 <= sample_rate)
+        .unwrap_or(&configs[0]);
+
+    // 如果配置不支持目标采样率，使用配置的采样率
+    let target_rate = if config.max_sample_rate().0 >= sample_rate && config.min_sample_rate().0 <= sample_rate {
+        sample_rate
+    } else {
+        config.max_sample_rate().0
+    };
+
+    Ok(config.with_sample_rate(cpal::SampleRate(target_rate)))
+}
+
+fn find_device_by_name(host: &Host, name: &str, input: bool) -> Result<Device> {
+    let devices = if input {
+        host.input_devices()?.collect::<Vec<_>>()
+    } else {
+        host.output_devices()?.collect::<Vec<_>>()
+    };
+
+    for device in devices {
+        if let Ok(device_name) = device.name() {
+            if device_name.contains(name) {
+                return Ok(device);
+            }
+        }
+    }
+
+    // 列出可用设备帮助调试
+    warn!("未找到包含 '{}' 的设备，可用设备列表:", name);
+    for device in if input { host.input_devices()? } else { host.output_devices()? } {
+        if let Ok(name) = device.name() {
+            warn!("  - {}", name);
+        }
+    }
+
+    Err(anyhow::anyhow!("未找到音频设备: {}", name))
+}
@@ -0,0 +1,371 @@
+use anyhow::Result;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::loudness::db_to_amplitude;
+use crate::processor::{AudioProcessor, Parameter};
+
+/// 最小值统计把搜索窗口分成几段，每段结束时滚动一次
+const SUBWINDOWS: usize = 8;
+
+/// 功率谱平滑的时间常数 (秒)
+const POWER_SMOOTHING_SECONDS: f32 = 0.05;
+
+/// 平滑功率谱的最小值低于噪声均值，乘以该系数补偿（对白噪声和风扇噪声实测）
+const MINIMUM_BIAS: f32 = 1.8;
+
+/// 降噪参数，对应配置中 `type = "denoise"` 的字段
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseSuppressorSettings {
+    /// 噪声最多衰减的量 (dB)，越大降噪越明显，语音失真也越多
+    pub reduction_db: f32,
+    /// 分析帧长 (毫秒)，取不小于该时长的 2 的幂个采样；算法延迟等于帧长
+    pub frame_ms: f32,
+    /// 噪声底估计的搜索窗口 (毫秒)，应长于一个词的时长
+    pub noise_window_ms: u32,
+    /// 先验信噪比的平滑系数（判决引导法），越接近 1 残留的“音乐噪声”越少
+    pub smoothing: f32,
+}
+
+impl Default for NoiseSuppressorSettings {
+    fn default() -> Self {
+        Self {
+            reduction_db: 15.0,
+            frame_ms: 20.0,
+            noise_window_ms: 1500,
+            smoothing: 0.98,
+        }
+    }
+}
+
+/// 各声道共用的 FFT、窗函数和工作缓冲区
+struct Stft {
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    /// 分析和合成都使用的平方根汉宁窗，50% 重叠时两次加窗之和恒为 1
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Stft {
+    fn new(size: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(size);
+        let inverse = planner.plan_fft_inverse(size);
+        let scratch_len = forward.get_inplace_scratch_len().max(inverse.get_inplace_scratch_len());
+        let window = (0..size)
+            .map(|n| (0.5 - 0.5 * (2.0 * PI * n as f32 / size as f32).cos()).sqrt())
+            .collect();
+        Self {
+            forward,
+            inverse,
+            window,
+            buffer: vec![Complex::default(); size],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+}
+
+/// 一个声道的分析帧、重叠相加缓冲区和噪声估计
+struct ChannelState {
+    /// 最近一帧输入，新采样写在最后一个帧移中
+    input: Vec<f32>,
+    /// 重叠相加累加器
+    output: Vec<f32>,
+    /// 已合成、等待输出的一个帧移
+    ready: Vec<f32>,
+    /// 平滑后的功率谱
+    power: Vec<f32>,
+    /// 当前分段内平滑功率谱的最小值
+    minimum: Vec<f32>,
+    /// 之前各分段的最小值，`SUBWINDOWS` 段依次排列
+    minima: Vec<f32>,
+    /// 上一帧的纯净语音功率估计，用于判决引导法
+    clean: Vec<f32>,
+}
+
+impl ChannelState {
+    fn new(size: usize, hop: usize, bins: usize) -> Self {
+        Self {
+            input: vec![0.0; size],
+            output: vec![0.0; size],
+            ready: vec![0.0; hop],
+            power: vec![0.0; bins],
+            minimum: vec![f32::MAX; bins],
+            minima: vec![f32::MAX; bins * SUBWINDOWS],
+            clean: vec![0.0; bins],
+        }
+    }
+
+    /// 对一帧做降噪并重叠相加，准备好下一个帧移的输出
+    fn analyse(&mut self, stft: &mut Stft, frame: &FrameParameters) {
+        let size = stft.buffer.len();
+        let hop = self.ready.len();
+        for ((out, &sample), &w) in stft.buffer.iter_mut().zip(&self.input).zip(&stft.window) {
+            *out = Complex::new(sample * w, 0.0);
+        }
+        stft.forward.process_with_scratch(&mut stft.buffer, &mut stft.scratch);
+
+        for bin in 0..self.power.len() {
+            let periodogram = stft.buffer[bin].norm_sqr();
+            let power = if frame.first {
+                periodogram
+            } else {
+                frame.power_smoothing * self.power[bin] + (1.0 - frame.power_smoothing) * periodogram
+            };
+            self.power[bin] = power;
+
+            // 最小值统计：噪声底取最近一个搜索窗口内平滑功率谱的最小值
+            self.minimum[bin] = self.minimum[bin].min(power);
+            let mut noise = self.minimum[bin];
+            for minimum in self.minima[bin..].iter().step_by(self.power.len()) {
+                noise = noise.min(*minimum);
+            }
+            let noise = (noise * MINIMUM_BIAS).max(1e-20);
+
+            // 判决引导法估计先验信噪比，维纳增益
+            let posterior = periodogram / noise;
+            let prior = frame.smoothing * self.clean[bin] / noise
+                + (1.0 - frame.smoothing) * (posterior - 1.0).max(0.0);
+            let gain = (prior / (1.0 + prior)).max(frame.floor);
+            self.clean[bin] = gain * gain * periodogram;
+
+            stft.buffer[bin] *= gain;
+            if bin > 0 && bin < size - bin {
+                stft.buffer[size - bin] *= gain;
+            }
+        }
+        if frame.subwindow_finished {
+            let bins = self.power.len();
+            let slot = &mut self.minima[frame.subwindow * bins..(frame.subwindow + 1) * bins];
+            slot.copy_from_slice(&self.minimum);
+            self.minimum.copy_from_slice(&self.power);
+        }
+
+        stft.inverse.process_with_scratch(&mut stft.buffer, &mut stft.scratch);
+        let scale = 1.0 / size as f32;
+        for ((out, bin), &w) in self.output.iter_mut().zip(&stft.buffer).zip(&stft.window) {
+            *out += bin.re * w * scale;
+        }
+        self.ready.copy_from_slice(&self.output[..hop]);
+        self.output.copy_within(hop.., 0);
+        self.output[size - hop..].fill(0.0);
+        self.input.copy_within(hop.., 0);
+    }
+}
+
+/// 处理一帧时各声道共用的参数
+struct FrameParameters {
+    first: bool,
+    power_smoothing: f32,
+    smoothing: f32,
+    floor: f32,
+    subwindow: usize,
+    subwindow_finished: bool,
+}
+
+/// 频谱降噪：抑制键盘、风扇、空调等持续的背景噪声
+///
+/// 短时傅里叶变换（平方根汉宁窗，50% 重叠）后按频点处理：用最小值统计估计噪声底
+/// （平滑功率谱在最近 `noise_window_ms` 内的最小值，说话时也能跟踪变化的噪声），
+/// 判决引导法估计先验信噪比并计算维纳增益，增益不低于 `-reduction_db`，
+/// 平滑的先验信噪比避免残留噪声变成断续的“音乐噪声”；最后逆变换重叠相加。
+///
+/// 与噪音门不同，语音和噪声同时存在时也能降噪，不会切掉词尾。
+/// 算法延迟为一个分析帧（默认约 21 ms），见 [`AudioProcessor::latency_frames`]。
+pub struct NoiseSuppressor {
+    settings: NoiseSuppressorSettings,
+    reduction: Parameter,
+    stft: Stft,
+    channels: Vec<ChannelState>,
+    hop: usize,
+    /// 当前帧移内已写入的帧数
+    position: usize,
+    power_smoothing: f32,
+    /// 每个分段包含的分析帧数
+    subwindow_frames: usize,
+    subwindow_position: usize,
+    subwindow: usize,
+    first: bool,
+}
+
+impl NoiseSuppressor {
+    pub fn new(settings: NoiseSuppressorSettings) -> Self {
+        let mut suppressor = Self {
+            settings,
+            reduction: Parameter::new(settings.reduction_db),
+            stft: Stft::new(2),
+            channels: Vec::new(),
+            hop: 1,
+            position: 0,
+            power_smoothing: 0.0,
+            subwindow_frames: 1,
+            subwindow_position: 0,
+            subwindow: 0,
+            first: true,
+        };
+        suppressor.prepare(48000, 1);
+        suppressor
+    }
+
+    /// 最大衰减量 (dB) 的参数句柄，运行中修改在下一帧生效
+    pub fn reduction_db(&self) -> Parameter {
+        self.reduction.clone()
+    }
+
+    fn analyse(&mut self) {
+        self.subwindow_position += 1;
+        let subwindow_finished = self.subwindow_position == self.subwindow_frames;
+        let frame = FrameParameters {
+            first: self.first,
+            power_smoothing: self.power_smoothing,
+            smoothing: self.settings.smoothing,
+            floor: db_to_amplitude(-self.reduction.get().max(0.0) as f64) as f32,
+            subwindow: self.subwindow,
+            subwindow_finished,
+        };
+        for channel in &mut self.channels {
+            channel.analyse(&mut self.stft, &frame);
+        }
+        self.first = false;
+        if subwindow_finished {
+            self.subwindow_position = 0;
+            self.subwindow = (self.subwindow + 1) % SUBWINDOWS;
+        }
+    }
+}
+
+impl AudioProcessor for NoiseSuppressor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        let channels = self.channels.len();
+        let offset = self.stft.buffer.len() - self.hop;
+        for frame in buffer.chunks_exact_mut(channels) {
+            for (state, sample) in self.channels.iter_mut().zip(frame.iter_mut()) {
+                state.input[offset + self.position] = *sample;
+                *sample = state.ready[self.position];
+            }
+            self.position += 1;
+            if self.position == self.hop {
+                self.position = 0;
+                self.analyse();
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "频谱降噪"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        let frame = (self.settings.frame_ms * 0.001 * sample_rate as f32).round() as usize;
+        let size = frame.next_power_of_two().max(64);
+        let hop = size / 2;
+        self.stft = Stft::new(size);
+        self.channels = (0..channels.max(1)).map(|_| ChannelState::new(size, hop, size / 2 + 1)).collect();
+        self.hop = hop;
+        self.position = 0;
+        let hop_seconds = hop as f32 / sample_rate.max(1) as f32;
+        self.power_smoothing = (-hop_seconds / POWER_SMOOTHING_SECONDS).exp();
+        let window_frames = self.settings.noise_window_ms as f32 * 0.001 / hop_seconds;
+        self.subwindow_frames = ((window_frames / SUBWINDOWS as f32).round() as usize).max(1);
+        self.subwindow_position = 0;
+        self.subwindow = 0;
+        self.first = true;
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.stft.buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loudness::amplitude_to_db;
+
+    /// 确定性的白噪声，均匀分布在 [-amplitude, amplitude]
+    fn noise(samples: usize, amplitude: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..samples)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f64 {
+        amplitude_to_db((samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt())
+    }
+
+    #[test]
+    fn latency_is_one_analysis_frame() {
+        // 帧长取不小于 frame_ms 的 2 的幂个采样
+        for (sample_rate, frame_ms, expected) in [(48_000, 20.0, 1024), (44_100, 20.0, 1024), (16_000, 20.0, 512), (48_000, 10.0, 512)] {
+            let mut suppressor = NoiseSuppressor::new(NoiseSuppressorSettings {
+                frame_ms,
+                ..NoiseSuppressorSettings::default()
+            });
+            suppressor.prepare(sample_rate, 1);
+            assert_eq!(suppressor.latency_frames(), expected, "{} Hz，{} ms", sample_rate, frame_ms);
+        }
+    }
+
+    #[test]
+    fn zero_reduction_delays_input_by_latency() {
+        // 不衰减时平方根汉宁窗分析、合成后重叠相加恰好还原，输出是延迟一帧的输入
+        let mut suppressor = NoiseSuppressor::new(NoiseSuppressorSettings {
+            reduction_db: 0.0,
+            ..NoiseSuppressorSettings::default()
+        });
+        suppressor.prepare(48_000, 2);
+        let latency = suppressor.latency_frames();
+        let left = noise(9600, 0.5, 1);
+        let right = noise(9600, 0.5, 2);
+        let input: Vec<f32> = left.iter().zip(&right).flat_map(|(&l, &r)| [l, r]).collect();
+        let mut output = input.clone();
+        for block in output.chunks_mut(2 * 441) {
+            suppressor.process(block).unwrap();
+        }
+        for (n, frame) in output.chunks_exact(2).enumerate() {
+            let expected = n.checked_sub(latency).map_or([0.0, 0.0], |source| [left[source], right[source]]);
+            assert!((frame[0] - expected[0]).abs() < 1e-5, "左声道第 {} 帧", n);
+            assert!((frame[1] - expected[1]).abs() < 1e-5, "右声道第 {} 帧", n);
+        }
+    }
+
+    #[test]
+    fn steady_noise_is_reduced_and_tone_survives() {
+        let mut suppressor = NoiseSuppressor::new(NoiseSuppressorSettings::default());
+        suppressor.prepare(48_000, 1);
+        let latency = suppressor.latency_frames();
+        // -40 dBFS 左右的白噪声，3 秒后加入 -20 dBFS 的 1 kHz 正弦
+        let mut input = noise(48_000 * 5, 0.017, 3);
+        let tone = |n: usize| 0.1 * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / 48_000.0).sin();
+        for (n, sample) in input.iter_mut().enumerate().skip(48_000 * 3) {
+            *sample += tone(n);
+        }
+        let mut output = input.clone();
+        for block in output.chunks_mut(480) {
+            suppressor.process(block).unwrap();
+        }
+
+        // 噪声底收敛后只有噪声的一段衰减约 reduction_db（15 dB）
+        let noise_only = 48_000 * 2 + latency..48_000 * 3;
+        let reduction = rms_db(&input[noise_only.start - latency..noise_only.end - latency]) - rms_db(&output[noise_only]);
+        assert!((12.0..=16.0).contains(&reduction), "噪声衰减 {:.1} dB", reduction);
+
+        // 正弦远高于噪声底，按延迟对齐后几乎不变；持续超过噪声搜索窗口的稳态音会被当作噪声，只看开始后的 1 秒
+        let tone_part = 48_000 * 13 / 4 + latency..48_000 * 17 / 4 + latency;
+        let residual: Vec<f32> = output[tone_part.clone()].iter().zip(tone_part.clone()).map(|(&out, n)| out - tone(n - latency)).collect();
+        let tone_db = rms_db(&output[tone_part]);
+        assert!((tone_db - amplitude_to_db(0.1 / 2f64.sqrt())).abs() < 0.5, "正弦电平 {:.2} dB", tone_db);
+        assert!(rms_db(&residual) < tone_db - 20.0, "正弦失真 {:.1} dB", rms_db(&residual));
+    }
+}
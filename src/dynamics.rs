@@ -0,0 +1,1094 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::biquad::{Biquad, Coefficients, FilterKind};
+use crate::config::DuckingConfig;
+use crate::loudness::{amplitude_to_db, db_to_amplitude};
+use crate::processor::{AudioProcessor, Bus, Parameter, ProcessContext};
+
+/// 检测电平的下限，避免对静音取对数
+const MIN_LEVEL_DB: f32 = -120.0;
+
+/// 真峰值检测的过采样倍数
+const OVERSAMPLING: usize = 4;
+
+/// 真峰值插值滤波器每个相位的抽头数
+const INTERPOLATION_TAPS: usize = 16;
+
+/// 真峰值限幅的余量 (dB)：补偿插值滤波器的误差，以及增益变化在采样点之间引起的过冲
+const TRUE_PEAK_MARGIN_DB: f32 = 0.1;
+
+/// 按时间常数计算一阶平滑系数：阶跃输入经过 `ms` 后达到终值的 1 - 1/e
+fn smoothing_coefficient(ms: f32, sample_rate: u32) -> f32 {
+    let samples = ms * 0.001 * sample_rate as f32;
+    if samples <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / samples).exp()
+    }
+}
+
+fn milliseconds_to_frames(ms: f32, sample_rate: u32) -> usize {
+    (ms * 0.001 * sample_rate as f32).round().max(0.0) as usize
+}
+
+/// 交错排列的多声道延迟线，用于前视（lookahead），缓冲区在 `resize` 时分配
+struct DelayLine {
+    buffer: Vec<f32>,
+    channels: usize,
+    position: usize,
+}
+
+impl DelayLine {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            channels: 1,
+            position: 0,
+        }
+    }
+
+    fn resize(&mut self, frames: usize, channels: usize) {
+        self.channels = channels;
+        self.buffer = vec![0.0; frames * channels];
+        self.position = 0;
+    }
+
+    /// 写入一帧并原地换出延迟前的那一帧
+    fn swap(&mut self, frame: &mut [f32]) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let slot = &mut self.buffer[self.position..self.position + self.channels];
+        slot.swap_with_slice(frame);
+        self.position = (self.position + self.channels) % self.buffer.len();
+    }
+
+    /// 延迟的帧数
+    fn frames(&self) -> usize {
+        self.buffer.len() / self.channels.max(1)
+    }
+}
+
+/// 压缩器参数，对应配置中 `type = "compressor"` 的字段
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressorSettings {
+    /// 门限 (dBFS)
+    pub threshold_db: f32,
+    /// 压缩比，门限以上输入增加 ratio dB 输出增加 1 dB
+    pub ratio: f32,
+    /// 软拐点宽度 (dB)，0 为硬拐点
+    pub knee_db: f32,
+    /// 启动时间 (毫秒)
+    pub attack_ms: f32,
+    /// 释放时间 (毫秒)
+    pub release_ms: f32,
+    /// 补偿增益 (dB)
+    pub makeup_db: f32,
+    /// 前视时长 (毫秒)，音频延迟相同时长，让增益在瞬态到来前开始下降
+    pub lookahead_ms: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -18.0,
+            ratio: 4.0,
+            knee_db: 6.0,
+            attack_ms: 5.0,
+            release_ms: 100.0,
+            makeup_db: 0.0,
+            lookahead_ms: 0.0,
+        }
+    }
+}
+
+impl CompressorSettings {
+    /// 静态增益曲线：输入电平 (dB) 对应的增益 (dB，不含补偿增益，≤ 0)
+    pub fn gain_db(&self, level_db: f32) -> f32 {
+        let ratio = self.ratio.max(1.0);
+        let overshoot = level_db - self.threshold_db;
+        let half_knee = self.knee_db.max(0.0) / 2.0;
+        if overshoot <= -half_knee {
+            0.0
+        } else if overshoot < half_knee {
+            // 拐点内按二次曲线平滑过渡
+            let x = overshoot + half_knee;
+            (1.0 / ratio - 1.0) * x * x / (2.0 * self.knee_db)
+        } else {
+            overshoot * (1.0 / ratio - 1.0)
+        }
+    }
+}
+
+/// 前馈式动态压缩器
+///
+/// 每帧取各声道的最大绝对值作为检测电平（声道联动，不改变声像），经静态曲线得到目标增益，
+/// 增益按启动/释放时间常数在 dB 域平滑。可选前视把音频延迟后再施加增益。
+/// 设置旁链总线后改用该总线的电平检测（外部触发压缩），总线不存在时退回检测自身信号。
+pub struct CompressorProcessor {
+    settings: CompressorSettings,
+    sidechain: Option<String>,
+    channels: usize,
+    attack: f32,
+    release: f32,
+    makeup: f32,
+    /// 平滑后的增益 (dB)
+    gain_db: f32,
+    delay: DelayLine,
+    gain_reduction: Parameter,
+}
+
+impl CompressorProcessor {
+    pub fn new(settings: CompressorSettings) -> Self {
+        let mut compressor = Self {
+            settings,
+            sidechain: None,
+            channels: 1,
+            attack: 0.0,
+            release: 0.0,
+            makeup: db_to_amplitude(settings.makeup_db as f64) as f32,
+            gain_db: 0.0,
+            delay: DelayLine::new(),
+            gain_reduction: Parameter::new(0.0),
+        };
+        compressor.prepare(48000, 1);
+        compressor
+    }
+
+    /// 改用名为 `name` 的旁链总线检测电平
+    pub fn with_sidechain(mut self, name: &str) -> Self {
+        self.sidechain = Some(name.to_string());
+        self
+    }
+
+    /// 当前的增益衰减量 (dB，≥ 0)，每次回调结束时更新，可在其它线程读取
+    pub fn gain_reduction(&self) -> Parameter {
+        self.gain_reduction.clone()
+    }
+
+    fn compress(&mut self, buffer: &mut [f32], sidechain: Option<Bus>) {
+        for (index, frame) in buffer.chunks_exact_mut(self.channels).enumerate() {
+            let key = match sidechain {
+                Some(bus) => {
+                    let channels = bus.channels.max(1) as usize;
+                    &bus.samples[index * channels..(index + 1) * channels]
+                }
+                None => &*frame,
+            };
+            let peak = key.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let level_db = (amplitude_to_db(peak as f64) as f32).max(MIN_LEVEL_DB);
+            let target = self.settings.gain_db(level_db);
+            // 增益下降（衰减加大）时按启动时间，回升时按释放时间
+            let coefficient = if target < self.gain_db { self.attack } else { self.release };
+            self.gain_db = target + (self.gain_db - target) * coefficient;
+
+            self.delay.swap(frame);
+            let gain = db_to_amplitude(self.gain_db as f64) as f32 * self.makeup;
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+        self.gain_reduction.set(0.0 - self.gain_db);
+    }
+}
+
+impl AudioProcessor for CompressorProcessor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        self.compress(buffer, None);
+        Ok(())
+    }
+
+    fn process_context(&mut self, context: &mut ProcessContext) -> Result<()> {
+        let sidechain = self.sidechain.as_deref().and_then(|name| context.sidechain(name));
+        self.compress(context.main, sidechain);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "压缩器"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.attack = smoothing_coefficient(self.settings.attack_ms, sample_rate);
+        self.release = smoothing_coefficient(self.settings.release_ms, sample_rate);
+        let lookahead = milliseconds_to_frames(self.settings.lookahead_ms, sample_rate);
+        self.delay.resize(lookahead, self.channels);
+        self.gain_db = 0.0;
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.delay.frames()
+    }
+}
+
+/// 限幅器参数，对应配置中 `type = "limiter"` 的字段
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimiterSettings {
+    /// 输出上限 (dBFS；检测真峰值时为 dBTP)
+    pub ceiling_db: f32,
+    /// 释放时间 (毫秒)
+    pub release_ms: f32,
+    /// 前视时长 (毫秒)，增益在峰值到来前平滑下降
+    pub lookahead_ms: f32,
+    /// 按 4 倍过采样检测采样点之间的真峰值，避免数模转换或重采样后超过上限
+    pub true_peak: bool,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        Self {
+            ceiling_db: -1.0,
+            release_ms: 50.0,
+            lookahead_ms: 1.5,
+            true_peak: true,
+        }
+    }
+}
+
+/// 4 倍过采样的真峰值检测（ITU-R BS.1770 附录 2 的方法，插值滤波器为加窗 sinc）
+struct TruePeakDetector {
+    /// 每个相位的插值系数，相位 0 即原采样点
+    phases: [[f32; INTERPOLATION_TAPS]; OVERSAMPLING],
+    /// 各声道最近的输入采样，按声道连续存放
+    history: Vec<f32>,
+    position: usize,
+    /// 各声道最近两个过采样点的绝对值，用于在过采样点之间按抛物线估计峰值
+    recent: Vec<[f32; 2]>,
+}
+
+impl TruePeakDetector {
+    fn new() -> Self {
+        let mut phases = [[0.0; INTERPOLATION_TAPS]; OVERSAMPLING];
+        let center = (INTERPOLATION_TAPS / 2) as f64;
+        let half_width = center + 0.5;
+        for (phase, coefficients) in phases.iter_mut().enumerate() {
+            for (tap, coefficient) in coefficients.iter_mut().enumerate() {
+                // 插值点位于最新采样之前 center - phase / 4 个采样处
+                let t = center - tap as f64 - phase as f64 / OVERSAMPLING as f64;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t)
+                };
+                let window = 0.5 * (1.0 + (std::f64::consts::PI * t / half_width).cos());
+                *coefficient = (sinc * window) as f32;
+            }
+            let sum: f32 = coefficients.iter().sum();
+            coefficients.iter_mut().for_each(|coefficient| *coefficient /= sum);
+        }
+        Self {
+            phases,
+            history: Vec::new(),
+            position: 0,
+            recent: Vec::new(),
+        }
+    }
+
+    fn resize(&mut self, channels: usize) {
+        self.history = vec![0.0; channels * INTERPOLATION_TAPS];
+        self.recent = vec![[0.0; 2]; channels];
+        self.position = 0;
+    }
+
+    /// 送入一帧，返回各声道在最近一个采样间隔内的最大峰值（延迟半个滤波器长度再加一个采样）
+    ///
+    /// 过采样点本身会错过两点之间的峰值（接近奈奎斯特频率时约 0.2 dB），
+    /// 在局部最大的过采样点处按相邻三点的抛物线估计真正的峰值。
+    fn process(&mut self, frame: &[f32]) -> f32 {
+        for (channel, &sample) in frame.iter().enumerate() {
+            self.history[channel * INTERPOLATION_TAPS + self.position] = sample;
+        }
+        self.position = (self.position + 1) % INTERPOLATION_TAPS;
+
+        let mut peak = 0.0f32;
+        for (history, recent) in self.history.chunks_exact(INTERPOLATION_TAPS).zip(self.recent.iter_mut()) {
+            for coefficients in &self.phases {
+                // position 指向最旧的采样，tap 0 对应最新的采样
+                let mut value = 0.0;
+                for (tap, coefficient) in coefficients.iter().enumerate() {
+                    let index = (self.position + INTERPOLATION_TAPS - 1 - tap) % INTERPOLATION_TAPS;
+                    value += history[index] * coefficient;
+                }
+                let [before, center] = *recent;
+                let after = value.abs();
+                peak = peak.max(center);
+                let curvature = 2.0 * center - before - after;
+                if center >= before && center >= after && curvature > 0.0 {
+                    peak = peak.max(center + (after - before) * (after - before) / (8.0 * curvature));
+                }
+                *recent = [center, after];
+            }
+        }
+        peak
+    }
+}
+
+/// 滑动窗口最小值（单调队列），缓冲区在 `resize` 时分配
+struct MinimumHold {
+    /// (帧序号, 值)，值单调递增
+    queue: Vec<(u64, f32)>,
+    head: usize,
+    len: usize,
+    window: u64,
+    index: u64,
+}
+
+impl MinimumHold {
+    fn new() -> Self {
+        Self {
+            queue: Vec::new(),
+            head: 0,
+            len: 0,
+            window: 1,
+            index: 0,
+        }
+    }
+
+    fn resize(&mut self, window: usize) {
+        self.queue = vec![(0, 0.0); window.max(1)];
+        self.window = window.max(1) as u64;
+        self.head = 0;
+        self.len = 0;
+        self.index = 0;
+    }
+
+    /// 送入一个值，返回最近 `window` 个值中的最小值
+    fn push(&mut self, value: f32) -> f32 {
+        let capacity = self.queue.len();
+        // 丢弃队尾不小于新值的项
+        while self.len > 0 && self.queue[(self.head + self.len - 1) % capacity].1 >= value {
+            self.len -= 1;
+        }
+        // 丢弃已滑出窗口的队首
+        if self.len > 0 && self.queue[self.head].0 + self.window <= self.index {
+            self.head = (self.head + 1) % capacity;
+            self.len -= 1;
+        }
+        self.queue[(self.head + self.len) % capacity] = (self.index, value);
+        self.len += 1;
+        self.index += 1;
+        self.queue[self.head].1
+    }
+}
+
+/// 滑动窗口平均值，缓冲区在 `resize` 时分配
+struct MovingAverage {
+    values: Vec<f32>,
+    position: usize,
+    sum: f64,
+}
+
+impl MovingAverage {
+    fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            position: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn resize(&mut self, window: usize, initial: f32) {
+        self.values = vec![initial; window.max(1)];
+        self.position = 0;
+        self.sum = initial as f64 * self.values.len() as f64;
+    }
+
+    fn push(&mut self, value: f32) -> f32 {
+        self.sum += value as f64 - self.values[self.position] as f64;
+        self.values[self.position] = value;
+        self.position = (self.position + 1) % self.values.len();
+        (self.sum / self.values.len() as f64) as f32
+    }
+}
+
+/// 前视砖墙限幅器
+///
+/// 检测每帧（可选真峰值）所需的增益，在前视窗口内取最小值并平滑成斜坡，
+/// 音频延迟前视时长后再施加增益，峰值到达时增益已降到位，输出不超过上限；
+/// 增益回升按释放时间常数。最后按上限截断，只处理插值估计的残余误差。
+pub struct LimiterProcessor {
+    settings: LimiterSettings,
+    channels: usize,
+    ceiling: f32,
+    /// 检测电平超过该值时降低增益，检测真峰值时比上限低一点余量
+    threshold: f32,
+    release: f32,
+    gain: f32,
+    true_peak: TruePeakDetector,
+    hold: MinimumHold,
+    smoothing: MovingAverage,
+    delay: DelayLine,
+    gain_reduction: Parameter,
+}
+
+impl LimiterProcessor {
+    pub fn new(settings: LimiterSettings) -> Self {
+        let mut limiter = Self {
+            settings,
+            channels: 1,
+            ceiling: db_to_amplitude(settings.ceiling_db as f64) as f32,
+            threshold: db_to_amplitude(
+                (settings.ceiling_db - if settings.true_peak { TRUE_PEAK_MARGIN_DB } else { 0.0 }) as f64,
+            ) as f32,
+            release: 0.0,
+            gain: 1.0,
+            true_peak: TruePeakDetector::new(),
+            hold: MinimumHold::new(),
+            smoothing: MovingAverage::new(),
+            delay: DelayLine::new(),
+            gain_reduction: Parameter::new(0.0),
+        };
+        limiter.prepare(48000, 1);
+        limiter
+    }
+
+    /// 当前的增益衰减量 (dB，≥ 0)，每次回调结束时更新，可在其它线程读取
+    pub fn gain_reduction(&self) -> Parameter {
+        self.gain_reduction.clone()
+    }
+}
+
+impl AudioProcessor for LimiterProcessor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        for frame in buffer.chunks_exact_mut(self.channels) {
+            // 真峰值检测的相位 0 即原采样点，结果比输入晚半个插值滤波器加一个采样，已计入延迟
+            let peak = if self.settings.true_peak {
+                self.true_peak.process(frame)
+            } else {
+                frame.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+            };
+            let required = if peak > self.threshold { self.threshold / peak } else { 1.0 };
+            // 窗口内的最小增益经平均后成为斜坡，峰值到达延迟输出时斜坡已降到所需增益
+            let target = self.smoothing.push(self.hold.push(required));
+            self.gain = if target < self.gain {
+                target
+            } else {
+                target + (self.gain - target) * self.release
+            };
+
+            self.delay.swap(frame);
+            for sample in frame.iter_mut() {
+                *sample = (*sample * self.gain).clamp(-self.ceiling, self.ceiling);
+            }
+        }
+        self.gain_reduction.set(amplitude_to_db(1.0 / self.gain as f64) as f32);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "限幅器"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.release = smoothing_coefficient(self.settings.release_ms, sample_rate);
+        let lookahead = milliseconds_to_frames(self.settings.lookahead_ms, sample_rate);
+        // 真峰值检测的结果晚半个插值滤波器加一个采样，音频多延迟这段时间；斜坡只能用检测到输出之间的前视时长
+        let detector_delay = if self.settings.true_peak { INTERPOLATION_TAPS / 2 + 1 } else { 0 };
+        self.true_peak.resize(self.channels);
+        // 最小值多保持一帧，覆盖峰值所在采样及其后一个采样之间的插值点
+        self.hold.resize(lookahead + 2);
+        self.smoothing.resize(lookahead + 1, 1.0);
+        self.delay.resize(lookahead + detector_delay, self.channels);
+        self.gain = 1.0;
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.delay.frames()
+    }
+}
+
+/// 噪音门参数，对应配置中 `type = "noise_gate"` 的字段
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseGateSettings {
+    /// 打开门限 (dBFS)，检测电平高于该值时开门
+    pub threshold_db: f32,
+    /// 关闭门限 (dBFS)，检测电平低于该值并经过保持时间后关门；省略时比打开门限低 6 dB
+    pub close_threshold_db: Option<f32>,
+    /// 开门时间 (毫秒)
+    pub attack_ms: f32,
+    /// 电平低于关闭门限后保持开门的时间 (毫秒)，避免词间短暂停顿时反复开关
+    pub hold_ms: f32,
+    /// 关门时间 (毫秒)
+    pub release_ms: f32,
+    /// 关门时的衰减 (dB)，不完全静音听起来更自然
+    pub floor_db: f32,
+    /// 检测信号的高通滤波频率 (Hz)，0 为不滤波；滤除低频嗡声、脚步声，避免误开门
+    pub sidechain_hpf_hz: f32,
+}
+
+impl Default for NoiseGateSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -40.0,
+            close_threshold_db: None,
+            attack_ms: 1.0,
+            hold_ms: 50.0,
+            release_ms: 100.0,
+            floor_db: -80.0,
+            sidechain_hpf_hz: 0.0,
+        }
+    }
+}
+
+impl NoiseGateSettings {
+    /// 打开门限与关闭门限之差 (dB，≥ 0)
+    pub fn hysteresis_db(&self) -> f32 {
+        self.close_threshold_db
+            .map_or(6.0, |close| self.threshold_db - close)
+            .max(0.0)
+    }
+}
+
+/// 检测电平的释放时间 (毫秒)：峰值包络在波形过零处不会跌落
+const GATE_DETECTOR_RELEASE_MS: f32 = 10.0;
+
+/// 噪音门：电平低于门限时衰减输入
+///
+/// 各声道（可先经过检测用的高通滤波）的最大绝对值经峰值包络跟随得到检测电平，
+/// 高于打开门限时开门；低于关闭门限后再经过保持时间才关门，两个门限之间不改变状态。
+/// 增益在 dB 域按开门/关门时间过渡到 0 dB 或 `floor_db`，不会在语音的过零点处截断波形。
+pub struct NoiseGateProcessor {
+    settings: NoiseGateSettings,
+    threshold: Parameter,
+    channels: usize,
+    attack: f32,
+    release: f32,
+    detector_release: f32,
+    hold_frames: usize,
+    /// 检测信号的高通滤波器，每个声道一个；不滤波时为空
+    sidechain: Vec<Biquad>,
+    envelope: f32,
+    open: bool,
+    /// 剩余的保持帧数
+    hold: usize,
+    /// 平滑后的增益 (dB)
+    gain_db: f32,
+    gain_reduction: Parameter,
+}
+
+impl NoiseGateProcessor {
+    pub fn new(settings: NoiseGateSettings) -> Self {
+        let mut gate = Self {
+            settings,
+            threshold: Parameter::new(settings.threshold_db),
+            channels: 1,
+            attack: 0.0,
+            release: 0.0,
+            detector_release: 0.0,
+            hold_frames: 0,
+            sidechain: Vec::new(),
+            envelope: 0.0,
+            open: false,
+            hold: 0,
+            gain_db: settings.floor_db,
+            gain_reduction: Parameter::new(0.0),
+        };
+        gate.prepare(48000, 1);
+        gate
+    }
+
+    /// 打开门限 (dBFS) 的参数句柄，运行中修改立即生效，关闭门限随之保持相同的差值
+    pub fn threshold_db(&self) -> Parameter {
+        self.threshold.clone()
+    }
+
+    /// 当前的增益衰减量 (dB，≥ 0)，每次回调结束时更新，可在其它线程读取
+    pub fn gain_reduction(&self) -> Parameter {
+        self.gain_reduction.clone()
+    }
+}
+
+impl AudioProcessor for NoiseGateProcessor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        let threshold_db = self.threshold.get();
+        let open_level = db_to_amplitude(threshold_db as f64) as f32;
+        let close_level = db_to_amplitude((threshold_db - self.settings.hysteresis_db()) as f64) as f32;
+        let floor_db = self.settings.floor_db.max(MIN_LEVEL_DB);
+        let mut gain = db_to_amplitude(self.gain_db as f64) as f32;
+        for frame in buffer.chunks_exact_mut(self.channels) {
+            let peak = if self.sidechain.is_empty() {
+                frame.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+            } else {
+                self.sidechain
+                    .iter_mut()
+                    .zip(frame.iter())
+                    .fold(0.0f32, |peak, (filter, &sample)| peak.max((filter.process(sample as f64) as f32).abs()))
+            };
+            self.envelope = peak.max(self.envelope * self.detector_release);
+
+            if self.envelope >= open_level {
+                self.open = true;
+                self.hold = self.hold_frames;
+            } else if self.envelope < close_level && self.open {
+                if self.hold == 0 {
+                    self.open = false;
+                } else {
+                    self.hold -= 1;
+                }
+            } else if self.open {
+                // 两个门限之间保持开门，重新计算保持时间
+                self.hold = self.hold_frames;
+            }
+
+            let target = if self.open { 0.0 } else { floor_db };
+            if self.gain_db != target {
+                let coefficient = if target > self.gain_db { self.attack } else { self.release };
+                self.gain_db = target + (self.gain_db - target) * coefficient;
+                if (self.gain_db - target).abs() < 0.01 {
+                    self.gain_db = target;
+                }
+                gain = db_to_amplitude(self.gain_db as f64) as f32;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+        self.gain_reduction.set(0.0 - self.gain_db);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "噪音门"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.attack = smoothing_coefficient(self.settings.attack_ms, sample_rate);
+        self.release = smoothing_coefficient(self.settings.release_ms, sample_rate);
+        self.detector_release = smoothing_coefficient(GATE_DETECTOR_RELEASE_MS, sample_rate);
+        self.hold_frames = milliseconds_to_frames(self.settings.hold_ms, sample_rate);
+        self.sidechain = if self.settings.sidechain_hpf_hz > 0.0 {
+            let coefficients = Coefficients::design(
+                FilterKind::HighPass,
+                self.settings.sidechain_hpf_hz as f64,
+                std::f64::consts::FRAC_1_SQRT_2,
+                0.0,
+                sample_rate,
+            );
+            vec![Biquad::new(coefficients); self.channels]
+        } else {
+            Vec::new()
+        };
+        self.envelope = 0.0;
+        self.open = false;
+        self.hold = 0;
+        self.gain_db = self.settings.floor_db.max(MIN_LEVEL_DB);
+    }
+}
+
+/// 闪避深度的上限 (dB)
+const MAX_DUCKING_DEPTH_DB: f32 = 60.0;
+
+/// 闪避：旁链信号（合成语音）高于门限时压低主信号（原声）
+///
+/// 旁链从 [`ProcessContext`] 中名为 `sidechain` 的总线读取，没有该总线时按静音处理。旁链各声道的最大绝对值经峰值包络跟随，高于门限时衰减 `depth_db`；
+/// 低于门限后再经过保持时间才开始恢复，词与词之间的停顿不会让原声忽大忽小。
+/// 增益在 dB 域按压低/恢复时间过渡。
+pub struct Ducker {
+    config: DuckingConfig,
+    sidechain: String,
+    depth: Parameter,
+    channels: usize,
+    attack: f32,
+    release: f32,
+    detector_release: f32,
+    hold_frames: usize,
+    envelope: f32,
+    /// 剩余的保持帧数
+    hold: usize,
+    /// 平滑后的增益 (dB)
+    gain_db: f32,
+    gain_reduction: Parameter,
+}
+
+impl Ducker {
+    pub fn new(config: DuckingConfig, sidechain: &str) -> Self {
+        let mut ducker = Self {
+            depth: Parameter::new(config.depth_db),
+            config,
+            sidechain: sidechain.to_string(),
+            channels: 1,
+            attack: 0.0,
+            release: 0.0,
+            detector_release: 0.0,
+            hold_frames: 0,
+            envelope: 0.0,
+            hold: 0,
+            gain_db: 0.0,
+            gain_reduction: Parameter::new(0.0),
+        };
+        ducker.prepare(48000, 1);
+        ducker
+    }
+
+    /// 闪避深度 (dB) 的参数句柄，运行中修改立即生效
+    pub fn depth_db(&self) -> Parameter {
+        self.depth.clone()
+    }
+
+    /// 当前增益衰减量 (dB)，可在其它线程读取用于电平表
+    pub fn gain_reduction(&self) -> Parameter {
+        self.gain_reduction.clone()
+    }
+}
+
+impl Ducker {
+    fn duck(&mut self, buffer: &mut [f32], sidechain: Option<Bus>) {
+        let threshold = db_to_amplitude(self.config.threshold_db as f64) as f32;
+        let depth = self.depth.get().clamp(0.0, MAX_DUCKING_DEPTH_DB);
+        let mut gain = db_to_amplitude(self.gain_db as f64) as f32;
+        for (index, frame) in buffer.chunks_exact_mut(self.channels).enumerate() {
+            let peak = sidechain.map_or(0.0, |bus| {
+                let channels = bus.channels.max(1) as usize;
+                bus.samples[index * channels..(index + 1) * channels]
+                    .iter()
+                    .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+            });
+            self.envelope = peak.max(self.envelope * self.detector_release);
+
+            let active = if self.envelope >= threshold {
+                self.hold = self.hold_frames;
+                true
+            } else if self.hold > 0 {
+                self.hold -= 1;
+                true
+            } else {
+                false
+            };
+
+            let target = if active { -depth } else { 0.0 };
+            if self.gain_db != target {
+                let coefficient = if target < self.gain_db { self.attack } else { self.release };
+                self.gain_db = target + (self.gain_db - target) * coefficient;
+                if (self.gain_db - target).abs() < 0.01 {
+                    self.gain_db = target;
+                }
+                gain = db_to_amplitude(self.gain_db as f64) as f32;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+        self.gain_reduction.set(0.0 - self.gain_db);
+    }
+}
+
+impl AudioProcessor for Ducker {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        self.duck(buffer, None);
+        Ok(())
+    }
+
+    fn process_context(&mut self, context: &mut ProcessContext) -> Result<()> {
+        let sidechain = context.sidechain(&self.sidechain);
+        self.duck(context.main, sidechain);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "闪避"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.attack = smoothing_coefficient(self.config.attack_ms.max(0.0), sample_rate);
+        self.release = smoothing_coefficient(self.config.release_ms.max(0.0), sample_rate);
+        self.detector_release = smoothing_coefficient(GATE_DETECTOR_RELEASE_MS, sample_rate);
+        self.hold_frames = milliseconds_to_frames(self.config.hold_ms.max(0.0), sample_rate);
+        self.envelope = 0.0;
+        self.hold = 0;
+        self.gain_db = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// 逐帧送入恒定电平（直流）信号，返回各帧的增益 (dB)
+    fn gains_db(processor: &mut dyn AudioProcessor, levels_db: &[(f32, usize)]) -> Vec<f32> {
+        let mut gains = Vec::new();
+        for &(level_db, frames) in levels_db {
+            let level = db_to_amplitude(level_db as f64) as f32;
+            let mut buffer = vec![level; frames];
+            processor.process(&mut buffer).unwrap();
+            gains.extend(buffer.iter().map(|&sample| amplitude_to_db((sample / level) as f64) as f32));
+        }
+        gains
+    }
+
+    #[test]
+    fn compressor_static_curve_follows_knee() {
+        let settings = CompressorSettings {
+            threshold_db: -20.0,
+            ratio: 4.0,
+            knee_db: 6.0,
+            attack_ms: 0.5,
+            release_ms: 0.5,
+            makeup_db: 3.0,
+            lookahead_ms: 0.0,
+        };
+        // 拐点 [-23, -17] dB 之外是两段直线，拐点内是与两段直线相切的二次曲线
+        let expected = |level: f32| -> f32 {
+            let output = if level <= -23.0 {
+                level
+            } else if level >= -17.0 {
+                -20.0 + (level + 20.0) / 4.0
+            } else {
+                level - 0.75 * (level + 23.0).powi(2) / 12.0
+            };
+            output - level + 3.0
+        };
+        for level in [-60.0, -23.0, -22.0, -20.0, -18.0, -17.0, -10.0, -1.0] {
+            let mut compressor = CompressorProcessor::new(settings);
+            let gain = *gains_db(&mut compressor, &[(level, 4800)]).last().unwrap();
+            assert!((gain - expected(level)).abs() < 0.01, "输入 {} dB 时增益 {} dB，应为 {} dB", level, gain, expected(level));
+            let reduction = compressor.gain_reduction().get();
+            assert!((reduction - (3.0 - expected(level))).abs() < 0.01, "增益衰减 {} dB", reduction);
+        }
+    }
+
+    #[test]
+    fn compressor_attack_and_release_reach_63_percent_at_their_time_constants() {
+        let mut compressor = CompressorProcessor::new(CompressorSettings {
+            threshold_db: -20.0,
+            ratio: 4.0,
+            knee_db: 0.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            makeup_db: 0.0,
+            lookahead_ms: 0.0,
+        });
+        // -4 dB 比门限高 16 dB，稳定后衰减 12 dB
+        let gains = gains_db(&mut compressor, &[(-40.0, 4800), (-4.0, 9600), (-40.0, 48_000)]);
+        let crossing = |from: usize, reached: &dyn Fn(f32) -> bool| gains[from..].iter().position(|&gain| reached(gain)).unwrap() + 1;
+        let attack = crossing(4800, &|gain| gain <= -12.0 * (1.0 - (-1.0f32).exp()));
+        assert!((attack as i32 - 480).abs() <= 2, "启动 {} 帧达到 63%，应为 10 ms", attack);
+        assert!((gains[4800 + 9599] + 12.0).abs() < 0.01);
+        let release = crossing(4800 + 9600, &|gain| gain >= -12.0 * (-1.0f32).exp());
+        assert!((release as i32 - 4800).abs() <= 5, "释放 {} 帧恢复 63%，应为 100 ms", release);
+    }
+
+    fn gate(hold_ms: f32) -> NoiseGateProcessor {
+        NoiseGateProcessor::new(NoiseGateSettings {
+            threshold_db: -40.0,
+            close_threshold_db: Some(-46.0),
+            attack_ms: 1.0,
+            hold_ms,
+            release_ms: 10.0,
+            floor_db: -60.0,
+            sidechain_hpf_hz: 0.0,
+        })
+    }
+
+    #[test]
+    fn gate_opens_and_closes_at_its_thresholds() {
+        let mut gate = gate(50.0);
+        // 低于关闭门限时保持关门，衰减到 floor_db
+        let closed = gains_db(&mut gate, &[(-50.0, 4800)]);
+        assert!((closed[4799] + 60.0).abs() < 0.01);
+        assert!((gate.gain_reduction().get() - 60.0).abs() < 0.01);
+        // 两个门限之间不会开门
+        let between = gains_db(&mut gate, &[(-42.0, 9600)]);
+        assert!(between.iter().all(|&gain| gain < -59.0), "关门状态下电平在两个门限之间时开了门");
+        // 高于打开门限后按开门时间打开
+        let opened = gains_db(&mut gate, &[(-39.0, 4800)]);
+        assert!(opened[0] > -59.0);
+        assert!(opened[4799].abs() < 0.01);
+        assert_eq!(gate.gain_reduction().get(), 0.0);
+        // 开门后电平落到两个门限之间，超过保持时间也不关门
+        let held = gains_db(&mut gate, &[(-43.0, 48_000)]);
+        assert!(held.iter().all(|&gain| gain == 0.0), "开门状态下电平在两个门限之间时关了门");
+        // 低于关闭门限后先保持 50 ms，再按关门时间关闭
+        let closing = gains_db(&mut gate, &[(-50.0, 9600)]);
+        let start = closing.iter().position(|&gain| gain < 0.0).unwrap();
+        // 检测电平从 -43 dB 降到 -46 dB 约需 3.5 ms（检测释放时间 10 ms）
+        assert!((2400 + 150..2400 + 200).contains(&start), "低于关闭门限 {} 帧后开始关门", start);
+        assert!((closing[9599] + 60.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn hold_time_delays_closing() {
+        let close_start = |hold_ms: f32| {
+            let gains = gains_db(&mut gate(hold_ms), &[(-30.0, 4800), (-70.0, 19_200)]);
+            gains[4800..].iter().position(|&gain| gain < -1.0).unwrap()
+        };
+        let difference = close_start(100.0) as i64 - close_start(0.0) as i64;
+        assert!((difference - 4800).abs() <= 1, "保持时间使关门推迟 {} 帧，应为 100 ms", difference);
+    }
+
+    #[test]
+    fn threshold_handle_moves_both_thresholds() {
+        let mut gate = gate(0.0);
+        // 打开门限调到 -30 dB，关闭门限随之变为 -36 dB
+        gate.threshold_db().set(-30.0);
+        let gains = gains_db(&mut gate, &[(-35.0, 4800), (-29.0, 4800), (-35.0, 4800), (-37.0, 4800)]);
+        assert!(gains[..4800].iter().all(|&gain| gain < -59.0));
+        assert!(gains[9599].abs() < 0.01);
+        assert_eq!(gains[14_399], 0.0);
+        assert!((gains[19_199] + 60.0).abs() < 0.01);
+    }
+
+    /// 按 32 倍过采样（长加窗 sinc 插值）估计真峰值，比限幅器内部的检测器精确得多
+    fn reference_true_peak(samples: &[f32]) -> f32 {
+        const FACTOR: usize = 32;
+        const HALF_TAPS: usize = 64;
+        let kernels: Vec<Vec<f64>> = (0..FACTOR)
+            .map(|phase| {
+                (0..2 * HALF_TAPS)
+                    .map(|tap| {
+                        let t = tap as f64 - HALF_TAPS as f64 + 1.0 - phase as f64 / FACTOR as f64;
+                        let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+                        sinc * 0.5 * (1.0 + (PI * t / (HALF_TAPS as f64 + 1.0)).cos())
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut padded = vec![0.0f64; HALF_TAPS - 1];
+        padded.extend(samples.iter().map(|&sample| sample as f64));
+        padded.resize(padded.len() + HALF_TAPS, 0.0);
+        let mut peak = 0.0f64;
+        for window in padded.windows(2 * HALF_TAPS) {
+            for kernel in &kernels {
+                let value: f64 = window.iter().zip(kernel).map(|(sample, coefficient)| sample * coefficient).sum();
+                peak = peak.max(value.abs());
+            }
+        }
+        peak as f32
+    }
+
+    /// 升余弦淡入淡出并在末尾补静音，避免截断本身在采样点之间产生过冲
+    fn shaped(frames: usize, fade: usize, signal: impl Fn(f64) -> f64) -> Vec<f32> {
+        let mut samples: Vec<f32> = (0..frames)
+            .map(|n| {
+                let edge = n.min(frames - 1 - n);
+                let envelope = if edge < fade { 0.5 - 0.5 * (PI * edge as f64 / fade as f64).cos() } else { 1.0 };
+                (signal(n as f64 / 48_000.0) * envelope) as f32
+            })
+            .collect();
+        samples.resize(frames + 480, 0.0);
+        samples
+    }
+
+    #[test]
+    fn limiter_true_peak_ceiling_holds_between_samples() {
+        // 四分之一采样率、相位 45° 的正弦：采样点只有 -3 dBFS，采样点之间的峰值为 0 dBTP
+        let quarter = |t: f64| (2.0 * PI * 12_000.0 * t + PI / 4.0).sin();
+        let signals = [
+            ("四分之一采样率", shaped(2400, 240, quarter)),
+            ("突发", [vec![0.0; 1200], shaped(1200, 24, quarter)].concat()),
+            ("高频多音", shaped(2400, 240, |t| {
+                [(11_000.0, 0.0), (15_500.0, 1.0), (19_300.0, 2.0)]
+                    .iter()
+                    .map(|&(frequency, phase)| 0.35 * (2.0 * PI * frequency * t + phase).sin())
+                    .sum()
+            })),
+            ("20 kHz", shaped(2400, 240, |t| 1.2 * (2.0 * PI * 20_000.0 * t + 0.3).sin())),
+        ];
+        for (name, input) in &signals {
+            assert!(reference_true_peak(input) > 1.0, "{}", name);
+            let mut limiter = LimiterProcessor::new(LimiterSettings::default());
+            let mut output = input.clone();
+            limiter.process(&mut output).unwrap();
+            let peak_db = amplitude_to_db(reference_true_peak(&output) as f64);
+            assert!(peak_db <= -1.0, "{}: 输出真峰值 {:.3} dBTP 超过上限", name, peak_db);
+            assert!(peak_db > -1.5, "{}: 输出真峰值 {:.3} dBTP，衰减过多", name, peak_db);
+        }
+
+        // 只检测采样点时，采样点之间的峰值原样通过
+        let mut limiter = LimiterProcessor::new(LimiterSettings {
+            true_peak: false,
+            ..LimiterSettings::default()
+        });
+        let mut output = signals[0].1.clone();
+        limiter.process(&mut output).unwrap();
+        assert!(amplitude_to_db(reference_true_peak(&output) as f64) > -0.01);
+    }
+
+    fn ducker() -> Ducker {
+        Ducker::new(
+            DuckingConfig {
+                enabled: true,
+                depth_db: 12.0,
+                threshold_db: -30.0,
+                attack_ms: 10.0,
+                hold_ms: 100.0,
+                release_ms: 50.0,
+            },
+            "speech",
+        )
+    }
+
+    /// 原声为恒定电平，按各段旁链电平逐帧闪避，返回各帧的增益 (dB)
+    fn ducked_gains_db(ducker: &mut Ducker, sidechain_db: &[(f32, usize)]) -> Vec<f32> {
+        let mut gains = Vec::new();
+        for &(level_db, frames) in sidechain_db {
+            let sidechain = vec![db_to_amplitude(level_db as f64) as f32; frames];
+            let buses = [("speech", Bus::new(&sidechain, 1))];
+            let mut main = vec![0.5; frames];
+            let mut context = ProcessContext::new(&mut main, 1, 48_000, 0).with_sidechains(&buses);
+            ducker.process_context(&mut context).unwrap();
+            gains.extend(main.iter().map(|&sample| amplitude_to_db((sample / 0.5) as f64) as f32));
+        }
+        gains
+    }
+
+    #[test]
+    fn ducker_attenuates_by_depth_above_threshold() {
+        let mut ducker = ducker();
+        // 旁链低于门限时不闪避
+        let quiet = ducked_gains_db(&mut ducker, &[(-31.0, 9600)]);
+        assert!(quiet.iter().all(|&gain| gain == 0.0));
+        // 高于门限后按压低时间衰减到 depth_db
+        let gains = ducked_gains_db(&mut ducker, &[(-20.0, 9600)]);
+        let attack = gains.iter().position(|&gain| gain <= -12.0 * (1.0 - (-1.0f32).exp())).unwrap() + 1;
+        assert!((attack as i32 - 480).abs() <= 2, "压低 {} 帧达到 63%，应为 10 ms", attack);
+        assert!((gains[9599] + 12.0).abs() < 0.01);
+        assert!((ducker.gain_reduction().get() - 12.0).abs() < 0.01);
+        // 没有旁链总线时按静音处理，保持后恢复
+        let mut main = vec![0.5; 48_000];
+        ducker.process(&mut main).unwrap();
+        assert_eq!(main[47_999], 0.5);
+        assert_eq!(ducker.gain_reduction().get(), 0.0);
+    }
+
+    #[test]
+    fn ducker_holds_then_releases() {
+        let mut ducker = ducker();
+        // 旁链恰好在门限上，变为静音后检测电平立即低于门限
+        let gains = ducked_gains_db(&mut ducker, &[(-30.0, 9600), (-120.0, 28_800)]);
+        assert!((gains[9599] + 12.0).abs() < 0.01);
+        let held = gains[9600..].iter().take_while(|&&gain| gain == -12.0).count();
+        assert!((held as i32 - 4800).abs() <= 1, "保持 {} 帧，应为 100 ms", held);
+        let release = gains[9600 + held..].iter().position(|&gain| gain >= -12.0 * (-1.0f32).exp()).unwrap() + 1;
+        assert!((release as i32 - 2400).abs() <= 3, "恢复 {} 帧达到 63%，应为 50 ms", release);
+        assert_eq!(gains[9600 + 28_799], 0.0);
+    }
+
+    #[test]
+    fn depth_handle_changes_attenuation() {
+        let mut ducker = ducker();
+        let depth = ducker.depth_db();
+        ducked_gains_db(&mut ducker, &[(-20.0, 9600)]);
+        // 加深按压低时间过渡，变浅按恢复时间过渡
+        depth.set(20.0);
+        let deeper = ducked_gains_db(&mut ducker, &[(-20.0, 9600)]);
+        assert!(deeper[0] < -12.0 && deeper[0] > -13.0);
+        assert!((deeper[9599] + 20.0).abs() < 0.01);
+        depth.set(6.0);
+        let shallower = ducked_gains_db(&mut ducker, &[(-20.0, 48_000)]);
+        assert!((shallower[47_999] + 6.0).abs() < 0.01);
+        assert!((ducker.gain_reduction().get() - 6.0).abs() < 0.01);
+        // 超出 0 ~ 60 dB 的深度被限制
+        depth.set(100.0);
+        let clamped = ducked_gains_db(&mut ducker, &[(-20.0, 48_000)]);
+        assert!((clamped[47_999] + 60.0).abs() < 0.01);
+    }
+}
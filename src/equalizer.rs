@@ -0,0 +1,308 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::biquad::{Biquad, Coefficients, FilterKind};
+use crate::processor::{AudioProcessor, Parameter};
+
+/// 参数变化时每隔多少帧重新计算一次系数
+const UPDATE_FRAMES: usize = 32;
+
+/// 参数平滑的时间常数 (秒)
+const SMOOTHING_SECONDS: f32 = 0.02;
+
+fn default_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+/// 均衡器的一个频段
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BandSettings {
+    pub filter: FilterKind,
+    /// 中心/转折频率 (Hz)
+    pub frequency: f32,
+    /// 品质因数，越大频带越窄；默认 0.707
+    #[serde(default = "default_q")]
+    pub q: f32,
+    /// 增益 (dB)，只用于搁架和峰值滤波器
+    #[serde(default)]
+    pub gain_db: f32,
+}
+
+impl BandSettings {
+    pub fn coefficients(&self, sample_rate: u32) -> Coefficients {
+        Coefficients::design(
+            self.filter,
+            self.frequency as f64,
+            self.q as f64,
+            self.gain_db as f64,
+            sample_rate,
+        )
+    }
+}
+
+/// 均衡器参数，对应配置中 `type = "eq"` 的字段
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EqualizerSettings {
+    /// 按顺序串联的频段
+    pub bands: Vec<BandSettings>,
+}
+
+impl EqualizerSettings {
+    /// 所有频段串联后在指定频率 (Hz) 处的幅度响应 (dB)
+    pub fn magnitude_db(&self, frequency: f64, sample_rate: u32) -> f64 {
+        self.bands
+            .iter()
+            .map(|band| band.coefficients(sample_rate).magnitude_db(frequency, sample_rate))
+            .sum()
+    }
+}
+
+/// 运行中调整一个频段的参数句柄，修改后在约 20 ms 内平滑过渡到新值
+#[derive(Debug, Clone)]
+pub struct BandControl {
+    pub frequency: Parameter,
+    pub q: Parameter,
+    pub gain_db: Parameter,
+}
+
+struct Band {
+    filter: FilterKind,
+    control: BandControl,
+    /// 当前使用的 (频率, Q, 增益)，逐步逼近句柄中的目标值
+    current: [f32; 3],
+    /// 每个声道一个滤波器
+    filters: Vec<Biquad>,
+}
+
+impl Band {
+    fn target(&self) -> [f32; 3] {
+        [
+            self.control.frequency.get(),
+            self.control.q.get(),
+            self.control.gain_db.get(),
+        ]
+    }
+
+    fn design(&mut self, sample_rate: u32) {
+        let [frequency, q, gain_db] = self.current;
+        let coefficients = Coefficients::design(self.filter, frequency as f64, q as f64, gain_db as f64, sample_rate);
+        for filter in &mut self.filters {
+            filter.set_coefficients(coefficients);
+        }
+    }
+
+    /// 参数向目标值平滑一步，有变化时重新计算系数
+    fn update(&mut self, smoothing: f32, sample_rate: u32) {
+        let target = self.target();
+        if target == self.current {
+            return;
+        }
+        let [frequency, q, gain_db] = self.current;
+        // 频率和 Q 在对数域平滑，听感上变化均匀
+        let log_step = |current: f32, target: f32| {
+            let next = (target.ln() + (current.ln() - target.ln()) * smoothing).exp();
+            if (next / target - 1.0).abs() < 1e-4 {
+                target
+            } else {
+                next
+            }
+        };
+        let linear_step = |current: f32, target: f32| {
+            let next = target + (current - target) * smoothing;
+            if (next - target).abs() < 1e-3 {
+                target
+            } else {
+                next
+            }
+        };
+        self.current = [
+            log_step(frequency, target[0].max(1.0)),
+            log_step(q, target[1].max(0.01)),
+            linear_step(gain_db, target[2]),
+        ];
+        self.design(sample_rate);
+    }
+}
+
+/// 多频段参数均衡器：各频段的双二阶滤波器按顺序串联
+///
+/// 系数按实际采样率计算；通过 [`EqualizerProcessor::band`] 的句柄修改参数时，
+/// 参数在约 20 ms 内平滑过渡，每 32 帧重新计算一次系数，避免爆音和拉链噪声。
+pub struct EqualizerProcessor {
+    bands: Vec<Band>,
+    channels: usize,
+    sample_rate: u32,
+    smoothing: f32,
+    /// 距下一次更新系数的帧数
+    countdown: usize,
+}
+
+impl EqualizerProcessor {
+    pub fn new(settings: &EqualizerSettings) -> Self {
+        let bands = settings
+            .bands
+            .iter()
+            .map(|band| Band {
+                filter: band.filter,
+                control: BandControl {
+                    frequency: Parameter::new(band.frequency),
+                    q: Parameter::new(band.q),
+                    gain_db: Parameter::new(band.gain_db),
+                },
+                current: [band.frequency, band.q, band.gain_db],
+                filters: Vec::new(),
+            })
+            .collect();
+        let mut equalizer = Self {
+            bands,
+            channels: 1,
+            sample_rate: 48000,
+            smoothing: 0.0,
+            countdown: 0,
+        };
+        equalizer.prepare(48000, 1);
+        equalizer
+    }
+
+    /// 第 `index` 个频段的参数句柄
+    pub fn band(&self, index: usize) -> Option<BandControl> {
+        self.bands.get(index).map(|band| band.control.clone())
+    }
+}
+
+impl AudioProcessor for EqualizerProcessor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        for frame in buffer.chunks_exact_mut(self.channels) {
+            if self.countdown == 0 {
+                self.countdown = UPDATE_FRAMES;
+                for band in &mut self.bands {
+                    band.update(self.smoothing, self.sample_rate);
+                }
+            }
+            self.countdown -= 1;
+
+            for band in &mut self.bands {
+                for (filter, sample) in band.filters.iter_mut().zip(frame.iter_mut()) {
+                    *sample = filter.process(*sample as f64) as f32;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "均衡器"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.sample_rate = sample_rate;
+        self.smoothing = (-(UPDATE_FRAMES as f32) / (SMOOTHING_SECONDS * sample_rate.max(1) as f32)).exp();
+        self.countdown = 0;
+        for band in &mut self.bands {
+            band.current = band.target();
+            band.filters = vec![Biquad::new(Coefficients::IDENTITY); self.channels];
+            band.design(sample_rate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn band(filter: FilterKind, frequency: f32, q: f32, gain_db: f32) -> BandSettings {
+        BandSettings {
+            filter,
+            frequency,
+            q,
+            gain_db,
+        }
+    }
+
+    /// 立体声正弦（右声道幅度减半）通过均衡器后，跳过前 0.25 秒，返回左、右声道的增益 (dB)
+    fn measured_db(equalizer: &mut EqualizerProcessor, sample_rate: u32, frequency: f64) -> [f64; 2] {
+        let frames = sample_rate as usize;
+        let mut buffer: Vec<f32> = (0..frames)
+            .flat_map(|n| {
+                let value = (2.0 * PI * frequency * n as f64 / sample_rate as f64).sin() as f32;
+                [value, value * 0.5]
+            })
+            .collect();
+        for block in buffer.chunks_mut(2 * 256) {
+            equalizer.process(block).unwrap();
+        }
+        let skip = frames / 4;
+        let len = (frames - skip) as f64;
+        [0, 1].map(|channel| {
+            let (mut re, mut im, mut weight) = (0.0, 0.0, 0.0);
+            for n in skip..frames {
+                let window = 0.5 - 0.5 * (2.0 * PI * (n - skip) as f64 / len).cos();
+                let angle = 2.0 * PI * frequency * n as f64 / sample_rate as f64;
+                re += window * buffer[2 * n + channel] as f64 * angle.cos();
+                im += window * buffer[2 * n + channel] as f64 * angle.sin();
+                weight += window;
+            }
+            let amplitude = 2.0 * re.hypot(im) / weight / if channel == 0 { 1.0 } else { 0.5 };
+            20.0 * amplitude.log10()
+        })
+    }
+
+    #[test]
+    fn cascade_matches_summed_band_responses() {
+        let settings = EqualizerSettings {
+            bands: vec![
+                band(FilterKind::HighPass, 80.0, 0.707, 0.0),
+                band(FilterKind::LowShelf, 200.0, 0.707, 4.0),
+                band(FilterKind::Peaking, 1000.0, 2.0, -6.0),
+                band(FilterKind::HighShelf, 6000.0, 0.707, 3.0),
+                band(FilterKind::LowPass, 16000.0, 0.707, 0.0),
+            ],
+        };
+        for sample_rate in [44_100, 48_000] {
+            let mut equalizer = EqualizerProcessor::new(&settings);
+            equalizer.prepare(sample_rate, 2);
+            for frequency in [60.0, 150.0, 700.0, 1000.0, 3000.0, 9000.0, 18000.0] {
+                let expected = settings.magnitude_db(frequency, sample_rate);
+                for (channel, measured) in measured_db(&mut equalizer, sample_rate, frequency).into_iter().enumerate() {
+                    assert!(
+                        (measured - expected).abs() < 0.02,
+                        "{} Hz 采样率下 {} Hz 第 {} 声道: 实测 {:.3} dB，理论 {:.3} dB",
+                        sample_rate,
+                        frequency,
+                        channel + 1,
+                        measured,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn band_changes_glide_to_new_response() {
+        let settings = EqualizerSettings {
+            bands: vec![band(FilterKind::Peaking, 1000.0, 1.0, 0.0)],
+        };
+        let mut equalizer = EqualizerProcessor::new(&settings);
+        equalizer.prepare(48_000, 2);
+        let control = equalizer.band(0).unwrap();
+        assert!(equalizer.band(1).is_none());
+        control.gain_db.set(9.0);
+        control.frequency.set(2000.0);
+
+        // 句柄修改在约 20 ms 内平滑过渡，测量跳过的 0.25 秒后已是新参数的响应
+        let target = EqualizerSettings {
+            bands: vec![band(FilterKind::Peaking, 2000.0, 1.0, 9.0)],
+        };
+        for frequency in [1000.0, 2000.0, 5000.0] {
+            let [measured, _] = measured_db(&mut equalizer, 48_000, frequency);
+            let expected = target.magnitude_db(frequency, 48_000);
+            assert!((measured - expected).abs() < 0.02, "{} Hz: 实测 {:.3} dB，理论 {:.3} dB", frequency, measured, expected);
+        }
+        assert!((target.magnitude_db(2000.0, 48_000) - 9.0).abs() < 1e-6);
+    }
+}
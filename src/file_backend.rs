@@ -0,0 +1,397 @@
+use anyhow::{bail, Context, Result};
+use cpal::SampleFormat;
+use log::info;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{ActiveStream, AudioBackend, AudioDevice, DataCallback, DeviceFormat, ErrorCallback, FormatRequest};
+use crate::config::{FileDeviceConfig, WavFormat};
+use crate::wav::{self, WavReader, WavWriter};
+
+/// 文件设备每次回调的时长 (秒)
+const PERIOD_SECONDS: f64 = 0.01;
+
+/// 输出文件每写入多少秒的音频更新一次文件头，程序被中断时文件仍然有效
+const FLUSH_SECONDS: u64 = 1;
+
+enum StreamKind {
+    Input {
+        reader: WavReader,
+        /// 文件读完时的时钟
+        eof_at: Option<f64>,
+        finished: Arc<AtomicBool>,
+    },
+    Output {
+        writer: Option<WavWriter>,
+        format: WavFormat,
+        unflushed_frames: u64,
+    },
+}
+
+struct StreamEntry {
+    id: u64,
+    path: PathBuf,
+    kind: StreamKind,
+    playing: bool,
+    sample_rate: u32,
+    channels: usize,
+    interval: f64,
+    next_due: f64,
+    buffer: Vec<f32>,
+    on_data: DataCallback,
+    on_error: ErrorCallback,
+}
+
+struct State {
+    /// 文件设备时钟 (秒)
+    clock: f64,
+    /// 实时模式下时钟零点对应的时刻
+    start: Option<Instant>,
+    streams: Vec<StreamEntry>,
+    next_id: u64,
+    thread_started: bool,
+}
+
+struct Scheduler {
+    config: FileDeviceConfig,
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+impl Scheduler {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// WAV 文件音频后端：设备名称即文件路径
+///
+/// 输入设备从 WAV 文件读取（PCM 16/24/32 位或 32 位浮点），读完后输出静音；
+/// 输出设备写入 WAV 文件，格式由配置决定，采样率和声道数跟随请求。
+/// 所有文件流由同一个调度线程按时间顺序驱动：实时模式下按真实时间推进，
+/// 否则尽快处理，结果与实时运行完全一致。
+pub struct FileBackend {
+    scheduler: Arc<Scheduler>,
+}
+
+impl FileBackend {
+    pub fn new(config: FileDeviceConfig) -> Self {
+        Self {
+            scheduler: Arc::new(Scheduler {
+                config,
+                state: Mutex::new(State {
+                    clock: 0.0,
+                    start: None,
+                    streams: Vec::new(),
+                    next_id: 0,
+                    thread_started: false,
+                }),
+                wake: Condvar::new(),
+            }),
+        }
+    }
+}
+
+impl AudioBackend for FileBackend {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn open_input(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        let path = PathBuf::from(name);
+        let spec = wav::open(&path)?.spec();
+        Ok(Box::new(FileDevice {
+            scheduler: self.scheduler.clone(),
+            path,
+            input_spec: Some(spec),
+        }))
+    }
+
+    fn open_output(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        let path = PathBuf::from(name);
+        // 文件在创建音频流时才写入，这里先检查目录，让设备检查能发现路径错误
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            if !parent.is_dir() {
+                bail!("输出文件所在目录不存在: {}", parent.display());
+            }
+        }
+        Ok(Box::new(FileDevice {
+            scheduler: self.scheduler.clone(),
+            path,
+            input_spec: None,
+        }))
+    }
+}
+
+struct FileDevice {
+    scheduler: Arc<Scheduler>,
+    path: PathBuf,
+    /// 输入文件的格式，输出设备为 None
+    input_spec: Option<hound::WavSpec>,
+}
+
+impl FileDevice {
+    fn add_stream(&self, format: &DeviceFormat, kind: StreamKind, on_data: DataCallback, on_error: ErrorCallback) -> Result<FileStream> {
+        let finished = match &kind {
+            StreamKind::Input { finished, .. } => Some(finished.clone()),
+            StreamKind::Output { .. } => None,
+        };
+        let period_frames = ((format.sample_rate as f64 * PERIOD_SECONDS).round() as usize).max(1);
+        let channels = format.channels as usize;
+
+        let mut state = self.scheduler.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.streams.push(StreamEntry {
+            id,
+            path: self.path.clone(),
+            kind,
+            playing: false,
+            sample_rate: format.sample_rate,
+            channels,
+            interval: period_frames as f64 / format.sample_rate as f64,
+            next_due: 0.0,
+            buffer: vec![0.0; period_frames * channels],
+            on_data,
+            on_error,
+        });
+        if !state.thread_started {
+            state.thread_started = true;
+            let scheduler = Arc::downgrade(&self.scheduler);
+            thread::Builder::new()
+                .name("file-devices".to_string())
+                .spawn(move || run_scheduler(scheduler))
+                .context("启动文件设备线程失败")?;
+        }
+
+        Ok(FileStream {
+            scheduler: self.scheduler.clone(),
+            id,
+            finished,
+        })
+    }
+}
+
+impl AudioDevice for FileDevice {
+    fn name(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+
+    fn negotiate(&self, request: &FormatRequest) -> Result<DeviceFormat> {
+        match &self.input_spec {
+            Some(spec) => {
+                if let Some(channels) = request.channels {
+                    if channels != spec.channels {
+                        bail!("文件 {} 为 {} 声道，与配置的 {} 声道不符", self.path.display(), spec.channels, channels);
+                    }
+                }
+                Ok(DeviceFormat {
+                    sample_rate: spec.sample_rate,
+                    channels: spec.channels,
+                    sample_format: match (spec.sample_format, spec.bits_per_sample) {
+                        (hound::SampleFormat::Float, _) => SampleFormat::F32,
+                        (hound::SampleFormat::Int, 16) => SampleFormat::I16,
+                        _ => SampleFormat::I32,
+                    },
+                })
+            }
+            None => Ok(DeviceFormat {
+                sample_rate: request.sample_rate,
+                channels: request.channels.or(request.prefer_channels).unwrap_or(2),
+                sample_format: match self.scheduler.config.output_format {
+                    WavFormat::Pcm16 => SampleFormat::I16,
+                    WavFormat::Pcm24 => SampleFormat::I32,
+                    WavFormat::Float32 => SampleFormat::F32,
+                },
+            }),
+        }
+    }
+
+    fn build_input_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>> {
+        if self.input_spec.is_none() {
+            bail!("{} 不是输入文件", self.path.display());
+        }
+        let reader = wav::open(&self.path)?;
+        let kind = StreamKind::Input {
+            reader,
+            eof_at: None,
+            finished: Arc::new(AtomicBool::new(false)),
+        };
+        Ok(Box::new(self.add_stream(format, kind, on_data, on_error)?))
+    }
+
+    fn build_output_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>> {
+        let output_format = self.scheduler.config.output_format;
+        let writer = wav::create(&self.path, output_format, format.sample_rate, format.channels)?;
+        let kind = StreamKind::Output {
+            writer: Some(writer),
+            format: output_format,
+            unflushed_frames: 0,
+        };
+        Ok(Box::new(self.add_stream(format, kind, on_data, on_error)?))
+    }
+}
+
+struct FileStream {
+    scheduler: Arc<Scheduler>,
+    id: u64,
+    finished: Option<Arc<AtomicBool>>,
+}
+
+impl ActiveStream for FileStream {
+    fn play(&self) -> Result<()> {
+        let mut state = self.scheduler.state();
+        let realtime = self.scheduler.config.realtime;
+        let now = match state.start {
+            Some(start) if realtime => state.clock.max(start.elapsed().as_secs_f64()),
+            Some(_) => state.clock,
+            None => {
+                state.start = Some(Instant::now());
+                state.clock
+            }
+        };
+        if let Some(stream) = state.streams.iter_mut().find(|stream| stream.id == self.id) {
+            if !stream.playing {
+                stream.playing = true;
+                stream.next_due = now + stream.interval;
+            }
+        }
+        self.scheduler.wake.notify_all();
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+            .as_ref()
+            .is_some_and(|finished| finished.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for FileStream {
+    fn drop(&mut self) {
+        let removed: Vec<StreamEntry> = {
+            let mut state = self.scheduler.state();
+            let (removed, kept) = std::mem::take(&mut state.streams)
+                .into_iter()
+                .partition(|stream| stream.id == self.id);
+            state.streams = kept;
+            removed
+        };
+        for mut stream in removed {
+            if let StreamKind::Output { writer: Some(writer), .. } = stream.kind {
+                match writer.finalize() {
+                    Ok(()) => info!("输出文件已保存: {}", stream.path.display()),
+                    Err(e) => (stream.on_error)(format!("保存输出文件失败: {}", e)),
+                }
+            }
+        }
+    }
+}
+
+/// 非实时模式下所有输入文件都已读完时停止调度，避免无限写入静音
+fn halted(state: &State, config: &FileDeviceConfig) -> bool {
+    let mut inputs = state
+        .streams
+        .iter()
+        .filter_map(|stream| match &stream.kind {
+            StreamKind::Input { finished, .. } => Some(finished.load(Ordering::Relaxed)),
+            StreamKind::Output { .. } => None,
+        })
+        .peekable();
+    !config.realtime && inputs.peek().is_some() && inputs.all(|finished| finished)
+}
+
+fn run_scheduler(scheduler: Weak<Scheduler>) {
+    while let Some(scheduler) = scheduler.upgrade() {
+        let mut state = scheduler.state();
+        let next = if halted(&state, &scheduler.config) {
+            None
+        } else {
+            state
+                .streams
+                .iter()
+                .enumerate()
+                .filter(|(_, stream)| stream.playing)
+                .min_by(|(_, a), (_, b)| {
+                    let a_input = matches!(a.kind, StreamKind::Input { .. });
+                    let b_input = matches!(b.kind, StreamKind::Input { .. });
+                    a.next_due.total_cmp(&b.next_due).then(b_input.cmp(&a_input))
+                })
+                .map(|(index, _)| index)
+        };
+
+        let Some(index) = next else {
+            let _ = scheduler.wake.wait_timeout(state, Duration::from_millis(100));
+            continue;
+        };
+
+        let due = state.streams[index].next_due;
+        if scheduler.config.realtime {
+            if let Some(start) = state.start {
+                let deadline = start + Duration::from_secs_f64(due);
+                let now = Instant::now();
+                if now < deadline {
+                    let _ = scheduler.wake.wait_timeout(state, deadline - now);
+                    continue;
+                }
+            }
+        }
+
+        state.clock = due;
+        let tail = scheduler.config.tail_ms as f64 / 1000.0;
+        let stream = &mut state.streams[index];
+        stream.next_due += stream.interval;
+        run_stream(stream, due, tail);
+    }
+}
+
+fn run_stream(stream: &mut StreamEntry, clock: f64, tail: f64) {
+    match &mut stream.kind {
+        StreamKind::Input { reader, eof_at, finished } => {
+            if eof_at.is_none() && wav::read_samples(reader, &mut stream.buffer) < stream.buffer.len() {
+                info!("输入文件播放完毕: {}", stream.path.display());
+                *eof_at = Some(clock);
+            }
+            if eof_at.is_some() {
+                stream.buffer.fill(0.0);
+            }
+            (stream.on_data)(&mut stream.buffer);
+            // 读完后再运行一段时间，让缓冲区和处理器中的尾部数据输出
+            if eof_at.is_some_and(|eof| clock >= eof + tail) {
+                finished.store(true, Ordering::Relaxed);
+            }
+        }
+        StreamKind::Output { writer, format, unflushed_frames } => {
+            stream.buffer.fill(0.0);
+            (stream.on_data)(&mut stream.buffer);
+            let Some(active) = writer.as_mut() else {
+                return;
+            };
+            if let Err(e) = wav::write_samples(active, *format, &stream.buffer) {
+                (stream.on_error)(format!("写入输出文件失败: {}", e));
+                *writer = None;
+                return;
+            }
+            *unflushed_frames += (stream.buffer.len() / stream.channels) as u64;
+            if *unflushed_frames >= stream.sample_rate as u64 * FLUSH_SECONDS {
+                *unflushed_frames = 0;
+                if let Err(e) = active.flush() {
+                    (stream.on_error)(format!("写入输出文件失败: {}", e));
+                }
+            }
+        }
+    }
+}
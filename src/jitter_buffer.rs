@@ -0,0 +1,349 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::config::{Concealment, JitterConfig};
+use crate::resampler::{Resampler, ResamplerQuality};
+use crate::ring_buffer::{self, Consumer, Producer};
+
+/// 每次从队列取出送入漂移补偿重采样器的帧数
+const CHUNK_FRAMES: usize = 64;
+
+/// 漂移补偿的最大比例偏差（±0.2%，约 3.5 音分）
+const MAX_DRIFT_ADJUST: f64 = 0.002;
+
+/// 缓冲水位平滑系数（按输出回调次数计，约 1 秒的时间常数）
+const LEVEL_SMOOTHING: f64 = 0.01;
+
+/// 淡出补偿的时长 (秒)
+const FADE_SECONDS: f32 = 0.005;
+
+/// 抖动缓冲区运行统计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct JitterStats {
+    /// 输出回调取不到足够数据的次数
+    pub underruns: u64,
+    /// 缓冲超过上限而丢弃旧数据的次数
+    pub overruns: u64,
+    /// 当前缓冲的帧数
+    pub level_frames: usize,
+    /// 当前漂移补偿的比例偏差 (ppm)，正值表示放慢消耗
+    pub drift_ppm: i64,
+}
+
+struct Shared {
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+    level_frames: AtomicUsize,
+    drift_ppm: AtomicI64,
+}
+
+/// 读取抖动缓冲区统计信息的句柄
+#[derive(Clone)]
+pub struct JitterMonitor {
+    shared: Arc<Shared>,
+}
+
+impl JitterMonitor {
+    pub fn stats(&self) -> JitterStats {
+        JitterStats {
+            underruns: self.shared.underruns.load(Ordering::Relaxed),
+            overruns: self.shared.overruns.load(Ordering::Relaxed),
+            level_frames: self.shared.level_frames.load(Ordering::Relaxed),
+            drift_ppm: self.shared.drift_ppm.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 写入端，在输入回调中使用
+pub struct JitterWriter {
+    shared: Arc<Shared>,
+    producer: Producer,
+    channels: usize,
+}
+
+impl JitterWriter {
+    /// 写入交错排列的音频；环形缓冲区写满时丢弃放不下的帧
+    ///
+    /// 水位超过上限时由读取端丢弃最旧的数据，写入端只在读取端长时间停止时才会丢帧。
+    pub fn write(&mut self, samples: &[f32]) {
+        let fits = self.producer.free() / self.channels * self.channels;
+        let written = self.producer.push_slice(&samples[..samples.len().min(fits)]);
+        if written < samples.len() {
+            self.shared.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 读取端，在输出回调中使用
+///
+/// 启动或欠载后先缓冲到目标水位再开始输出；
+/// 输出期间根据平滑后的水位微调重采样比例，使缓冲延迟稳定在目标值附近。
+pub struct JitterReader {
+    shared: Arc<Shared>,
+    consumer: Consumer,
+    channels: usize,
+    target_frames: usize,
+    max_frames: usize,
+    concealment: Concealment,
+    fade_decay: f32,
+    resampler: Option<Resampler>,
+    buffering: bool,
+    level: f64,
+    chunk: Vec<f32>,
+    chunk_len: usize,
+    pending: Vec<f32>,
+    pending_pos: usize,
+    last_frame: Vec<f32>,
+}
+
+impl JitterReader {
+    /// 读取交错排列的音频填满 `output`，数据不足时按配置补偿
+    pub fn read(&mut self, output: &mut [f32]) {
+        let channels = self.channels;
+        let mut queued = self.consumer.len() / channels;
+        if queued > self.max_frames {
+            // 写入端长期快于读取端（如输出设备卡顿），丢弃最旧的数据回落到目标水位
+            self.consumer.skip((queued - self.target_frames) * channels);
+            self.shared.overruns.fetch_add(1, Ordering::Relaxed);
+            queued = self.target_frames;
+        }
+        self.shared.level_frames.store(queued, Ordering::Relaxed);
+        if self.buffering {
+            if queued < self.target_frames {
+                output.fill(0.0);
+                return;
+            }
+            self.buffering = false;
+            self.level = queued as f64;
+        }
+        self.update_drift(queued);
+
+        let mut written = 0;
+        while written < output.len() {
+            if self.pending_pos < self.pending.len() {
+                let count = (self.pending.len() - self.pending_pos).min(output.len() - written);
+                output[written..written + count]
+                    .copy_from_slice(&self.pending[self.pending_pos..self.pending_pos + count]);
+                self.pending_pos += count;
+                written += count;
+                continue;
+            }
+
+            self.pending.clear();
+            self.pending_pos = 0;
+            if !self.pull_chunk() {
+                self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+                self.conceal(&mut output[written..]);
+                self.buffering = true;
+                return;
+            }
+            let chunk = &self.chunk[..self.chunk_len];
+            match &mut self.resampler {
+                Some(resampler) => resampler.process(chunk, &mut self.pending),
+                None => self.pending.extend_from_slice(chunk),
+            }
+        }
+
+        if output.len() >= channels {
+            self.last_frame.copy_from_slice(&output[output.len() - channels..]);
+        }
+    }
+
+    fn pull_chunk(&mut self) -> bool {
+        self.chunk_len = self.consumer.pop_slice(&mut self.chunk);
+        self.chunk_len > 0
+    }
+
+    /// 水位高于目标时加快消耗，低于目标时放慢消耗
+    fn update_drift(&mut self, queued: usize) {
+        let Some(resampler) = &mut self.resampler else {
+            return;
+        };
+        self.level += (queued as f64 - self.level) * LEVEL_SMOOTHING;
+        let error = (self.level - self.target_frames as f64) / self.target_frames.max(1) as f64;
+        let adjust = (-error * MAX_DRIFT_ADJUST).clamp(-MAX_DRIFT_ADJUST, MAX_DRIFT_ADJUST);
+        resampler.set_ratio_adjust(adjust);
+        self.shared
+            .drift_ppm
+            .store((adjust * 1_000_000.0).round() as i64, Ordering::Relaxed);
+    }
+
+    fn conceal(&mut self, output: &mut [f32]) {
+        match self.concealment {
+            Concealment::Silence => output.fill(0.0),
+            Concealment::Fade => {
+                for frame in output.chunks_mut(self.channels) {
+                    for (sample, last) in frame.iter_mut().zip(self.last_frame.iter_mut()) {
+                        *last *= self.fade_decay;
+                        *sample = *last;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 创建抖动缓冲区，`sample_rate` 与 `channels` 为写入数据的格式
+pub fn channel(sample_rate: u32, channels: u16, config: &JitterConfig) -> (JitterWriter, JitterReader, JitterMonitor) {
+    let channels = channels.max(1) as usize;
+    let ms_to_frames = |ms: u32| (sample_rate as u64 * ms as u64 / 1000) as usize;
+    let target_frames = ms_to_frames(config.target_ms).max(1);
+    let max_frames = ms_to_frames(config.max_ms).max(target_frames * 2);
+
+    // 留出一倍余量，读取端丢弃旧数据之前写入端不会丢帧
+    let (producer, consumer) = ring_buffer::ring_buffer(max_frames * 2 * channels);
+    let shared = Arc::new(Shared {
+        underruns: AtomicU64::new(0),
+        overruns: AtomicU64::new(0),
+        level_frames: AtomicUsize::new(0),
+        drift_ppm: AtomicI64::new(0),
+    });
+
+    let writer = JitterWriter {
+        shared: shared.clone(),
+        producer,
+        channels,
+    };
+    let mut resampler = config
+        .drift_correction
+        .then(|| Resampler::new(sample_rate, sample_rate, channels as u16, ResamplerQuality::Balanced));
+    if let Some(resampler) = &mut resampler {
+        resampler.reserve(CHUNK_FRAMES);
+    }
+    let reader = JitterReader {
+        shared: shared.clone(),
+        consumer,
+        channels,
+        target_frames,
+        max_frames,
+        concealment: config.concealment,
+        fade_decay: (-1.0 / (FADE_SECONDS * sample_rate as f32)).exp(),
+        resampler,
+        buffering: true,
+        level: 0.0,
+        chunk: vec![0.0; CHUNK_FRAMES * channels],
+        chunk_len: 0,
+        pending: Vec::with_capacity(CHUNK_FRAMES * channels * 2),
+        pending_pos: 0,
+        last_frame: vec![0.0; channels],
+    };
+    (writer, reader, JitterMonitor { shared })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+    /// 每次输出回调读取 10 ms
+    const BLOCK: usize = 160;
+
+    fn config(concealment: Concealment, drift_correction: bool) -> JitterConfig {
+        JitterConfig {
+            target_ms: 10,
+            max_ms: 100,
+            concealment,
+            drift_correction,
+        }
+    }
+
+    #[test]
+    fn underrun_conceals_and_rebuffers() {
+        let (mut writer, mut reader, monitor) = channel(SAMPLE_RATE, 1, &config(Concealment::Fade, false));
+        let mut output = [1.0f32; BLOCK];
+
+        // 未达到目标水位前输出静音
+        writer.write(&[0.5; BLOCK / 2]);
+        reader.read(&mut output);
+        assert!(output.iter().all(|&sample| sample == 0.0));
+
+        writer.write(&[0.5; BLOCK / 2]);
+        reader.read(&mut output);
+        assert!(output.iter().all(|&sample| sample == 0.5));
+        assert_eq!(monitor.stats().underruns, 0);
+
+        // 数据耗尽：计一次欠载，从最后一个样本开始淡出
+        reader.read(&mut output);
+        assert_eq!(monitor.stats().underruns, 1);
+        assert!(output[0] < 0.5 && output[0] > 0.4, "淡出应从上一帧平滑开始: {}", output[0]);
+        assert!(output.windows(2).all(|pair| pair[1] < pair[0]));
+
+        // 欠载后重新缓冲到目标水位才恢复输出，期间不重复计欠载
+        writer.write(&[0.25; BLOCK / 2]);
+        reader.read(&mut output);
+        assert!(output.iter().all(|&sample| sample == 0.0));
+        writer.write(&[0.25; BLOCK / 2]);
+        reader.read(&mut output);
+        assert!(output.iter().all(|&sample| sample == 0.25));
+        assert_eq!(monitor.stats().underruns, 1);
+        assert_eq!(monitor.stats().overruns, 0);
+    }
+
+    #[test]
+    fn silence_concealment_fills_remaining_block() {
+        let (mut writer, mut reader, monitor) = channel(SAMPLE_RATE, 2, &config(Concealment::Silence, false));
+        writer.write(&[0.5; BLOCK * 2]);
+        let mut output = [1.0f32; BLOCK * 3];
+        reader.read(&mut output);
+        assert!(output[..BLOCK * 2].iter().all(|&sample| sample == 0.5));
+        assert!(output[BLOCK * 2..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(monitor.stats().underruns, 1);
+    }
+
+    #[test]
+    fn backlog_above_max_is_dropped_to_target() {
+        let (mut writer, mut reader, monitor) = channel(SAMPLE_RATE, 1, &config(Concealment::Silence, false));
+        // 上限 100 ms = 1600 帧，环形缓冲区留有一倍余量
+        writer.write(&[0.5; 2000]);
+        assert_eq!(monitor.stats().overruns, 0);
+
+        let mut output = [0.0f32; BLOCK];
+        reader.read(&mut output);
+        let stats = monitor.stats();
+        assert_eq!(stats.overruns, 1);
+        assert_eq!(stats.level_frames, BLOCK, "应回落到 10 ms 的目标水位");
+        assert!(output.iter().all(|&sample| sample == 0.5));
+
+        // 读取端长时间不读时写入端丢弃放不下的帧
+        writer.write(&[0.5; 4000]);
+        assert_eq!(monitor.stats().overruns, 2);
+    }
+
+    /// 写入端时钟比读取端快 `ppm`，模拟运行 `seconds` 秒
+    fn run_with_drift(drift_correction: bool, ppm: f64, seconds: usize) -> JitterStats {
+        let (mut writer, mut reader, monitor) = channel(SAMPLE_RATE, 1, &config(Concealment::Silence, drift_correction));
+        let input = [0.1f32; BLOCK * 2];
+        let mut output = [0.0f32; BLOCK];
+        let frames_per_block = BLOCK as f64 * (1.0 + ppm / 1_000_000.0);
+        let mut owed = 0.0;
+
+        // 先写满目标水位
+        writer.write(&input[..BLOCK]);
+        for _ in 0..seconds * SAMPLE_RATE as usize / BLOCK {
+            owed += frames_per_block;
+            let frames = owed as usize;
+            owed -= frames as f64;
+            writer.write(&input[..frames]);
+            reader.read(&mut output);
+        }
+        monitor.stats()
+    }
+
+    #[test]
+    fn drift_correction_holds_level_below_limit() {
+        // 1500 ppm 持续 90 秒累积约 2160 帧，超过 100 ms (1600 帧) 的上限
+        let uncorrected = run_with_drift(false, 1500.0, 90);
+        assert!(uncorrected.overruns > 0, "不补偿时缓冲应当溢出: {:?}", uncorrected);
+
+        let corrected = run_with_drift(true, 1500.0, 90);
+        assert_eq!(corrected.overruns, 0, "{:?}", corrected);
+        assert_eq!(corrected.underruns, 0, "{:?}", corrected);
+        // 写入端偏快，读取端应加快消耗（负值），且偏差接近实际漂移
+        assert!(
+            (-2000..=-1000).contains(&corrected.drift_ppm),
+            "漂移补偿量不符: {:?}",
+            corrected
+        );
+        assert!(corrected.level_frames < 1600, "{:?}", corrected);
+    }
+}
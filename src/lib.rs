@@ -0,0 +1,32 @@
+pub mod aec;
+pub mod agc;
+pub mod alloc_check;
+pub mod asr;
+pub mod audio_io;
+pub mod backend;
+pub mod biquad;
+pub mod caption_server;
+pub mod channels;
+pub mod config;
+pub mod config_watcher;
+pub mod cpal_backend;
+pub mod denoise;
+pub mod dynamics;
+pub mod equalizer;
+pub mod file_backend;
+pub mod jitter_buffer;
+pub mod live_chain;
+pub mod loudness;
+pub mod offline;
+pub mod processor;
+pub mod registry;
+pub mod resampler;
+pub mod ring_buffer;
+pub mod sample_format;
+pub mod subtitle;
+pub mod supervisor;
+pub mod translate;
+pub mod tts;
+pub mod vad;
+pub mod virtual_backend;
+pub mod wav;
@@ -0,0 +1,272 @@
+use anyhow::{bail, Result};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use crate::processor::{AudioProcessor, ProcessContext, ProcessorChain};
+use crate::sample_format::CALLBACK_FRAMES;
+
+/// 新旧处理器链交叉淡化的时长 (秒)
+const CROSSFADE_SECONDS: f32 = 0.02;
+
+/// 运行中可以整体替换的处理器链
+///
+/// 新的处理器链在替换方线程中创建并 `prepare`，通过无锁通道交给音频回调，
+/// 回调在一次淡化时长内同时运行新旧两条链并交叉淡化，避免参数突变产生爆音。
+/// 旧链通过通道交还给替换方释放，通道已满时留在预留的位置，下一次回调再交还，回调中不分配也不释放内存。
+/// 淡化期间再次替换时，新链排队等当前淡化结束（并且旧链已交还）后开始。
+pub struct LiveChain {
+    current: ProcessorChain,
+    /// 正在淡入的新链
+    next: Option<ProcessorChain>,
+    /// 淡化进度（已淡化的帧数）
+    fade_position: usize,
+    fade_frames: usize,
+    channels: usize,
+    sample_rate: u32,
+    /// 通过 `process` 调用时的帧计数
+    frame: u64,
+    /// 新链的输入副本
+    scratch: Vec<f32>,
+    pending: Receiver<ProcessorChain>,
+    retired: Sender<ProcessorChain>,
+    /// 还没有交还的旧链
+    retiring: Option<ProcessorChain>,
+    format: Arc<Mutex<Option<(u32, u16)>>>,
+}
+
+/// 替换 [`LiveChain`] 中处理器链的句柄
+#[derive(Clone)]
+pub struct LiveChainHandle {
+    pending: Sender<ProcessorChain>,
+    retired: Receiver<ProcessorChain>,
+    format: Arc<Mutex<Option<(u32, u16)>>>,
+}
+
+impl LiveChain {
+    pub fn new(chain: ProcessorChain) -> (Self, LiveChainHandle) {
+        let (pending_sender, pending) = crossbeam_channel::bounded(1);
+        // 每次替换最多退回一条旧链，留出余量
+        let (retired, retired_receiver) = crossbeam_channel::bounded(4);
+        let format = Arc::new(Mutex::new(None));
+        let live = Self {
+            current: chain,
+            next: None,
+            fade_position: 0,
+            fade_frames: 1,
+            channels: 1,
+            sample_rate: 48000,
+            frame: 0,
+            scratch: Vec::new(),
+            pending,
+            retired,
+            retiring: None,
+            format: format.clone(),
+        };
+        let handle = LiveChainHandle {
+            pending: pending_sender,
+            retired: retired_receiver,
+            format,
+        };
+        (live, handle)
+    }
+
+    /// 把等待交还的旧链交给替换方，通道满时留到下一次再试
+    fn flush_retired(&mut self) {
+        if let Some(chain) = self.retiring.take() {
+            match self.retired.try_send(chain) {
+                Ok(()) => {}
+                Err(TrySendError::Full(chain) | TrySendError::Disconnected(chain)) => self.retiring = Some(chain),
+            }
+        }
+    }
+}
+
+impl AudioProcessor for LiveChain {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        let mut context = ProcessContext::new(buffer, self.channels as u16, self.sample_rate, self.frame);
+        self.process_context(&mut context)
+    }
+
+    fn process_context(&mut self, context: &mut ProcessContext) -> Result<()> {
+        self.frame = context.frame + context.frames() as u64;
+        self.flush_retired();
+        // 上一条旧链交还之前不开始新的淡化，预留的位置只放得下一条
+        if self.next.is_none() && self.retiring.is_none() {
+            if let Ok(chain) = self.pending.try_recv() {
+                self.next = Some(chain);
+                self.fade_position = 0;
+            }
+        }
+        let Some(next) = &mut self.next else {
+            return self.current.process_context(context);
+        };
+
+        // 分块处理，每块不超过预先分配的副本缓冲区
+        let block_frames = (self.scratch.len() / self.channels).max(1);
+        let frames = context.frames();
+        let mut start = 0;
+        while start < frames {
+            let len = (frames - start).min(block_frames);
+            let mut block = context.split(start, len);
+            let scratch = &mut self.scratch[..block.main.len()];
+            scratch.copy_from_slice(block.main);
+            next.process_context(&mut block.with_main(scratch))?;
+            self.current.process_context(&mut block)?;
+            for (frame, next_frame) in block.main.chunks_mut(self.channels).zip(scratch.chunks(self.channels)) {
+                let gain = (self.fade_position as f32 / self.fade_frames as f32).min(1.0);
+                for (sample, &next_sample) in frame.iter_mut().zip(next_frame) {
+                    *sample += (next_sample - *sample) * gain;
+                }
+                self.fade_position += 1;
+            }
+            start += len;
+        }
+
+        if self.fade_position >= self.fade_frames {
+            if let Some(next) = self.next.take() {
+                self.retiring = Some(std::mem::replace(&mut self.current, next));
+                self.flush_retired();
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "可替换处理器链"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        // 流启动前直接切换到最新的链，不需要淡化；不在音频回调中，旧链直接释放
+        self.retiring = None;
+        if let Some(next) = self.next.take().or_else(|| self.pending.try_recv().ok()) {
+            self.current = next;
+        }
+        self.channels = channels.max(1) as usize;
+        self.sample_rate = sample_rate;
+        self.frame = 0;
+        self.fade_frames = ((sample_rate as f32 * CROSSFADE_SECONDS) as usize).max(1);
+        self.scratch = vec![0.0; CALLBACK_FRAMES * self.channels];
+        self.current.prepare(sample_rate, channels);
+        if let Ok(mut format) = self.format.lock() {
+            *format = Some((sample_rate, channels));
+        }
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.current.latency_frames()
+    }
+}
+
+impl LiveChainHandle {
+    /// 用新的处理器链替换当前链，音频回调在下一次调用时开始交叉淡化
+    pub fn replace(&self, mut chain: ProcessorChain) -> Result<()> {
+        // 释放之前替换下来的旧链
+        self.retired.try_iter().for_each(drop);
+        if let Some((sample_rate, channels)) = self.format.lock().ok().and_then(|format| *format) {
+            chain.prepare(sample_rate, channels);
+        }
+        match self.pending.try_send(chain) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => bail!("上一次替换的处理器链尚未生效"),
+            Err(TrySendError::Disconnected(_)) => bail!("音频流已停止"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::GainProcessor;
+
+    fn gain_chain(gain: f32) -> ProcessorChain {
+        let mut chain = ProcessorChain::new();
+        chain.add_processor(Box::new(GainProcessor::new(gain)));
+        chain
+    }
+
+    /// 直流输入经过链后的输出
+    fn run(live: &mut LiveChain, frames: usize) -> Vec<f32> {
+        let mut buffer = vec![1.0; frames];
+        live.process(&mut buffer).unwrap();
+        buffer
+    }
+
+    /// 从 1 倍增益的链替换到 0 倍增益的链，输出就是新链的淡入增益的补
+    fn fade_gains(live: &mut LiveChain, frames: usize) -> Vec<f32> {
+        run(live, frames).iter().map(|&sample| 1.0 - sample).collect()
+    }
+
+    #[test]
+    fn crossfade_is_linear_over_fade_length() {
+        let (mut live, handle) = LiveChain::new(gain_chain(1.0));
+        live.prepare(48_000, 1);
+        handle.replace(gain_chain(0.0)).unwrap();
+        // 20 ms 内新链的增益从 0 线性升到 1，分多次回调处理时保持连续
+        let gains = [fade_gains(&mut live, 500), fade_gains(&mut live, 500)].concat();
+        for (n, &gain) in gains.iter().enumerate() {
+            let expected = (n as f32 / 960.0).min(1.0);
+            assert!((gain - expected).abs() < 1e-6, "第 {} 帧新链增益 {}，应为 {}", n, gain, expected);
+        }
+        assert!(live.next.is_none());
+
+        // 新旧两条链输出相同时，淡化期间输出不变（两路增益之和为 1）
+        handle.replace(gain_chain(0.0)).unwrap();
+        assert!(run(&mut live, 2000).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn old_chain_is_returned_through_retired() {
+        let (mut live, handle) = LiveChain::new(gain_chain(1.0));
+        live.prepare(48_000, 2);
+        handle.replace(gain_chain(0.5)).unwrap();
+        run(&mut live, 2000);
+        // 交还的是原来的 1 倍增益链，并已按流的格式准备好
+        let mut old = handle.retired.try_recv().unwrap();
+        let mut buffer = [0.25f32; 4];
+        old.process(&mut buffer).unwrap();
+        assert_eq!(buffer, [0.25; 4]);
+        assert!(handle.retired.try_recv().is_err());
+        assert_eq!(run(&mut live, 4), [0.5; 4]);
+    }
+
+    #[test]
+    fn replacement_during_fade_is_queued() {
+        let (mut live, handle) = LiveChain::new(gain_chain(1.0));
+        live.prepare(48_000, 1);
+        handle.replace(gain_chain(0.5)).unwrap();
+        run(&mut live, 480);
+        // 淡化到一半时再次替换：排队等待，第三次替换被拒绝
+        handle.replace(gain_chain(0.25)).unwrap();
+        let error = handle.replace(gain_chain(0.0)).unwrap_err();
+        assert_eq!(error.to_string(), "上一次替换的处理器链尚未生效");
+        // 第一次淡化不受影响，完成后才开始第二次
+        let output = run(&mut live, 480);
+        assert!((output[479] - 0.5).abs() < 0.001);
+        let output = run(&mut live, 960);
+        assert!(output[0] == 0.5 && output[1] < 0.5 && output[959] > 0.25);
+        assert_eq!(run(&mut live, 4), [0.25; 4]);
+        assert_eq!(handle.retired.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn retired_chain_waits_when_channel_is_full() {
+        let (mut live, handle) = LiveChain::new(gain_chain(1.0));
+        live.prepare(48_000, 1);
+        handle.replace(gain_chain(0.5)).unwrap();
+        // 替换方一直没有取走旧链，通道已满
+        while live.retired.try_send(ProcessorChain::new()).is_ok() {}
+        run(&mut live, 2000);
+        // 旧链留在预留的位置，不在回调中释放；此时新的替换排队等待
+        assert!(live.retiring.is_some());
+        handle.pending.try_send(gain_chain(0.0)).unwrap();
+        assert_eq!(run(&mut live, 4), [0.5; 4]);
+        assert!(live.next.is_none());
+
+        // 替换方取走旧链后，下一次回调交还并开始新的淡化
+        let drained = handle.retired.try_iter().count();
+        assert_eq!(drained, 4);
+        run(&mut live, 4);
+        assert!(live.retiring.is_none() && live.next.is_some());
+        assert_eq!(handle.retired.try_iter().count(), 1);
+    }
+}
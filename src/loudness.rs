@@ -0,0 +1,240 @@
+use std::f64::consts::PI;
+
+use crate::biquad::{Biquad, Coefficients};
+
+/// 门限以下的块不计入积分响度 (LUFS)
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// 相对门限：低于未加相对门限的积分响度该值的块不计入 (LU)
+const RELATIVE_GATE: f64 = -10.0;
+
+/// 每个测量块由 4 个 100 ms 子块组成（400 ms，重叠 75%）
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+
+/// ITU-R BS.1770 K 加权滤波器（高频搁架 + 高通），系数按采样率计算
+#[derive(Debug, Clone, Copy)]
+pub struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    pub fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(Coefficients {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        });
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad::new(Coefficients {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        });
+
+        Self { shelf, highpass }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.highpass.process(self.shelf.process(sample as f64)) as f32
+    }
+
+    pub fn reset(&mut self) {
+        self.shelf.reset();
+        self.highpass.reset();
+    }
+}
+
+/// 把 K 加权后的均方值换算为响度 (LUFS)
+pub fn loudness_from_power(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(1e-20).log10()
+}
+
+/// 线性幅度换算为 dBFS
+pub fn amplitude_to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.max(1e-10).log10()
+}
+
+/// dB 换算为线性幅度
+pub fn db_to_amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// 音频电平统计
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReport {
+    /// 采样峰值 (dBFS)
+    pub peak_db: f64,
+    /// 所有声道的均方根电平 (dBFS)
+    pub rms_db: f64,
+    /// BS.1770 积分响度 (LUFS)，音频太短或全部低于绝对门限时为 None
+    pub integrated_lufs: Option<f64>,
+}
+
+/// 按 ITU-R BS.1770 测量峰值、RMS 和积分响度
+///
+/// 各声道权重相同（不区分环绕声道），输入为交错排列的采样，可以分多次送入。
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<KWeighting>,
+    sub_block_frames: usize,
+    sub_block_position: usize,
+    /// 当前子块各声道 K 加权平方和之和
+    sub_block_energy: f64,
+    /// 已完成的子块均方值
+    sub_blocks: Vec<f64>,
+    peak: f32,
+    sum_squares: f64,
+    samples: u64,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            filters: vec![KWeighting::new(sample_rate); channels],
+            sub_block_frames: (sample_rate as usize / 10).max(1),
+            sub_block_position: 0,
+            sub_block_energy: 0.0,
+            sub_blocks: Vec::new(),
+            peak: 0.0,
+            sum_squares: 0.0,
+            samples: 0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (filter, &sample) in self.filters.iter_mut().zip(frame) {
+                self.peak = self.peak.max(sample.abs());
+                self.sum_squares += sample as f64 * sample as f64;
+                let weighted = filter.process(sample) as f64;
+                self.sub_block_energy += weighted * weighted;
+            }
+            self.samples += self.channels as u64;
+            self.sub_block_position += 1;
+            if self.sub_block_position == self.sub_block_frames {
+                self.sub_blocks.push(self.sub_block_energy / self.sub_block_frames as f64);
+                self.sub_block_position = 0;
+                self.sub_block_energy = 0.0;
+            }
+        }
+    }
+
+    /// 目前为止的统计结果
+    pub fn report(&self) -> LoudnessReport {
+        let rms = if self.samples == 0 {
+            0.0
+        } else {
+            (self.sum_squares / self.samples as f64).sqrt()
+        };
+        LoudnessReport {
+            peak_db: amplitude_to_db(self.peak as f64),
+            rms_db: amplitude_to_db(rms),
+            integrated_lufs: self.integrated(),
+        }
+    }
+
+    fn integrated(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .sub_blocks
+            .windows(SUB_BLOCKS_PER_BLOCK)
+            .map(|window| window.iter().sum::<f64>() / SUB_BLOCKS_PER_BLOCK as f64)
+            .filter(|&power| loudness_from_power(power) > ABSOLUTE_GATE)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let mean = blocks.iter().sum::<f64>() / blocks.len() as f64;
+        let threshold = loudness_from_power(mean) + RELATIVE_GATE;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&power| loudness_from_power(power) > threshold)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(loudness_from_power(gated.iter().sum::<f64>() / gated.len() as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 kHz 立体声正弦，各段为 (峰值 dBFS, 秒)
+    fn stereo_sine(sample_rate: u32, segments: &[(f64, f64)]) -> Vec<f32> {
+        let mut samples = Vec::new();
+        let mut n = 0u64;
+        for &(level_db, seconds) in segments {
+            let amplitude = db_to_amplitude(level_db);
+            for _ in 0..(seconds * sample_rate as f64) as usize {
+                let value = (amplitude * (2.0 * PI * 1000.0 * n as f64 / sample_rate as f64).sin()) as f32;
+                samples.extend([value, value]);
+                n += 1;
+            }
+        }
+        samples
+    }
+
+    fn measure(sample_rate: u32, samples: &[f32]) -> LoudnessReport {
+        let mut meter = LoudnessMeter::new(sample_rate, 2);
+        // 分多次送入，块边界与子块边界不对齐
+        for block in samples.chunks(2 * 1000 + 2) {
+            meter.process(block);
+        }
+        meter.report()
+    }
+
+    fn assert_lufs(report: LoudnessReport, expected: f64) {
+        let lufs = report.integrated_lufs.expect("应当有积分响度");
+        // EBU Tech 3341 允许 ±0.1 LU
+        assert!((lufs - expected).abs() < 0.1, "积分响度 {:.2} LUFS，期望 {}", lufs, expected);
+    }
+
+    #[test]
+    fn reference_tone_measures_minus_23_lufs() {
+        // EBU Tech 3341 测试 1、2：-23 / -33 dBFS 的 1 kHz 立体声正弦
+        for sample_rate in [44_100, 48_000] {
+            assert_lufs(measure(sample_rate, &stereo_sine(sample_rate, &[(-23.0, 20.0)])), -23.0);
+        }
+        assert_lufs(measure(48_000, &stereo_sine(48_000, &[(-33.0, 20.0)])), -33.0);
+
+        let report = measure(48_000, &stereo_sine(48_000, &[(-23.0, 5.0)]));
+        assert!((report.peak_db + 23.0).abs() < 0.01);
+        // 正弦的有效值比峰值低 3 dB
+        assert!((report.rms_db + 26.01).abs() < 0.01, "{:?}", report);
+    }
+
+    #[test]
+    fn gates_exclude_quiet_passages() {
+        // EBU Tech 3341 测试 3：-36 dBFS 的段落低于相对门限，不计入
+        let samples = stereo_sine(48_000, &[(-36.0, 10.0), (-23.0, 20.0), (-36.0, 10.0)]);
+        assert_lufs(measure(48_000, &samples), -23.0);
+        // EBU Tech 3341 测试 5：-26 / -20 / -26 dBFS 都在门限以内，按能量平均
+        let samples = stereo_sine(48_000, &[(-26.0, 20.0), (-20.0, 20.1), (-26.0, 20.0)]);
+        assert_lufs(measure(48_000, &samples), -23.0);
+        // 低于 -70 LUFS 的绝对门限时没有积分响度
+        let samples = stereo_sine(48_000, &[(-80.0, 5.0)]);
+        assert_eq!(measure(48_000, &samples).integrated_lufs, None);
+        // 不足一个 400 ms 测量块
+        let samples = stereo_sine(48_000, &[(-23.0, 0.3)]);
+        assert_eq!(measure(48_000, &samples).integrated_lufs, None);
+    }
+}
@@ -1,18 +1,45 @@
-mod audio_io;
-mod processor;
-mod config;
-
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use dialoguer::{theme::ColorfulTheme, Select};
-use log::info;
-use std::sync::Arc;
+use crossbeam_channel::RecvTimeoutError;
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use audio_io::AudioStream;
-use processor::ProcessorChain;
+use trans::aec::{EchoCanceller, EchoMonitor, EchoReference};
+use trans::audio_io::{AudioTap, Direction, StreamHooks};
+use trans::backend::{AudioBackend, RoutedBackend};
+use trans::caption_server::{CaptionEvent, CaptionServer};
+use trans::config::{self, AsrTap};
+use trans::config_watcher::{self, ChainChange};
+use trans::equalizer::EqualizerSettings;
+use trans::cpal_backend::CpalBackend;
+use trans::file_backend::FileBackend;
+use trans::jitter_buffer::JitterStats;
+use trans::live_chain::LiveChain;
+use trans::processor::ProcessorChain;
+use trans::registry::{self, ChainControls, ParameterUpdate, ProcessorRegistry};
+use trans::supervisor::{RunningStream, StreamEvent};
+use trans::vad::VadProcessor;
+use trans::{asr, offline, subtitle, translate, tts};
 use cpal::traits::{DeviceTrait, HostTrait};
 
+#[cfg(feature = "alloc-check")]
+#[global_allocator]
+static ALLOCATOR: trans::alloc_check::CountingAllocator = trans::alloc_check::CountingAllocator;
+
+/// 语音识别旁路最多缓存的采样数（48 kHz 立体声约 10 秒）
+const TAP_CAPACITY: usize = 48000 * 2 * 10;
+
+/// 主循环检查配置文件和设备状态的间隔
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 调试日志中输出处理器测量值（增益衰减量等）的间隔
+const METER_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
 // 获取系统默认输入设备
 fn get_default_input_device() -> Option<String> {
     let host = cpal::default_host();
@@ -42,7 +69,11 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// 运行音频处理程序
-    Run,
+    Run {
+        /// 将字幕（SRT 和 WebVTT）实时写入该目录
+        #[arg(long, value_name = "DIR")]
+        subtitles: Option<PathBuf>,
+    },
     /// 交互式配置向导
     Config,
     /// 检查音频设备
@@ -51,6 +82,27 @@ enum Commands {
     ListDevices,
     /// 显示设备详细信息（格式、采样率等）
     DeviceInfo,
+    /// 用处理器链离线处理 WAV 文件，并对比处理前后的电平
+    Process {
+        /// 处理器链：预设（passthrough、gate、boost、voice、level），input/output 使用 config.toml 中的处理器链，
+        /// 或逗号分隔的处理器列表，如 "noise_gate:threshold_db=-34,gain:gain_db=3.5"、
+        /// "eq:bands=[{filter=\"high_pass\",frequency=80}]"
+        #[arg(long, default_value = "passthrough")]
+        chain: String,
+        /// 输入 WAV 文件
+        input: PathBuf,
+        /// 输出 WAV 文件
+        output: PathBuf,
+    },
+    /// 打印处理器链中均衡器的频率响应
+    Response {
+        /// 处理器链，写法与 process 命令相同，默认使用 config.toml 中的 [[output_chain]]
+        #[arg(long, default_value = "output")]
+        chain: String,
+        /// 计算响应使用的采样率 (Hz)
+        #[arg(long, default_value_t = 48000)]
+        sample_rate: u32,
+    },
 }
 
 fn list_devices() -> Result<(Vec<String>, Vec<String>)> {
@@ -144,10 +196,9 @@ fn interactive_config() -> Result<()> {
     let mic_index = Select::with_theme(&ColorfulTheme::default())
         .items(&mic_items)
         .default(default_mic_index)
-        .with_prompt(if default_mic.is_some() {
-            format!("当前系统默认: {}", default_mic.unwrap())
-        } else {
-            "选择麦克风".to_string()
+        .with_prompt(match &default_mic {
+            Some(name) => format!("当前系统默认: {}", name),
+            None => "选择麦克风".to_string(),
         })
         .interact()?;
     let input_device = physical_input_devices[mic_index].clone();
@@ -175,10 +226,9 @@ fn interactive_config() -> Result<()> {
     let speaker_index = Select::with_theme(&ColorfulTheme::default())
         .items(&speaker_items)
         .default(default_speaker_index)
-        .with_prompt(if default_speaker.is_some() {
-            format!("当前系统默认: {}", default_speaker.unwrap())
-        } else {
-            "选择扬声器".to_string()
+        .with_prompt(match &default_speaker {
+            Some(name) => format!("当前系统默认: {}", name),
+            None => "选择扬声器".to_string(),
         })
         .interact()?;
     let output_device = physical_output_devices[speaker_index].clone();
@@ -213,16 +263,44 @@ fn interactive_config() -> Result<()> {
     let vbcable_output = if available_vbcable_outputs.is_empty() {
         // 如果只有一个虚拟设备，使用同一个
         println!("   ℹ️  只有一个虚拟设备，将同时用于输入和输出");
-        vbcable_a_output.clone()
+        *vbcable_a_output
     } else {
         let items: Vec<&str> = available_vbcable_outputs.iter().map(|s| s.as_str()).collect();
         let index = Select::with_theme(&ColorfulTheme::default())
             .items(&items)
             .default(0)
             .interact()?;
-        available_vbcable_outputs[index].clone()
+        *available_vbcable_outputs[index]
     };
 
+    // 选择麦克风处理器链
+    println!("\n🎛️  选择麦克风处理（之后可在 config.toml 的 [[input_chain]] 中调整）:");
+    let preset_items: Vec<String> = registry::PRESETS
+        .iter()
+        .map(|(name, description)| format!("{} - {}", name, description))
+        .collect();
+    let preset_index = Select::with_theme(&ColorfulTheme::default())
+        .items(&preset_items)
+        .default(0)
+        .interact()?;
+
+    // 选择对方声音处理器链，level 可以统一不同发言者的音量
+    println!("\n🔈 选择对方声音处理（之后可在 config.toml 的 [[output_chain]] 中调整）:");
+    let output_preset_index = Select::with_theme(&ColorfulTheme::default())
+        .items(&preset_items)
+        .default(0)
+        .interact()?;
+    let mut chains = toml::Table::new();
+    chains.insert(
+        "input_chain".to_string(),
+        toml::Value::try_from(registry::preset(registry::PRESETS[preset_index].0)?)?,
+    );
+    chains.insert(
+        "output_chain".to_string(),
+        toml::Value::try_from(registry::preset(registry::PRESETS[output_preset_index].0)?)?,
+    );
+    let chains_str = toml::to_string(&chains)?;
+
     // 保存配置
     let config_str = format!(
         r#"# 音频设备配置 - 全双工音频处理程序
@@ -268,8 +346,13 @@ output_device_name = "{}"
 # ========================================
 sample_rate = 48000  # 采样率 (Hz)
 buffer_size = 512    # 缓冲区大小 (帧)
-"#,
-        input_device, vbcable_input, vbcable_output, output_device
+
+# ========================================
+# 处理器链
+# ========================================
+# 按顺序执行，可用类型见 config.toml.example
+{}"#,
+        input_device, vbcable_input, vbcable_output, output_device, chains_str
     );
 
     std::fs::write("config.toml", config_str)?;
@@ -352,6 +435,183 @@ fn show_device_info() -> Result<()> {
     Ok(())
 }
 
+/// 为指定方向启动语音识别线程，返回接入音频流的旁路和识别线程句柄
+///
+/// 启用 VAD 时会在该方向的处理器链末尾加入语音活动检测，为识别提供语句边界。
+fn start_recognition(
+    config: &config::AudioConfig,
+    direction: Direction,
+    chain: &mut ProcessorChain,
+    events: &crossbeam_channel::Sender<asr::TranscriptEvent>,
+) -> Result<(AudioTap, JoinHandle<()>)> {
+    let boundaries = if config.vad.enabled {
+        let (vad, boundaries) = VadProcessor::with_channel(config.vad.clone());
+        chain.add_processor(Box::new(vad));
+        Some(boundaries)
+    } else {
+        None
+    };
+
+    let (tap, frames) = AudioTap::bounded(TAP_CAPACITY);
+    let recognizer = asr::build_recognizer(config, direction);
+    let worker = asr::spawn_worker(direction, recognizer, frames, boundaries, events.clone())?;
+    Ok((tap, worker))
+}
+
+/// 识别和翻译结果的下游
+#[derive(Clone, Default)]
+struct CaptionSinks {
+    /// 字幕文件
+    subtitles: Option<crossbeam_channel::Sender<subtitle::SubtitleCue>>,
+    /// 浏览器字幕叠加层
+    server: Option<CaptionServer>,
+    /// 译文的语音合成，按语音的方向
+    speech: HashMap<Direction, tts::SpeechQueue>,
+}
+
+/// 启动翻译线程，返回用于提交最终识别结果的发送端
+///
+/// 译文按方向提交给语音合成（我方语音的译文注入 CABLE-A，对方语音的译文在扬声器播放），
+/// 所有译文写入字幕并推送到字幕服务器。
+fn start_translation(
+    config: &config::AudioConfig,
+    sinks: CaptionSinks,
+) -> Result<crossbeam_channel::Sender<asr::TranscriptEvent>> {
+    let mut translators = HashMap::new();
+    if config.translation.local_to_remote {
+        translators.insert(Direction::Input, translate::build_translator(config, Direction::Input)?);
+    }
+    if config.translation.remote_to_local {
+        translators.insert(Direction::Output, translate::build_translator(config, Direction::Output)?);
+    }
+
+    let (final_sender, final_receiver) = crossbeam_channel::unbounded();
+    let (translated_sender, translated_receiver) = crossbeam_channel::unbounded::<translate::TranslatedSegment>();
+    translate::spawn_worker(
+        translators,
+        final_receiver,
+        translated_sender,
+        Duration::from_millis(config.translation.batch_window_ms),
+        config.translation.max_batch,
+    )?;
+
+    std::thread::spawn(move || {
+        for segment in translated_receiver {
+            info!(
+                "[{}] {} → {}",
+                segment.direction.label(),
+                segment.source_text,
+                segment.text.green()
+            );
+            if let Some(sender) = &sinks.subtitles {
+                let _ = sender.send(subtitle::SubtitleCue::from(&segment));
+            }
+            if let Some(server) = &sinks.server {
+                server.broadcast(&CaptionEvent::from(&segment));
+            }
+            if let Some(speech) = sinks.speech.get(&segment.direction) {
+                speech.speak(segment.text);
+            }
+        }
+    });
+
+    Ok(final_sender)
+}
+
+/// 离线处理 WAV 文件并打印处理前后的电平
+/// 按 `--chain` 参数取得处理器链配置并创建处理器链
+fn resolve_chain(chain: &str) -> Result<(Vec<config::ProcessorConfig>, ProcessorChain)> {
+    let configs = match chain {
+        "input" => config::AudioConfig::load_or_default()?.input_chain,
+        "output" => config::AudioConfig::load_or_default()?.output_chain,
+        spec => registry::preset(spec)?,
+    };
+    let registry = ProcessorRegistry::with_builtin();
+    let processors = registry.build_chain(chain, &configs).map_err(|e| {
+        let presets: Vec<String> = registry::PRESETS
+            .iter()
+            .map(|(name, description)| format!("{}（{}）", name, description))
+            .collect();
+        let kinds: Vec<String> = registry
+            .kinds()
+            .map(|(kind, description)| format!("{}（{}）", kind, description))
+            .collect();
+        e.context(format!("可用预设: {}\n可用处理器: {}", presets.join("、"), kinds.join("、")))
+    })?;
+    Ok((configs, processors))
+}
+
+fn process_file(chain: &str, input: &std::path::Path, output: &std::path::Path) -> Result<()> {
+    let (_, mut processors) = resolve_chain(chain)?;
+    let report = offline::process_file(input, output, &mut processors)?;
+
+    let lufs = |value: Option<f64>| value.map_or("-".to_string(), |lufs| format!("{:.1}", lufs));
+    println!("{} → {}", input.display(), output.display());
+    println!(
+        "  {} Hz，{} 声道，时长 {:.1} 秒，用时 {:.2} 秒（{:.0} 倍实时）",
+        report.sample_rate,
+        report.channels,
+        report.duration().as_secs_f64(),
+        report.elapsed.as_secs_f64(),
+        report.duration().as_secs_f64() / report.elapsed.as_secs_f64().max(1e-6)
+    );
+    if report.latency_frames > 0 {
+        println!(
+            "  算法延迟 {} 帧（{:.1} ms），输出已对齐",
+            report.latency_frames,
+            report.latency_frames as f64 * 1000.0 / report.sample_rate as f64
+        );
+    }
+    // 标签放在最后，避免中文宽度影响对齐
+    println!("    处理前   处理后");
+    println!("  {:>8.1} {:>8.1}  峰值 (dBFS)", report.input.peak_db, report.output.peak_db);
+    println!("  {:>8.1} {:>8.1}  RMS (dBFS)", report.input.rms_db, report.output.rms_db);
+    println!(
+        "  {:>8} {:>8}  响度 (LUFS)",
+        lufs(report.input.integrated_lufs),
+        lufs(report.output.integrated_lufs)
+    );
+    Ok(())
+}
+
+/// 打印处理器链中均衡器和增益的频率响应（1/3 倍频程）
+fn print_response(chain: &str, sample_rate: u32) -> Result<()> {
+    let (configs, _) = resolve_chain(chain)?;
+    let mut equalizers = Vec::new();
+    let mut gain_db = 0.0;
+    let mut skipped = Vec::new();
+    for config in &configs {
+        match config.kind.as_str() {
+            "eq" => equalizers.push(registry::parse::<EqualizerSettings>(&config.params)?),
+            "gain" => gain_db += config.params.get("gain_db").and_then(toml::Value::as_float).unwrap_or(0.0),
+            "passthrough" => {}
+            other => skipped.push(other),
+        }
+    }
+
+    println!("处理器链: {}（{} Hz）", registry::describe(&configs), sample_rate);
+    println!("  频率 (Hz)   响应 (dB)");
+    for band in 0..31 {
+        // ISO 1/3 倍频程中心频率 20 Hz ~ 20 kHz
+        let frequency = 1000.0 * 2f64.powf((band as f64 - 17.0) / 3.0);
+        if frequency >= sample_rate as f64 / 2.0 {
+            break;
+        }
+        let response = gain_db
+            + equalizers
+                .iter()
+                .map(|settings| settings.magnitude_db(frequency, sample_rate))
+                .sum::<f64>();
+        let bar = "█".repeat((response.abs() * 2.0).round().min(48.0) as usize);
+        let bar = if response < 0.0 { format!("-{}", bar) } else { format!("+{}", bar) };
+        println!("  {:>9.0}   {:>+9.2}  {}", frequency, response, bar);
+    }
+    if !skipped.is_empty() {
+        println!("未计入的非线性处理器: {}", skipped.join("、"));
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     env_logger::Builder::from_default_env()
         .filter_level(log::LevelFilter::Info)
@@ -359,7 +619,7 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    match cli.command {
+    let subtitles_dir = match cli.command {
         Some(Commands::Config) => {
             return interactive_config();
         }
@@ -381,32 +641,53 @@ fn main() -> Result<()> {
         Some(Commands::DeviceInfo) => {
             return show_device_info();
         }
-        Some(Commands::Run) | None => {
-            // 检查配置文件是否存在，如果不存在则自动运行配置向导
-            if !std::path::Path::new("config.toml").exists() {
-                println!("⚠️  {} 未找到配置文件", "config.toml".yellow());
-                println!("{} 正在启动配置向导...", "🚀".green());
-                println!();
-                interactive_config()?;
-                println!();
-                println!("{} 配置完成！正在启动程序...", "✅".green());
-                println!();
-            }
+        Some(Commands::Process { chain, input, output }) => {
+            return process_file(&chain, &input, &output);
         }
+        Some(Commands::Response { chain, sample_rate }) => {
+            return print_response(&chain, sample_rate);
+        }
+        Some(Commands::Run { subtitles }) => subtitles,
+        None => None,
+    };
+
+    // 检查配置文件是否存在，如果不存在则自动运行配置向导
+    if !std::path::Path::new("config.toml").exists() {
+        println!("⚠️  {} 未找到配置文件", "config.toml".yellow());
+        println!("{} 正在启动配置向导...", "🚀".green());
+        println!();
+        interactive_config()?;
+        println!();
+        println!("{} 配置完成！正在启动程序...", "✅".green());
+        println!();
     }
 
     info!("启动全双工音频处理程序...");
 
-    // 创建处理器链
-    let mut input_processor = ProcessorChain::new();
-    input_processor.add_processor(Box::new(processor::PassThroughProcessor));
-
-    let mut output_processor = ProcessorChain::new();
-    output_processor.add_processor(Box::new(processor::PassThroughProcessor));
-
     // 获取音频设备配置
     let config = config::AudioConfig::load_or_default()?;
 
+    // 按配置创建处理器链，运行中修改配置文件后在线调整参数或替换
+    let registry = ProcessorRegistry::with_builtin();
+    let (input_chain, input_controls) = registry.build_chain_with_controls("input_chain", &config.input_chain)?;
+    let (output_chain, output_controls) = registry.build_chain_with_controls("output_chain", &config.output_chain)?;
+    let (input_live, input_chain) = LiveChain::new(input_chain);
+    let (output_live, output_chain) = LiveChain::new(output_chain);
+    let mut input_processor = ProcessorChain::new();
+    // 回声消除放在最前面：参考信号是扬声器播放的声音，需要在其他处理改变麦克风信号之前消除
+    let mut echo_reference = None;
+    let mut echo_monitor = None;
+    if config.aec.enabled {
+        let (reference, source) = EchoReference::channel();
+        let canceller = EchoCanceller::new(config.aec.clone(), source);
+        echo_monitor = Some(canceller.monitor());
+        echo_reference = Some(reference);
+        input_processor.add_processor(Box::new(canceller));
+    }
+    input_processor.add_processor(Box::new(input_live));
+    let mut output_processor = ProcessorChain::new();
+    output_processor.add_processor(Box::new(output_live));
+
     info!("配置:");
     info!("╔════════════════════════════════════════════════════════════════╗");
     info!("║ 输入流（你说话）                                                ║");
@@ -423,33 +704,354 @@ fn main() -> Result<()> {
     info!("║   缓冲区大小: {} 帧", config.buffer_size);
     info!("╚════════════════════════════════════════════════════════════════╝");
 
+    // 语音识别旁路：识别线程在后台运行，不阻塞音频回调
+    let (transcript_sender, transcript_receiver) = crossbeam_channel::unbounded::<asr::TranscriptEvent>();
+    let mut input_tap = None;
+    let mut output_tap = None;
+    let mut recognition_workers = Vec::new();
+    if config.asr.enabled {
+        if matches!(config.asr.tap, AsrTap::Input | AsrTap::Both) {
+            let (tap, worker) = start_recognition(&config, Direction::Input, &mut input_processor, &transcript_sender)?;
+            input_tap = Some(tap);
+            recognition_workers.push(worker);
+        }
+        if matches!(config.asr.tap, AsrTap::Output | AsrTap::Both) {
+            let (tap, worker) = start_recognition(&config, Direction::Output, &mut output_processor, &transcript_sender)?;
+            output_tap = Some(tap);
+            recognition_workers.push(worker);
+        }
+    }
+    drop(transcript_sender);
+
+    let mut sinks = CaptionSinks::default();
+
+    // 语音合成：我方语音的译文合成后注入 CABLE-A，对方语音的译文在扬声器播放，
+    // 与原声混合时原声自动闪避
+    let mut input_injector = None;
+    let mut output_injector = None;
+    if config.tts.enabled {
+        for (direction, enabled) in [
+            (Direction::Input, config.tts.local_to_remote),
+            (Direction::Output, config.tts.remote_to_local),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let (queue, injector, _) = tts::spawn_queue(tts::build_synthesizer(&config, direction), &config.tts)?;
+            sinks.speech.insert(direction, queue);
+            match direction {
+                Direction::Input => input_injector = Some(injector),
+                Direction::Output => output_injector = Some(injector),
+            }
+        }
+    }
+
+    // 字幕：未翻译的方向写入原文，翻译的方向写入译文
+    if let Some(dir) = &subtitles_dir {
+        let writer = subtitle::SubtitleWriter::create(dir, config.subtitles.clone())?;
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        subtitle::spawn_writer(writer, receiver)?;
+        sinks.subtitles = Some(sender);
+    }
+
+    // 字幕服务器：推送中间结果、最终结果和译文
+    if config.caption_server.enabled {
+        sinks.server = Some(CaptionServer::start(&config.caption_server)?);
+    }
+
+    // 翻译：消费最终识别结果，翻译结果写入日志
+    let final_sender = if config.translation.enabled {
+        Some(start_translation(&config, sinks.clone())?)
+    } else {
+        None
+    };
+    let translated = |direction: Direction| {
+        config.translation.enabled
+            && match direction {
+                Direction::Input => config.translation.local_to_remote,
+                Direction::Output => config.translation.remote_to_local,
+            }
+    };
+    let translate_input = translated(Direction::Input);
+    let translate_output = translated(Direction::Output);
+
+    let dispatcher = std::thread::spawn(move || {
+        for event in transcript_receiver {
+            let segment = &event.segment;
+            match segment.kind {
+                asr::TranscriptKind::Partial => log::debug!(
+                    "[{}] {:.1}s … {}",
+                    event.direction.label(),
+                    segment.start.as_secs_f32(),
+                    segment.text
+                ),
+                asr::TranscriptKind::Final => info!(
+                    "[{}] {:.1}s-{:.1}s {}",
+                    event.direction.label(),
+                    segment.start.as_secs_f32(),
+                    segment.end.as_secs_f32(),
+                    segment.text.cyan()
+                ),
+            }
+            if let Some(server) = &sinks.server {
+                server.broadcast(&CaptionEvent::from(&event));
+            }
+            if event.segment.kind != asr::TranscriptKind::Final {
+                continue;
+            }
+            let is_translated = match event.direction {
+                Direction::Input => translate_input,
+                Direction::Output => translate_output,
+            };
+            if let (Some(sender), false) = (&sinks.subtitles, is_translated) {
+                let _ = sender.send(subtitle::SubtitleCue::from(&event));
+            }
+            if let Some(sender) = &final_sender {
+                let _ = sender.send(event);
+            }
+        }
+    });
+
+    // 名称以 file: 开头的设备读写 WAV 文件，其余为系统声卡
+    let backend = RoutedBackend::new(Box::new(CpalBackend::new()))
+        .with_route("file:", Box::new(FileBackend::new(config.file_devices.clone())));
+
+    // 设备断开、切换到备用设备等状态事件
+    let (stream_events, stream_event_receiver) = crossbeam_channel::unbounded::<StreamEvent>();
+
     // 启动输入流: 物理麦克风 -> 处理器 -> CABLE-A Input
     // 音频通过内部管道传到 CABLE-A Output，视频会议软件从 CABLE-A Output 读取
-    let _input_stream = AudioStream::create_duplex_stream(
-        &config.input_device_name,
-        &config.vbcable_input_name,
-        config.sample_rate,
-        config.buffer_size,
-        Arc::new(std::sync::Mutex::new(input_processor)),
-        true,
+    let input_stream = RunningStream::start(
+        &backend,
+        &config,
+        Direction::Input,
+        input_processor,
+        input_chain,
+        StreamHooks {
+            tap: input_tap,
+            injector: input_injector,
+            echo_reference: None,
+        },
+        stream_events.clone(),
     )?;
 
     // 启动输出流: CABLE Output -> 处理器 -> 物理扬声器
     // 视频会议软件输出到 CABLE Output，程序处理后传到物理扬声器
-    let _output_stream = AudioStream::create_duplex_stream(
-        &config.vbcable_output_name,
-        &config.output_device_name,
-        config.sample_rate,
-        config.buffer_size,
-        Arc::new(std::sync::Mutex::new(output_processor)),
-        false,
+    let output_stream = RunningStream::start(
+        &backend,
+        &config,
+        Direction::Output,
+        output_processor,
+        output_chain,
+        StreamHooks {
+            tap: output_tap,
+            injector: output_injector,
+            echo_reference,
+        },
+        stream_events,
     )?;
 
-    // 运行音频流（保持程序运行）
-    info!("音频流已启动，按 Ctrl+C 退出...");
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+    // 运行音频流（保持程序运行），配置文件修改后自动应用
+    let reloads = config_watcher::spawn(PathBuf::from(config::CONFIG_PATH))?;
+    info!("音频流已启动，按 Ctrl+C 退出；修改 {} 后自动生效...", config::CONFIG_PATH);
+    let mut current_config = config.clone();
+    let mut streams = [input_stream, output_stream];
+    let mut controls = [input_controls, output_controls];
+    let mut last_stats = [JitterStats::default(); 2];
+    let mut last_meter_log = Instant::now();
+    // 两个流的输入都是已读完的文件时退出；实时设备永远不会结束
+    while !streams.iter().all(RunningStream::is_finished) {
+        match reloads.recv_timeout(SUPERVISE_INTERVAL) {
+            Ok(Ok(new_config)) => {
+                apply_reload(&mut current_config, new_config, &registry, &backend, &mut streams, &mut controls)
+            },
+            Ok(Err(e)) => error!("配置文件无效，保持当前配置: {:#}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(SUPERVISE_INTERVAL),
+        }
+        // 设备断开时自动切换到备用设备并重试，首选设备恢复后切回
+        for stream in streams.iter_mut() {
+            stream.supervise(&backend, &current_config, Instant::now());
+        }
+        // 扬声器采样率与回声消除准备时不同，重启输入流按新的采样率准备
+        if echo_monitor.as_ref().is_some_and(EchoMonitor::take_reference_rate_changed) {
+            info!("扬声器采样率已变化，重启输入流以重新准备回声消除");
+            if let Err(e) = streams[0].reopen(&backend, &current_config) {
+                warn!("重启输入流失败: {:#}", e);
+            }
+        }
+        for event in stream_event_receiver.try_iter() {
+            if event.is_degraded() {
+                warn!("{}", event);
+            } else {
+                info!("{}", event);
+            }
+        }
+        // 欠载/溢出次数增加时提示，便于调整 [jitter] 配置
+        for (running, last) in streams.iter().zip(last_stats.iter_mut()) {
+            let Some(stream) = running.stream() else {
+                continue;
+            };
+            let stats = stream.jitter_stats();
+            if stats.underruns > last.underruns || stats.overruns > last.overruns {
+                warn!(
+                    "{}流缓冲: 欠载 {} 次，溢出 {} 次，当前 {} 帧，漂移补偿 {} ppm",
+                    running.direction().label(),
+                    stats.underruns,
+                    stats.overruns,
+                    stats.level_frames,
+                    stats.drift_ppm
+                );
+            }
+            *last = stats;
+        }
+        // RUST_LOG=debug 时定期输出压缩器、限幅器等的增益衰减量，便于调整参数
+        if log::log_enabled!(log::Level::Debug) && last_meter_log.elapsed() >= METER_LOG_INTERVAL {
+            last_meter_log = Instant::now();
+            for (running, chain_controls) in streams.iter().zip(&controls) {
+                if let Some(meters) = chain_controls.describe_meters() {
+                    debug!("{}处理器: {}", running.direction().label(), meters);
+                }
+            }
+        }
     }
 
+    // 停止音频流（输出文件在此时写完），等待识别线程处理完剩余的音频
+    info!("输入文件已全部处理，正在退出...");
+    drop(streams);
+    for worker in recognition_workers {
+        let _ = worker.join();
+    }
+    let _ = dispatcher.join();
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 重新加载配置时对一个方向处理器链的修改
+enum ChainUpdate {
+    /// 只写入参数句柄
+    Parameters(ParameterUpdate),
+    /// 交叉淡化到新建的处理器链
+    Rebuild(ProcessorChain, ChainControls),
+}
+
+/// 应用重新加载的配置
+///
+/// 只改了参数的处理器链通过参数句柄直接调整，其余变化的处理器链在线替换（交叉淡化），
+/// 设备或格式变化的音频流单独重启，其余配置提示需要重启程序。
+/// 新的处理器链有任何错误时整份配置都不应用。`controls` 与 `streams` 按位置对应。
+fn apply_reload(
+    current: &mut config::AudioConfig,
+    mut new: config::AudioConfig,
+    registry: &ProcessorRegistry,
+    backend: &dyn AudioBackend,
+    streams: &mut [RunningStream],
+    controls: &mut [ChainControls],
+) {
+    let changes = match config_watcher::diff(current, &new) {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("比较配置失败，保持当前配置: {:#}", e);
+            return;
+        }
+    };
+    if changes.is_empty() {
+        info!("配置文件已修改，没有需要应用的变化");
+        return;
+    }
+
+    // 先算出所有参数调整、创建所有新的处理器链，任何一项无效都不应用本次修改
+    let mut updates = Vec::new();
+    for (index, stream) in streams.iter().enumerate() {
+        let change = changes.chain(stream.direction());
+        if change == ChainChange::None {
+            continue;
+        }
+        let (name, configs) = match stream.direction() {
+            Direction::Input => ("input_chain", &new.input_chain),
+            Direction::Output => ("output_chain", &new.output_chain),
+        };
+        let planned = match change {
+            ChainChange::Parameters => controls[index].plan(configs),
+            _ => Ok(None),
+        };
+        let update = match planned {
+            Ok(Some(parameters)) => ChainUpdate::Parameters(parameters),
+            Ok(None) => match registry.build_chain_with_controls(name, configs) {
+                Ok((chain, chain_controls)) => ChainUpdate::Rebuild(chain, chain_controls),
+                Err(e) => {
+                    error!("配置无效，保持当前配置: {:#}", e);
+                    return;
+                }
+            },
+            Err(e) => {
+                error!("配置无效，保持当前配置: {:#}", e);
+                return;
+            }
+        };
+        updates.push((index, update));
+    }
+
+    let mut applied = Vec::new();
+    for (index, update) in updates {
+        let direction = streams[index].direction();
+        let (configs, previous) = match direction {
+            Direction::Input => (&mut new.input_chain, &current.input_chain),
+            Direction::Output => (&mut new.output_chain, &current.output_chain),
+        };
+        match update {
+            ChainUpdate::Parameters(parameters) => {
+                parameters.apply();
+                applied.push(format!("{}处理器参数: {}", direction.label(), registry::describe(configs)));
+            }
+            ChainUpdate::Rebuild(chain, chain_controls) => match streams[index].chain().replace(chain) {
+                Ok(()) => {
+                    controls[index] = chain_controls;
+                    applied.push(format!("{}处理器链: {}", direction.label(), registry::describe(configs)));
+                }
+                Err(e) => {
+                    warn!("{}处理器链更新失败: {:#}", direction.label(), e);
+                    *configs = previous.clone();
+                }
+            },
+        }
+    }
+
+    for stream in streams.iter_mut() {
+        if !changes.restart(stream.direction()) {
+            continue;
+        }
+        let label = stream.direction().label();
+        match stream.restart(backend, &new) {
+            Ok(()) => {
+                if let Some(running) = stream.stream() {
+                    applied.push(format!("{}流设备: {} → {}", label, running.input_name(), running.output_name()));
+                }
+            }
+            Err(e) => {
+                warn!("{}流切换设备失败，继续使用原设备: {:#}", label, e);
+                config_watcher::restore_stream_settings(&mut new, current, stream.direction());
+                if stream.stream().is_none() {
+                    if let Err(e) = stream.restart(backend, current) {
+                        error!("{}流恢复失败，该方向音频已停止: {:#}", label, e);
+                    }
+                }
+            }
+        }
+    }
+
+    if changes.recovery {
+        applied.push("设备自动恢复设置".to_string());
+    }
+    if !changes.requires_restart.is_empty() {
+        warn!("以下配置需要重启程序才能生效: {}", changes.requires_restart.join(", "));
+    }
+    if applied.is_empty() {
+        info!("配置已重新加载，没有生效的变化");
+    } else {
+        info!("配置已重新加载:");
+        for item in &applied {
+            info!("  {}", item);
+        }
+    }
+    *current = new;
+}
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::loudness::{LoudnessMeter, LoudnessReport};
+use crate::processor::ProcessorChain;
+use crate::wav;
+
+/// 每次送入处理器链的帧数，与实时回调的典型缓冲区大小相当
+const BLOCK_FRAMES: usize = 1024;
+
+/// 离线处理结果
+#[derive(Debug, Clone, Copy)]
+pub struct OfflineReport {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 处理的帧数
+    pub frames: u64,
+    /// 处理器链的算法延迟（帧数），输出文件已去掉这段延迟
+    pub latency_frames: usize,
+    /// 处理用时
+    pub elapsed: Duration,
+    /// 处理前的电平
+    pub input: LoudnessReport,
+    /// 处理后的电平
+    pub output: LoudnessReport,
+}
+
+impl OfflineReport {
+    /// 音频时长
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate as f64)
+    }
+}
+
+/// 用处理器链尽快处理整个 WAV 文件，输出保持输入的采样率、声道数和精度
+///
+/// 处理器与实时音频流中的实现相同，按输入文件的实际格式调用 `prepare`。
+/// 处理器链有算法延迟时丢弃输出开头的延迟部分，并在末尾补静音冲出剩余的音频，
+/// 输出与输入逐帧对齐、长度相同。
+pub fn process_file(input: &Path, output: &Path, chain: &mut ProcessorChain) -> Result<OfflineReport> {
+    let start = Instant::now();
+    let mut reader = wav::open(input)?;
+    let spec = reader.spec();
+    let format = wav::format_of(&spec);
+    let mut writer = wav::create(output, format, spec.sample_rate, spec.channels)?;
+
+    chain.prepare(spec.sample_rate, spec.channels);
+    let mut input_meter = LoudnessMeter::new(spec.sample_rate, spec.channels);
+    let mut output_meter = LoudnessMeter::new(spec.sample_rate, spec.channels);
+
+    let channels = spec.channels as usize;
+    let latency = chain.latency_frames();
+    let mut buffer = vec![0.0f32; BLOCK_FRAMES * channels];
+    let mut frames = 0u64;
+    // 还需要丢弃的延迟帧数
+    let mut skip = latency * channels;
+    let mut emit = |block: &mut [f32], chain: &mut ProcessorChain| -> Result<()> {
+        chain.process(block).context("处理音频失败")?;
+        let dropped = skip.min(block.len());
+        skip -= dropped;
+        let block = &block[dropped..];
+        output_meter.process(block);
+        wav::write_samples(&mut writer, format, block)
+            .with_context(|| format!("写入输出文件失败: {}", output.display()))
+    };
+    loop {
+        // 只处理完整的帧，文件末尾不完整的帧丢弃
+        let read = wav::read_samples(&mut reader, &mut buffer) / channels * channels;
+        if read == 0 {
+            break;
+        }
+        let block = &mut buffer[..read];
+        input_meter.process(block);
+        emit(block, chain)?;
+        frames += (read / channels) as u64;
+        if read < buffer.len() {
+            break;
+        }
+    }
+    // 补静音，冲出处理器链中延迟的音频
+    let mut flush = latency * channels;
+    while flush > 0 {
+        let block = &mut buffer[..flush.min(BLOCK_FRAMES * channels)];
+        block.fill(0.0);
+        flush -= block.len();
+        emit(block, chain)?;
+    }
+    writer
+        .finalize()
+        .with_context(|| format!("保存输出文件失败: {}", output.display()))?;
+
+    Ok(OfflineReport {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        frames,
+        latency_frames: latency,
+        elapsed: start.elapsed(),
+        input: input_meter.report(),
+        output: output_meter.report(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WavFormat;
+    use crate::processor::{AudioProcessor, GainProcessor};
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+
+    /// 固定延迟若干帧的处理器，模拟有前视的处理器
+    struct DelayProcessor {
+        frames: usize,
+        line: VecDeque<f32>,
+    }
+
+    impl AudioProcessor for DelayProcessor {
+        fn process(&mut self, buffer: &mut [f32]) -> anyhow::Result<()> {
+            for sample in buffer.iter_mut() {
+                self.line.push_back(*sample);
+                *sample = self.line.pop_front().unwrap_or(0.0);
+            }
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "延迟"
+        }
+
+        fn prepare(&mut self, _sample_rate: u32, channels: u16) {
+            self.line = VecDeque::from(vec![0.0; self.frames * channels as usize]);
+        }
+
+        fn latency_frames(&self) -> usize {
+            self.frames
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("trans-offline-{}-{}.wav", name, std::process::id()))
+    }
+
+    #[test]
+    fn output_is_aligned_with_input_and_metered() {
+        let input_path = temp_path("input");
+        let output_path = temp_path("output");
+        let frames = 48_000 * 3 + 123;
+        let input: Vec<f32> = (0..frames * 2)
+            .map(|n| (0.5 * (2.0 * std::f64::consts::PI * 1000.0 * (n / 2) as f64 / 48_000.0).sin()) as f32)
+            .collect();
+        let mut writer = wav::create(&input_path, WavFormat::Float32, 48_000, 2).unwrap();
+        wav::write_samples(&mut writer, WavFormat::Float32, &input).unwrap();
+        writer.finalize().unwrap();
+
+        let mut chain = ProcessorChain::new();
+        chain.add_processor(Box::new(DelayProcessor {
+            frames: 300,
+            line: VecDeque::new(),
+        }));
+        chain.add_processor(Box::new(GainProcessor::new(0.5)));
+        let report = process_file(&input_path, &output_path, &mut chain).unwrap();
+
+        let mut reader = wav::open(&output_path).unwrap();
+        assert_eq!(wav::format_of(&reader.spec()), WavFormat::Float32);
+        let mut output = vec![0.0; reader.len() as usize];
+        wav::read_samples(&mut reader, &mut output);
+        std::fs::remove_file(&input_path).unwrap();
+        std::fs::remove_file(&output_path).unwrap();
+
+        // 去掉延迟后与输入逐帧对齐，长度相同
+        assert_eq!(output.len(), input.len());
+        for (n, (&out, &sample)) in output.iter().zip(&input).enumerate() {
+            assert_eq!(out, sample * 0.5, "第 {} 个采样", n);
+        }
+        assert_eq!(report.frames, frames as u64);
+        assert_eq!(report.latency_frames, 300);
+        assert_eq!((report.sample_rate, report.channels), (48_000, 2));
+        let gain = report.output.integrated_lufs.unwrap() - report.input.integrated_lufs.unwrap();
+        assert!((gain + 6.02).abs() < 0.01, "响度变化 {:.3} LU", gain);
+        assert!((report.output.peak_db - report.input.peak_db + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn missing_input_is_reported() {
+        let error = process_file(&temp_path("missing"), &temp_path("unused"), &mut ProcessorChain::new()).unwrap_err();
+        assert!(format!("{:#}", error).contains("打开 WAV 文件失败"));
+    }
+}
@@ -1,12 +1,168 @@
 use anyhow::Result;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::sample_format::CALLBACK_FRAMES;
+
+/// 可在其它线程实时调整的处理器参数
+///
+/// 以 f32 位模式存放在原子变量中，音频回调读取时不加锁；克隆得到的句柄共享同一个值。
+#[derive(Debug, Clone)]
+pub struct Parameter(Arc<AtomicU32>);
+
+impl Parameter {
+    pub fn new(value: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(value.to_bits())))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// 一路交错排列的音频信号及其声道数，用作旁链输入
+#[derive(Debug, Clone, Copy)]
+pub struct Bus<'a> {
+    pub samples: &'a [f32],
+    pub channels: u16,
+}
+
+impl<'a> Bus<'a> {
+    pub fn new(samples: &'a [f32], channels: u16) -> Self {
+        Self { samples, channels }
+    }
+
+    /// 帧数
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    /// 从第 `start` 帧起取 `frames` 帧，帧数不足时返回 None
+    fn slice(self, start: usize, frames: usize) -> Option<Self> {
+        let channels = self.channels.max(1) as usize;
+        let samples = self.samples.get(start * channels..(start + frames) * channels)?;
+        Some(Self { samples, channels: self.channels })
+    }
+}
+
+/// 按名称查找旁链总线
+pub trait Buses {
+    fn bus(&self, name: &str) -> Option<Bus<'_>>;
+}
+
+/// 没有旁链输入
+pub struct NoBuses;
+
+impl Buses for NoBuses {
+    fn bus(&self, _name: &str) -> Option<Bus<'_>> {
+        None
+    }
+}
+
+impl<const N: usize> Buses for [(&str, Bus<'_>); N] {
+    fn bus(&self, name: &str) -> Option<Bus<'_>> {
+        self.iter().find(|(bus_name, _)| *bus_name == name).map(|(_, bus)| *bus)
+    }
+}
+
+/// 一次处理调用的上下文：主信号、旁链输入、声道布局、采样率和时间戳
+pub struct ProcessContext<'a> {
+    /// 主信号，交错排列，原地修改
+    pub main: &'a mut [f32],
+    /// 主信号的声道数
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// 主信号第一帧的帧序号，从流启动（`prepare`）时的 0 开始计数
+    pub frame: u64,
+    sidechains: &'a dyn Buses,
+    /// 主信号第一帧在旁链总线中的位置
+    offset: usize,
+}
+
+impl<'a> ProcessContext<'a> {
+    /// 没有旁链输入的上下文
+    pub fn new(main: &'a mut [f32], channels: u16, sample_rate: u32, frame: u64) -> Self {
+        Self {
+            main,
+            channels,
+            sample_rate,
+            frame,
+            sidechains: &NoBuses,
+            offset: 0,
+        }
+    }
+
+    /// 附加旁链输入，各总线的第一帧与主信号的第一帧对齐
+    pub fn with_sidechains(mut self, sidechains: &'a dyn Buses) -> Self {
+        self.sidechains = sidechains;
+        self.offset = 0;
+        self
+    }
+
+    /// 主信号的帧数
+    pub fn frames(&self) -> usize {
+        self.main.len() / self.channels.max(1) as usize
+    }
+
+    /// 与主信号同一时间段的旁链总线，不存在或帧数不足时返回 None
+    pub fn sidechain(&self, name: &str) -> Option<Bus<'a>> {
+        self.sidechains.bus(name)?.slice(self.offset, self.frames())
+    }
+
+    /// 从第 `start` 帧起取 `frames` 帧的子上下文，旁链和时间戳随之偏移
+    pub fn split(&mut self, start: usize, frames: usize) -> ProcessContext<'_> {
+        let channels = self.channels.max(1) as usize;
+        ProcessContext {
+            main: &mut self.main[start * channels..(start + frames) * channels],
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            frame: self.frame + start as u64,
+            sidechains: self.sidechains,
+            offset: self.offset + start,
+        }
+    }
+
+    /// 以另一段帧数相同的信号作为主信号，旁链和时间戳不变
+    pub fn with_main<'b>(&'b self, main: &'b mut [f32]) -> ProcessContext<'b> {
+        ProcessContext {
+            main,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            frame: self.frame,
+            sidechains: self.sidechains,
+            offset: self.offset,
+        }
+    }
+}
 
 /// 音频处理器接口
 pub trait AudioProcessor: Send + Sync {
     /// 处理音频数据，原地修改 buffer
+    ///
+    /// 在实时回调中调用，返回的错误交给监控线程记录，链不再附加上下文，错误信息应说明出错的处理器。
     fn process(&mut self, buffer: &mut [f32]) -> Result<()>;
-    
+
+    /// 带上下文处理音频数据，需要旁链输入或时间戳的处理器实现此方法
+    ///
+    /// 默认忽略旁链，直接对主信号调用 `process`，只实现了 `process` 的处理器无需改动。
+    fn process_context(&mut self, context: &mut ProcessContext) -> Result<()> {
+        self.process(context.main)
+    }
+
     /// 获取处理器名称
     fn name(&self) -> &str;
+
+    /// 音频流启动前告知实际的采样率和声道数（buffer 为交错排列）
+    fn prepare(&mut self, _sample_rate: u32, _channels: u16) {}
+
+    /// 算法延迟（帧数），即输出相对输入延后的帧数，在 `prepare` 之后有效
+    fn latency_frames(&self) -> usize {
+        0
+    }
 }
 
 /// 直通处理器（不做任何处理，直接传递音频）
@@ -23,45 +179,231 @@ impl AudioProcessor for PassThroughProcessor {
     }
 }
 
+/// 处理器链中的一步
+enum Step {
+    Processor(Box<dyn AudioProcessor>),
+    /// 把当前信号复制到第几条总线
+    Send(usize),
+}
+
+/// 处理器链内部的命名总线
+struct SendBus {
+    name: String,
+    samples: Vec<f32>,
+    /// 本块中已写入的采样数，0 表示本块中还没有经过对应的 send
+    len: usize,
+}
+
 /// 处理器链：按顺序执行多个处理器
+///
+/// 链中可以用 [`ProcessorChain::add_send`] 把某一步的信号复制到命名总线，
+/// 后面的处理器通过 [`ProcessContext::sidechain`] 读取；同名的链内总线优先于外部传入的旁链。
+/// 总线不做延迟补偿，send 与读取之间的前视等延迟会让两路信号错开。
 pub struct ProcessorChain {
-    processors: Vec<Box<dyn AudioProcessor>>,
+    steps: Vec<Step>,
+    buses: Vec<SendBus>,
+    sample_rate: u32,
+    channels: u16,
+    /// 通过 `process` 调用时的帧计数
+    frame: u64,
 }
 
 impl ProcessorChain {
     pub fn new() -> Self {
         Self {
-            processors: Vec::new(),
+            steps: Vec::new(),
+            buses: Vec::new(),
+            sample_rate: 48000,
+            channels: 1,
+            frame: 0,
         }
     }
 
     pub fn add_processor(&mut self, processor: Box<dyn AudioProcessor>) {
-        self.processors.push(processor);
+        self.steps.push(Step::Processor(processor));
+    }
+
+    /// 把此处的信号复制到名为 `name` 的总线，同名总线再次 send 时覆盖
+    ///
+    /// 新总线的缓冲区按当前声道数分配（还没有 `prepare` 时为单声道），`prepare` 时按新的声道数重新分配。
+    pub fn add_send(&mut self, name: &str) {
+        let index = match self.buses.iter().position(|bus| bus.name == name) {
+            Some(index) => index,
+            None => {
+                self.buses.push(SendBus {
+                    name: name.to_string(),
+                    samples: vec![0.0; CALLBACK_FRAMES * self.channels.max(1) as usize],
+                    len: 0,
+                });
+                self.buses.len() - 1
+            }
+        };
+        self.steps.push(Step::Send(index));
     }
 
+    pub fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.frame = 0;
+        for bus in &mut self.buses {
+            bus.samples = vec![0.0; CALLBACK_FRAMES * channels.max(1) as usize];
+            bus.len = 0;
+        }
+        for step in &mut self.steps {
+            if let Step::Processor(processor) = step {
+                processor.prepare(sample_rate, channels);
+            }
+        }
+    }
+
+    /// 各处理器算法延迟之和（帧数）
+    pub fn latency_frames(&self) -> usize {
+        self.processors().map(|processor| processor.latency_frames()).sum()
+    }
+
+    fn processors(&self) -> impl Iterator<Item = &dyn AudioProcessor> + '_ {
+        self.steps.iter().filter_map(|step| match step {
+            Step::Processor(processor) => Some(processor.as_ref()),
+            Step::Send(_) => None,
+        })
+    }
+
+    /// 没有旁链输入时处理一段音频，时间戳由链自己计数
     pub fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
-        for processor in &mut self.processors {
-            processor.process(buffer)?;
+        let mut context = ProcessContext::new(buffer, self.channels, self.sample_rate, self.frame);
+        let frames = context.frames() as u64;
+        self.process_context(&mut context)?;
+        self.frame += frames;
+        Ok(())
+    }
+
+    /// 带上下文处理一段音频，外部旁链传给链中的每个处理器
+    pub fn process_context(&mut self, context: &mut ProcessContext) -> Result<()> {
+        if self.buses.is_empty() {
+            return Self::run(&mut self.steps, &mut self.buses, context);
+        }
+        // 分块处理，每块不超过预先分配的总线缓冲区
+        let block_frames = self.buses[0].samples.len() / context.channels.max(1) as usize;
+        let frames = context.frames();
+        let mut start = 0;
+        while start < frames {
+            let len = (frames - start).min(block_frames.max(1));
+            Self::run(&mut self.steps, &mut self.buses, &mut context.split(start, len))?;
+            start += len;
         }
         Ok(())
     }
+
+    fn run(steps: &mut [Step], buses: &mut [SendBus], context: &mut ProcessContext) -> Result<()> {
+        for bus in buses.iter_mut() {
+            bus.len = 0;
+        }
+        for step in steps {
+            match step {
+                Step::Processor(processor) => {
+                    let layered = LayeredBuses {
+                        local: buses,
+                        channels: context.channels,
+                        outer: context.sidechains,
+                        offset: context.offset,
+                        frames: context.frames(),
+                    };
+                    let mut inner = ProcessContext {
+                        main: &mut *context.main,
+                        channels: context.channels,
+                        sample_rate: context.sample_rate,
+                        frame: context.frame,
+                        sidechains: &layered,
+                        offset: 0,
+                    };
+                    processor.process_context(&mut inner)?;
+                }
+                Step::Send(index) => {
+                    let bus = &mut buses[*index];
+                    let len = context.main.len();
+                    bus.samples[..len].copy_from_slice(context.main);
+                    bus.len = len;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 链内总线叠加在外部旁链之上，本块中还没有 send 的链内总线不可见
+struct LayeredBuses<'a> {
+    local: &'a [SendBus],
+    channels: u16,
+    outer: &'a dyn Buses,
+    offset: usize,
+    frames: usize,
+}
+
+impl Buses for LayeredBuses<'_> {
+    fn bus(&self, name: &str) -> Option<Bus<'_>> {
+        if let Some(bus) = self.local.iter().find(|bus| bus.len > 0 && bus.name == name) {
+            return Some(Bus::new(&bus.samples[..bus.len], self.channels));
+        }
+        self.outer.bus(name)?.slice(self.offset, self.frames)
+    }
 }
 
+impl Default for ProcessorChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 增益变化的平滑时间常数 (秒)
+const GAIN_SMOOTHING_SECONDS: f32 = 0.01;
+
 /// 音量增益处理器
+///
+/// 不截断超过 ±1.0 的采样，需要防止削波时在后面接限幅器（写入设备时才截断）。
+/// 运行中修改增益时逐帧平滑过渡到新值（时间常数 10 ms），不会因增益突变产生爆音。
 pub struct GainProcessor {
-    gain: f32,
+    gain: Parameter,
+    /// 平滑后的当前增益
+    current: f32,
+    smoothing: f32,
+    channels: usize,
 }
 
 impl GainProcessor {
     pub fn new(gain: f32) -> Self {
-        Self { gain }
+        let mut processor = Self {
+            gain: Parameter::new(gain),
+            current: gain,
+            smoothing: 0.0,
+            channels: 1,
+        };
+        processor.prepare(48000, 1);
+        processor
+    }
+
+    /// 增益参数句柄，运行中修改后平滑过渡到新值
+    pub fn gain(&self) -> Parameter {
+        self.gain.clone()
     }
 }
 
 impl AudioProcessor for GainProcessor {
     fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
-        for sample in buffer.iter_mut() {
-            *sample = (*sample * self.gain).clamp(-1.0, 1.0);
+        let target = self.gain.get();
+        if self.current == target {
+            for sample in buffer.iter_mut() {
+                *sample *= target;
+            }
+            return Ok(());
+        }
+        for frame in buffer.chunks_mut(self.channels) {
+            self.current = target + (self.current - target) * self.smoothing;
+            if (self.current - target).abs() < 1e-4 {
+                self.current = target;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= self.current;
+            }
         }
         Ok(())
     }
@@ -69,32 +411,120 @@ impl AudioProcessor for GainProcessor {
     fn name(&self) -> &str {
         "音量增益处理器"
     }
-}
 
-/// 噪音门（静音低音量输入）
-pub struct NoiseGateProcessor {
-    threshold: f32,
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels.max(1) as usize;
+        self.smoothing = (-1.0 / (GAIN_SMOOTHING_SECONDS * sample_rate.max(1) as f32)).exp();
+        self.current = self.gain.get();
+    }
 }
 
-impl NoiseGateProcessor {
-    pub fn new(threshold: f32) -> Self {
-        Self { threshold }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 用名为 `bus` 的旁链替换主信号，没有该旁链时输出静音
+    struct Listen {
+        bus: &'static str,
     }
-}
 
-impl AudioProcessor for NoiseGateProcessor {
-    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
-        let threshold_sq = self.threshold * self.threshold;
-        for sample in buffer.iter_mut() {
-            let sample_val = *sample;
-            if sample_val * sample_val < threshold_sq {
-                *sample = 0.0;
+    impl AudioProcessor for Listen {
+        fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+            buffer.fill(0.0);
+            Ok(())
+        }
+
+        fn process_context(&mut self, context: &mut ProcessContext) -> Result<()> {
+            match context.sidechain(self.bus) {
+                Some(bus) => context.main.copy_from_slice(bus.samples),
+                None => context.main.fill(0.0),
             }
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "监听"
         }
-        Ok(())
     }
 
-    fn name(&self) -> &str {
-        "噪音门处理器"
+    fn ramp(samples: usize) -> Vec<f32> {
+        (0..samples).map(|n| (n % 1000) as f32 / 1000.0 - 0.5).collect()
+    }
+
+    #[test]
+    fn send_bus_is_read_as_sidechain_by_later_processors() {
+        let mut chain = ProcessorChain::new();
+        chain.add_processor(Box::new(GainProcessor::new(0.5)));
+        chain.add_send("key");
+        chain.add_processor(Box::new(GainProcessor::new(0.0)));
+        chain.add_processor(Box::new(Listen { bus: "key" }));
+        chain.prepare(48_000, 2);
+
+        // 超过总线缓冲区的长度时分块处理
+        let input = ramp((CALLBACK_FRAMES * 2 + 100) * 2);
+        let mut buffer = input.clone();
+        chain.process(&mut buffer).unwrap();
+        for (n, (&out, &sample)) in buffer.iter().zip(&input).enumerate() {
+            assert_eq!(out, sample * 0.5, "第 {} 个采样", n);
+        }
+
+        // send 之前的处理器看不到链内总线，读到同名的外部旁链
+        let mut chain = ProcessorChain::new();
+        chain.add_processor(Box::new(Listen { bus: "key" }));
+        chain.add_send("key");
+        chain.prepare(48_000, 1);
+        let outer = vec![0.25; 64];
+        let buses = [("key", Bus::new(&outer, 1))];
+        let mut buffer = ramp(64);
+        chain
+            .process_context(&mut ProcessContext::new(&mut buffer, 1, 48_000, 0).with_sidechains(&buses))
+            .unwrap();
+        assert!(buffer.iter().all(|&sample| sample == 0.25));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sends_work_before_prepare_and_when_added_after_prepare() {
+        // 没有 prepare 时按单声道处理
+        let mut chain = ProcessorChain::new();
+        chain.add_send("key");
+        chain.add_processor(Box::new(Listen { bus: "key" }));
+        let input = ramp(CALLBACK_FRAMES + 10);
+        let mut buffer = input.clone();
+        chain.process(&mut buffer).unwrap();
+        assert_eq!(buffer, input);
+
+        // prepare 之后再加的总线按已准备的声道数分配
+        chain.prepare(48_000, 2);
+        chain.add_send("late");
+        chain.add_processor(Box::new(Listen { bus: "late" }));
+        let input = ramp((CALLBACK_FRAMES + 10) * 2);
+        let mut buffer = input.clone();
+        chain.process(&mut buffer).unwrap();
+        assert_eq!(buffer, input);
+    }
+
+    #[test]
+    fn gain_changes_glide_to_new_value() {
+        let mut processor = GainProcessor::new(1.0);
+        processor.prepare(48_000, 2);
+        let gain = processor.gain();
+        gain.set(0.1);
+        let mut buffer = vec![1.0f32; 4800 * 2];
+        processor.process(&mut buffer).unwrap();
+        // 同一帧各声道增益相同，相邻帧之间没有突变
+        assert!(buffer.chunks(2).all(|frame| frame[0] == frame[1]));
+        let largest_step = buffer.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0f32, f32::max);
+        assert!(largest_step < 0.002, "相邻帧增益变化 {}", largest_step);
+        // 10 ms 时完成约 63%，100 ms 后到达新值
+        let expected = 0.1 + 0.9 * (-1.0f32).exp();
+        assert!((buffer[479 * 2] - expected).abs() < 0.01, "10 ms 时增益为 {}", buffer[479 * 2]);
+        assert_eq!(buffer[4799 * 2], 0.1);
+
+        // 重新准备时直接使用当前值
+        gain.set(0.5);
+        processor.prepare(48_000, 1);
+        let mut buffer = [1.0f32; 4];
+        processor.process(&mut buffer).unwrap();
+        assert_eq!(buffer, [0.5; 4]);
+    }
+}
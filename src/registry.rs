@@ -0,0 +1,799 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::agc::{AgcProcessor, AgcSettings};
+use crate::config::ProcessorConfig;
+use crate::denoise::{NoiseSuppressor, NoiseSuppressorSettings};
+use crate::dynamics::{
+    CompressorProcessor, CompressorSettings, LimiterProcessor, LimiterSettings, NoiseGateProcessor, NoiseGateSettings,
+};
+use crate::equalizer::{BandControl, EqualizerProcessor, EqualizerSettings};
+use crate::loudness::db_to_amplitude;
+use crate::processor::{AudioProcessor, GainProcessor, Parameter, PassThroughProcessor, ProcessorChain};
+
+/// 由配置参数创建处理器
+pub type ProcessorBuilder = fn(&toml::Table) -> Result<BuiltProcessor>;
+
+/// 按新的参数表算出要写入参数句柄的值；有参数不能在线调整时返回 `None`
+pub type ParameterAdjuster = Box<dyn Fn(&toml::Table) -> Result<Option<Vec<(Parameter, f32)>>> + Send>;
+
+/// 创建好的处理器，以及运行中不重建处理器、直接调整其参数的方法和测量值句柄
+pub struct BuiltProcessor {
+    pub processor: Box<dyn AudioProcessor>,
+    adjuster: Option<ParameterAdjuster>,
+    meters: Vec<(&'static str, Parameter)>,
+}
+
+impl BuiltProcessor {
+    pub fn new(processor: impl AudioProcessor + 'static) -> Self {
+        Self {
+            processor: Box::new(processor),
+            adjuster: None,
+            meters: Vec::new(),
+        }
+    }
+
+    /// 添加测量值句柄（如增益衰减量），名称带单位后缀，如 `gain_reduction_db`
+    pub fn with_meter(mut self, name: &'static str, meter: Parameter) -> Self {
+        self.meters.push((name, meter));
+        self
+    }
+
+    /// 设置在线调整参数的方法；没有设置时参数变化都需要重建处理器
+    pub fn with_adjuster(
+        mut self,
+        adjuster: impl Fn(&toml::Table) -> Result<Option<Vec<(Parameter, f32)>>> + Send + 'static,
+    ) -> Self {
+        self.adjuster = Some(Box::new(adjuster));
+        self
+    }
+}
+
+struct RegistryEntry {
+    kind: &'static str,
+    description: &'static str,
+    build: ProcessorBuilder,
+}
+
+/// 处理器注册表：按配置中的 `type` 创建处理器
+///
+/// 新的处理器在 [`ProcessorRegistry::with_builtin`] 中注册后即可在 config.toml 中使用。
+pub struct ProcessorRegistry {
+    entries: Vec<RegistryEntry>,
+}
+
+impl ProcessorRegistry {
+    /// 空注册表
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// 包含所有内置处理器的注册表
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register("passthrough", "直通，不做任何处理", |params| {
+            parse::<Empty>(params)?;
+            Ok(BuiltProcessor::new(PassThroughProcessor))
+        });
+        registry.register("send", "把此处的信号复制到命名总线，供后面的处理器作旁链，参数 bus", |_| {
+            bail!("send 只能在处理器链中使用")
+        });
+        registry.register("gain", "音量增益，参数 gain_db", |params| {
+            let processor = GainProcessor::new(gain_amplitude(params)?);
+            let gain = processor.gain();
+            Ok(BuiltProcessor::new(processor)
+                .with_adjuster(move |params| Ok(Some(vec![(gain.clone(), gain_amplitude(params)?)]))))
+        });
+        registry.register(
+            "noise_gate",
+            "噪音门，电平低于门限时衰减，参数 threshold_db、close_threshold_db、attack_ms、hold_ms、release_ms、floor_db、sidechain_hpf_hz（均可省略）",
+            |params| {
+                let settings = noise_gate_settings(params)?;
+                let processor = NoiseGateProcessor::new(settings);
+                let threshold = processor.threshold_db();
+                let gain_reduction = processor.gain_reduction();
+                // 只有打开门限可以在线调整，关闭门限随之保持相同的差值
+                let relative = |settings: NoiseGateSettings| NoiseGateSettings {
+                    threshold_db: 0.0,
+                    close_threshold_db: settings.close_threshold_db.map(|close| close - settings.threshold_db),
+                    ..settings
+                };
+                Ok(BuiltProcessor::new(processor)
+                    .with_meter("gain_reduction_db", gain_reduction)
+                    .with_adjuster(move |params| {
+                        let new = noise_gate_settings(params)?;
+                        Ok((relative(new) == relative(settings)).then(|| vec![(threshold.clone(), new.threshold_db)]))
+                    }))
+            },
+        );
+        registry.register(
+            "compressor",
+            "压缩器，参数 threshold_db、ratio、knee_db、attack_ms、release_ms、makeup_db、lookahead_ms、sidechain（均可省略）",
+            |params| {
+                let mut params = params.clone();
+                let sidechain = match params.remove("sidechain") {
+                    Some(toml::Value::String(name)) => Some(name),
+                    Some(_) => bail!("参数错误: sidechain 应为总线名称"),
+                    None => None,
+                };
+                let settings: CompressorSettings = parse(&params)?;
+                check_range("threshold_db", settings.threshold_db, -80.0, 0.0)?;
+                check_range("ratio", settings.ratio, 1.0, 100.0)?;
+                check_range("knee_db", settings.knee_db, 0.0, 24.0)?;
+                check_range("attack_ms", settings.attack_ms, 0.0, 500.0)?;
+                check_range("release_ms", settings.release_ms, 1.0, 5000.0)?;
+                check_range("makeup_db", settings.makeup_db, -20.0, 40.0)?;
+                check_range("lookahead_ms", settings.lookahead_ms, 0.0, 20.0)?;
+                let compressor = CompressorProcessor::new(settings);
+                let gain_reduction = compressor.gain_reduction();
+                let compressor = match sidechain {
+                    Some(name) => compressor.with_sidechain(&name),
+                    None => compressor,
+                };
+                Ok(BuiltProcessor::new(compressor).with_meter("gain_reduction_db", gain_reduction))
+            },
+        );
+        registry.register(
+            "limiter",
+            "限幅器，参数 ceiling_db、release_ms、lookahead_ms、true_peak（均可省略）",
+            |params| {
+                let settings: LimiterSettings = parse(params)?;
+                check_range("ceiling_db", settings.ceiling_db, -20.0, 0.0)?;
+                check_range("release_ms", settings.release_ms, 1.0, 5000.0)?;
+                check_range("lookahead_ms", settings.lookahead_ms, 0.0, 20.0)?;
+                let limiter = LimiterProcessor::new(settings);
+                let gain_reduction = limiter.gain_reduction();
+                Ok(BuiltProcessor::new(limiter).with_meter("gain_reduction_db", gain_reduction))
+            },
+        );
+        registry.register(
+            "agc",
+            "自动增益，把响度调整到目标 LUFS，参数 target_lufs、max_boost_db、max_cut_db、window_ms、gate_lufs、speed_db_per_s（均可省略）",
+            |params| {
+                let settings: AgcSettings = parse(params)?;
+                check_range("target_lufs", settings.target_lufs, -40.0, -5.0)?;
+                check_range("max_boost_db", settings.max_boost_db, 0.0, 40.0)?;
+                check_range("max_cut_db", settings.max_cut_db, 0.0, 40.0)?;
+                check_range("window_ms", settings.window_ms as f32, 400.0, 10000.0)?;
+                check_range("gate_lufs", settings.gate_lufs, -80.0, -20.0)?;
+                check_range("speed_db_per_s", settings.speed_db_per_s, 0.1, 60.0)?;
+                let agc = AgcProcessor::new(settings);
+                let (gain, loudness) = (agc.gain_db(), agc.loudness());
+                Ok(BuiltProcessor::new(agc).with_meter("gain_db", gain).with_meter("loudness_lufs", loudness))
+            },
+        );
+        registry.register(
+            "eq",
+            "参数均衡器，参数 bands（频段列表，每项 filter、frequency、q、gain_db）",
+            |params| {
+                let settings = equalizer_settings(params)?;
+                let processor = EqualizerProcessor::new(&settings);
+                let controls: Vec<BandControl> = (0..settings.bands.len()).filter_map(|index| processor.band(index)).collect();
+                // 频段数量和滤波器类型不变时，频率、Q 和增益可以在线调整
+                Ok(BuiltProcessor::new(processor).with_adjuster(move |params| {
+                    let new = equalizer_settings(params)?;
+                    let same_filters = new.bands.len() == settings.bands.len()
+                        && new.bands.iter().zip(&settings.bands).all(|(new, old)| new.filter == old.filter);
+                    Ok(same_filters.then(|| {
+                        controls
+                            .iter()
+                            .zip(&new.bands)
+                            .flat_map(|(control, band)| {
+                                [
+                                    (control.frequency.clone(), band.frequency),
+                                    (control.q.clone(), band.q),
+                                    (control.gain_db.clone(), band.gain_db),
+                                ]
+                            })
+                            .collect()
+                    }))
+                }))
+            },
+        );
+        registry.register(
+            "denoise",
+            "频谱降噪，抑制持续的背景噪声，参数 reduction_db、frame_ms、noise_window_ms、smoothing（均可省略）",
+            |params| {
+                let settings = noise_suppressor_settings(params)?;
+                let processor = NoiseSuppressor::new(settings);
+                let reduction = processor.reduction_db();
+                // 帧长等参数决定缓冲区大小和延迟，只有最大衰减量可以在线调整
+                Ok(BuiltProcessor::new(processor).with_adjuster(move |params| {
+                    let new = noise_suppressor_settings(params)?;
+                    let same = NoiseSuppressorSettings {
+                        reduction_db: settings.reduction_db,
+                        ..new
+                    } == settings;
+                    Ok(same.then(|| vec![(reduction.clone(), new.reduction_db)]))
+                }))
+            },
+        );
+        registry
+    }
+
+    /// 注册处理器类型，同名类型会被替换
+    pub fn register(&mut self, kind: &'static str, description: &'static str, build: ProcessorBuilder) {
+        self.entries.retain(|entry| entry.kind != kind);
+        self.entries.push(RegistryEntry { kind, description, build });
+    }
+
+    /// 已注册的处理器类型及说明
+    pub fn kinds(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.entries.iter().map(|entry| (entry.kind, entry.description))
+    }
+
+    /// 按配置创建单个处理器
+    pub fn build(&self, config: &ProcessorConfig) -> Result<Box<dyn AudioProcessor>> {
+        Ok(self.build_entry(config)?.processor)
+    }
+
+    fn build_entry(&self, config: &ProcessorConfig) -> Result<BuiltProcessor> {
+        let Some(entry) = self.entries.iter().find(|entry| entry.kind == config.kind) else {
+            let kinds: Vec<&str> = self.entries.iter().map(|entry| entry.kind).collect();
+            bail!("未知的处理器类型 \"{}\"，可用类型: {}", config.kind, kinds.join(", "));
+        };
+        (entry.build)(&config.params)
+    }
+
+    /// 按配置创建处理器链，出错时指出是 `name` 中的第几项
+    ///
+    /// `type = "send"` 在链中定义命名总线；处理器的 `sidechain` 参数只能引用前面已定义的总线。
+    pub fn build_chain(&self, name: &str, configs: &[ProcessorConfig]) -> Result<ProcessorChain> {
+        Ok(self.build_chain_with_controls(name, configs)?.0)
+    }
+
+    /// 与 [`ProcessorRegistry::build_chain`] 相同，同时返回运行中直接调整该链参数的 [`ChainControls`]
+    pub fn build_chain_with_controls(
+        &self,
+        name: &str,
+        configs: &[ProcessorConfig],
+    ) -> Result<(ProcessorChain, ChainControls)> {
+        let mut chain = ProcessorChain::new();
+        let mut controls = ChainControls {
+            name: name.to_string(),
+            entries: Vec::new(),
+        };
+        let mut sends: Vec<String> = Vec::new();
+        for (index, config) in configs.iter().enumerate() {
+            let context = || entry_context(name, index, config);
+            let (adjuster, meters) = if config.kind == "send" {
+                let params: SendParams = parse(&config.params).with_context(context)?;
+                chain.add_send(&params.bus);
+                sends.push(params.bus);
+                (None, Vec::new())
+            } else {
+                if let Some(toml::Value::String(bus)) = config.params.get("sidechain") {
+                    if !sends.contains(bus) {
+                        return Err(anyhow!("旁链总线 \"{}\" 需要先在前面用 type = \"send\" 定义", bus))
+                            .with_context(context);
+                    }
+                }
+                let built = self.build_entry(config).with_context(context)?;
+                chain.add_processor(built.processor);
+                (built.adjuster, built.meters)
+            };
+            controls.entries.push(ControlEntry {
+                config: config.clone(),
+                adjuster,
+                meters,
+            });
+        }
+        Ok((chain, controls))
+    }
+}
+
+fn entry_context(name: &str, index: usize, config: &ProcessorConfig) -> String {
+    format!("{} 第 {} 项 (type = \"{}\") 配置错误", name, index + 1, config.kind)
+}
+
+struct ControlEntry {
+    config: ProcessorConfig,
+    adjuster: Option<ParameterAdjuster>,
+    meters: Vec<(&'static str, Parameter)>,
+}
+
+/// 运行中直接调整处理器链参数、读取测量值的句柄，由 [`ProcessorRegistry::build_chain_with_controls`] 创建
+///
+/// 只改变参数的配置通过处理器的参数句柄写入，不需要重建处理器链，处理器保留已有的状态；
+/// 新值由各处理器自己平滑过渡，不会因参数突变产生爆音，见 [`ParameterUpdate::apply`]。
+/// 处理器放入链中后无法再取回，测量值（如压缩器的增益衰减量）通过这里按名称读取。
+pub struct ChainControls {
+    name: String,
+    entries: Vec<ControlEntry>,
+}
+
+impl ChainControls {
+    /// 算出新配置要写入参数句柄的值
+    ///
+    /// 处理器的类型和顺序不变、变化的参数都能在线调整时返回 `Some`；
+    /// 否则返回 `None`，需要重建处理器链。新参数无效时返回错误。
+    pub fn plan(&self, configs: &[ProcessorConfig]) -> Result<Option<ParameterUpdate>> {
+        if configs.len() != self.entries.len() {
+            return Ok(None);
+        }
+        let mut writes = Vec::new();
+        for (index, (entry, config)) in self.entries.iter().zip(configs).enumerate() {
+            if entry.config.kind != config.kind {
+                return Ok(None);
+            }
+            // 调整方法总是按完整的新参数计算，参数改回创建时的值也能写入
+            match &entry.adjuster {
+                Some(adjuster) => match adjuster(&config.params).with_context(|| entry_context(&self.name, index, config))? {
+                    Some(values) => writes.extend(values),
+                    None => return Ok(None),
+                },
+                None if entry.config == *config => {}
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(ParameterUpdate(writes)))
+    }
+
+    /// 第 `index` 项（从 0 开始）处理器名为 `name` 的测量值句柄
+    pub fn meter(&self, index: usize, name: &str) -> Option<Parameter> {
+        let entry = self.entries.get(index)?;
+        entry.meters.iter().find(|(meter, _)| *meter == name).map(|(_, value)| value.clone())
+    }
+
+    /// 所有测量值的简短描述，用于日志，如 `compressor[2].gain_reduction_db = 3.5`；没有测量值时为 `None`
+    pub fn describe_meters(&self) -> Option<String> {
+        let items: Vec<String> = self
+            .entries
+            .iter()
+            .enumerate()
+            .flat_map(|(index, entry)| {
+                entry.meters.iter().map(move |(name, value)| {
+                    format!("{}[{}].{} = {:.1}", entry.config.kind, index + 1, name, value.get())
+                })
+            })
+            .collect();
+        (!items.is_empty()).then(|| items.join(", "))
+    }
+}
+
+/// 待写入参数句柄的新值，由 [`ChainControls::plan`] 算出
+pub struct ParameterUpdate(Vec<(Parameter, f32)>);
+
+impl ParameterUpdate {
+    /// 写入参数句柄，处理器在下一次回调时开始向新值过渡
+    ///
+    /// 增益逐帧平滑（10 ms），均衡器的频率、Q 和增益逐块平滑（20 ms）；
+    /// 噪音门门限的变化只改变开关门的判断，增益仍按开关门时间过渡；
+    /// 降噪的最大衰减量按分析帧更新，经加窗重叠相加在相邻帧之间过渡。
+    pub fn apply(self) {
+        for (parameter, value) in self.0 {
+            parameter.set(value);
+        }
+    }
+}
+
+impl Default for ProcessorRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Empty {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SendParams {
+    bus: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GainParams {
+    gain_db: f32,
+}
+
+fn gain_amplitude(params: &toml::Table) -> Result<f32> {
+    let params: GainParams = parse(params)?;
+    check_range("gain_db", params.gain_db, -60.0, 40.0)?;
+    Ok(db_to_amplitude(params.gain_db as f64) as f32)
+}
+
+fn noise_gate_settings(params: &toml::Table) -> Result<NoiseGateSettings> {
+    let settings: NoiseGateSettings = parse(params)?;
+    check_range("threshold_db", settings.threshold_db, -120.0, 0.0)?;
+    if let Some(close) = settings.close_threshold_db {
+        check_range("close_threshold_db", close, -120.0, settings.threshold_db)?;
+    }
+    check_range("attack_ms", settings.attack_ms, 0.0, 500.0)?;
+    check_range("hold_ms", settings.hold_ms, 0.0, 5000.0)?;
+    check_range("release_ms", settings.release_ms, 1.0, 5000.0)?;
+    check_range("floor_db", settings.floor_db, -120.0, 0.0)?;
+    if settings.sidechain_hpf_hz != 0.0 {
+        check_range("sidechain_hpf_hz", settings.sidechain_hpf_hz, 10.0, 2000.0)?;
+    }
+    Ok(settings)
+}
+
+fn equalizer_settings(params: &toml::Table) -> Result<EqualizerSettings> {
+    let settings: EqualizerSettings = parse(params)?;
+    if settings.bands.is_empty() || settings.bands.len() > 16 {
+        bail!("bands 应有 1 到 16 个频段，实际 {} 个", settings.bands.len());
+    }
+    for (index, band) in settings.bands.iter().enumerate() {
+        let check = || -> Result<()> {
+            check_range("frequency", band.frequency, 10.0, 24000.0)?;
+            check_range("q", band.q, 0.1, 20.0)?;
+            check_range("gain_db", band.gain_db, -24.0, 24.0)
+        };
+        check().with_context(|| format!("第 {} 个频段", index + 1))?;
+    }
+    Ok(settings)
+}
+
+fn noise_suppressor_settings(params: &toml::Table) -> Result<NoiseSuppressorSettings> {
+    let settings: NoiseSuppressorSettings = parse(params)?;
+    check_range("reduction_db", settings.reduction_db, 0.0, 40.0)?;
+    check_range("frame_ms", settings.frame_ms, 5.0, 50.0)?;
+    check_range("noise_window_ms", settings.noise_window_ms as f32, 500.0, 10000.0)?;
+    check_range("smoothing", settings.smoothing, 0.0, 0.999)?;
+    Ok(settings)
+}
+
+/// 把参数表解析为处理器的参数结构，拒绝未知字段
+pub fn parse<T: DeserializeOwned>(params: &toml::Table) -> Result<T> {
+    toml::Value::Table(params.clone())
+        .try_into()
+        .map_err(|e: toml::de::Error| anyhow::anyhow!("参数错误: {}", e.message()))
+}
+
+/// 检查参数是否在 [min, max] 范围内
+pub fn check_range(name: &str, value: f32, min: f32, max: f32) -> Result<()> {
+    if !(min..=max).contains(&value) {
+        bail!("{} = {} 超出范围，应在 {} 到 {} 之间", name, value, min, max);
+    }
+    Ok(())
+}
+
+/// 内置处理器链预设
+pub const PRESETS: &[(&str, &str)] = &[
+    ("passthrough", "直通，不做任何处理"),
+    ("gate", "噪音门，门限 -40 dB"),
+    ("boost", "噪音门后增益 6 dB"),
+    ("voice", "噪音门、压缩器、补偿增益 6 dB 和限幅器，适合提升小声的发言"),
+    ("level", "自动增益到 -20 LUFS 后限幅，统一不同发言者的音量"),
+];
+
+/// 按预设名称或内联描述生成处理器链配置
+///
+/// 内联描述用逗号分隔处理器，冒号分隔参数，与 config.toml 中的写法对应，
+/// 如 `noise_gate:threshold_db=-34,gain:gain_db=3.5`；参数值可以是 TOML 数组或内联表，
+/// 如 `eq:bands=[{filter="high_pass",frequency=80},{filter="peaking",frequency=3000,gain_db=3}]`。
+pub fn preset(spec: &str) -> Result<Vec<ProcessorConfig>> {
+    match spec.trim() {
+        "passthrough" => return Ok(Vec::new()),
+        "gate" => return Ok(vec![ProcessorConfig::new("noise_gate").with("threshold_db", -40.0)]),
+        "boost" => {
+            return Ok(vec![
+                ProcessorConfig::new("noise_gate").with("threshold_db", -40.0),
+                ProcessorConfig::new("gain").with("gain_db", 6.0),
+            ])
+        }
+        "voice" => {
+            return Ok(vec![
+                ProcessorConfig::new("noise_gate").with("threshold_db", -50.0),
+                ProcessorConfig::new("compressor")
+                    .with("threshold_db", -24.0)
+                    .with("ratio", 3.0)
+                    .with("makeup_db", 6.0),
+                ProcessorConfig::new("limiter").with("ceiling_db", -1.0),
+            ])
+        }
+        "level" => {
+            return Ok(vec![
+                ProcessorConfig::new("agc").with("target_lufs", -20.0),
+                ProcessorConfig::new("limiter").with("ceiling_db", -1.0),
+            ])
+        }
+        _ => {}
+    }
+
+    let mut configs = Vec::new();
+    for item in split_top_level(spec, ',').into_iter().map(str::trim).filter(|item| !item.is_empty()) {
+        let mut parts = split_top_level(item, ':').into_iter();
+        let mut config = ProcessorConfig::new(parts.next().unwrap_or_default().trim());
+        for param in parts {
+            let Some((key, value)) = param.split_once('=') else {
+                bail!("处理器参数应写成 key=value: {}", param);
+            };
+            // 按 TOML 值解析（数字、布尔等），否则作为字符串
+            let value = toml::from_str::<toml::Table>(&format!("value = {}", value.trim()))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or_else(|| toml::Value::String(value.trim().to_string()));
+            config = config.with(key.trim(), value);
+        }
+        configs.push(config);
+    }
+    Ok(configs)
+}
+
+/// 按分隔符拆分，忽略括号和引号内的分隔符
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' | '{' if !quoted => depth += 1,
+            ']' | '}' if !quoted => depth -= 1,
+            c if c == separator && depth == 0 && !quoted => {
+                parts.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// 处理器链的简短描述，用于日志，如 `noise_gate(threshold_db = -40.0) → gain(gain_db = 6.0)`
+pub fn describe(configs: &[ProcessorConfig]) -> String {
+    if configs.is_empty() {
+        return "直通".to_string();
+    }
+    configs
+        .iter()
+        .map(|config| {
+            let params: Vec<String> = config.params.iter().map(|(key, value)| format!("{} = {}", key, value)).collect();
+            if params.is_empty() {
+                config.kind.clone()
+            } else {
+                format!("{}({})", config.kind, params.join(", "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Chains {
+        input_chain: Vec<ProcessorConfig>,
+    }
+
+    fn chain_configs(toml: &str) -> Vec<ProcessorConfig> {
+        toml::from_str::<Chains>(toml).unwrap().input_chain
+    }
+
+    /// 按 config.toml 的写法创建处理器链，返回完整的错误信息
+    fn build_error(toml: &str) -> String {
+        let configs = chain_configs(toml);
+        match ProcessorRegistry::with_builtin().build_chain("input_chain", &configs) {
+            Ok(_) => panic!("配置应当无效: {}", toml),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn builds_chain_from_config_toml() {
+        let configs = chain_configs(
+            r#"
+            [[input_chain]]
+            type = "noise_gate"
+            threshold_db = -40.0
+
+            [[input_chain]]
+            type = "send"
+            bus = "dry"
+
+            [[input_chain]]
+            type = "compressor"
+            ratio = 4.0
+            sidechain = "dry"
+
+            [[input_chain]]
+            type = "eq"
+            bands = [{ filter = "high_pass", frequency = 80.0 }, { filter = "peaking", frequency = 3000.0, gain_db = 3.0 }]
+            "#,
+        );
+        let mut chain = ProcessorRegistry::with_builtin().build_chain("input_chain", &configs).unwrap();
+        chain.prepare(48_000, 1);
+        chain.process(&mut [0.0; 480]).unwrap();
+        assert!(describe(&configs).starts_with("noise_gate(threshold_db = -40.0) → send(bus = \"dry\") → compressor("));
+        assert_eq!(describe(&[]), "直通");
+    }
+
+    #[test]
+    fn errors_name_the_failing_entry() {
+        let message = build_error(
+            r#"
+            [[input_chain]]
+            type = "gain"
+            gain_db = 3.0
+
+            [[input_chain]]
+            type = "reverb"
+            "#,
+        );
+        assert!(message.starts_with("input_chain 第 2 项 (type = \"reverb\") 配置错误"), "{}", message);
+        assert!(message.contains("未知的处理器类型 \"reverb\"，可用类型: passthrough, send, gain"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let unknown_field = build_error("[[input_chain]]\ntype = \"noise_gate\"\nthreshold = -40.0\n");
+        assert!(unknown_field.contains("参数错误"), "{}", unknown_field);
+        assert!(unknown_field.contains("threshold"), "{}", unknown_field);
+
+        let wrong_type = build_error("[[input_chain]]\ntype = \"gain\"\ngain_db = \"loud\"\n");
+        assert!(wrong_type.contains("参数错误"), "{}", wrong_type);
+
+        let missing = build_error("[[input_chain]]\ntype = \"gain\"\n");
+        assert!(missing.contains("gain_db"), "{}", missing);
+
+        let out_of_range = build_error("[[input_chain]]\ntype = \"compressor\"\nratio = 0.5\n");
+        assert!(out_of_range.contains("ratio = 0.5 超出范围，应在 1 到 100 之间"), "{}", out_of_range);
+
+        let band = build_error(
+            "[[input_chain]]\ntype = \"eq\"\nbands = [{ filter = \"peaking\", frequency = 1000.0 }, { filter = \"peaking\", frequency = 5.0 }]\n",
+        );
+        assert!(band.contains("第 2 个频段: frequency = 5 超出范围"), "{}", band);
+
+        let no_bands = build_error("[[input_chain]]\ntype = \"eq\"\nbands = []\n");
+        assert!(no_bands.contains("bands 应有 1 到 16 个频段"), "{}", no_bands);
+    }
+
+    #[test]
+    fn sidechain_must_follow_its_send() {
+        let message = build_error(
+            r#"
+            [[input_chain]]
+            type = "compressor"
+            sidechain = "key"
+
+            [[input_chain]]
+            type = "send"
+            bus = "key"
+            "#,
+        );
+        assert!(message.contains("第 1 项"), "{}", message);
+        assert!(message.contains("旁链总线 \"key\" 需要先在前面用 type = \"send\" 定义"), "{}", message);
+
+        let message = build_error("[[input_chain]]\ntype = \"send\"\n");
+        assert!(message.contains("参数错误"), "{}", message);
+        assert!(ProcessorRegistry::with_builtin().build(&ProcessorConfig::new("send")).is_err());
+    }
+
+    #[test]
+    fn parameter_changes_are_written_to_handles() {
+        let registry = ProcessorRegistry::with_builtin();
+        let (mut chain, controls) = registry.build_chain_with_controls("input_chain", &preset("gain:gain_db=0").unwrap()).unwrap();
+        // 增益平滑过渡，取 100 ms 后的值
+        let gain_of = |chain: &mut ProcessorChain| {
+            let mut buffer = [1.0f32; 4800];
+            chain.process(&mut buffer).unwrap();
+            buffer[4799]
+        };
+        controls.plan(&preset("gain:gain_db=-6.0206").unwrap()).unwrap().unwrap().apply();
+        assert!((gain_of(&mut chain) - 0.5).abs() < 1e-4);
+        // 改回创建时的值同样直接写入
+        controls.plan(&preset("gain:gain_db=0").unwrap()).unwrap().unwrap().apply();
+        assert_eq!(gain_of(&mut chain), 1.0);
+
+        let error = controls.plan(&preset("gain:gain_db=100").unwrap()).err().unwrap();
+        assert!(format!("{:#}", error).starts_with("input_chain 第 1 项 (type = \"gain\") 配置错误"));
+    }
+
+    #[test]
+    fn parameter_updates_glide_without_jumps() {
+        let registry = ProcessorRegistry::with_builtin();
+        let before = preset("gain:gain_db=0,noise_gate:threshold_db=-20").unwrap();
+        let (mut chain, controls) = registry.build_chain_with_controls("input_chain", &before).unwrap();
+        chain.prepare(48_000, 1);
+        // -26 dB 的直流低于门限，噪音门关闭
+        let mut buffer = vec![0.05f32; 9600];
+        chain.process(&mut buffer).unwrap();
+        assert!(buffer[9599] < 1e-4);
+
+        // 降低门限后噪音门按开门时间打开，降低增益后增益平滑过渡，输出都没有突变
+        let mut last = buffer[9599];
+        let mut reload = |spec: &str| {
+            controls.plan(&preset(spec).unwrap()).unwrap().unwrap().apply();
+            let mut buffer = vec![0.05f32; 9600];
+            chain.process(&mut buffer).unwrap();
+            let largest_step = std::iter::once(&last)
+                .chain(&buffer)
+                .zip(&buffer)
+                .map(|(previous, sample)| (sample - previous).abs())
+                .fold(0.0f32, f32::max);
+            assert!(largest_step < 0.002, "{} 之后相邻采样变化 {}", spec, largest_step);
+            last = buffer[9599];
+            last
+        };
+        assert!((reload("gain:gain_db=0,noise_gate:threshold_db=-40") - 0.05).abs() < 1e-4);
+        assert!((reload("gain:gain_db=-6.0206,noise_gate:threshold_db=-40") - 0.025).abs() < 1e-4);
+    }
+
+    #[test]
+    fn structural_changes_require_rebuild() {
+        let registry = ProcessorRegistry::with_builtin();
+        let plan = |before: &str, after: &str| {
+            let (_, controls) = registry.build_chain_with_controls("input_chain", &preset(before).unwrap()).unwrap();
+            controls.plan(&preset(after).unwrap()).unwrap().map(|update| update.0.len())
+        };
+        assert_eq!(plan("gain:gain_db=0,noise_gate", "gain:gain_db=3,noise_gate:threshold_db=-30"), Some(2));
+        assert_eq!(plan("gain:gain_db=0,noise_gate", "noise_gate,gain:gain_db=0"), None);
+        assert_eq!(plan("gain:gain_db=0", "gain:gain_db=0,limiter"), None);
+
+        // 关闭门限与打开门限的差值不变时可以调整
+        assert_eq!(plan("noise_gate:close_threshold_db=-50", "noise_gate:threshold_db=-30:close_threshold_db=-40"), Some(1));
+        assert_eq!(plan("noise_gate:close_threshold_db=-50", "noise_gate:close_threshold_db=-45"), None);
+        assert_eq!(plan("noise_gate", "noise_gate:hold_ms=200"), None);
+
+        let eq = |gain: &str, filter: &str| {
+            format!("eq:bands=[{{filter=\"high_pass\",frequency=80}},{{filter=\"{}\",frequency=3000,gain_db={}}}]", filter, gain)
+        };
+        assert_eq!(plan(&eq("3", "peaking"), &eq("-3", "peaking")), Some(6));
+        assert_eq!(plan(&eq("3", "peaking"), &eq("3", "high_shelf")), None);
+
+        assert_eq!(plan("denoise", "denoise:reduction_db=25"), Some(1));
+        assert_eq!(plan("denoise", "denoise:frame_ms=10"), None);
+        // 没有参数句柄的处理器只能重建
+        assert_eq!(plan("compressor", "compressor"), Some(0));
+        assert_eq!(plan("compressor", "compressor:ratio=8"), None);
+        assert_eq!(plan("send:bus=key,compressor:sidechain=key", "send:bus=key,compressor:sidechain=key"), Some(0));
+    }
+
+    #[test]
+    fn meters_are_read_by_name() {
+        let configs = preset("gain:gain_db=0,compressor:threshold_db=-20:ratio=4:knee_db=0:attack_ms=0,limiter").unwrap();
+        let (mut chain, controls) = ProcessorRegistry::with_builtin().build_chain_with_controls("input_chain", &configs).unwrap();
+        chain.process(&mut [0.5; 480]).unwrap();
+        // -6 dB 比门限高 14 dB，4:1 压缩衰减 10.5 dB
+        let reduction = controls.meter(1, "gain_reduction_db").unwrap().get();
+        assert!((reduction - 10.5).abs() < 0.1, "增益衰减 {} dB", reduction);
+        assert!(controls.meter(0, "gain_reduction_db").is_none());
+        assert!(controls.meter(1, "loudness_lufs").is_none());
+        let description = controls.describe_meters().unwrap();
+        assert!(description.starts_with("compressor[2].gain_reduction_db = 10.5, limiter[3].gain_reduction_db = "), "{}", description);
+
+        let (_, controls) = ProcessorRegistry::with_builtin().build_chain_with_controls("input_chain", &preset("gain:gain_db=0").unwrap()).unwrap();
+        assert!(controls.describe_meters().is_none());
+
+        // 自动增益的增益和短期响度，还没有测到有声音频时响度为负无穷
+        let (_, controls) = ProcessorRegistry::with_builtin().build_chain_with_controls("input_chain", &preset("level").unwrap()).unwrap();
+        assert_eq!(controls.meter(0, "gain_db").unwrap().get(), 0.0);
+        assert_eq!(controls.meter(0, "loudness_lufs").unwrap().get(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn presets_and_inline_specs() {
+        assert!(preset("passthrough").unwrap().is_empty());
+        let voice = preset("voice").unwrap();
+        assert_eq!(voice.iter().map(|config| config.kind.as_str()).collect::<Vec<_>>(), ["noise_gate", "compressor", "limiter"]);
+        let registry = ProcessorRegistry::with_builtin();
+        for (name, _) in PRESETS {
+            registry.build_chain(name, &preset(name).unwrap()).unwrap();
+        }
+
+        let configs = preset(
+            "noise_gate:threshold_db=-34, gain:gain_db=3.5, send:bus=dry, \
+             eq:bands=[{filter=\"high_pass\",frequency=80},{filter=\"peaking\",frequency=3000,gain_db=3}]",
+        )
+        .unwrap();
+        assert_eq!(configs.len(), 4);
+        assert_eq!(configs[0].params["threshold_db"], toml::Value::Integer(-34));
+        assert_eq!(configs[1].params["gain_db"], toml::Value::Float(3.5));
+        // 不是合法 TOML 值时按字符串处理
+        assert_eq!(configs[2].params["bus"], toml::Value::String("dry".to_string()));
+        assert_eq!(configs[3].params["bands"].as_array().unwrap().len(), 2);
+        registry.build_chain("--chain", &configs).unwrap();
+
+        let error = preset("gain:6").unwrap_err();
+        assert!(error.to_string().contains("处理器参数应写成 key=value"));
+    }
+}
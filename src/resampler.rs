@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// 重采样质量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResamplerQuality {
+    /// 16 抽头，约 40 dB 阻带衰减，CPU 占用最低
+    Fast,
+    /// 64 抽头，约 75 dB 阻带衰减
+    #[default]
+    Balanced,
+    /// 128 抽头，约 100 dB 阻带衰减
+    High,
+}
+
+impl ResamplerQuality {
+    /// (单侧抽头数, 相位数, Kaiser β, 通带边缘占奈奎斯特频率的比例)
+    fn parameters(self) -> (usize, usize, f64, f64) {
+        match self {
+            ResamplerQuality::Fast => (8, 64, 3.5, 0.75),
+            ResamplerQuality::Balanced => (32, 128, 7.5, 0.85),
+            ResamplerQuality::High => (64, 256, 10.0, 0.9),
+        }
+    }
+}
+
+/// 零阶第一类修正贝塞尔函数，用于 Kaiser 窗
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..64 {
+        term *= (half / k as f64) * (half / k as f64);
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+/// 多相加窗 sinc 重采样器，支持任意（可动态调整的）采样率比例
+///
+/// 滤波器按 `phases` 个相位预先计算，相位之间线性插值，
+/// 因此比例可以是任意实数，时钟漂移补偿时可以逐块微调。
+/// 输入输出均为交错排列，各声道独立滤波。
+pub struct Resampler {
+    channels: usize,
+    half_taps: usize,
+    phases: usize,
+    /// (phases + 1) × (2 × half_taps)，最后一行用于插值
+    coefficients: Vec<f32>,
+    /// 每个输出帧前进的输入帧数
+    step: f64,
+    nominal_step: f64,
+    /// 下一个输出帧在 history 中的位置
+    position: f64,
+    history: Vec<f32>,
+    input_rate: u32,
+    output_rate: u32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32, channels: u16, quality: ResamplerQuality) -> Self {
+        let (half_taps, phases, beta, passband) = quality.parameters();
+        let ratio = output_rate as f64 / input_rate.max(1) as f64;
+        // 降采样时过渡带按目标采样率收窄，抽头数按比例增加以保持相同的通带与阻带
+        let half_taps = half_taps * (1.0 / ratio).ceil().max(1.0) as usize;
+        let taps = half_taps * 2;
+
+        // 截止频率位于通带边缘与奈奎斯特频率的中点；降采样时按目标奈奎斯特频率收窄
+        let cutoff = (passband + 1.0) / 2.0 * ratio.min(1.0);
+        let i0_beta = bessel_i0(beta);
+
+        let mut coefficients = Vec::with_capacity((phases + 1) * taps);
+        for phase in 0..=phases {
+            let frac = phase as f64 / phases as f64;
+            let start = coefficients.len();
+            for k in 0..taps {
+                let offset = k as f64 - (half_taps as f64 - 1.0) - frac;
+                let x = offset * cutoff;
+                let sinc = if x.abs() < 1e-12 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                let window_pos = offset / half_taps as f64;
+                let window = if window_pos.abs() >= 1.0 {
+                    0.0
+                } else {
+                    bessel_i0(beta * (1.0 - window_pos * window_pos).sqrt()) / i0_beta
+                };
+                coefficients.push((cutoff * sinc * window) as f32);
+            }
+            // 每个相位归一化为单位直流增益
+            let sum: f32 = coefficients[start..].iter().sum();
+            if sum.abs() > 1e-9 {
+                for c in &mut coefficients[start..] {
+                    *c /= sum;
+                }
+            }
+        }
+
+        let channels = channels.max(1) as usize;
+        let step = input_rate as f64 / output_rate.max(1) as f64;
+        Self {
+            channels,
+            half_taps,
+            phases,
+            coefficients,
+            step,
+            nominal_step: step,
+            position: 0.0,
+            // 预填充半个滤波器长度的静音，使第一个输出帧对齐第一个输入帧
+            history: vec![0.0; (half_taps - 1) * channels],
+            input_rate,
+            output_rate,
+        }
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// 算法延迟（输入帧数）
+    pub fn latency_frames(&self) -> usize {
+        self.half_taps
+    }
+
+    /// 在标称比例基础上微调，`adjust` 为相对偏差（如 0.001 表示输出多 0.1%）
+    pub fn set_ratio_adjust(&mut self, adjust: f64) {
+        self.step = self.nominal_step / (1.0 + adjust);
+    }
+
+    /// 估算处理 `input_frames` 个输入帧后最多产生的输出帧数
+    pub fn max_output_frames(&self, input_frames: usize) -> usize {
+        ((input_frames + self.history.len() / self.channels) as f64 / self.step).ceil() as usize + 1
+    }
+
+    /// 预先分配内部缓冲，之后每次处理不超过 `input_frames` 帧时不再分配内存
+    pub fn reserve(&mut self, input_frames: usize) {
+        let frames = input_frames + self.half_taps * 2;
+        self.history.reserve(frames * self.channels - self.history.len());
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.history.resize((self.half_taps - 1) * self.channels, 0.0);
+        self.position = 0.0;
+        self.step = self.nominal_step;
+    }
+
+    /// 处理交错排列的输入，结果追加到 `output`
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        let channels = self.channels;
+        let taps = self.half_taps * 2;
+        self.history.extend_from_slice(input);
+        let frames = self.history.len() / channels;
+
+        loop {
+            let index = self.position as usize;
+            if index + taps > frames {
+                break;
+            }
+            let phase = (self.position - index as f64) * self.phases as f64;
+            let phase_index = (phase as usize).min(self.phases - 1);
+            let weight = (phase - phase_index as f64) as f32;
+            let low = &self.coefficients[phase_index * taps..(phase_index + 1) * taps];
+            let high = &self.coefficients[(phase_index + 1) * taps..(phase_index + 2) * taps];
+
+            for channel in 0..channels {
+                let mut acc = 0.0f32;
+                for k in 0..taps {
+                    let coefficient = low[k] + (high[k] - low[k]) * weight;
+                    acc += self.history[(index + k) * channels + channel] * coefficient;
+                }
+                output.push(acc);
+            }
+            self.position += self.step;
+        }
+
+        // 丢弃不再需要的历史数据
+        let consumed = (self.position as usize).min(frames);
+        self.history.drain(..consumed * channels);
+        self.position -= consumed as f64;
+    }
+
+    /// 补入半个滤波器长度的静音，输出缓存在滤波器中的尾部数据
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        let silence = vec![0.0; self.half_taps * self.channels];
+        self.process(&silence, output);
+    }
+}
+
+/// 一次性重采样整段交错音频，输出长度按比例取整
+pub fn resample(samples: &[f32], input_rate: u32, output_rate: u32, channels: u16, quality: ResamplerQuality) -> Vec<f32> {
+    if input_rate == output_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let mut resampler = Resampler::new(input_rate, output_rate, channels, quality);
+    let channels = resampler.channels;
+    let frames = samples.len() / channels;
+    let expected = (frames as f64 * output_rate as f64 / input_rate as f64).round() as usize;
+
+    let mut output = Vec::with_capacity(resampler.max_output_frames(frames) * channels);
+    resampler.process(samples, &mut output);
+    resampler.flush(&mut output);
+    output.truncate(expected * channels);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AMPLITUDE: f64 = 0.5;
+
+    /// 按块把 `frequency` 的正弦送入重采样器，返回输出中 `measure` 频率分量相对输入幅度的增益 (dB)
+    fn tone_gain(input_rate: u32, output_rate: u32, quality: ResamplerQuality, frequency: f64, measure: f64) -> f64 {
+        let mut resampler = Resampler::new(input_rate, output_rate, 1, quality);
+        let input: Vec<f32> = (0..input_rate as usize / 2)
+            .map(|n| (AMPLITUDE * (2.0 * PI * frequency * n as f64 / input_rate as f64).sin()) as f32)
+            .collect();
+        let mut output = Vec::new();
+        for block in input.chunks(256) {
+            resampler.process(block, &mut output);
+        }
+        // 跳过滤波器起始的瞬态
+        let settled = &output[resampler.latency_frames() * 4..];
+        20.0 * (component_amplitude(settled, output_rate, measure) / AMPLITUDE).log10()
+    }
+
+    /// 加 Hann 窗后估计单个频率分量的幅度
+    fn component_amplitude(samples: &[f32], sample_rate: u32, frequency: f64) -> f64 {
+        let len = samples.len() as f64;
+        let (mut re, mut im, mut weight) = (0.0, 0.0, 0.0);
+        for (n, &sample) in samples.iter().enumerate() {
+            let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / len).cos();
+            let angle = 2.0 * PI * frequency * n as f64 / sample_rate as f64;
+            re += window * sample as f64 * angle.cos();
+            im += window * sample as f64 * angle.sin();
+            weight += window;
+        }
+        2.0 * (re * re + im * im).sqrt() / weight
+    }
+
+    /// 各质量档位的 (通带纹波上限 dB, 镜像与混叠抑制下限 dB)
+    fn limits(quality: ResamplerQuality) -> (f64, f64) {
+        match quality {
+            ResamplerQuality::Fast => (0.5, 40.0),
+            ResamplerQuality::Balanced => (0.01, 75.0),
+            ResamplerQuality::High => (0.001, 100.0),
+        }
+    }
+
+    const QUALITIES: [ResamplerQuality; 3] = [ResamplerQuality::Fast, ResamplerQuality::Balanced, ResamplerQuality::High];
+
+    #[test]
+    fn passband_ripple_within_quality_limits() {
+        for quality in QUALITIES {
+            let (ripple, _) = limits(quality);
+            let edge = quality.parameters().3;
+            for (input_rate, output_rate) in [(44_100, 48_000), (48_000, 16_000)] {
+                let passband = input_rate.min(output_rate) as f64 / 2.0 * edge;
+                for step in 1..=8 {
+                    let frequency = passband * step as f64 / 8.0;
+                    let gain = tone_gain(input_rate, output_rate, quality, frequency, frequency);
+                    assert!(
+                        gain.abs() < ripple,
+                        "{:?} {} -> {} Hz 在 {:.0} Hz 的增益 {:.4} dB",
+                        quality,
+                        input_rate,
+                        output_rate,
+                        frequency,
+                        gain
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn upsampling_rejects_images() {
+        let (input_rate, output_rate) = (44_100.0, 48_000.0);
+        for quality in QUALITIES {
+            let (_, rejection) = limits(quality);
+            let passband = input_rate / 2.0 * quality.parameters().3;
+            for step in 1..=8 {
+                let frequency = passband * step as f64 / 8.0;
+                // 镜像位于 fs_in - f，高于输出奈奎斯特频率的部分折回
+                let image = input_rate - frequency;
+                let measured = if image > output_rate / 2.0 { output_rate - image } else { image };
+                let gain = tone_gain(input_rate as u32, output_rate as u32, quality, frequency, measured);
+                assert!(gain < -rejection, "{:?} {:.0} Hz 的镜像只衰减了 {:.1} dB", quality, frequency, -gain);
+            }
+        }
+    }
+
+    #[test]
+    fn downsampling_rejects_aliases() {
+        let output_rate = 16_000.0;
+        for quality in QUALITIES {
+            let (_, rejection) = limits(quality);
+            let passband = output_rate / 2.0 * quality.parameters().3;
+            // 输出奈奎斯特频率以上、混叠后落入通带的输入频率
+            for step in [-8, -6, -4, -2, 2, 4, 6, 8] {
+                let alias = passband * (step as f64).abs() / 8.0;
+                let frequency = output_rate + passband * step as f64 / 8.0;
+                let gain = tone_gain(48_000, 16_000, quality, frequency, alias);
+                assert!(gain < -rejection, "{:?} {:.0} Hz 的混叠只衰减了 {:.1} dB", quality, frequency, -gain);
+            }
+        }
+    }
+}
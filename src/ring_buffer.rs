@@ -0,0 +1,206 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Shared {
+    slots: Box<[AtomicU32]>,
+    /// 累计写入的采样数
+    head: AtomicUsize,
+    /// 累计读出的采样数
+    tail: AtomicUsize,
+}
+
+impl Shared {
+    fn len(&self) -> usize {
+        self.head
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+}
+
+/// 写入端
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+/// 读取端
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+/// 单生产者单消费者的无锁环形缓冲区（f32 采样）
+///
+/// 读写两端各自只推进自己的位置，任何操作都不加锁、不分配内存、不等待，
+/// 可以安全地在两个音频回调之间传递数据。采样以位模式存放在原子变量中，
+/// 写端先写数据再以 Release 发布位置，读端以 Acquire 读取位置后再读数据。
+///
+/// 容量为 `capacity` 个采样。
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    let shared = Arc::new(Shared {
+        slots: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+impl Producer {
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+
+    /// 当前可写入的采样数
+    pub fn free(&self) -> usize {
+        self.capacity() - self.shared.len()
+    }
+
+    /// 尽可能多地写入，返回实际写入的采样数
+    pub fn push_slice(&mut self, data: &[f32]) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.slots.len();
+        let head = shared.head.load(Ordering::Relaxed);
+        let tail = shared.tail.load(Ordering::Acquire);
+        let count = data.len().min(capacity - head.wrapping_sub(tail));
+        for (offset, &sample) in data[..count].iter().enumerate() {
+            shared.slots[head.wrapping_add(offset) % capacity].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        shared.head.store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// 读取端是否已被丢弃
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+impl Consumer {
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+
+    /// 当前可读取的采样数
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 尽可能多地读出到 `output`，返回实际读出的采样数
+    pub fn pop_slice(&mut self, output: &mut [f32]) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.slots.len();
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let head = shared.head.load(Ordering::Acquire);
+        let count = output.len().min(head.wrapping_sub(tail));
+        for (offset, sample) in output[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(shared.slots[tail.wrapping_add(offset) % capacity].load(Ordering::Relaxed));
+        }
+        shared.tail.store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// 丢弃最多 `count` 个采样，返回实际丢弃的数量
+    pub fn skip(&mut self, count: usize) -> usize {
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let count = count.min(shared.head.load(Ordering::Acquire).wrapping_sub(tail));
+        shared.tail.store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// 写入端是否已被丢弃（剩余数据仍可读出）
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around_capacity() {
+        let (mut producer, mut consumer) = ring_buffer(5);
+        let mut output = [0.0f32; 5];
+        assert_eq!(producer.push_slice(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(consumer.pop_slice(&mut output[..2]), 2);
+        assert_eq!(output[..2], [1.0, 2.0]);
+
+        // 写入跨过缓冲区末尾，只写得下剩余空间
+        assert_eq!(producer.push_slice(&[4.0, 5.0, 6.0, 7.0, 8.0]), 4);
+        assert_eq!(producer.free(), 0);
+        assert_eq!(consumer.len(), 5);
+        assert_eq!(consumer.pop_slice(&mut output), 5);
+        assert_eq!(output, [3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.pop_slice(&mut output), 0);
+    }
+
+    #[test]
+    fn positions_survive_counter_overflow() {
+        let (mut producer, mut consumer) = ring_buffer(4);
+        // 累计位置接近 usize::MAX，验证回绕后长度和读写位置仍然正确
+        producer.shared.head.store(usize::MAX - 1, Ordering::Relaxed);
+        producer.shared.tail.store(usize::MAX - 1, Ordering::Relaxed);
+        let mut output = [0.0f32; 4];
+        for round in 0..4 {
+            let value = round as f32;
+            assert_eq!(producer.push_slice(&[value, value + 0.5, value + 0.25]), 3);
+            assert_eq!(consumer.len(), 3);
+            assert_eq!(consumer.pop_slice(&mut output), 3);
+            assert_eq!(output[..3], [value, value + 0.5, value + 0.25]);
+        }
+    }
+
+    #[test]
+    fn skip_discards_oldest_samples() {
+        let (mut producer, mut consumer) = ring_buffer(4);
+        producer.push_slice(&[1.0, 2.0, 3.0]);
+        assert_eq!(consumer.skip(2), 2);
+        assert_eq!(consumer.skip(5), 1);
+        producer.push_slice(&[4.0, 5.0, 6.0, 7.0]);
+        let mut output = [0.0f32; 4];
+        assert_eq!(consumer.pop_slice(&mut output), 4);
+        assert_eq!(output, [4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn reports_abandoned_peer() {
+        let (producer, consumer) = ring_buffer(4);
+        assert!(!consumer.is_abandoned());
+        drop(producer);
+        assert!(consumer.is_abandoned());
+    }
+
+    #[test]
+    fn preserves_order_across_threads() {
+        let (mut producer, mut consumer) = ring_buffer(64);
+        const TOTAL: usize = 20_000;
+        let writer = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < TOTAL {
+                let chunk: Vec<f32> = (next..(next + 17).min(TOTAL)).map(|n| n as f32).collect();
+                let written = producer.push_slice(&chunk);
+                if written == 0 {
+                    std::thread::yield_now();
+                }
+                next += written;
+            }
+        });
+        let mut expected = 0;
+        let mut output = [0.0f32; 23];
+        while expected < TOTAL {
+            let read = consumer.pop_slice(&mut output);
+            if read == 0 {
+                std::thread::yield_now();
+            }
+            for &sample in &output[..read] {
+                assert_eq!(sample, expected as f32);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{bail, Result};
+use cpal::traits::DeviceTrait;
+use cpal::{Device, SampleFormat, SizedSample, Stream, StreamError, SupportedStreamConfig};
+
+/// 预先分配的回调缓冲区帧数，设备缓冲区不超过该值时回调中不分配内存
+pub const CALLBACK_FRAMES: usize = 8192;
+
+/// 设备采样与内部 f32 表示之间的转换
+///
+/// 整数转 f32 按 2^(N-1) 缩放，满幅负值对应 -1.0；
+/// f32 转整数先四舍五入再钳位到类型范围，超出 [-1.0, 1.0] 的值被削波。
+pub trait AudioSample: SizedSample + Send + 'static {
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+}
+
+impl AudioSample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+impl AudioSample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value * 32768.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+    }
+}
+
+impl AudioSample for u16 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 32768.0) / 32768.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        ((value * 32768.0).round() + 32768.0).clamp(u16::MIN as f32, u16::MAX as f32) as u16
+    }
+}
+
+impl AudioSample for i32 {
+    fn to_f32(self) -> f32 {
+        (self as f64 / 2_147_483_648.0) as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        // 用 f64 计算，避免 f32 精度不足导致满幅值溢出
+        (value as f64 * 2_147_483_648.0)
+            .round()
+            .clamp(i32::MIN as f64, i32::MAX as f64) as i32
+    }
+}
+
+/// 选择设备配置时的格式优先级，数值越小越优先；不支持的格式返回 None
+pub fn format_rank(format: SampleFormat) -> Option<u8> {
+    match format {
+        SampleFormat::F32 => Some(0),
+        SampleFormat::I32 => Some(1),
+        SampleFormat::I16 => Some(2),
+        SampleFormat::U16 => Some(3),
+        _ => None,
+    }
+}
+
+/// 按设备原生格式创建输入流，回调中收到转换后的 f32 数据
+///
+/// 转换缓冲区在回调之间复用，只在设备缓冲区变大时重新分配。
+pub fn build_input_stream<F, E>(
+    device: &Device,
+    supported: &SupportedStreamConfig,
+    on_data: F,
+    on_error: E,
+) -> Result<Stream>
+where
+    F: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    match supported.sample_format() {
+        SampleFormat::F32 => build_input::<f32, _, _>(device, supported, on_data, on_error),
+        SampleFormat::I32 => build_input::<i32, _, _>(device, supported, on_data, on_error),
+        SampleFormat::I16 => build_input::<i16, _, _>(device, supported, on_data, on_error),
+        SampleFormat::U16 => build_input::<u16, _, _>(device, supported, on_data, on_error),
+        other => bail!("不支持的输入采样格式: {:?}", other),
+    }
+}
+
+/// 按设备原生格式创建输出流，回调中填写 f32 数据（已清零），随后转换为设备格式
+pub fn build_output_stream<F, E>(
+    device: &Device,
+    supported: &SupportedStreamConfig,
+    on_data: F,
+    on_error: E,
+) -> Result<Stream>
+where
+    F: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    match supported.sample_format() {
+        SampleFormat::F32 => build_output::<f32, _, _>(device, supported, on_data, on_error),
+        SampleFormat::I32 => build_output::<i32, _, _>(device, supported, on_data, on_error),
+        SampleFormat::I16 => build_output::<i16, _, _>(device, supported, on_data, on_error),
+        SampleFormat::U16 => build_output::<u16, _, _>(device, supported, on_data, on_error),
+        other => bail!("不支持的输出采样格式: {:?}", other),
+    }
+}
+
+fn build_input<T, F, E>(
+    device: &Device,
+    supported: &SupportedStreamConfig,
+    mut on_data: F,
+    on_error: E,
+) -> Result<Stream>
+where
+    T: AudioSample,
+    F: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let mut buffer: Vec<f32> = Vec::with_capacity(CALLBACK_FRAMES * supported.channels() as usize);
+    let stream = device.build_input_stream(
+        &supported.config(),
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            buffer.clear();
+            buffer.extend(data.iter().map(|&sample| sample.to_f32()));
+            on_data(&mut buffer);
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn build_output<T, F, E>(
+    device: &Device,
+    supported: &SupportedStreamConfig,
+    mut on_data: F,
+    on_error: E,
+) -> Result<Stream>
+where
+    T: AudioSample,
+    F: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let mut buffer: Vec<f32> = Vec::with_capacity(CALLBACK_FRAMES * supported.channels() as usize);
+    let stream = device.build_output_stream(
+        &supported.config(),
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            buffer.clear();
+            buffer.resize(data.len(), 0.0);
+            on_data(&mut buffer);
+            for (out, &sample) in data.iter_mut().zip(buffer.iter()) {
+                *out = T::from_f32(sample);
+            }
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f32_clips_to_full_scale() {
+        for (input, expected) in [
+            (0.25, 0.25),
+            (1.0, 1.0),
+            (-1.0, -1.0),
+            (1.000_1, 1.0),
+            (-3.0, -1.0),
+        ] {
+            assert_eq!(f32::from_f32(input), expected);
+        }
+    }
+
+    #[test]
+    fn i16_conversion_table() {
+        let to_i16 = [
+            (0.0, 0),
+            (0.5, 16384),
+            (-0.5, -16384),
+            (1.0, i16::MAX),
+            (1.000_1, i16::MAX),
+            (2.0, i16::MAX),
+            (-1.0, i16::MIN),
+            (-1.000_1, i16::MIN),
+            // 四舍五入到最近的整数，0.5 远离零
+            (1.4 / 32768.0, 1),
+            (1.5 / 32768.0, 2),
+            (-1.5 / 32768.0, -2),
+            (-0.4 / 32768.0, 0),
+        ];
+        for (input, expected) in to_i16 {
+            assert_eq!(i16::from_f32(input), expected, "{}", input);
+        }
+        assert_eq!(i16::MIN.to_f32(), -1.0);
+        assert_eq!(i16::MAX.to_f32(), 32767.0 / 32768.0);
+        assert_eq!(0i16.to_f32(), 0.0);
+    }
+
+    #[test]
+    fn u16_conversion_table() {
+        let to_u16 = [
+            (0.0, 32768),
+            (-1.0, 0),
+            (-1.5, 0),
+            (1.0, u16::MAX),
+            (1.000_1, u16::MAX),
+            (0.5, 49152),
+            (-0.5, 16384),
+        ];
+        for (input, expected) in to_u16 {
+            assert_eq!(u16::from_f32(input), expected, "{}", input);
+        }
+        assert_eq!(32768u16.to_f32(), 0.0);
+        assert_eq!(0u16.to_f32(), -1.0);
+        assert_eq!(u16::MAX.to_f32(), 32767.0 / 32768.0);
+    }
+
+    #[test]
+    fn i32_conversion_table() {
+        let to_i32 = [
+            (0.0, 0),
+            (0.5, 1 << 30),
+            (-0.5, -(1 << 30)),
+            (1.0, i32::MAX),
+            (1.000_1, i32::MAX),
+            (-1.0, i32::MIN),
+            (-1.5, i32::MIN),
+        ];
+        for (input, expected) in to_i32 {
+            assert_eq!(i32::from_f32(input), expected, "{}", input);
+        }
+        assert_eq!(i32::MIN.to_f32(), -1.0);
+        assert_eq!((1i32 << 30).to_f32(), 0.5);
+    }
+
+    #[test]
+    fn integer_round_trips_are_exact() {
+        for value in i16::MIN..=i16::MAX {
+            assert_eq!(i16::from_f32(value.to_f32()), value);
+        }
+        for value in u16::MIN..=u16::MAX {
+            assert_eq!(u16::from_f32(value.to_f32()), value);
+        }
+        // f32 有 24 位有效数字，24 位精度以内的 32 位整数可以精确往返
+        for step in (-(1i32 << 23)..(1 << 23)).step_by(997) {
+            let value = step << 8;
+            assert_eq!(i32::from_f32(value.to_f32()), value);
+        }
+        assert_eq!(i32::from_f32(i32::MIN.to_f32()), i32::MIN);
+    }
+
+    #[test]
+    fn f32_is_preferred_format() {
+        use SampleFormat::{F32, I16, I32, U16, U8};
+        let mut formats = [U16, I16, F32, I32];
+        formats.sort_by_key(|&format| format_rank(format));
+        assert_eq!(formats, [F32, I32, I16, U16]);
+        assert_eq!(format_rank(U8), None);
+    }
+}
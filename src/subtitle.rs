@@ -0,0 +1,359 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::Receiver;
+use log::{info, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::asr::TranscriptEvent;
+use crate::config::SubtitleConfig;
+use crate::translate::TranslatedSegment;
+
+/// 一条字幕，时间戳相对于音频流起点
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleCue {
+    pub start: Duration,
+    pub end: Duration,
+    /// 识别出的原文
+    pub text: String,
+    /// 译文，未启用翻译时为空
+    pub translation: Option<String>,
+}
+
+impl From<&TranscriptEvent> for SubtitleCue {
+    fn from(event: &TranscriptEvent) -> Self {
+        Self {
+            start: event.segment.start,
+            end: event.segment.end,
+            text: event.segment.text.clone(),
+            translation: None,
+        }
+    }
+}
+
+impl From<&TranslatedSegment> for SubtitleCue {
+    fn from(segment: &TranslatedSegment) -> Self {
+        Self {
+            start: segment.start,
+            end: segment.end,
+            text: segment.source_text.clone(),
+            translation: Some(segment.text.clone()),
+        }
+    }
+}
+
+/// 格式化字幕时间戳：SRT 使用逗号分隔毫秒，WebVTT 使用句点
+pub fn format_timestamp(time: Duration, millis_separator: char) -> String {
+    let total_ms = time.as_millis();
+    let hours = total_ms / 3_600_000;
+    let minutes = total_ms / 60_000 % 60;
+    let seconds = total_ms / 1000 % 60;
+    let millis = total_ms % 1000;
+    format!("{:02}:{:02}:{:02}{}{:03}", hours, minutes, seconds, millis_separator, millis)
+}
+
+/// 中日韩文字之间可以任意断行
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{30FF}'   // 标点、假名
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}' // 韩文
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF00}'..='\u{FFEF}') // 全角符号
+}
+
+/// 按字符数折行：英文等按单词断行，中日韩文字逐字断行，超长单词强制截断
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_len = 0;
+
+    // 拆分为不可再分的片段：单词或单个中日韩字符，并记录前面是否有空格
+    let mut tokens: Vec<(String, bool)> = Vec::new();
+    let mut word = String::new();
+    let mut word_spaced = false;
+    let mut pending_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() || is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push((std::mem::take(&mut word), word_spaced));
+            }
+            if c.is_whitespace() {
+                pending_space = true;
+            } else {
+                tokens.push((c.to_string(), pending_space));
+                pending_space = false;
+            }
+        } else {
+            if word.is_empty() {
+                word_spaced = pending_space;
+                pending_space = false;
+            }
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push((word, word_spaced));
+    }
+
+    for (token, spaced) in tokens {
+        let token_len = token.chars().count();
+        let separator = if spaced && line_len > 0 { 1 } else { 0 };
+
+        if line_len > 0 && line_len + separator + token_len > width {
+            lines.push(std::mem::take(&mut line));
+            line_len = 0;
+        } else if separator == 1 {
+            line.push(' ');
+            line_len += 1;
+        }
+
+        // 单个片段超过行宽时强制截断
+        let mut chars = token.chars().peekable();
+        while chars.peek().is_some() {
+            if line_len == width {
+                lines.push(std::mem::take(&mut line));
+                line_len = 0;
+            }
+            let take = width - line_len;
+            let piece: String = chars.by_ref().take(take).collect();
+            line_len += piece.chars().count();
+            line.push_str(&piece);
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// 字幕文件写入器，同时生成 SRT 和 WebVTT
+///
+/// 每条字幕以一次写入追加到文件末尾并立即落盘，
+/// 程序异常退出时文件中只会缺少最后一条，不会出现半条字幕。
+pub struct SubtitleWriter {
+    srt: File,
+    vtt: File,
+    srt_path: PathBuf,
+    vtt_path: PathBuf,
+    next_index: usize,
+    config: SubtitleConfig,
+}
+
+impl SubtitleWriter {
+    /// 在 `dir` 下创建以启动时间命名的字幕文件
+    pub fn create(dir: &Path, config: SubtitleConfig) -> Result<Self> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self::open(dir, &format!("captions-{}", stamp), config)
+    }
+
+    /// 打开 `dir/{stem}.srt` 和 `dir/{stem}.vtt`，已存在时继续追加
+    pub fn open(dir: &Path, stem: &str, config: SubtitleConfig) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("创建字幕目录失败: {}", dir.display()))?;
+        let srt_path = dir.join(format!("{}.srt", stem));
+        let vtt_path = dir.join(format!("{}.vtt", stem));
+
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("打开字幕文件失败: {}", path.display()))
+        };
+        let srt = open(&srt_path)?;
+        let mut vtt = open(&vtt_path)?;
+
+        let existing = fs::read_to_string(&srt_path).unwrap_or_default();
+        let next_index = existing.split("\n\n").filter(|block| !block.trim().is_empty()).count() + 1;
+
+        if vtt.metadata()?.len() == 0 {
+            vtt.write_all(b"WEBVTT\n\n")?;
+            vtt.sync_data()?;
+        }
+
+        Ok(Self {
+            srt,
+            vtt,
+            srt_path,
+            vtt_path,
+            next_index,
+            config,
+        })
+    }
+
+    pub fn srt_path(&self) -> &Path {
+        &self.srt_path
+    }
+
+    pub fn vtt_path(&self) -> &Path {
+        &self.vtt_path
+    }
+
+    /// 字幕正文：双语模式下原文在上、译文在下，否则优先显示译文
+    fn cue_lines(&self, cue: &SubtitleCue) -> Vec<String> {
+        let width = self.config.line_width;
+        match &cue.translation {
+            Some(translation) if self.config.bilingual => {
+                let mut lines = wrap_text(&cue.text, width);
+                lines.extend(wrap_text(translation, width));
+                lines
+            }
+            Some(translation) => wrap_text(translation, width),
+            None => wrap_text(&cue.text, width),
+        }
+    }
+
+    pub fn write_cue(&mut self, cue: &SubtitleCue) -> Result<()> {
+        let lines = self.cue_lines(cue);
+        if lines.is_empty() {
+            return Ok(());
+        }
+        // 结束时间至少比开始晚 1 毫秒，部分播放器会忽略零时长字幕
+        let end = cue.end.max(cue.start + Duration::from_millis(1));
+        let body = lines.join("\n");
+
+        let srt_block = format!(
+            "{}\n{} --> {}\n{}\n\n",
+            self.next_index,
+            format_timestamp(cue.start, ','),
+            format_timestamp(end, ','),
+            body
+        );
+        let vtt_block = format!(
+            "{}\n{} --> {}\n{}\n\n",
+            self.next_index,
+            format_timestamp(cue.start, '.'),
+            format_timestamp(end, '.'),
+            body
+        );
+
+        self.srt.write_all(srt_block.as_bytes()).context("写入 SRT 字幕失败")?;
+        self.srt.sync_data()?;
+        self.vtt.write_all(vtt_block.as_bytes()).context("写入 WebVTT 字幕失败")?;
+        self.vtt.sync_data()?;
+        self.next_index += 1;
+        Ok(())
+    }
+}
+
+/// 启动字幕写入线程
+pub fn spawn_writer(mut writer: SubtitleWriter, cues: Receiver<SubtitleCue>) -> Result<JoinHandle<()>> {
+    let handle = thread::Builder::new()
+        .name("subtitles".to_string())
+        .spawn(move || {
+            info!(
+                "字幕输出: {} / {}",
+                writer.srt_path().display(),
+                writer.vtt_path().display()
+            );
+            for cue in cues {
+                if let Err(e) = writer.write_cue(&cue) {
+                    warn!("写入字幕失败: {:#}", e);
+                }
+            }
+        })
+        .context("启动字幕线程失败")?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start_ms: u64, end_ms: u64, text: &str, translation: Option<&str>) -> SubtitleCue {
+        SubtitleCue {
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+            text: text.to_string(),
+            translation: translation.map(str::to_string),
+        }
+    }
+
+    /// 测试用的临时目录，每个测试独立
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("trans-subtitle-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn formats_srt_and_vtt_timestamps() {
+        let cases = [
+            (0, ',', "00:00:00,000"),
+            (999, ',', "00:00:00,999"),
+            (61_001, '.', "00:01:01.001"),
+            (3_599_999, ',', "00:59:59,999"),
+            (3_600_000, '.', "01:00:00.000"),
+            (36_000_000 + 62_345, ',', "10:01:02,345"),
+        ];
+        for (ms, separator, expected) in cases {
+            assert_eq!(format_timestamp(Duration::from_millis(ms), separator), expected);
+        }
+        // 不足 1 毫秒的部分舍去
+        assert_eq!(format_timestamp(Duration::from_micros(1_999), ','), "00:00:00,001");
+    }
+
+    #[test]
+    fn wraps_words_and_cjk() {
+        assert_eq!(wrap_text("the quick brown fox", 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap_text("大家好欢迎参加会议", 4), vec!["大家好欢", "迎参加会", "议"]);
+        // 中英混排：中文逐字断行，英文单词保持完整，原有空格保留
+        assert_eq!(wrap_text("今天讨论 Rust 语言", 6), vec!["今天讨论", "Rust 语", "言"]);
+        assert_eq!(wrap_text("你好world", 4), vec!["你好", "worl", "d"]);
+        // 超长单词强制截断，多余空白合并
+        assert_eq!(wrap_text("supercalifragilistic  ok", 8), vec!["supercal", "ifragili", "stic ok"]);
+        assert!(wrap_text("   ", 10).is_empty());
+    }
+
+    #[test]
+    fn writes_matching_srt_and_vtt_blocks() {
+        let dir = temp_dir("write");
+        let config = SubtitleConfig {
+            line_width: 42,
+            bilingual: true,
+        };
+        let mut writer = SubtitleWriter::open(&dir, "captions", config.clone()).unwrap();
+        writer.write_cue(&cue(1_500, 3_250, "你好", Some("hello"))).unwrap();
+        // 零时长字幕延长 1 毫秒
+        writer.write_cue(&cue(4_000, 4_000, "再见", None)).unwrap();
+        drop(writer);
+
+        let srt = fs::read_to_string(dir.join("captions.srt")).unwrap();
+        assert_eq!(
+            srt,
+            "1\n00:00:01,500 --> 00:00:03,250\n你好\nhello\n\n2\n00:00:04,000 --> 00:00:04,001\n再见\n\n"
+        );
+        let vtt = fs::read_to_string(dir.join("captions.vtt")).unwrap();
+        assert_eq!(
+            vtt,
+            "WEBVTT\n\n1\n00:00:01.500 --> 00:00:03.250\n你好\nhello\n\n2\n00:00:04.000 --> 00:00:04.001\n再见\n\n"
+        );
+
+        // 重新打开时继续编号，不重复写入 WEBVTT 头
+        let mut writer = SubtitleWriter::open(&dir, "captions", config).unwrap();
+        writer.write_cue(&cue(5_000, 6_000, "继续", None)).unwrap();
+        let srt = fs::read_to_string(dir.join("captions.srt")).unwrap();
+        assert!(srt.ends_with("3\n00:00:05,000 --> 00:00:06,000\n继续\n\n"));
+        let vtt = fs::read_to_string(dir.join("captions.vtt")).unwrap();
+        assert_eq!(vtt.matches("WEBVTT").count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn monolingual_cues_prefer_translation() {
+        let dir = temp_dir("monolingual");
+        let mut writer = SubtitleWriter::open(&dir, "captions", SubtitleConfig::default()).unwrap();
+        writer.write_cue(&cue(0, 1_000, "你好", Some("hello"))).unwrap();
+        let srt = fs::read_to_string(writer.srt_path()).unwrap();
+        assert_eq!(srt, "1\n00:00:00,000 --> 00:00:01,000\nhello\n\n");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,762 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use log::error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::audio_io::{AudioStream, Direction, StreamConfig, StreamHooks, StreamParts};
+use crate::backend::AudioBackend;
+use crate::config::{AudioConfig, RecoveryConfig};
+use crate::live_chain::LiveChainHandle;
+use crate::processor::ProcessorChain;
+
+/// 音频流的设备状态事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// 音频流出错或设备断开，音频流已停止
+    Failed { direction: Direction, error: String },
+    /// 所有候选设备都无法打开，等待 `delay` 后第 `attempt` 次重试
+    Retrying {
+        direction: Direction,
+        attempt: u32,
+        delay: Duration,
+        error: String,
+    },
+    /// 音频流已恢复，`fallback` 表示使用的是备用设备
+    Recovered {
+        direction: Direction,
+        input: String,
+        output: String,
+        fallback: bool,
+    },
+    /// 首选设备重新可用，已从备用设备切回
+    Restored {
+        direction: Direction,
+        input: String,
+        output: String,
+    },
+}
+
+impl StreamEvent {
+    pub fn direction(&self) -> Direction {
+        match self {
+            StreamEvent::Failed { direction, .. }
+            | StreamEvent::Retrying { direction, .. }
+            | StreamEvent::Recovered { direction, .. }
+            | StreamEvent::Restored { direction, .. } => *direction,
+        }
+    }
+
+    /// 音频流是否处于异常状态（已停止或使用备用设备）
+    pub fn is_degraded(&self) -> bool {
+        match self {
+            StreamEvent::Failed { .. } | StreamEvent::Retrying { .. } => true,
+            StreamEvent::Recovered { fallback, .. } => *fallback,
+            StreamEvent::Restored { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for StreamEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = self.direction().label();
+        match self {
+            StreamEvent::Failed { error, .. } => write!(f, "{}流已停止: {}", label, error),
+            StreamEvent::Retrying { attempt, delay, error, .. } => write!(
+                f,
+                "{}流所有设备都无法打开，{:.1} 秒后第 {} 次重试: {}",
+                label,
+                delay.as_secs_f32(),
+                attempt,
+                error
+            ),
+            StreamEvent::Recovered { input, output, fallback: true, .. } => {
+                write!(f, "{}流使用备用设备: {} -> {}", label, input, output)
+            }
+            StreamEvent::Recovered { input, output, fallback: false, .. } => {
+                write!(f, "{}流已恢复: {} -> {}", label, input, output)
+            }
+            StreamEvent::Restored { input, output, .. } => {
+                write!(f, "{}流首选设备已恢复，切回: {} -> {}", label, input, output)
+            }
+        }
+    }
+}
+
+/// 指定方向音频流的输入设备、输出设备和格式配置
+pub fn stream_settings(config: &AudioConfig, direction: Direction) -> (&str, &str, StreamConfig) {
+    let (input_name, output_name, input_channels, output_channels) = match direction {
+        Direction::Input => (
+            &config.input_device_name,
+            &config.vbcable_input_name,
+            config.channels.input_device,
+            config.channels.vbcable_input,
+        ),
+        Direction::Output => (
+            &config.vbcable_output_name,
+            &config.output_device_name,
+            config.channels.vbcable_output,
+            config.channels.output_device,
+        ),
+    };
+    let stream_config = StreamConfig {
+        sample_rate: config.sample_rate,
+        buffer_size: config.buffer_size,
+        input_channels,
+        output_channels,
+        resample_quality: config.resample_quality,
+        jitter: config.jitter,
+    };
+    (input_name, output_name, stream_config)
+}
+
+/// 按优先顺序排列的 (输入设备, 输出设备) 候选
+///
+/// 只替换物理设备（输入流的麦克风、输出流的扬声器）：首选设备、备用设备、系统默认设备；
+/// 虚拟声卡是会议软件约定好的设备，不做替换。未启用自动恢复时只有首选设备。
+pub fn candidates(backend: &dyn AudioBackend, config: &AudioConfig, direction: Direction) -> Vec<(String, String)> {
+    let (input_name, output_name, _) = stream_settings(config, direction);
+    let recovery = &config.recovery;
+    let (preferred, fallbacks) = match direction {
+        Direction::Input => (input_name, &recovery.input_fallbacks),
+        Direction::Output => (output_name, &recovery.output_fallbacks),
+    };
+
+    let mut devices = vec![preferred.to_string()];
+    if recovery.enabled {
+        devices.extend(fallbacks.iter().cloned());
+        if recovery.system_default {
+            let default = match direction {
+                Direction::Input => backend.default_input(),
+                Direction::Output => backend.default_output(),
+            };
+            devices.extend(default);
+        }
+    }
+    let mut seen = Vec::new();
+    devices.retain(|device| {
+        let new = !seen.contains(device);
+        seen.push(device.clone());
+        new
+    });
+
+    devices
+        .into_iter()
+        .map(|device| match direction {
+            Direction::Input => (device, output_name.to_string()),
+            Direction::Output => (input_name.to_string(), device),
+        })
+        .collect()
+}
+
+/// 第 `attempt` 次重试前的等待时间：从 `retry_initial_ms` 开始加倍，不超过 `retry_max_ms`
+pub fn retry_delay(recovery: &RecoveryConfig, attempt: u32) -> Duration {
+    let initial = recovery.retry_initial_ms as u64;
+    let delay = initial.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    Duration::from_millis(delay.min(recovery.retry_max_ms.max(recovery.retry_initial_ms) as u64))
+}
+
+/// 一个方向的音频流：处理器链在线替换，设备变化时单独重启，设备断开时自动恢复
+///
+/// 由主循环定期调用 [`RunningStream::supervise`]：记录回调中的处理错误；音频流报告错误（如设备被拔出）后停止，
+/// 依次尝试候选设备，全部失败时按退避间隔重试；使用备用设备期间定期检查首选设备，
+/// 可用时切回。状态变化通过 [`StreamEvent`] 发出。
+pub struct RunningStream {
+    direction: Direction,
+    stream: Option<AudioStream>,
+    /// 音频流停止期间保存的处理器链和旁路
+    parts: Option<StreamParts>,
+    chain: LiveChainHandle,
+    /// 当前使用的 (输入设备, 输出设备)，与候选列表中的名称对应
+    devices: (String, String),
+    /// 连续重试失败的次数
+    attempt: u32,
+    /// 音频流停止时下一次重试的时间
+    next_retry: Instant,
+    /// 使用备用设备时下一次检查首选设备的时间
+    next_restore_check: Instant,
+    events: Sender<StreamEvent>,
+}
+
+impl RunningStream {
+    /// 在首选设备上启动音频流，首选设备不可用时尝试备用设备
+    pub fn start(
+        backend: &dyn AudioBackend,
+        config: &AudioConfig,
+        direction: Direction,
+        processor: ProcessorChain,
+        chain: LiveChainHandle,
+        hooks: StreamHooks,
+        events: Sender<StreamEvent>,
+    ) -> Result<Self> {
+        let now = Instant::now();
+        let mut running = Self {
+            direction,
+            stream: None,
+            parts: Some(StreamParts { processor, hooks }),
+            chain,
+            devices: (String::new(), String::new()),
+            attempt: 0,
+            next_retry: now,
+            next_restore_check: now,
+            events,
+        };
+        let candidates = candidates(backend, config, direction);
+        let index = running.start_first(backend, config, &candidates)?;
+        if index > 0 {
+            running.recovered(config, now, true);
+        }
+        Ok(running)
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// 正在运行的音频流，停止期间为 None
+    pub fn stream(&self) -> Option<&AudioStream> {
+        self.stream.as_ref()
+    }
+
+    /// 替换处理器链的句柄
+    pub fn chain(&self) -> &LiveChainHandle {
+        &self.chain
+    }
+
+    /// 按新配置在首选设备上重启音频流，处理器链和旁路沿用
+    pub fn restart(&mut self, backend: &dyn AudioBackend, config: &AudioConfig) -> Result<()> {
+        let (input_name, output_name, stream_config) = stream_settings(config, self.direction);
+        // 先确认新设备可用，不可用时不打断正在运行的音频流
+        AudioStream::probe(backend, input_name, output_name, &stream_config)?;
+        let devices = (input_name.to_string(), output_name.to_string());
+        self.launch(backend, config, devices)?;
+        self.attempt = 0;
+        Ok(())
+    }
+
+    /// 在当前设备上重启音频流，处理器链按新的格式重新准备
+    pub fn reopen(&mut self, backend: &dyn AudioBackend, config: &AudioConfig) -> Result<()> {
+        let devices = self.devices.clone();
+        self.launch(backend, config, devices)
+    }
+
+    /// 输入设备已没有更多数据（如输入文件已读完）
+    pub fn is_finished(&self) -> bool {
+        self.stream.as_ref().is_some_and(AudioStream::is_finished)
+    }
+
+    /// 检查音频流状态：出错时停止并重试，使用备用设备时检查首选设备是否恢复
+    pub fn supervise(&mut self, backend: &dyn AudioBackend, config: &AudioConfig, now: Instant) {
+        self.report_callback_errors();
+        let recovery = &config.recovery;
+        if !recovery.enabled || self.is_lost() {
+            return;
+        }
+
+        if let Some(stream) = &self.stream {
+            let Some(error) = stream.take_error() else {
+                if now >= self.next_restore_check {
+                    self.next_restore_check = now + Duration::from_millis(recovery.restore_check_ms as u64);
+                    self.restore(backend, config, now);
+                }
+                return;
+            };
+            self.emit(StreamEvent::Failed {
+                direction: self.direction,
+                error,
+            });
+            self.stop();
+            if self.is_lost() {
+                return;
+            }
+            // 出错后立即尝试一次，通常可以马上切换到备用设备
+            self.attempt = 0;
+            self.next_retry = now;
+        }
+
+        if now < self.next_retry {
+            return;
+        }
+        let candidates = candidates(backend, config, self.direction);
+        match self.start_first(backend, config, &candidates) {
+            Ok(index) => self.recovered(config, now, index > 0),
+            Err(e) => {
+                self.attempt += 1;
+                let delay = retry_delay(recovery, self.attempt);
+                self.next_retry = now + delay;
+                self.emit(StreamEvent::Retrying {
+                    direction: self.direction,
+                    attempt: self.attempt,
+                    delay,
+                    error: format!("{:#}", e),
+                });
+            }
+        }
+    }
+
+    /// 记录回调中产生的处理错误，这类错误不影响音频流继续运行
+    fn report_callback_errors(&self) {
+        let Some(stream) = &self.stream else {
+            return;
+        };
+        let label = self.direction.label();
+        let (errors, dropped) = stream.take_callback_errors();
+        for e in errors {
+            error!("{}流处理音频数据失败: {:#}", label, e);
+        }
+        if dropped > 0 {
+            error!("{}流另有 {} 个处理错误未能记录", label, dropped);
+        }
+    }
+
+    /// 使用备用设备时，优先级更高的设备可用则切换过去
+    fn restore(&mut self, backend: &dyn AudioBackend, config: &AudioConfig, now: Instant) {
+        let candidates = candidates(backend, config, self.direction);
+        let current = candidates
+            .iter()
+            .position(|devices| *devices == self.devices)
+            .unwrap_or(candidates.len());
+        let (_, _, stream_config) = stream_settings(config, self.direction);
+        let Some(devices) = candidates[..current]
+            .iter()
+            .find(|(input, output)| AudioStream::probe(backend, input, output, &stream_config).is_ok())
+        else {
+            return;
+        };
+        match self.launch(backend, config, devices.clone()) {
+            Ok(()) => {
+                let stream = self.stream.as_ref();
+                self.emit(StreamEvent::Restored {
+                    direction: self.direction,
+                    input: stream.map_or_else(String::new, |stream| stream.input_name().to_string()),
+                    output: stream.map_or_else(String::new, |stream| stream.output_name().to_string()),
+                });
+            }
+            // 处理器链已丢失，`stop` 已发出最终的 Failed 事件
+            Err(_) if self.is_lost() => {}
+            // 原来的音频流已经停止，回到候选设备中重新选择
+            Err(e) => {
+                self.emit(StreamEvent::Failed {
+                    direction: self.direction,
+                    error: format!("{:#}", e),
+                });
+                self.attempt = 0;
+                self.next_retry = now;
+            }
+        }
+    }
+
+    fn recovered(&mut self, config: &AudioConfig, now: Instant, fallback: bool) {
+        self.attempt = 0;
+        self.next_restore_check = now + Duration::from_millis(config.recovery.restore_check_ms as u64);
+        let Some(stream) = &self.stream else {
+            return;
+        };
+        self.emit(StreamEvent::Recovered {
+            direction: self.direction,
+            input: stream.input_name().to_string(),
+            output: stream.output_name().to_string(),
+            fallback,
+        });
+    }
+
+    /// 依次尝试候选设备，返回成功的序号；全部失败时返回首选设备的错误
+    fn start_first(
+        &mut self,
+        backend: &dyn AudioBackend,
+        config: &AudioConfig,
+        candidates: &[(String, String)],
+    ) -> Result<usize> {
+        let mut first_error = None;
+        for (index, devices) in candidates.iter().enumerate() {
+            match self.launch(backend, config, devices.clone()) {
+                Ok(()) => return Ok(index),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or_else(|| anyhow::anyhow!("没有可用的音频设备")))
+    }
+
+    /// 停止当前音频流（如果有），在指定设备上重新启动
+    fn launch(&mut self, backend: &dyn AudioBackend, config: &AudioConfig, devices: (String, String)) -> Result<()> {
+        let (_, _, stream_config) = stream_settings(config, self.direction);
+        self.stop();
+        let parts = self.parts.take().context("音频流的处理器链已丢失")?;
+        let is_input_direction = self.direction == Direction::Input;
+        match AudioStream::start(backend, &devices.0, &devices.1, stream_config, parts, is_input_direction) {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.devices = devices;
+                Ok(())
+            }
+            Err((e, parts)) => {
+                self.parts = Some(*parts);
+                Err(e)
+            }
+        }
+    }
+
+    /// 停止音频流，取回处理器链和旁路
+    ///
+    /// 回调没有交还处理器链时无法再启动音频流，发出最终的 [`StreamEvent::Failed`]，之后不再重试。
+    fn stop(&mut self) {
+        let Some(stream) = self.stream.take() else {
+            return;
+        };
+        match stream.shutdown() {
+            Ok(parts) => self.parts = Some(parts),
+            Err(e) => self.emit(StreamEvent::Failed {
+                direction: self.direction,
+                error: format!("停止时未能取回处理器链，不再重试: {:#}", e),
+            }),
+        }
+    }
+
+    /// 处理器链已丢失，音频流无法再启动
+    fn is_lost(&self) -> bool {
+        self.stream.is_none() && self.parts.is_none()
+    }
+
+    fn emit(&self, event: StreamEvent) {
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{ActiveStream, AudioDevice, DataCallback, DeviceFormat, ErrorCallback, FormatRequest};
+    use crate::live_chain::LiveChain;
+    use crate::processor::{GainProcessor, Parameter};
+    use crate::virtual_backend::{CaptureBuffer, Signal, VirtualBackend, VirtualDeviceSpec};
+    use crossbeam_channel::Receiver;
+
+    fn spec() -> VirtualDeviceSpec {
+        VirtualDeviceSpec {
+            channels: 1,
+            ..VirtualDeviceSpec::default()
+        }
+    }
+
+    fn constant(value: f32) -> Signal {
+        Signal::Samples {
+            samples: vec![value],
+            looping: true,
+        }
+    }
+
+    fn config(fallbacks: &[&str]) -> AudioConfig {
+        AudioConfig {
+            input_device_name: "Headset".to_string(),
+            vbcable_input_name: "Cable".to_string(),
+            buffer_size: 480,
+            recovery: RecoveryConfig {
+                input_fallbacks: fallbacks.iter().map(|name| name.to_string()).collect(),
+                system_default: false,
+                ..RecoveryConfig::default()
+            },
+            ..AudioConfig::default()
+        }
+    }
+
+    /// 耳机输入 0.5、摄像头输入 0.8，都输出到虚拟声卡
+    fn devices() -> (VirtualBackend, CaptureBuffer) {
+        let backend = VirtualBackend::new();
+        backend.add_input("Headset", spec(), constant(0.5));
+        backend.add_input("Webcam", spec(), constant(0.8));
+        let capture = backend.add_output("Cable", spec());
+        (backend, capture)
+    }
+
+    fn start(backend: &dyn AudioBackend, config: &AudioConfig) -> (RunningStream, Parameter, Receiver<StreamEvent>) {
+        let gain = GainProcessor::new(0.5);
+        let parameter = gain.gain();
+        let mut chain = ProcessorChain::new();
+        chain.add_processor(Box::new(gain));
+        let (live, handle) = LiveChain::new(chain);
+        let mut processor = ProcessorChain::new();
+        processor.add_processor(Box::new(live));
+        let (events, receiver) = crossbeam_channel::unbounded();
+        let running = RunningStream::start(
+            backend,
+            config,
+            Direction::Input,
+            processor,
+            handle,
+            StreamHooks::default(),
+            events,
+        )
+        .unwrap();
+        (running, parameter, receiver)
+    }
+
+    /// 推进虚拟时钟，返回最后一次输出回调的最后一个采样
+    fn output_level(backend: &VirtualBackend, capture: &CaptureBuffer) -> f32 {
+        capture.take();
+        backend.advance(Duration::from_millis(200));
+        *capture.take().last().unwrap()
+    }
+
+    fn headset_removed() -> StreamEvent {
+        StreamEvent::Failed {
+            direction: Direction::Input,
+            error: "输入设备 Headset 出错: 设备已断开: Headset".to_string(),
+        }
+    }
+
+    fn retrying(attempt: u32, delay_ms: u64, error: &str) -> StreamEvent {
+        StreamEvent::Retrying {
+            direction: Direction::Input,
+            attempt,
+            delay: Duration::from_millis(delay_ms),
+            error: error.to_string(),
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_cap() {
+        let recovery = RecoveryConfig::default();
+        let delays: Vec<u128> = (1..=7).map(|attempt| retry_delay(&recovery, attempt).as_millis()).collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 10000, 10000]);
+        assert_eq!(retry_delay(&recovery, 0), Duration::from_millis(500));
+        assert_eq!(retry_delay(&recovery, u32::MAX), Duration::from_millis(10000));
+
+        // 上限小于初始值时按初始值
+        let recovery = RecoveryConfig {
+            retry_initial_ms: 800,
+            retry_max_ms: 100,
+            ..RecoveryConfig::default()
+        };
+        assert_eq!(retry_delay(&recovery, 5), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn candidates_follow_fallback_order() {
+        let backend = VirtualBackend::new();
+        backend.set_default_input(Some("Laptop"));
+        backend.set_default_output(Some("Monitor"));
+        let mut config = config(&["Webcam", "Headset", "Laptop"]);
+        let pair = |input: &str, output: &str| (input.to_string(), output.to_string());
+
+        // 首选设备、备用设备，重复的只保留第一次出现的位置
+        assert_eq!(
+            candidates(&backend, &config, Direction::Input),
+            [pair("Headset", "Cable"), pair("Webcam", "Cable"), pair("Laptop", "Cable")]
+        );
+
+        config.recovery.input_fallbacks = vec!["Webcam".to_string()];
+        config.recovery.system_default = true;
+        assert_eq!(
+            candidates(&backend, &config, Direction::Input),
+            [pair("Headset", "Cable"), pair("Webcam", "Cable"), pair("Laptop", "Cable")]
+        );
+
+        // 输出流只替换扬声器，虚拟声卡不变
+        config.output_device_name = "Speakers".to_string();
+        config.recovery.output_fallbacks = vec!["Headphones".to_string()];
+        assert_eq!(
+            candidates(&backend, &config, Direction::Output),
+            [
+                pair("CABLE Output", "Speakers"),
+                pair("CABLE Output", "Headphones"),
+                pair("CABLE Output", "Monitor")
+            ]
+        );
+
+        config.recovery.enabled = false;
+        assert_eq!(candidates(&backend, &config, Direction::Input), [pair("Headset", "Cable")]);
+    }
+
+    #[test]
+    fn device_loss_falls_back_and_restores_preferred() {
+        let (backend, capture) = devices();
+        let config = config(&["Webcam"]);
+        let (mut running, gain, events) = start(&backend, &config);
+        assert!((output_level(&backend, &capture) - 0.25).abs() < 1e-4);
+
+        let t0 = Instant::now();
+        running.supervise(&backend, &config, t0);
+        assert_eq!(events.try_recv().ok(), None);
+
+        backend.remove_device("Headset");
+        running.supervise(&backend, &config, t0);
+        let events_now: Vec<_> = events.try_iter().collect();
+        assert_eq!(
+            events_now,
+            [
+                headset_removed(),
+                StreamEvent::Recovered {
+                    direction: Direction::Input,
+                    input: "Webcam".to_string(),
+                    output: "Cable".to_string(),
+                    fallback: true,
+                },
+            ]
+        );
+        // 同一个处理器链继续运行：增益不变，参数句柄仍然有效
+        assert!((output_level(&backend, &capture) - 0.4).abs() < 1e-4);
+        gain.set(0.25);
+        assert!((output_level(&backend, &capture) - 0.2).abs() < 1e-4);
+
+        // 首选设备恢复后，到了检查时间才切回
+        backend.add_input("Headset", spec(), constant(0.5));
+        running.supervise(&backend, &config, t0 + Duration::from_millis(1999));
+        assert_eq!(events.try_recv().ok(), None);
+        running.supervise(&backend, &config, t0 + Duration::from_millis(2000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [StreamEvent::Restored {
+                direction: Direction::Input,
+                input: "Headset".to_string(),
+                output: "Cable".to_string(),
+            }]
+        );
+        assert_eq!(running.stream().unwrap().input_name(), "Headset");
+        assert!((output_level(&backend, &capture) - 0.125).abs() < 1e-4);
+    }
+
+    #[test]
+    fn retries_back_off_until_a_device_appears() {
+        let (backend, capture) = devices();
+        let mut config = config(&[]);
+        config.recovery.retry_max_ms = 1500;
+        let (mut running, _gain, events) = start(&backend, &config);
+
+        let t0 = Instant::now();
+        backend.remove_device("Headset");
+        running.supervise(&backend, &config, t0);
+        let missing = "未找到虚拟音频设备: Headset";
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [headset_removed(), retrying(1, 500, missing)]
+        );
+        assert!(running.stream().is_none());
+
+        // 等待期间不重试，到期后重试并加倍等待时间，不超过上限
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+        running.supervise(&backend, &config, at(499));
+        assert_eq!(events.try_recv().ok(), None);
+        running.supervise(&backend, &config, at(500));
+        running.supervise(&backend, &config, at(1500));
+        running.supervise(&backend, &config, at(3000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [retrying(2, 1000, missing), retrying(3, 1500, missing), retrying(4, 1500, missing)]
+        );
+
+        backend.add_input("Headset", spec(), constant(0.5));
+        running.supervise(&backend, &config, at(4000));
+        assert_eq!(events.try_recv().ok(), None);
+        running.supervise(&backend, &config, at(4500));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [StreamEvent::Recovered {
+                direction: Direction::Input,
+                input: "Headset".to_string(),
+                output: "Cable".to_string(),
+                fallback: false,
+            }]
+        );
+        assert!((output_level(&backend, &capture) - 0.25).abs() < 1e-4);
+
+        // 恢复后重试次数清零
+        backend.remove_device("Headset");
+        running.supervise(&backend, &config, at(5000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [headset_removed(), retrying(1, 500, missing)]
+        );
+    }
+
+    /// 输入流停止后不释放回调的后端，模拟处理器链无法取回
+    struct LeakingBackend(VirtualBackend);
+
+    struct LeakingDevice(Box<dyn AudioDevice>);
+
+    struct LeakingStream(Option<Box<dyn ActiveStream>>);
+
+    impl AudioBackend for LeakingBackend {
+        fn name(&self) -> &str {
+            "leaking"
+        }
+
+        fn open_input(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+            Ok(Box::new(LeakingDevice(self.0.open_input(name)?)))
+        }
+
+        fn open_output(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+            self.0.open_output(name)
+        }
+    }
+
+    impl AudioDevice for LeakingDevice {
+        fn name(&self) -> String {
+            self.0.name()
+        }
+
+        fn negotiate(&self, request: &FormatRequest) -> Result<DeviceFormat> {
+            self.0.negotiate(request)
+        }
+
+        fn build_input_stream(
+            &self,
+            format: &DeviceFormat,
+            on_data: DataCallback,
+            on_error: ErrorCallback,
+        ) -> Result<Box<dyn ActiveStream>> {
+            let stream = self.0.build_input_stream(format, on_data, on_error)?;
+            Ok(Box::new(LeakingStream(Some(stream))))
+        }
+
+        fn build_output_stream(
+            &self,
+            format: &DeviceFormat,
+            on_data: DataCallback,
+            on_error: ErrorCallback,
+        ) -> Result<Box<dyn ActiveStream>> {
+            self.0.build_output_stream(format, on_data, on_error)
+        }
+    }
+
+    impl ActiveStream for LeakingStream {
+        fn play(&self) -> Result<()> {
+            self.0.as_ref().map_or(Ok(()), |stream| stream.play())
+        }
+    }
+
+    impl Drop for LeakingStream {
+        fn drop(&mut self) {
+            std::mem::forget(self.0.take());
+        }
+    }
+
+    #[test]
+    fn lost_chain_stops_retrying() {
+        let (virtual_backend, _capture) = devices();
+        let backend = LeakingBackend(virtual_backend.clone());
+        let config = config(&["Webcam"]);
+        let (mut running, _gain, events) = start(&backend, &config);
+
+        let t0 = Instant::now();
+        virtual_backend.remove_device("Headset");
+        running.supervise(&backend, &config, t0);
+        let events_now: Vec<_> = events.try_iter().collect();
+        assert_eq!(events_now.len(), 2, "{:?}", events_now);
+        assert_eq!(events_now[0], headset_removed());
+        assert!(
+            matches!(&events_now[1], StreamEvent::Failed { error, .. } if error.contains("不再重试")),
+            "{:?}",
+            events_now[1]
+        );
+
+        // 不再尝试备用设备，也不再发出重试事件
+        for ms in [0, 500, 5000, 60000] {
+            running.supervise(&backend, &config, t0 + Duration::from_millis(ms));
+        }
+        assert_eq!(events.try_recv().ok(), None);
+        assert!(running.stream().is_none());
+        assert!(running.reopen(&backend, &config).is_err());
+    }
+}
@@ -0,0 +1,454 @@
+use anyhow::{anyhow, bail, Context, Result};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::asr::{TranscriptEvent, TranscriptKind};
+use crate::audio_io::Direction;
+use crate::config::{AudioConfig, TranslationBackend, TranslationConfig};
+
+/// 翻译语言对（ISO 639-1 语言代码，如 "zh"、"en"）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguagePair {
+    pub source: String,
+    pub target: String,
+}
+
+/// 一段翻译结果，时间戳沿用原识别结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslatedSegment {
+    pub direction: Direction,
+    pub languages: LanguagePair,
+    pub source_text: String,
+    pub text: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// 机器翻译接口
+///
+/// 每个翻译器只负责一个固定的语言对，按批次翻译多条语句，
+/// 返回的结果与输入一一对应。
+pub trait Translator: Send {
+    /// 翻译器的语言对
+    fn languages(&self) -> &LanguagePair;
+
+    /// 批量翻译
+    fn translate(&mut self, texts: &[String]) -> Result<Vec<String>>;
+
+    /// 获取翻译器名称
+    fn name(&self) -> &str;
+}
+
+/// 词典翻译器：整句或逐词查表，查不到的内容原样输出
+///
+/// 词典为空时即为回显翻译器，用于离线调试。
+pub struct DictionaryTranslator {
+    languages: LanguagePair,
+    dictionary: HashMap<String, String>,
+}
+
+impl DictionaryTranslator {
+    pub fn new(languages: LanguagePair, dictionary: HashMap<String, String>) -> Self {
+        let dictionary = dictionary
+            .into_iter()
+            .map(|(k, v)| (k.to_lowercase(), v))
+            .collect();
+        Self { languages, dictionary }
+    }
+
+    fn lookup(&self, text: &str) -> String {
+        if let Some(sentence) = self.dictionary.get(&text.trim().to_lowercase()) {
+            return sentence.clone();
+        }
+        text.split_whitespace()
+            .map(|word| {
+                self.dictionary
+                    .get(&word.to_lowercase())
+                    .map(String::as_str)
+                    .unwrap_or(word)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Translator for DictionaryTranslator {
+    fn languages(&self) -> &LanguagePair {
+        &self.languages
+    }
+
+    fn translate(&mut self, texts: &[String]) -> Result<Vec<String>> {
+        Ok(texts.iter().map(|text| self.lookup(text)).collect())
+    }
+
+    fn name(&self) -> &str {
+        "词典翻译器"
+    }
+}
+
+#[derive(Serialize)]
+struct LibreTranslateRequest<'a> {
+    q: &'a [String],
+    source: &'a str,
+    target: &'a str,
+    format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LibreTranslateText {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct LibreTranslateResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: LibreTranslateText,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    temperature: f32,
+}
+
+#[derive(Deserialize)]
+struct ChatResponseMessage {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+/// HTTP 翻译器，支持本地部署的 LibreTranslate 或 OpenAI 兼容的对话接口
+pub struct HttpTranslator {
+    languages: LanguagePair,
+    backend: TranslationBackend,
+    agent: ureq::Agent,
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl HttpTranslator {
+    pub fn new(config: &TranslationConfig, languages: LanguagePair) -> Result<Self> {
+        if config.backend == TranslationBackend::Dictionary {
+            bail!("词典翻译不需要 HTTP 翻译器");
+        }
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build();
+
+        Ok(Self {
+            languages,
+            backend: config.backend,
+            agent,
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            model: config.model.clone(),
+            api_key: config.api_key.clone(),
+        })
+    }
+
+    fn translate_libre(&self, texts: &[String]) -> Result<Vec<String>> {
+        let url = format!("{}/translate", self.endpoint);
+        let request = LibreTranslateRequest {
+            q: texts,
+            source: &self.languages.source,
+            target: &self.languages.target,
+            format: "text",
+            api_key: self.api_key.as_deref(),
+        };
+
+        let response: LibreTranslateResponse = self.agent
+            .post(&url)
+            .send_json(&request)
+            .with_context(|| format!("请求翻译服务失败: {}", url))?
+            .into_json()
+            .context("解析翻译结果失败")?;
+
+        let translated = match response.translated_text {
+            LibreTranslateText::Batch(texts) => texts,
+            LibreTranslateText::Single(text) => vec![text],
+        };
+        if translated.len() != texts.len() {
+            bail!("翻译结果数量不匹配: 请求 {} 条，返回 {} 条", texts.len(), translated.len());
+        }
+        Ok(translated)
+    }
+
+    fn translate_chat(&self, text: &str) -> Result<String> {
+        let url = format!("{}/v1/chat/completions", self.endpoint);
+        let system = format!(
+            "You are a simultaneous interpreter in a video meeting. Translate the user's message from {} to {}. Reply with the translation only.",
+            self.languages.source, self.languages.target
+        );
+        let request = ChatRequest {
+            model: &self.model,
+            messages: vec![
+                ChatMessage { role: "system", content: &system },
+                ChatMessage { role: "user", content: text },
+            ],
+            temperature: 0.0,
+        };
+
+        let mut call = self.agent.post(&url);
+        if let Some(key) = &self.api_key {
+            call = call.set("Authorization", &format!("Bearer {}", key));
+        }
+        let response: ChatResponse = call
+            .send_json(&request)
+            .with_context(|| format!("请求翻译服务失败: {}", url))?
+            .into_json()
+            .context("解析翻译结果失败")?;
+
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.trim().to_string())
+            .ok_or_else(|| anyhow!("翻译服务未返回结果"))
+    }
+}
+
+impl Translator for HttpTranslator {
+    fn languages(&self) -> &LanguagePair {
+        &self.languages
+    }
+
+    fn translate(&mut self, texts: &[String]) -> Result<Vec<String>> {
+        match self.backend {
+            TranslationBackend::LibreTranslate => self.translate_libre(texts),
+            // 对话接口逐条翻译，避免模型合并或拆分语句
+            _ => texts.iter().map(|text| self.translate_chat(text)).collect(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self.backend {
+            TranslationBackend::LibreTranslate => "LibreTranslate 翻译器",
+            _ => "OpenAI 兼容翻译器",
+        }
+    }
+}
+
+/// 指定方向的翻译语言对：对方语音 → 我方语言，我方语音 → 对方语言
+pub fn direction_languages(config: &AudioConfig, direction: Direction) -> Result<LanguagePair> {
+    let (local, remote) = match (&config.local_language, &config.remote_language) {
+        (Some(local), Some(remote)) => (local.clone(), remote.clone()),
+        _ => bail!("启用翻译时必须设置 local_language 和 remote_language"),
+    };
+
+    Ok(match direction {
+        Direction::Input => LanguagePair { source: local, target: remote },
+        Direction::Output => LanguagePair { source: remote, target: local },
+    })
+}
+
+/// 根据配置创建指定方向的翻译器
+pub fn build_translator(config: &AudioConfig, direction: Direction) -> Result<Box<dyn Translator>> {
+    let languages = direction_languages(config, direction)?;
+    let translator: Box<dyn Translator> = match config.translation.backend {
+        TranslationBackend::Dictionary => Box::new(DictionaryTranslator::new(
+            languages,
+            config.translation.dictionary.clone(),
+        )),
+        _ => Box::new(HttpTranslator::new(&config.translation, languages)?),
+    };
+    Ok(translator)
+}
+
+/// 启动翻译线程：消费最终识别结果，按批次翻译后发送到 `output`
+///
+/// 收到第一条语句后最多等待 `batch_window` 收集同批语句，
+/// 没有对应方向翻译器的语句会被忽略。
+pub fn spawn_worker(
+    mut translators: HashMap<Direction, Box<dyn Translator>>,
+    transcripts: Receiver<TranscriptEvent>,
+    output: Sender<TranslatedSegment>,
+    batch_window: Duration,
+    max_batch: usize,
+) -> Result<JoinHandle<()>> {
+    let handle = thread::Builder::new()
+        .name("translation".to_string())
+        .spawn(move || {
+            for (direction, translator) in &translators {
+                let languages = translator.languages();
+                info!(
+                    "{}方向翻译已启动: {} ({} → {})",
+                    direction.label(),
+                    translator.name(),
+                    languages.source,
+                    languages.target
+                );
+            }
+
+            while let Ok(first) = transcripts.recv() {
+                let mut batch = vec![first];
+                let deadline = Instant::now() + batch_window;
+                while batch.len() < max_batch.max(1) {
+                    match transcripts.recv_deadline(deadline) {
+                        Ok(event) => batch.push(event),
+                        Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                batch.retain(|event| {
+                    event.segment.kind == TranscriptKind::Final && !event.segment.text.trim().is_empty()
+                });
+
+                for (direction, translator) in translators.iter_mut() {
+                    let events: Vec<&TranscriptEvent> =
+                        batch.iter().filter(|event| event.direction == *direction).collect();
+                    if events.is_empty() {
+                        continue;
+                    }
+
+                    let texts: Vec<String> = events.iter().map(|event| event.segment.text.clone()).collect();
+                    match translator.translate(&texts) {
+                        Ok(translated) => {
+                            for (event, text) in events.into_iter().zip(translated) {
+                                let _ = output.send(TranslatedSegment {
+                                    direction: *direction,
+                                    languages: translator.languages().clone(),
+                                    source_text: event.segment.text.clone(),
+                                    text,
+                                    start: event.segment.start,
+                                    end: event.segment.end,
+                                });
+                            }
+                        }
+                        Err(e) => warn!("{}方向翻译失败: {:#}", direction.label(), e),
+                    }
+                }
+            }
+        })
+        .context("启动翻译线程失败")?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::TranscriptSegment;
+    use std::sync::{Arc, Mutex};
+
+    fn pair(source: &str, target: &str) -> LanguagePair {
+        LanguagePair {
+            source: source.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    fn event(direction: Direction, kind: TranscriptKind, text: &str, start_ms: u64) -> TranscriptEvent {
+        TranscriptEvent {
+            direction,
+            segment: TranscriptSegment {
+                kind,
+                text: text.to_string(),
+                start: Duration::from_millis(start_ms),
+                end: Duration::from_millis(start_ms + 500),
+            },
+        }
+    }
+
+    /// 记录每批语句条数，译文为原文加上标记
+    struct RecordingTranslator {
+        languages: LanguagePair,
+        batches: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Translator for RecordingTranslator {
+        fn languages(&self) -> &LanguagePair {
+            &self.languages
+        }
+
+        fn translate(&mut self, texts: &[String]) -> Result<Vec<String>> {
+            self.batches.lock().unwrap().push(texts.len());
+            Ok(texts.iter().map(|text| format!("<{}>", text)).collect())
+        }
+
+        fn name(&self) -> &str {
+            "记录翻译器"
+        }
+    }
+
+    #[test]
+    fn dictionary_prefers_sentences_then_words() {
+        let dictionary = HashMap::from([
+            ("Good morning".to_string(), "早上好".to_string()),
+            ("hello".to_string(), "你好".to_string()),
+        ]);
+        let mut translator = DictionaryTranslator::new(pair("en", "zh"), dictionary);
+        let texts = vec![" good MORNING ".to_string(), "Hello world".to_string()];
+        assert_eq!(translator.translate(&texts).unwrap(), vec!["早上好", "你好 world"]);
+    }
+
+    #[test]
+    fn direction_languages_swap_for_remote_speech() {
+        let mut config = AudioConfig::default();
+        assert!(direction_languages(&config, Direction::Input).is_err());
+        config.local_language = Some("zh".to_string());
+        config.remote_language = Some("en".to_string());
+        assert_eq!(direction_languages(&config, Direction::Input).unwrap(), pair("zh", "en"));
+        assert_eq!(direction_languages(&config, Direction::Output).unwrap(), pair("en", "zh"));
+    }
+
+    #[test]
+    fn worker_batches_final_segments_per_direction() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let translators: HashMap<Direction, Box<dyn Translator>> = HashMap::from([(
+            Direction::Input,
+            Box::new(RecordingTranslator {
+                languages: pair("zh", "en"),
+                batches: batches.clone(),
+            }) as Box<dyn Translator>,
+        )]);
+
+        // 事件在线程启动前全部排好，批次只由 max_batch 决定
+        let (sender, transcripts) = crossbeam_channel::unbounded();
+        for event in [
+            event(Direction::Input, TranscriptKind::Final, "一", 0),
+            event(Direction::Input, TranscriptKind::Partial, "二", 500),
+            event(Direction::Input, TranscriptKind::Final, "二", 500),
+            event(Direction::Input, TranscriptKind::Final, "三", 1000),
+            event(Direction::Input, TranscriptKind::Final, "  ", 1500),
+            event(Direction::Output, TranscriptKind::Final, "没有翻译器", 2000),
+        ] {
+            sender.send(event).unwrap();
+        }
+        drop(sender);
+
+        let (output, translated) = crossbeam_channel::unbounded();
+        let worker = spawn_worker(translators, transcripts, output, Duration::from_secs(1), 2).unwrap();
+        worker.join().unwrap();
+
+        let segments: Vec<TranslatedSegment> = translated.try_iter().collect();
+        let texts: Vec<&str> = segments.iter().map(|segment| segment.text.as_str()).collect();
+        assert_eq!(texts, vec!["<一>", "<二>", "<三>"]);
+        assert_eq!(*batches.lock().unwrap(), vec![1, 2]);
+        assert_eq!(segments[2].source_text, "三");
+        assert_eq!(segments[2].start, Duration::from_millis(1000));
+        assert_eq!(segments[2].end, Duration::from_millis(1500));
+        assert!(segments.iter().all(|segment| segment.direction == Direction::Input));
+    }
+}
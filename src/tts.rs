@@ -0,0 +1,507 @@
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use log::{info, warn};
+use std::f32::consts::PI;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::audio_io::Direction;
+use crate::config::{AudioConfig, TtsBackend, TtsConfig, TtsMode};
+use crate::dynamics::Ducker;
+use crate::processor::{AudioProcessor, Bus, ProcessContext};
+use crate::resampler::{self, ResamplerQuality};
+
+/// 合成语音总线每次混合的帧数，缓冲区在创建时分配
+const BUS_FRAMES: usize = 1024;
+
+/// 闪避读取的合成语音旁链总线名称
+pub const SPEECH_BUS: &str = "speech";
+
+/// 语音合成接口
+pub trait SpeechSynthesizer: Send {
+    /// 合成单声道 PCM，采样率为 `sample_rate`
+    fn synthesize(&mut self, text: &str, sample_rate: u32) -> Result<Vec<f32>>;
+
+    /// 获取合成器名称
+    fn name(&self) -> &str;
+}
+
+/// 模拟合成器：每个字符输出一小段正弦提示音，用于调试
+pub struct BeepSynthesizer {
+    frequency: f32,
+    amplitude: f32,
+    per_char: Duration,
+}
+
+impl BeepSynthesizer {
+    pub fn new(frequency: f32, amplitude: f32, per_char: Duration) -> Self {
+        Self {
+            frequency,
+            amplitude,
+            per_char,
+        }
+    }
+}
+
+impl Default for BeepSynthesizer {
+    fn default() -> Self {
+        Self::new(880.0, 0.3, Duration::from_millis(60))
+    }
+}
+
+impl SpeechSynthesizer for BeepSynthesizer {
+    fn synthesize(&mut self, text: &str, sample_rate: u32) -> Result<Vec<f32>> {
+        let chars = text.chars().filter(|c| !c.is_whitespace()).count();
+        let beep_len = (self.per_char.as_secs_f32() * sample_rate as f32) as usize;
+        let mut samples = Vec::with_capacity(beep_len * chars);
+
+        for _ in 0..chars {
+            // 每段提示音后 1/4 为静音，便于听出字数
+            let tone_len = beep_len * 3 / 4;
+            for n in 0..beep_len {
+                let sample = if n < tone_len {
+                    let t = n as f32 / sample_rate as f32;
+                    (2.0 * PI * self.frequency * t).sin() * self.amplitude
+                } else {
+                    0.0
+                };
+                samples.push(sample);
+            }
+        }
+        Ok(samples)
+    }
+
+    fn name(&self) -> &str {
+        "提示音合成器"
+    }
+}
+
+/// 外部命令合成器：调用 piper、espeak-ng 等程序，从标准输出读取 WAV
+///
+/// 参数中的 `{text}` 和 `{lang}` 会被替换为待合成文本和语言代码；
+/// 参数中不含 `{text}` 时，文本通过标准输入传递（piper 的用法）。
+pub struct CommandSynthesizer {
+    program: String,
+    args: Vec<String>,
+    language: String,
+}
+
+impl CommandSynthesizer {
+    pub fn new(program: String, args: Vec<String>, language: String) -> Self {
+        Self {
+            program,
+            args,
+            language,
+        }
+    }
+}
+
+impl SpeechSynthesizer for CommandSynthesizer {
+    fn synthesize(&mut self, text: &str, sample_rate: u32) -> Result<Vec<f32>> {
+        let text_in_args = self.args.iter().any(|arg| arg.contains("{text}"));
+        let args: Vec<String> = self.args
+            .iter()
+            .map(|arg| arg.replace("{text}", text).replace("{lang}", &self.language))
+            .collect();
+
+        let mut child = Command::new(&self.program)
+            .args(&args)
+            .stdin(if text_in_args { Stdio::null() } else { Stdio::piped() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("启动语音合成程序失败: {}", self.program))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).context("写入合成文本失败")?;
+        }
+
+        let output = child.wait_with_output().context("等待语音合成程序失败")?;
+        if !output.status.success() {
+            bail!("语音合成程序退出异常: {}", output.status);
+        }
+
+        let (samples, source_rate) = decode_wav(&output.stdout)?;
+        Ok(resampler::resample(&samples, source_rate, sample_rate, 1, ResamplerQuality::High))
+    }
+
+    fn name(&self) -> &str {
+        "外部命令合成器"
+    }
+}
+
+/// 解码 WAV 为单声道 f32，返回采样数据和采样率
+///
+/// 以流方式输出的 WAV（如 espeak-ng --stdout）头部长度字段不准确，
+/// 读到数据末尾即停止。
+fn decode_wav(bytes: &[u8]) -> Result<(Vec<f32>, u32)> {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes)).context("解析合成音频失败")?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().map_while(Result::ok).collect(),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map_while(Result::ok)
+                .map(|s| s as f32 * scale)
+                .collect()
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
+
+/// 音频回调侧的合成语音播放器和混音器
+///
+/// 在输出回调中逐帧取出排队的语音写入合成语音总线，按模式与原声混合或替换原声。
+/// 混合时以合成语音为旁链对原声做闪避（或按 `voice_gain` 固定衰减），再与合成语音相加。
+/// 只使用 `try_recv`，不会阻塞音频线程。
+pub struct SpeechInjector {
+    utterances: Receiver<Vec<f32>>,
+    /// 播放完的语音交还合成线程释放，避免在音频回调中释放内存
+    finished: Sender<Vec<f32>>,
+    current: Vec<f32>,
+    position: usize,
+    mode: TtsMode,
+    voice_gain: f32,
+    speech_gain: f32,
+    /// 合成语音总线（单声道），作为闪避的旁链 [`SPEECH_BUS`]
+    bus: Vec<f32>,
+    ducker: Option<Ducker>,
+    sample_rate: Arc<AtomicU32>,
+    /// 已输出的帧数
+    frame: u64,
+}
+
+impl SpeechInjector {
+    /// 由音频流告知实际输出采样率和声道数，合成线程按此采样率生成语音
+    pub fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.frame = 0;
+        if let Some(ducker) = &mut self.ducker {
+            ducker.prepare(sample_rate, channels);
+        }
+    }
+
+    /// 是否正在播放合成语音
+    pub fn is_speaking(&self) -> bool {
+        self.position < self.current.len()
+    }
+
+    /// 将合成语音写入交错排列的输出缓冲区
+    ///
+    /// 闪避处理失败时原声不衰减、照常混入合成语音，返回错误由调用方转交，音频回调中不写日志。
+    pub fn mix_into(&mut self, data: &mut [f32], channels: u16) -> Result<()> {
+        let mut result = Ok(());
+        let layout = channels;
+        let channels = channels.max(1) as usize;
+        for block in data.chunks_mut(BUS_FRAMES * channels) {
+            let frames = block.len().div_ceil(channels);
+            self.bus.resize(frames, 0.0);
+            for i in 0..frames {
+                self.bus[i] = self.next_sample();
+            }
+
+            if self.mode == TtsMode::Mix {
+                match &mut self.ducker {
+                    Some(ducker) => {
+                        let sidechains = [(SPEECH_BUS, Bus::new(&self.bus, 1))];
+                        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+                        let mut context =
+                            ProcessContext::new(block, layout, sample_rate, self.frame).with_sidechains(&sidechains);
+                        if let Err(e) = ducker.process_context(&mut context) {
+                            result = Err(e.context("闪避处理失败"));
+                        }
+                    }
+                    None => block.iter_mut().for_each(|sample| *sample *= self.voice_gain),
+                }
+            }
+            for (frame, &speech) in block.chunks_mut(channels).zip(&self.bus) {
+                for sample in frame.iter_mut() {
+                    *sample = match self.mode {
+                        TtsMode::Mix => (*sample + speech).clamp(-1.0, 1.0),
+                        TtsMode::Replace => speech.clamp(-1.0, 1.0),
+                    };
+                }
+            }
+            self.frame += frames as u64;
+        }
+        result
+    }
+
+    /// 合成语音总线的下一个采样，当前语句播放完时取出下一条
+    fn next_sample(&mut self) -> f32 {
+        if !self.is_speaking() {
+            if let Ok(next) = self.utterances.try_recv() {
+                let finished = std::mem::replace(&mut self.current, next);
+                let _ = self.finished.try_send(finished);
+                self.position = 0;
+            }
+        }
+        if self.is_speaking() {
+            let sample = self.current[self.position];
+            self.position += 1;
+            sample * self.speech_gain
+        } else {
+            0.0
+        }
+    }
+}
+
+/// 合成语音队列的提交端：提交文本，由后台线程合成后排入播放器
+#[derive(Clone)]
+pub struct SpeechQueue {
+    texts: Sender<String>,
+}
+
+impl SpeechQueue {
+    /// 提交一条待合成文本；队列已满时丢弃并返回 false
+    pub fn speak(&self, text: String) -> bool {
+        match self.texts.try_send(text) {
+            Ok(()) => true,
+            Err(TrySendError::Full(text)) => {
+                warn!("合成语音队列已满，丢弃: {}", text);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// 创建合成语音队列，启动合成线程
+///
+/// 返回文本提交端和需要接入输出回调的播放器。
+pub fn spawn_queue(
+    mut synthesizer: Box<dyn SpeechSynthesizer>,
+    config: &TtsConfig,
+) -> Result<(SpeechQueue, SpeechInjector, JoinHandle<()>)> {
+    let (text_sender, text_receiver) = crossbeam_channel::bounded::<String>(config.max_pending.max(1));
+    let (pcm_sender, pcm_receiver) = crossbeam_channel::bounded::<Vec<f32>>(config.max_pending.max(1));
+    // 播放器同一时刻只持有一条语音，交还队列比排队上限多留一些余量即不会满
+    let (finished_sender, finished_receiver) = crossbeam_channel::bounded::<Vec<f32>>(config.max_pending.max(1) + 2);
+    let sample_rate = Arc::new(AtomicU32::new(48000));
+    let gap = Duration::from_millis(config.gap_ms);
+
+    let injector = SpeechInjector {
+        utterances: pcm_receiver,
+        finished: finished_sender,
+        current: Vec::new(),
+        position: 0,
+        mode: config.mode,
+        voice_gain: config.voice_gain,
+        speech_gain: config.speech_gain,
+        bus: Vec::with_capacity(BUS_FRAMES),
+        ducker: config.ducking.enabled.then(|| Ducker::new(config.ducking.clone(), SPEECH_BUS)),
+        sample_rate: sample_rate.clone(),
+        frame: 0,
+    };
+
+    let handle = thread::Builder::new()
+        .name("tts".to_string())
+        .spawn(move || {
+            info!("语音合成已启动: {}", synthesizer.name());
+            for text in text_receiver {
+                finished_receiver.try_iter().for_each(drop);
+                let rate = sample_rate.load(Ordering::Relaxed);
+                match synthesizer.synthesize(&text, rate) {
+                    Ok(mut samples) => {
+                        // 语句之间留出停顿
+                        samples.resize(samples.len() + (gap.as_secs_f32() * rate as f32) as usize, 0.0);
+                        if pcm_sender.send(samples).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("语音合成失败: {:#}", e),
+                }
+            }
+        })
+        .context("启动语音合成线程失败")?;
+
+    Ok((SpeechQueue { texts: text_sender }, injector, handle))
+}
+
+/// 根据配置创建合成器，语言为该方向译文的语言：我方语音合成为对方语言，对方语音合成为我方语言
+pub fn build_synthesizer(config: &AudioConfig, direction: Direction) -> Box<dyn SpeechSynthesizer> {
+    let language = match direction {
+        Direction::Input => &config.remote_language,
+        Direction::Output => &config.local_language,
+    };
+    match config.tts.backend {
+        TtsBackend::Beep => Box::new(BeepSynthesizer::default()),
+        TtsBackend::Command => Box::new(CommandSynthesizer::new(
+            config.tts.program.clone(),
+            config.tts.args.clone(),
+            language.clone().unwrap_or_default(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const RATE: u32 = 16000;
+
+    /// 逐帧混合，直到播放器取到合成好的语音；`frame` 中留下语音的第一帧
+    fn wait_for_speech(injector: &mut SpeechInjector, frame: &mut [f32], channels: u16) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let original = frame.to_vec();
+        loop {
+            frame.copy_from_slice(&original);
+            injector.mix_into(frame, channels).unwrap();
+            if injector.is_speaking() {
+                return;
+            }
+            assert!(Instant::now() < deadline, "合成线程没有输出语音");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn config(mode: TtsMode) -> TtsConfig {
+        let mut config = TtsConfig {
+            mode,
+            gap_ms: 0,
+            ..TtsConfig::default()
+        };
+        config.ducking.enabled = false;
+        config
+    }
+
+    #[test]
+    fn beep_synthesizer_emits_one_beep_per_character() {
+        let mut synthesizer = BeepSynthesizer::new(1000.0, 0.5, Duration::from_millis(40));
+        let samples = synthesizer.synthesize("a b\tc", RATE).unwrap();
+        let beep = RATE as usize * 40 / 1000;
+        assert_eq!(samples.len(), beep * 3);
+        for chunk in samples.chunks(beep) {
+            let tone = beep * 3 / 4;
+            assert!(chunk[..tone].iter().any(|sample| sample.abs() > 0.4));
+            assert!(chunk[..tone].iter().all(|sample| sample.abs() <= 0.5));
+            assert!(chunk[tone..].iter().all(|&sample| sample == 0.0));
+        }
+    }
+
+    #[test]
+    fn decode_wav_downmixes_integer_stereo() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+            for (left, right) in [(16384i16, 0i16), (-32768, -32768)] {
+                writer.write_sample(left).unwrap();
+                writer.write_sample(right).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let (samples, sample_rate) = decode_wav(&cursor.into_inner()).unwrap();
+        assert_eq!(sample_rate, 22050);
+        assert_eq!(samples, vec![0.25, -1.0]);
+    }
+
+    #[test]
+    fn replace_mode_plays_synthesized_speech_on_all_channels() {
+        let config = config(TtsMode::Replace);
+        let (queue, mut injector, _) = spawn_queue(Box::new(BeepSynthesizer::default()), &config).unwrap();
+        injector.prepare(RATE, 2);
+        assert!(queue.speak("好".to_string()));
+
+        let mut first = [0.7f32; 2];
+        wait_for_speech(&mut injector, &mut first, 2);
+        let expected = BeepSynthesizer::default().synthesize("好", RATE).unwrap();
+        let mut data = vec![0.7f32; expected.len() * 2];
+        injector.mix_into(&mut data, 2).unwrap();
+
+        assert_eq!(first, [expected[0]; 2]);
+        for (frame, &speech) in data.chunks(2).zip(&expected[1..]) {
+            assert_eq!(frame, [speech; 2]);
+        }
+        // 语音播放完后输出静音，原声被替换
+        assert!(!injector.is_speaking());
+        assert!(data[(expected.len() - 1) * 2..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn mix_mode_without_ducking_attenuates_voice_by_fixed_gain() {
+        let config = config(TtsMode::Mix);
+        let (queue, mut injector, _) = spawn_queue(Box::new(BeepSynthesizer::default()), &config).unwrap();
+        injector.prepare(RATE, 1);
+
+        // 没有语音时原声也按 voice_gain 衰减
+        let mut data = vec![0.5f32; 64];
+        injector.mix_into(&mut data, 1).unwrap();
+        assert!(data.iter().all(|&sample| (sample - 0.5 * config.voice_gain).abs() < 1e-6));
+
+        queue.speak("好".to_string());
+        let mut first = [0.5f32];
+        wait_for_speech(&mut injector, &mut first, 1);
+        let expected = BeepSynthesizer::default().synthesize("好", RATE).unwrap();
+        assert!((first[0] - (0.5 * config.voice_gain + expected[0])).abs() < 1e-6);
+        let mut data = vec![0.5f32; 256];
+        injector.mix_into(&mut data, 1).unwrap();
+        for (&sample, &speech) in data.iter().zip(&expected[1..]) {
+            assert!((sample - (0.5 * config.voice_gain + speech).clamp(-1.0, 1.0)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn mix_mode_ducks_voice_while_speech_plays() {
+        let mut config = config(TtsMode::Mix);
+        config.ducking.enabled = true;
+        let ducking = config.ducking.clone();
+        let (queue, mut injector, _) = spawn_queue(Box::new(BeepSynthesizer::default()), &config).unwrap();
+        injector.prepare(RATE, 1);
+
+        // 没有语音时原声不衰减
+        let mut data = vec![0.1f32; 1600];
+        injector.mix_into(&mut data, 1).unwrap();
+        assert!(data.iter().all(|&sample| sample == 0.1));
+
+        queue.speak("好好好".to_string());
+        let mut first = [0.1f32];
+        wait_for_speech(&mut injector, &mut first, 1);
+        let expected = BeepSynthesizer::default().synthesize("好好好", RATE).unwrap();
+        let mut data = vec![0.1f32; RATE as usize * 5];
+        injector.mix_into(&mut data, 1).unwrap();
+        let voice: Vec<f32> = data
+            .iter()
+            .zip(expected[1..].iter().chain(std::iter::repeat(&0.0)))
+            .map(|(&sample, &speech)| sample - speech)
+            .collect();
+        let ducked = 0.1 * 10f32.powf(-ducking.depth_db / 20.0);
+        // 语音播放期间（包括字与字之间的停顿）原声衰减 depth_db，语音结束后保持一段时间再恢复
+        let end = expected.len() - 1;
+        let hold = RATE as usize * ducking.hold_ms as usize / 1000;
+        assert!((voice[end - 1] - ducked).abs() < 1e-4, "语音结尾原声为 {}", voice[end - 1]);
+        assert!((voice[end + hold - 1] - ducked).abs() < 1e-4, "保持时间内原声为 {}", voice[end + hold - 1]);
+        assert!(voice[end + 2 * hold] > ducked * 1.2);
+        assert!((voice[voice.len() - 1] - 0.1).abs() < 1e-4, "恢复后原声为 {}", voice[voice.len() - 1]);
+    }
+
+    #[test]
+    fn full_queue_drops_new_texts() {
+        let config = TtsConfig {
+            max_pending: 1,
+            ..config(TtsMode::Mix)
+        };
+        // 播放器不取语音：语音队列、正在发送的语音和文本队列各占一条后，新文本被丢弃
+        let (queue, _injector, _) = spawn_queue(Box::new(BeepSynthesizer::default()), &config).unwrap();
+        let accepted = (0..8).filter(|_| queue.speak("好".to_string())).count();
+        assert!((1..=3).contains(&accepted));
+    }
+}
@@ -0,0 +1,392 @@
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::VadConfig;
+use crate::processor::AudioProcessor;
+
+/// 语音活动事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEventKind {
+    SpeechStart,
+    SpeechEnd,
+}
+
+/// 语音活动事件，`sample` 为自音频流起点的帧序号（每声道一个采样为一帧）
+///
+/// 语音开始的位置已经包含预留的 pre-roll。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VadEvent {
+    pub kind: VadEventKind,
+    pub sample: u64,
+    pub sample_rate: u32,
+}
+
+impl VadEvent {
+    pub fn time(&self) -> Duration {
+        Duration::from_secs_f64(self.sample as f64 / self.sample_rate.max(1) as f64)
+    }
+}
+
+/// 频谱平坦度计算（几何平均 / 算术平均），白噪声接近 1，浊音远小于 1
+struct FlatnessAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl FlatnessAnalyzer {
+    fn new(len: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(len);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        let window = (0..len)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / len as f32).cos())
+            .collect();
+        Self {
+            fft,
+            window,
+            buffer: vec![Complex::default(); len],
+            scratch,
+        }
+    }
+
+    fn flatness(&mut self, frame: &[f32]) -> f32 {
+        for ((out, &sample), &w) in self.buffer.iter_mut().zip(frame).zip(&self.window) {
+            *out = Complex::new(sample * w, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let bins = &self.buffer[1..self.buffer.len() / 2];
+        if bins.is_empty() {
+            return 1.0;
+        }
+        let mut log_sum = 0.0f32;
+        let mut sum = 0.0f32;
+        for bin in bins {
+            let power = bin.norm_sqr() + 1e-12;
+            log_sum += power.ln();
+            sum += power;
+        }
+        let n = bins.len() as f32;
+        (log_sum / n).exp() / (sum / n)
+    }
+}
+
+/// 基于分帧的语音活动检测（VAD）处理器
+///
+/// 每帧计算能量、过零率（可选频谱平坦度），能量门限随噪声底自适应。
+/// 连续 `min_speech_ms` 有声后判定语音开始，连续 `hangover_ms` 无声后判定结束。
+/// 事件通过通道发送，处理器本身不修改音频。
+pub struct VadProcessor {
+    config: VadConfig,
+    events: Sender<VadEvent>,
+    sample_rate: u32,
+    channels: usize,
+    frame_len: usize,
+    frame: Vec<f32>,
+    /// 当前帧第一个采样的位置
+    frame_start: u64,
+    flatness: Option<FlatnessAnalyzer>,
+    noise_floor_db: Option<f32>,
+    speaking: bool,
+    voiced_frames: usize,
+    unvoiced_frames: usize,
+    onset: u64,
+    last_voiced_end: u64,
+    last_end: u64,
+}
+
+impl VadProcessor {
+    pub fn new(config: VadConfig, events: Sender<VadEvent>) -> Self {
+        let mut vad = Self {
+            config,
+            events,
+            sample_rate: 0,
+            channels: 1,
+            frame_len: 0,
+            frame: Vec::new(),
+            frame_start: 0,
+            flatness: None,
+            noise_floor_db: None,
+            speaking: false,
+            voiced_frames: 0,
+            unvoiced_frames: 0,
+            onset: 0,
+            last_voiced_end: 0,
+            last_end: 0,
+        };
+        vad.prepare(48000, 1);
+        vad
+    }
+
+    /// 创建 VAD 及其事件接收端
+    pub fn with_channel(config: VadConfig) -> (Self, Receiver<VadEvent>) {
+        let (sender, receiver) = crossbeam_channel::bounded(256);
+        (Self::new(config, sender), receiver)
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    fn ms_to_frames(&self, ms: u32) -> usize {
+        let frame_ms = self.config.frame_ms.max(1);
+        (ms as usize).div_ceil(frame_ms as usize)
+    }
+
+    fn emit(&self, kind: VadEventKind, sample: u64) {
+        // 事件队列满时丢弃，不阻塞音频线程
+        let _ = self.events.try_send(VadEvent {
+            kind,
+            sample,
+            sample_rate: self.sample_rate,
+        });
+    }
+
+    fn analyze_frame(&mut self) {
+        let len = self.frame.len();
+        let energy = self.frame.iter().map(|s| s * s).sum::<f32>() / len as f32;
+        let level_db = 10.0 * (energy + 1e-12).log10();
+        let crossings = self.frame
+            .windows(2)
+            .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+            .count();
+        let zcr = crossings as f32 / (len - 1).max(1) as f32;
+
+        // 噪声底：遇到更安静的帧时快速下降，否则缓慢上升（每秒 1 dB）
+        let frame_secs = len as f32 / self.sample_rate as f32;
+        let floor = match self.noise_floor_db {
+            Some(floor) if level_db < floor => floor + (level_db - floor) * 0.5,
+            Some(floor) => floor + frame_secs,
+            None => level_db,
+        };
+        self.noise_floor_db = Some(floor);
+        let threshold_db = self.config.threshold_db.max(floor + self.config.noise_margin_db);
+
+        // 高过零率的帧（摩擦音或噪声）需要更高的能量才算有声
+        let loud = level_db > threshold_db;
+        let tonal = zcr <= self.config.zcr_max || level_db > threshold_db + 10.0;
+        let flat_ok = match (&mut self.flatness, self.config.flatness_max) {
+            (Some(analyzer), Some(max)) => analyzer.flatness(&self.frame) <= max,
+            _ => true,
+        };
+        let voiced = loud && tonal && flat_ok;
+
+        let amplitude = 10f32.powf(threshold_db / 20.0);
+        let frame_end = self.frame_start + len as u64;
+
+        if voiced {
+            if self.voiced_frames == 0 && !self.speaking {
+                // 语音起点精确到帧内第一个超过门限的采样
+                let offset = self.frame.iter().position(|s| s.abs() >= amplitude).unwrap_or(0);
+                self.onset = self.frame_start + offset as u64;
+            }
+            let offset = self.frame.iter().rposition(|s| s.abs() >= amplitude).map_or(len, |i| i + 1);
+            self.last_voiced_end = self.frame_start + offset as u64;
+            self.voiced_frames += 1;
+            self.unvoiced_frames = 0;
+
+            if !self.speaking && self.voiced_frames >= self.ms_to_frames(self.config.min_speech_ms).max(1) {
+                self.speaking = true;
+                let pre_roll = self.sample_rate as u64 * self.config.pre_roll_ms as u64 / 1000;
+                let start = self.onset.saturating_sub(pre_roll).max(self.last_end);
+                self.emit(VadEventKind::SpeechStart, start);
+            }
+        } else {
+            self.unvoiced_frames += 1;
+            if !self.speaking {
+                self.voiced_frames = 0;
+            } else if self.unvoiced_frames >= self.ms_to_frames(self.config.hangover_ms).max(1) {
+                self.speaking = false;
+                self.voiced_frames = 0;
+                self.last_end = self.last_voiced_end;
+                self.emit(VadEventKind::SpeechEnd, self.last_voiced_end);
+            }
+        }
+
+        self.frame_start = frame_end;
+        self.frame.clear();
+    }
+}
+
+impl AudioProcessor for VadProcessor {
+    fn process(&mut self, buffer: &mut [f32]) -> Result<()> {
+        for frame in buffer.chunks(self.channels) {
+            let mono = frame.iter().sum::<f32>() / frame.len() as f32;
+            self.frame.push(mono);
+            if self.frame.len() >= self.frame_len {
+                self.analyze_frame();
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "语音活动检测"
+    }
+
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.sample_rate = sample_rate.max(1);
+        self.channels = channels.max(1) as usize;
+        self.frame_len = (sample_rate as usize * self.config.frame_ms.max(1) as usize / 1000).max(2);
+        self.frame = Vec::with_capacity(self.frame_len);
+        self.flatness = self.config.flatness_max.map(|_| FlatnessAnalyzer::new(self.frame_len));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+    /// 每次送入的采样数，与 20 ms 分析帧对齐
+    const BLOCK: usize = 320;
+
+    /// 确定性的低电平白噪声（约 -65 dBFS）
+    fn noise(len: usize, seed: &mut u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (*seed >> 8) as f32 / (1 << 24) as f32 * 0.002 - 0.001
+            })
+            .collect()
+    }
+
+    /// 类似浊音的信号：150 Hz 基频加谐波，4 Hz 幅度调制，叠加背景噪声
+    fn voiced(len: usize, seed: &mut u32) -> Vec<f32> {
+        noise(len, seed)
+            .into_iter()
+            .enumerate()
+            .map(|(n, noise)| {
+                let t = n as f32 / RATE as f32;
+                let envelope = 0.6 + 0.4 * (2.0 * PI * 4.0 * t).sin();
+                let harmonics: f32 = (1..=6).map(|k| (2.0 * PI * 150.0 * k as f32 * t).sin() / k as f32).sum();
+                0.15 * envelope * harmonics + noise
+            })
+            .collect()
+    }
+
+    /// 按 (是否有声, 毫秒) 拼接信号
+    fn script(parts: &[(bool, usize)]) -> Vec<f32> {
+        let mut seed = 1;
+        parts
+            .iter()
+            .flat_map(|&(speech, ms)| {
+                let len = RATE as usize * ms / 1000;
+                if speech {
+                    voiced(len, &mut seed)
+                } else {
+                    noise(len, &mut seed)
+                }
+            })
+            .collect()
+    }
+
+    /// 分块送入信号，返回各事件及发出事件时已送入的采样数
+    fn run(config: VadConfig, signal: &[f32]) -> Vec<(VadEvent, u64)> {
+        let (mut vad, events) = VadProcessor::with_channel(config);
+        vad.prepare(RATE, 1);
+        let mut fed = 0u64;
+        let mut emitted = Vec::new();
+        for block in signal.chunks(BLOCK) {
+            let mut block = block.to_vec();
+            vad.process(&mut block).unwrap();
+            fed += block.len() as u64;
+            emitted.extend(events.try_iter().map(|event| (event, fed)));
+        }
+        emitted
+    }
+
+    fn ms(ms: u64) -> u64 {
+        RATE as u64 * ms / 1000
+    }
+
+    fn assert_near(actual: u64, expected: u64, tolerance: u64) {
+        assert!(
+            actual.abs_diff(expected) <= tolerance,
+            "{} 与期望的 {} 相差超过 {}",
+            actual,
+            expected,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn detects_segment_boundaries_with_pre_roll() {
+        let config = VadConfig::default();
+        let signal = script(&[(false, 500), (true, 1000), (false, 1000), (true, 600), (false, 1000)]);
+        let events = run(config.clone(), &signal);
+        let kinds: Vec<VadEventKind> = events.iter().map(|(event, _)| event.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                VadEventKind::SpeechStart,
+                VadEventKind::SpeechEnd,
+                VadEventKind::SpeechStart,
+                VadEventKind::SpeechEnd
+            ]
+        );
+
+        // 起点向前预留 pre-roll，终点为最后一个有声采样之后
+        let pre_roll = ms(config.pre_roll_ms as u64);
+        assert_near(events[0].0.sample, ms(500) - pre_roll, BLOCK as u64);
+        assert_near(events[1].0.sample, ms(1500), BLOCK as u64);
+        assert_near(events[2].0.sample, ms(2500) - pre_roll, BLOCK as u64);
+        assert_near(events[3].0.sample, ms(3100), BLOCK as u64);
+        assert!(events.iter().all(|(event, _)| event.sample_rate == RATE));
+        assert_near(events[1].0.time().as_millis() as u64, 1500, 20);
+    }
+
+    #[test]
+    fn speech_end_is_reported_after_hangover() {
+        let config = VadConfig {
+            hangover_ms: 300,
+            ..VadConfig::default()
+        };
+        let signal = script(&[(false, 300), (true, 800), (false, 1000)]);
+        let events = run(config, &signal);
+        let (end, reported_at) = events
+            .iter()
+            .find(|(event, _)| event.kind == VadEventKind::SpeechEnd)
+            .copied()
+            .expect("没有语音结束事件");
+        // 连续 300 ms 无声后才判定结束，报告时刻比终点晚一个挂起时长（按帧取整）
+        assert_near(reported_at - end.sample, ms(300), 2 * BLOCK as u64);
+    }
+
+    #[test]
+    fn short_clicks_do_not_start_speech() {
+        // 短于 min_speech_ms 的突发不算语音
+        let config = VadConfig {
+            min_speech_ms: 100,
+            ..VadConfig::default()
+        };
+        let signal = script(&[(false, 500), (true, 40), (false, 500), (true, 40), (false, 500)]);
+        assert!(run(config, &signal).is_empty());
+    }
+
+    #[test]
+    fn pre_roll_does_not_overlap_previous_segment() {
+        // 两段语音间隔 500 ms，预留 800 ms 时起点截断在上一段的终点
+        let config = VadConfig {
+            pre_roll_ms: 800,
+            ..VadConfig::default()
+        };
+        let signal = script(&[(false, 1000), (true, 500), (false, 500), (true, 500), (false, 1000)]);
+        let events = run(config, &signal);
+        assert_eq!(events.len(), 4);
+        assert_near(events[0].0.sample, ms(1000) - ms(800), BLOCK as u64);
+        assert_eq!(events[2].0.sample, events[1].0.sample);
+
+        // 预留部分覆盖起点之前的噪声，不会切掉字头
+        let (start, onset) = (events[0].0.sample as usize, ms(1000) as usize);
+        let pre_roll = &signal[start..onset];
+        assert!(pre_roll.iter().all(|sample| sample.abs() <= 0.001));
+        assert!(signal[onset..onset + BLOCK].iter().any(|sample| sample.abs() > 0.05));
+    }
+}
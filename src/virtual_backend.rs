@@ -0,0 +1,399 @@
+use anyhow::{bail, Result};
+use cpal::SampleFormat;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::backend::{ActiveStream, AudioBackend, AudioDevice, DataCallback, DeviceFormat, ErrorCallback, FormatRequest};
+
+/// 虚拟设备参数
+#[derive(Debug, Clone, Copy)]
+pub struct VirtualDeviceSpec {
+    /// 设备唯一支持的采样率，请求其它采样率时回退到该值
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 每次回调的帧数
+    pub period_frames: usize,
+    /// 设备时钟相对标称采样率的偏差 (ppm)，用于模拟两个设备的时钟漂移
+    pub clock_ppm: f64,
+}
+
+impl Default for VirtualDeviceSpec {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            channels: 2,
+            period_frames: 480,
+            clock_ppm: 0.0,
+        }
+    }
+}
+
+/// 虚拟输入设备产生的信号，所有声道相同（`Samples` 除外）
+#[derive(Debug, Clone)]
+pub enum Signal {
+    Silence,
+    Sine { frequency: f32, amplitude: f32 },
+    /// 交错排列的采样，声道数需与设备一致；放完后输出静音或从头循环
+    Samples { samples: Vec<f32>, looping: bool },
+}
+
+impl Signal {
+    fn fill(&self, buffer: &mut [f32], position: u64, sample_rate: u32, channels: usize) {
+        match self {
+            Signal::Silence => buffer.fill(0.0),
+            Signal::Sine { frequency, amplitude } => {
+                for (n, frame) in buffer.chunks_mut(channels).enumerate() {
+                    let t = (position + n as u64) as f64 / sample_rate as f64;
+                    let value = (2.0 * PI * *frequency as f64 * t).sin() as f32 * amplitude;
+                    frame.fill(value);
+                }
+            }
+            Signal::Samples { samples, looping } => {
+                let start = position as usize * channels;
+                for (n, sample) in buffer.iter_mut().enumerate() {
+                    let index = start + n;
+                    *sample = match (samples.get(index), *looping && !samples.is_empty()) {
+                        (Some(&value), _) => value,
+                        (None, true) => samples[index % samples.len()],
+                        (None, false) => 0.0,
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// 虚拟输出设备收到的全部数据（交错排列）
+#[derive(Clone, Default)]
+pub struct CaptureBuffer {
+    samples: Arc<Mutex<Vec<f32>>>,
+}
+
+impl CaptureBuffer {
+    /// 复制目前收到的数据
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.lock().map(|samples| samples.clone()).unwrap_or_default()
+    }
+
+    /// 取出目前收到的数据并清空
+    pub fn take(&self) -> Vec<f32> {
+        self.samples.lock().map(|mut samples| std::mem::take(&mut *samples)).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().map(|samples| samples.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct DeviceEntry {
+    spec: VirtualDeviceSpec,
+    input: bool,
+    signal: Signal,
+    /// 输入信号已播放的帧数，重新打开设备时继续
+    position: u64,
+    capture: CaptureBuffer,
+}
+
+struct StreamEntry {
+    id: u64,
+    device: String,
+    input: bool,
+    playing: bool,
+    format: DeviceFormat,
+    /// 两次回调之间的时间（已计入时钟偏差）
+    interval: f64,
+    next_due: f64,
+    buffer: Vec<f32>,
+    on_data: DataCallback,
+    on_error: ErrorCallback,
+}
+
+#[derive(Default)]
+struct State {
+    now: f64,
+    devices: HashMap<String, DeviceEntry>,
+    streams: Vec<StreamEntry>,
+    next_id: u64,
+    default_input: Option<String>,
+    default_output: Option<String>,
+}
+
+/// 内存中的虚拟音频后端，时钟由调用方推进
+///
+/// 输入设备按脚本产生信号，输出设备记录收到的全部数据；
+/// 调用 [`VirtualBackend::advance`] 时按各设备的回调时刻依次执行回调，
+/// 结果完全确定，适合在没有声卡的环境中测试完整的双工流程。
+#[derive(Clone, Default)]
+pub struct VirtualBackend {
+    state: Arc<Mutex<State>>,
+}
+
+impl VirtualBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 添加虚拟输入设备
+    pub fn add_input(&self, name: &str, spec: VirtualDeviceSpec, signal: Signal) {
+        self.state().devices.insert(
+            name.to_string(),
+            DeviceEntry {
+                spec,
+                input: true,
+                signal,
+                position: 0,
+                capture: CaptureBuffer::default(),
+            },
+        );
+    }
+
+    /// 添加虚拟输出设备，返回记录输出数据的缓冲区
+    pub fn add_output(&self, name: &str, spec: VirtualDeviceSpec) -> CaptureBuffer {
+        let capture = CaptureBuffer::default();
+        self.state().devices.insert(
+            name.to_string(),
+            DeviceEntry {
+                spec,
+                input: false,
+                signal: Signal::Silence,
+                position: 0,
+                capture: capture.clone(),
+            },
+        );
+        capture
+    }
+
+    /// 移除设备，模拟设备被拔出：该设备上的音频流停止回调并收到错误
+    pub fn remove_device(&self, name: &str) {
+        let mut state = self.state();
+        if state.devices.remove(name).is_none() {
+            return;
+        }
+        for stream in state.streams.iter_mut().filter(|stream| stream.device == name) {
+            stream.playing = false;
+            (stream.on_error)(format!("设备已断开: {}", name));
+        }
+    }
+
+    /// 设置系统默认输入设备
+    pub fn set_default_input(&self, name: Option<&str>) {
+        self.state().default_input = name.map(str::to_string);
+    }
+
+    /// 设置系统默认输出设备
+    pub fn set_default_output(&self, name: Option<&str>) {
+        self.state().default_output = name.map(str::to_string);
+    }
+
+    /// 虚拟时钟的当前时间
+    pub fn now(&self) -> Duration {
+        Duration::from_secs_f64(self.state().now)
+    }
+
+    /// 推进虚拟时钟，按时间顺序执行期间到期的所有回调（同一时刻输入先于输出）
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state();
+        let target = state.now + duration.as_secs_f64();
+        loop {
+            let next = state
+                .streams
+                .iter()
+                .enumerate()
+                .filter(|(_, stream)| stream.playing && stream.next_due <= target)
+                .min_by(|(_, a), (_, b)| a.next_due.total_cmp(&b.next_due).then(b.input.cmp(&a.input)))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                break;
+            };
+
+            let State { now, devices, streams, .. } = &mut *state;
+            let stream = &mut streams[index];
+            *now = stream.next_due;
+            stream.next_due += stream.interval;
+            let Some(device) = devices.get_mut(&stream.device) else {
+                continue;
+            };
+            let channels = stream.format.channels as usize;
+            if stream.input {
+                device
+                    .signal
+                    .fill(&mut stream.buffer, device.position, stream.format.sample_rate, channels);
+                device.position += (stream.buffer.len() / channels) as u64;
+                (stream.on_data)(&mut stream.buffer);
+            } else {
+                stream.buffer.fill(0.0);
+                (stream.on_data)(&mut stream.buffer);
+                if let Ok(mut samples) = device.capture.samples.lock() {
+                    samples.extend_from_slice(&stream.buffer);
+                }
+            }
+        }
+        state.now = target;
+    }
+
+    fn open(&self, name: &str, input: bool) -> Result<Box<dyn AudioDevice>> {
+        let state = self.state();
+        // 名称完全相同的设备优先，否则按包含关系查找（与系统设备一致）
+        let mut candidates = state.devices.iter().filter(|(_, device)| device.input == input);
+        let found = state
+            .devices
+            .iter()
+            .find(|(device_name, device)| device.input == input && device_name.as_str() == name)
+            .or_else(|| candidates.find(|(device_name, _)| device_name.contains(name)));
+        match found {
+            Some((device_name, _)) => Ok(Box::new(VirtualDevice {
+                backend: self.clone(),
+                name: device_name.clone(),
+                input,
+            })),
+            None => bail!("未找到虚拟音频设备: {}", name),
+        }
+    }
+}
+
+impl AudioBackend for VirtualBackend {
+    fn name(&self) -> &str {
+        "virtual"
+    }
+
+    fn open_input(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        self.open(name, true)
+    }
+
+    fn open_output(&self, name: &str) -> Result<Box<dyn AudioDevice>> {
+        self.open(name, false)
+    }
+
+    fn default_input(&self) -> Option<String> {
+        self.state().default_input.clone()
+    }
+
+    fn default_output(&self) -> Option<String> {
+        self.state().default_output.clone()
+    }
+}
+
+struct VirtualDevice {
+    backend: VirtualBackend,
+    name: String,
+    input: bool,
+}
+
+impl VirtualDevice {
+    fn build(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+        input: bool,
+    ) -> Result<Box<dyn ActiveStream>> {
+        if input != self.input {
+            bail!("虚拟设备 {} 不是{}设备", self.name, if input { "输入" } else { "输出" });
+        }
+        let mut state = self.backend.state();
+        let Some(device) = state.devices.get(&self.name) else {
+            bail!("虚拟音频设备已移除: {}", self.name);
+        };
+        let spec = device.spec;
+        let rate = spec.sample_rate as f64 * (1.0 + spec.clock_ppm / 1_000_000.0);
+        let id = state.next_id;
+        state.next_id += 1;
+        state.streams.push(StreamEntry {
+            id,
+            device: self.name.clone(),
+            input,
+            playing: false,
+            format: *format,
+            interval: spec.period_frames as f64 / rate,
+            next_due: 0.0,
+            buffer: vec![0.0; spec.period_frames * format.channels as usize],
+            on_data,
+            on_error,
+        });
+        Ok(Box::new(VirtualStream {
+            backend: self.backend.clone(),
+            id,
+        }))
+    }
+}
+
+impl AudioDevice for VirtualDevice {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn negotiate(&self, request: &FormatRequest) -> Result<DeviceFormat> {
+        let state = self.backend.state();
+        let Some(device) = state.devices.get(&self.name) else {
+            bail!("虚拟音频设备已移除: {}", self.name);
+        };
+        if let Some(channels) = request.channels {
+            if channels != device.spec.channels {
+                bail!(
+                    "设备不支持 {} 声道，可用声道数: {:?}",
+                    channels,
+                    [device.spec.channels]
+                );
+            }
+        }
+        Ok(DeviceFormat {
+            sample_rate: device.spec.sample_rate,
+            channels: device.spec.channels,
+            sample_format: SampleFormat::F32,
+        })
+    }
+
+    fn build_input_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>> {
+        self.build(format, on_data, on_error, true)
+    }
+
+    fn build_output_stream(
+        &self,
+        format: &DeviceFormat,
+        on_data: DataCallback,
+        on_error: ErrorCallback,
+    ) -> Result<Box<dyn ActiveStream>> {
+        self.build(format, on_data, on_error, false)
+    }
+}
+
+struct VirtualStream {
+    backend: VirtualBackend,
+    id: u64,
+}
+
+impl ActiveStream for VirtualStream {
+    fn play(&self) -> Result<()> {
+        let mut state = self.backend.state();
+        let now = state.now;
+        if let Some(stream) = state.streams.iter_mut().find(|stream| stream.id == self.id) {
+            if !stream.playing {
+                stream.playing = true;
+                // 第一次回调在一个周期之后，与真实设备填满缓冲区后才回调一致
+                stream.next_due = now + stream.interval;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VirtualStream {
+    fn drop(&mut self) {
+        self.backend.state().streams.retain(|stream| stream.id != self.id);
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use crate::config::WavFormat;
+use crate::sample_format::AudioSample;
+
+pub type WavReader = hound::WavReader<BufReader<File>>;
+pub type WavWriter = hound::WavWriter<BufWriter<File>>;
+
+/// 打开 WAV 文件读取
+pub fn open(path: &Path) -> Result<WavReader> {
+    hound::WavReader::open(path).with_context(|| format!("打开 WAV 文件失败: {}", path.display()))
+}
+
+/// 按指定格式创建 WAV 文件
+pub fn create(path: &Path, format: WavFormat, sample_rate: u32, channels: u16) -> Result<WavWriter> {
+    let (bits_per_sample, sample_format) = match format {
+        WavFormat::Pcm16 => (16, hound::SampleFormat::Int),
+        WavFormat::Pcm24 => (24, hound::SampleFormat::Int),
+        WavFormat::Float32 => (32, hound::SampleFormat::Float),
+    };
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample,
+        sample_format,
+    };
+    hound::WavWriter::create(path, spec).with_context(|| format!("创建 WAV 文件失败: {}", path.display()))
+}
+
+/// 与输入文件精度相同的输出格式（32 位整数用 32 位浮点保存）
+pub fn format_of(spec: &hound::WavSpec) -> WavFormat {
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, bits) if bits <= 16 => WavFormat::Pcm16,
+        (hound::SampleFormat::Int, 24) => WavFormat::Pcm24,
+        _ => WavFormat::Float32,
+    }
+}
+
+/// 读取交错排列的 f32 采样到 `buffer`，返回读到的采样数；读完后剩余部分不变
+pub fn read_samples(reader: &mut WavReader, buffer: &mut [f32]) -> usize {
+    let spec = reader.spec();
+    let mut count = 0;
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for (sample, value) in buffer.iter_mut().zip(reader.samples::<f32>().map_while(Result::ok)) {
+                *sample = value;
+                count += 1;
+            }
+        }
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            for (sample, value) in buffer.iter_mut().zip(reader.samples::<i32>().map_while(Result::ok)) {
+                *sample = value as f32 * scale;
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// 按指定格式写入交错排列的 f32 采样，超出 [-1.0, 1.0] 的值被削波
+pub fn write_samples(writer: &mut WavWriter, format: WavFormat, samples: &[f32]) -> hound::Result<()> {
+    for &sample in samples {
+        match format {
+            WavFormat::Pcm16 => writer.write_sample(i16::from_f32(sample))?,
+            WavFormat::Pcm24 => writer.write_sample((i32::from_f32(sample) >> 8).clamp(-(1 << 23), (1 << 23) - 1))?,
+            WavFormat::Float32 => writer.write_sample(f32::from_f32(sample))?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("trans-wav-{}-{}.wav", name, std::process::id()))
+    }
+
+    /// 按格式写入后读回，返回读到的采样和文件格式
+    fn round_trip(name: &str, format: WavFormat, samples: &[f32]) -> (Vec<f32>, hound::WavSpec) {
+        let path = temp_path(name);
+        let mut writer = create(&path, format, 16_000, 2).unwrap();
+        write_samples(&mut writer, format, samples).unwrap();
+        writer.finalize().unwrap();
+
+        let mut reader = open(&path).unwrap();
+        let spec = reader.spec();
+        let mut buffer = vec![9.0; samples.len() + 4];
+        let count = read_samples(&mut reader, &mut buffer);
+        std::fs::remove_file(&path).unwrap();
+        buffer.truncate(count);
+        (buffer, spec)
+    }
+
+    #[test]
+    fn integer_formats_round_trip_exactly_on_their_grid() {
+        let pcm16: Vec<f32> = [-32768, -12345, -1, 0, 1, 4096, 16384, 32767]
+            .iter()
+            .map(|&v| v as f32 / 32768.0)
+            .collect();
+        let (read, spec) = round_trip("pcm16", WavFormat::Pcm16, &pcm16);
+        assert_eq!(read, pcm16);
+        assert_eq!((spec.bits_per_sample, spec.sample_format), (16, hound::SampleFormat::Int));
+        assert_eq!((spec.sample_rate, spec.channels), (16_000, 2));
+
+        let pcm24: Vec<f32> = [-8_388_608, -1_234_567, -1, 0, 1, 8_388_607]
+            .iter()
+            .map(|&v| v as f32 / 8_388_608.0)
+            .collect();
+        let (read, spec) = round_trip("pcm24", WavFormat::Pcm24, &pcm24);
+        assert_eq!(read, pcm24);
+        assert_eq!(spec.bits_per_sample, 24);
+    }
+
+    #[test]
+    fn float_round_trips_and_all_formats_clip() {
+        let samples = [0.1234567, -0.75, 1.5, -2.0];
+        let (read, spec) = round_trip("float", WavFormat::Float32, &samples);
+        assert_eq!(read, [0.1234567, -0.75, 1.0, -1.0]);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+
+        let (read, _) = round_trip("pcm16-clip", WavFormat::Pcm16, &[1.5, -1.5]);
+        assert_eq!(read, [32767.0 / 32768.0, -1.0]);
+        let (read, _) = round_trip("pcm24-clip", WavFormat::Pcm24, &[1.5, -1.5]);
+        assert_eq!(read, [8_388_607.0 / 8_388_608.0, -1.0]);
+    }
+
+    #[test]
+    fn read_stops_at_end_of_file() {
+        let path = temp_path("short");
+        let mut writer = create(&path, WavFormat::Pcm16, 8_000, 1).unwrap();
+        write_samples(&mut writer, WavFormat::Pcm16, &[0.5, -0.5, 0.25]).unwrap();
+        writer.finalize().unwrap();
+
+        let mut reader = open(&path).unwrap();
+        let mut buffer = [7.0f32; 2];
+        assert_eq!(read_samples(&mut reader, &mut buffer), 2);
+        assert_eq!(buffer, [0.5, -0.5]);
+        // 读完后剩余部分保持不变
+        assert_eq!(read_samples(&mut reader, &mut buffer), 1);
+        assert_eq!(buffer, [0.25, -0.5]);
+        assert_eq!(read_samples(&mut reader, &mut buffer), 0);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(open(&temp_path("missing")).is_err());
+    }
+
+    #[test]
+    fn output_format_follows_input_precision() {
+        let spec = |bits_per_sample, sample_format| hound::WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample,
+            sample_format,
+        };
+        assert_eq!(format_of(&spec(8, hound::SampleFormat::Int)), WavFormat::Pcm16);
+        assert_eq!(format_of(&spec(16, hound::SampleFormat::Int)), WavFormat::Pcm16);
+        assert_eq!(format_of(&spec(24, hound::SampleFormat::Int)), WavFormat::Pcm24);
+        assert_eq!(format_of(&spec(32, hound::SampleFormat::Int)), WavFormat::Float32);
+        assert_eq!(format_of(&spec(32, hound::SampleFormat::Float)), WavFormat::Float32);
+    }
+}
@@ -0,0 +1,112 @@
+//! 实时回调的内存分配检查，需启用特性运行：`cargo test --features alloc-check --test alloc_check`
+#![cfg(feature = "alloc-check")]
+
+use std::thread;
+use std::time::Duration;
+
+use trans::aec::{EchoCanceller, EchoReference};
+use trans::alloc_check::{self, CountingAllocator};
+use trans::audio_io::{AudioStream, AudioTap, StreamConfig, StreamHooks};
+use trans::config::{AecConfig, JitterConfig, TtsConfig};
+use trans::live_chain::LiveChain;
+use trans::processor::ProcessorChain;
+use trans::registry::{self, ProcessorRegistry};
+use trans::resampler::ResamplerQuality;
+use trans::sample_format::CALLBACK_FRAMES;
+use trans::tts::{self, BeepSynthesizer};
+use trans::virtual_backend::{Signal, VirtualBackend, VirtualDeviceSpec};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// 以 44.1 kHz 立体声麦克风、48 kHz 单声道扬声器运行双工流，覆盖重采样、声道混合、旁链总线、旁路、
+/// 合成语音闪避，以及可选的回声消除（参考信号取自同一输出流）；回调中不应分配或释放内存
+fn run_duplex(input_period: usize, output_period: usize, aec: bool) -> AudioStream {
+    let backend = VirtualBackend::new();
+    let input = VirtualDeviceSpec {
+        sample_rate: 44_100,
+        channels: 2,
+        period_frames: input_period,
+        clock_ppm: 0.0,
+    };
+    let output = VirtualDeviceSpec {
+        sample_rate: 48_000,
+        channels: 1,
+        period_frames: output_period,
+        clock_ppm: 100.0,
+    };
+    backend.add_input("mic", input, Signal::Sine { frequency: 440.0, amplitude: 0.3 });
+    let capture = backend.add_output("speaker", output);
+
+    let configs = registry::preset(
+        "noise_gate:threshold_db=-50,send:bus=key,compressor:sidechain=key,\
+         eq:bands=[{filter=\"high_pass\",frequency=80}],denoise,agc,limiter:ceiling_db=-1:true_peak=true",
+    )
+    .unwrap();
+    let mut chain = ProcessorChain::new();
+    let mut echo_reference = None;
+    if aec {
+        // 参考信号按扬声器采样率写入，回声消除准备时据此创建参考信号的重采样器
+        let (reference, source) = EchoReference::channel();
+        reference.set_sample_rate(output.sample_rate);
+        let config = AecConfig {
+            enabled: true,
+            ..AecConfig::default()
+        };
+        chain.add_processor(Box::new(EchoCanceller::new(config, source)));
+        echo_reference = Some(reference);
+    }
+    // 与主程序相同，配置的处理器链放在可替换链中，回声消除在最前面
+    let (live, _handle) = LiveChain::new(ProcessorRegistry::with_builtin().build_chain("input_chain", &configs).unwrap());
+    chain.add_processor(Box::new(live));
+    let (tap, _receiver) = AudioTap::bounded(96_000);
+    let tts_config = TtsConfig::default();
+    let (queue, injector, _synthesis) =
+        tts::spawn_queue(Box::new(BeepSynthesizer::new(880.0, 0.2, Duration::from_millis(50))), &tts_config).unwrap();
+
+    let stream_config = StreamConfig {
+        sample_rate: 44_100,
+        buffer_size: input_period as u32,
+        input_channels: None,
+        output_channels: None,
+        resample_quality: ResamplerQuality::Balanced,
+        jitter: JitterConfig::default(),
+    };
+    let hooks = StreamHooks {
+        tap: Some(tap),
+        injector: Some(injector),
+        echo_reference,
+    };
+    let stream = AudioStream::create_duplex_stream(&backend, "mic", "speaker", stream_config, chain, true, hooks).unwrap();
+
+    backend.advance(Duration::from_millis(500));
+    // 合成线程按输出采样率生成语音，等语音排入播放器后再继续推进时钟
+    assert!(queue.speak("你好世界".to_string()));
+    thread::sleep(Duration::from_millis(200));
+    backend.advance(Duration::from_secs(2));
+
+    assert_eq!(alloc_check::allocations(), 0);
+    assert!(stream.take_error().is_none());
+    let (errors, dropped) = stream.take_callback_errors();
+    assert!(errors.is_empty() && dropped == 0, "回调错误: {:?}", errors);
+    assert!(capture.len() >= 48_000 * 2);
+    stream
+}
+
+#[test]
+fn duplex_stream_callbacks_do_not_allocate() {
+    let stream = run_duplex(441, 480, false);
+    assert_eq!(stream.jitter_stats().underruns, 0);
+}
+
+#[test]
+fn echo_cancellation_does_not_allocate() {
+    let stream = run_duplex(441, 480, true);
+    assert_eq!(stream.jitter_stats().underruns, 0);
+}
+
+/// 宿主给出的缓冲区超过预分配的 `CALLBACK_FRAMES` 帧时分段处理，仍不分配内存
+#[test]
+fn oversized_callbacks_do_not_allocate() {
+    run_duplex(CALLBACK_FRAMES + 1000, CALLBACK_FRAMES * 2 + 7, true);
+}
@@ -0,0 +1,156 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use trans::audio_io::{AudioStream, StreamConfig, StreamHooks};
+use trans::config::JitterConfig;
+use trans::processor::{AudioProcessor, GainProcessor, ProcessorChain};
+use trans::resampler::ResamplerQuality;
+use trans::virtual_backend::{CaptureBuffer, Signal, VirtualBackend, VirtualDeviceSpec};
+
+fn spec(sample_rate: u32, channels: u16) -> VirtualDeviceSpec {
+    VirtualDeviceSpec {
+        sample_rate,
+        channels,
+        period_frames: sample_rate as usize / 100,
+        clock_ppm: 0.0,
+    }
+}
+
+fn stream_config(sample_rate: u32) -> StreamConfig {
+    StreamConfig {
+        sample_rate,
+        buffer_size: sample_rate / 100,
+        input_channels: None,
+        output_channels: None,
+        resample_quality: ResamplerQuality::Balanced,
+        jitter: JitterConfig::default(),
+    }
+}
+
+fn start(backend: &VirtualBackend, signal: Signal, chain: ProcessorChain) -> (AudioStream, CaptureBuffer) {
+    start_with(backend, (spec(48_000, 1), signal), spec(48_000, 1), stream_config(48_000), chain)
+}
+
+fn start_with(
+    backend: &VirtualBackend,
+    (input, signal): (VirtualDeviceSpec, Signal),
+    output: VirtualDeviceSpec,
+    config: StreamConfig,
+    chain: ProcessorChain,
+) -> (AudioStream, CaptureBuffer) {
+    backend.add_input("mic", input, signal);
+    let capture = backend.add_output("speaker", output);
+    let stream =
+        AudioStream::create_duplex_stream(backend, "mic", "speaker", config, chain, true, StreamHooks::default())
+            .unwrap();
+    (stream, capture)
+}
+
+fn gain_chain(gain: f32) -> ProcessorChain {
+    let mut chain = ProcessorChain::new();
+    chain.add_processor(Box::new(GainProcessor::new(gain)));
+    chain
+}
+
+/// 加 Hann 窗后估计单个频率分量的幅度
+fn component_amplitude(samples: &[f32], sample_rate: u32, frequency: f64) -> f64 {
+    let len = samples.len() as f64;
+    let (mut re, mut im, mut weight) = (0.0, 0.0, 0.0);
+    for (n, &sample) in samples.iter().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / len).cos();
+        let angle = 2.0 * PI * frequency * n as f64 / sample_rate as f64;
+        re += window * sample as f64 * angle.cos();
+        im += window * sample as f64 * angle.sin();
+        weight += window;
+    }
+    2.0 * (re * re + im * im).sqrt() / weight
+}
+
+#[test]
+fn gain_chain_output_is_delayed_scaled_input() {
+    let backend = VirtualBackend::new();
+    let input: Vec<f32> = (0..4800).map(|n| (n % 1000) as f32 / 1000.0 - 0.5).collect();
+    let signal = Signal::Samples {
+        samples: input.clone(),
+        looping: true,
+    };
+    // 关闭漂移补偿，输出应是输入逐个采样的精确副本
+    let mut config = stream_config(48_000);
+    config.jitter.drift_correction = false;
+    let (stream, capture) = start_with(&backend, (spec(48_000, 1), signal), spec(48_000, 1), config, gain_chain(0.5));
+
+    // 第一次回调在一个周期之后，多推进半个周期以包含第 100 次回调
+    backend.advance(Duration::from_millis(1005));
+    let output = capture.samples();
+    assert_eq!(output.len(), 48_000);
+    let delay = output.iter().position(|&sample| sample != 0.0).expect("输出全是静音");
+    // 抖动缓冲区缓冲到 40 ms 目标水位后才开始输出
+    assert!(delay <= 1920, "延迟 {} 帧", delay);
+    for (n, &sample) in output[delay..].iter().enumerate() {
+        assert_eq!(sample, input[n % input.len()] * 0.5, "第 {} 帧", n);
+    }
+    assert!(stream.take_error().is_none());
+    assert_eq!(stream.jitter_stats().underruns, 0);
+}
+
+#[test]
+fn stereo_input_is_downmixed_and_resampled() {
+    let backend = VirtualBackend::new();
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        amplitude: 0.4,
+    };
+    // 两个设备时钟一致，关闭漂移补偿，避免微调比例使频率偏离 1 kHz
+    let mut config = stream_config(44_100);
+    config.jitter.drift_correction = false;
+    let (stream, capture) = start_with(&backend, (spec(44_100, 2), signal), spec(48_000, 1), config, gain_chain(0.5));
+    assert_eq!(stream.input_format().sample_rate, 44_100);
+    assert_eq!(stream.output_format().channels, 1);
+
+    backend.advance(Duration::from_millis(2005));
+    let output = capture.samples();
+    assert_eq!(output.len(), 96_000);
+    // 跳过起始的缓冲，稳定后 1 kHz 正弦按 48 kHz 播放，幅度为 0.4 × 0.5
+    let settled = &output[48_000..];
+    let amplitude = component_amplitude(settled, 48_000, 1000.0);
+    assert!((amplitude - 0.2).abs() < 0.002, "1 kHz 幅度 {}", amplitude);
+    let rms = (settled.iter().map(|&sample| (sample as f64).powi(2)).sum::<f64>() / settled.len() as f64).sqrt();
+    assert!((rms - 0.2 / 2f64.sqrt()).abs() < 0.002, "有效值 {}，输出中混入了其它频率", rms);
+    assert_eq!(stream.jitter_stats().underruns, 0);
+}
+
+/// 每次处理都失败的处理器
+struct FailingProcessor;
+
+impl AudioProcessor for FailingProcessor {
+    fn process(&mut self, _buffer: &mut [f32]) -> Result<()> {
+        bail!("故障处理器执行失败")
+    }
+
+    fn name(&self) -> &str {
+        "故障处理器"
+    }
+}
+
+#[test]
+fn processor_errors_are_handed_to_the_caller() {
+    let backend = VirtualBackend::new();
+    let mut chain = ProcessorChain::new();
+    chain.add_processor(Box::new(FailingProcessor));
+    let (stream, capture) = start(&backend, Signal::Sine { frequency: 440.0, amplitude: 0.5 }, chain);
+
+    // 第一次回调在一个周期之后，1.005 秒内共 100 次回调，各失败一次；队列只暂存一部分，其余计数
+    backend.advance(Duration::from_millis(1005));
+    let (errors, dropped) = stream.take_callback_errors();
+    assert!(!errors.is_empty());
+    assert_eq!(errors.len() as u64 + dropped, 100);
+    assert_eq!(errors[0].to_string(), "故障处理器执行失败");
+
+    // 处理错误不会停止音频流，取出后计数清零
+    assert!(stream.take_error().is_none());
+    assert!(!capture.is_empty());
+    let (errors, dropped) = stream.take_callback_errors();
+    assert!(errors.is_empty());
+    assert_eq!(dropped, 0);
+}
@@ -0,0 +1,101 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trans::asr::{TranscriptEvent, TranscriptKind, TranscriptSegment};
+use trans::audio_io::Direction;
+use trans::caption_server::{CaptionEvent, CaptionServer};
+use trans::config::CaptionServerConfig;
+
+fn start_server() -> CaptionServer {
+    let config = CaptionServerConfig {
+        enabled: true,
+        bind: "127.0.0.1".to_string(),
+        port: 0,
+    };
+    CaptionServer::start(&config).unwrap()
+}
+
+/// 等待服务器登记 WebSocket 客户端（握手完成后在连接线程中登记）
+fn wait_for_clients(server: &CaptionServer, count: usize) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.client_count() < count {
+        assert!(Instant::now() < deadline, "客户端没有登记到字幕服务器");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn final_event(text: &str) -> CaptionEvent {
+    CaptionEvent::from(&TranscriptEvent {
+        direction: Direction::Output,
+        segment: TranscriptSegment {
+            kind: TranscriptKind::Final,
+            text: text.to_string(),
+            start: Duration::from_millis(1200),
+            end: Duration::from_millis(2500),
+        },
+    })
+}
+
+#[test]
+fn websocket_client_receives_published_captions() {
+    let server = start_server();
+    let (mut client, _) = tungstenite::connect(format!("ws://{}/ws", server.local_addr())).unwrap();
+    wait_for_clients(&server, 1);
+
+    server.broadcast(&final_event("大家好"));
+    let message = client.read().unwrap();
+    let json: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(json["type"], "final");
+    assert_eq!(json["direction"], "output");
+    assert_eq!(json["speaker"], "remote");
+    assert_eq!(json["text"], "大家好");
+    assert_eq!(json["start_ms"], 1200);
+    assert_eq!(json["end_ms"], 2500);
+    assert!(json.get("source_text").is_none());
+}
+
+#[test]
+fn fragmented_upgrade_request_is_accepted() {
+    let server = start_server();
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let request = format!(
+        "GET /ws HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        server.local_addr()
+    );
+    // 请求行和升级头分两个分段发送，中间停顿，服务器需要等完整请求头再判断
+    let (first, rest) = request.split_at(20);
+    stream.write_all(first.as_bytes()).unwrap();
+    stream.flush().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    stream.write_all(rest.as_bytes()).unwrap();
+
+    let mut response = [0u8; 256];
+    let len = stream.read(&mut response).unwrap();
+    let response = String::from_utf8_lossy(&response[..len]);
+    assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+    assert!(response.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+    wait_for_clients(&server, 1);
+}
+
+#[test]
+fn serves_overlay_page_over_http() {
+    let server = start_server();
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("text/html"));
+    assert!(response.contains("<html"));
+
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream.write_all(b"GET /missing HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"));
+    assert_eq!(server.client_count(), 0);
+}
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trans::audio_io::{AudioStream, StreamConfig, StreamHooks};
+use trans::config::{FileDeviceConfig, JitterConfig, WavFormat};
+use trans::file_backend::FileBackend;
+use trans::processor::{GainProcessor, ProcessorChain};
+use trans::resampler::ResamplerQuality;
+use trans::wav;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("trans-file-backend-{}-{}.wav", name, std::process::id()))
+}
+
+#[test]
+fn processes_input_file_into_output_file() {
+    let input_path = temp_path("input");
+    let output_path = temp_path("output");
+    // 立体声 16 位输入，左右声道不同，1 秒
+    let input: Vec<f32> = (0..16_000 * 2)
+        .map(|n| ((n * 37) % 2000) as f32 / 2000.0 - 0.5)
+        .map(|value| (value * 32768.0).round() / 32768.0)
+        .collect();
+    let mut writer = wav::create(&input_path, WavFormat::Pcm16, 16_000, 2).unwrap();
+    wav::write_samples(&mut writer, WavFormat::Pcm16, &input).unwrap();
+    writer.finalize().unwrap();
+
+    let backend = FileBackend::new(FileDeviceConfig {
+        realtime: false,
+        output_format: WavFormat::Float32,
+        tail_ms: 200,
+    });
+    let mut chain = ProcessorChain::new();
+    chain.add_processor(Box::new(GainProcessor::new(0.5)));
+    let stream_config = StreamConfig {
+        sample_rate: 48_000,
+        buffer_size: 480,
+        input_channels: None,
+        output_channels: None,
+        resample_quality: ResamplerQuality::Balanced,
+        jitter: JitterConfig {
+            drift_correction: false,
+            ..JitterConfig::default()
+        },
+    };
+    let stream = AudioStream::create_duplex_stream(
+        &backend,
+        input_path.to_str().unwrap(),
+        output_path.to_str().unwrap(),
+        stream_config,
+        chain,
+        true,
+        StreamHooks::default(),
+    )
+    .unwrap();
+    // 输出文件跟随输入文件的采样率和声道数，不需要重采样
+    assert_eq!(stream.input_format().sample_rate, 16_000);
+    assert_eq!(stream.output_format().sample_rate, 16_000);
+    assert_eq!(stream.output_format().channels, 2);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !stream.is_finished() {
+        assert!(Instant::now() < deadline, "输入文件没有处理完");
+        thread::sleep(Duration::from_millis(10));
+    }
+    stream.shutdown().unwrap();
+
+    let mut reader = wav::open(&output_path).unwrap();
+    let spec = reader.spec();
+    assert_eq!((spec.sample_rate, spec.channels, spec.sample_format), (16_000, 2, hound::SampleFormat::Float));
+    let mut output = vec![0.0; reader.len() as usize];
+    wav::read_samples(&mut reader, &mut output);
+    std::fs::remove_file(&input_path).unwrap();
+    std::fs::remove_file(&output_path).unwrap();
+
+    // 输出是延后整数帧、按增益缩放的输入，尾部留有静音
+    let delay = output.iter().position(|&sample| sample != 0.0).expect("输出全是静音");
+    assert_eq!(delay % 2, 0);
+    assert!(output.len() >= delay + input.len());
+    for (n, (&out, &expected)) in output[delay..].iter().zip(&input).enumerate() {
+        assert_eq!(out, expected * 0.5, "第 {} 个采样", n);
+    }
+    assert!(output[delay + input.len()..].iter().all(|&sample| sample == 0.0));
+}